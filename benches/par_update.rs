@@ -0,0 +1,70 @@
+//! Times syncing the transforms of 100k renderables into a scene, the way the graphics plugin does every tick.
+//! Run with `cargo bench --bench par_update`.
+
+use std::time::{Duration, Instant};
+use hecs::World;
+use hecs_game::math::Transform;
+use hecs_game::{g3d, Scene, Tracker};
+use rayon::prelude::*;
+
+const RENDERABLES: usize = 100_000;
+const FRAMES: u32 = 50;
+const ROUNDS: u32 = 10;
+
+fn main() {
+    let mut world = World::new();
+    let mut scene = Scene::<g3d::Renderable>::new();
+    for i in 0..RENDERABLES {
+        let tracker = scene.insert(g3d::Renderable::empty());
+        world.spawn((tracker, Transform::default().with_xyz(i as f32, 0.0, 0.0)));
+    }
+
+    // Alternates between both, keeping the fastest round of each to reduce noise
+    let mut sequential = Duration::MAX;
+    let mut parallel = Duration::MAX;
+    for _ in 0..ROUNDS {
+        sequential = sequential.min(time_frames(&mut world, &mut scene, sync_sequential));
+        parallel = parallel.min(time_frames(&mut world, &mut scene, sync_parallel));
+    }
+    println!("sequential:  {sequential:?} per frame");
+    println!("par_update:  {parallel:?} per frame");
+}
+
+/// Moves every transform, syncs them into the scene, and propagates them, returning the mean time per frame.
+fn time_frames(world: &mut World, scene: &mut Scene<g3d::Renderable>, sync: fn(&mut World, &mut Scene<g3d::Renderable>)) -> Duration {
+    let start = Instant::now();
+    for frame in 0..FRAMES {
+        for (_, transform) in world.query_mut::<&mut Transform>() {
+            transform.translation.y = frame as f32;
+        }
+        sync(world, scene);
+        scene.graph.propagate_dirty((), |_, _, _| ((), false));
+    }
+    start.elapsed() / FRAMES
+}
+
+fn sync_sequential(world: &mut World, scene: &mut Scene<g3d::Renderable>) {
+    for (_, (transform, tracker)) in world.query_mut::<(&Transform, &Tracker<g3d::Renderable>)>() {
+        let Some(renderable) = scene.get(tracker.id()) else { continue };
+        if renderable.transform() == *transform && !renderable.is_interpolating() { continue }
+        let Some(renderable) = scene.get_mut(tracker.id()) else { continue };
+        renderable.set_transform(*transform);
+    }
+}
+
+fn sync_parallel(world: &mut World, scene: &mut Scene<g3d::Renderable>) {
+    let batches: Vec<_> = world
+        .query_mut::<(&Transform, &Tracker<g3d::Renderable>)>()
+        .into_iter_batched(10000)
+        .collect();
+    let transforms = batches
+        .into_par_iter()
+        .flat_map_iter(|batch| batch.map(|(_, (transform, tracker))| (tracker.id(), transform)));
+    scene.graph.par_update(transforms, |renderable, transform| {
+        if renderable.transform() == *transform && !renderable.is_interpolating() {
+            return false;
+        }
+        renderable.set_transform(*transform);
+        true
+    });
+}
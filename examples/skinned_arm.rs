@@ -0,0 +1,108 @@
+use glam::{Mat4, Quat, Vec3, Vec4};
+use hecs_game::math::Transform;
+use hecs_game::{g3d, App, AssetManager, Camera, CameraController, Color, EnginePlugin, FlycamMode, FlycamPlugin, Game, GraphicsState, PerspectiveProjector, RunContext, ScalingMode, Scene, Stage, StartEvent};
+use hecs::World;
+
+const ELBOW: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+
+fn main() {
+    let mut builder = App::builder();
+    builder
+        .plugin(EnginePlugin::default())
+        .plugin(FlycamPlugin)
+        .system(Stage::Update, wave_arms)
+        .tick_rate(60.0)
+        .event_handler(handle_start);
+    builder.run();
+}
+
+fn handle_start(game: &mut Game, _event: &StartEvent, _ctx: &mut RunContext) {
+
+    // Extracts domains
+    let mut world       = game.get::<&mut World>();
+    let mut scene       = game.get::<&mut Scene<g3d::Renderable>>();
+    let state           = game.get::<&GraphicsState>();
+    let mut assets      = game.get::<&mut AssetManager>();
+
+    // Spawns flycam
+    let cam_tracker = scene.insert(g3d::Renderable::camera());
+    let cam_transform = Transform::default().with_xyz(0.0, 1.0, 4.0);
+    world.spawn((
+        cam_tracker,
+        cam_transform,
+        Camera::default(),
+        CameraController {
+            perspective: PerspectiveProjector {
+                aspect_ratio: 1.0,
+                near: 0.2,
+                far: 1000.0,
+                ..Default::default()
+            },
+            t: 1.0,
+            scaling_mode: ScalingMode::ScaleSmallest,
+            flycam_mode: FlycamMode::Disabled,
+            ..Default::default()
+        },
+    ));
+
+    // Creates an arm with an upper arm and a forearm, each following its own bone
+    let upper_arm = arm_segment(0.5, Color::WHITE, 0);
+    let forearm = arm_segment(1.5, Color::new(1.0, 0.5, 0.2, 1.0), 1);
+    let arm = merge(upper_arm, forearm);
+    let mesh = assets.insert(g3d::Mesh::from_data(&arm, &state.device));
+    let material = assets.insert(g3d::Material::from(Color::WHITE));
+
+    // Spawns arm
+    let renderable = scene.insert(g3d::Renderable::mat_mesh(material, mesh));
+    world.spawn((
+        renderable,
+        Transform::IDENTITY,
+        g3d::SkinnedMeshPose::default(),
+        Wave { angle: 0.0, speed: 3.0 },
+    ));
+}
+
+/// Segment of an arm, one unit tall, centered at height y, fully weighted to a single bone.
+fn arm_segment(y: f32, color: Color, bone: u8) -> g3d::MeshData {
+    let mut segment = g3d::MeshData::from(g3d::Cuboid {
+        center: Vec3::new(0.0, y, 0.0),
+        half_extents: Vec3::new(0.15, 0.5, 0.15),
+        color,
+    });
+    let vertex_count = segment.positions.len();
+    segment.bone_indices = Some(vec![[bone, 0, 0, 0]; vertex_count]);
+    segment.bone_weights = Some(vec![Vec4::new(1.0, 0.0, 0.0, 0.0); vertex_count]);
+    segment
+}
+
+/// Appends the vertices and indices of b to those of a.
+fn merge(mut a: g3d::MeshData, b: g3d::MeshData) -> g3d::MeshData {
+    let offset = a.positions.len() as u32;
+    a.indices.extend(b.indices.iter().map(|index| index + offset));
+    a.positions.extend(b.positions);
+    if let (Some(colors), Some(b_colors)) = (&mut a.colors, b.colors) { colors.extend(b_colors) }
+    if let (Some(normals), Some(b_normals)) = (&mut a.normals, b.normals) { normals.extend(b_normals) }
+    if let (Some(uvs), Some(b_uvs)) = (&mut a.uvs, b.uvs) { uvs.extend(b_uvs) }
+    if let (Some(indices), Some(b_indices)) = (&mut a.bone_indices, b.bone_indices) { indices.extend(b_indices) }
+    if let (Some(weights), Some(b_weights)) = (&mut a.bone_weights, b.bone_weights) { weights.extend(b_weights) }
+    a
+}
+
+struct Wave {
+    angle: f32,
+    speed: f32,
+}
+
+/// Swings the upper arm from the shoulder, and the forearm from the elbow.
+fn wave_arms(game: &mut Game, ctx: RunContext) {
+    let mut world = game.get::<&mut World>();
+    let delta = ctx.delta_secs();
+    for (_, (pose, wave)) in world.query_mut::<(&mut g3d::SkinnedMeshPose, &mut Wave)>() {
+        wave.angle += wave.speed * delta;
+        let shoulder = Mat4::from_quat(Quat::from_rotation_z(wave.angle.sin() * 0.4));
+        let elbow = Mat4::from_translation(ELBOW)
+            * Mat4::from_quat(Quat::from_rotation_z((wave.angle * 2.0).sin() * 0.8 - 0.8))
+            * Mat4::from_translation(-ELBOW);
+        pose.matrices = vec![shoulder, shoulder * elbow];
+    }
+}
@@ -0,0 +1,48 @@
+use hecs_game::math::Transform;
+use hecs_game::{g2d, App, AssetManager, Color, EnginePlugin, FpsCounterPlugin, Game, RunContext, Scene, Stage, StartEvent, Window};
+use hecs::World;
+
+const FONT_PATH: &str = "fonts/DejaVuSansMono.ttf";
+
+fn main() {
+    let mut builder = App::builder();
+    builder
+        .plugin(EnginePlugin::default())
+        .plugin(FpsCounterPlugin::new(FONT_PATH).with_color(Color::new(1.0, 1.0, 0.0, 1.0)))
+        .system(Stage::Update, bob_texts)
+        .tick_rate(60.0)
+        .event_handler(handle_start);
+    builder.run();
+}
+
+fn handle_start(game: &mut Game, _event: &StartEvent, _ctx: &mut RunContext) {
+
+    // Extracts domains
+    let mut world       = game.get::<&mut World>();
+    let mut texts       = game.get::<&mut Scene<g2d::Text>>();
+    let mut assets      = game.get::<&mut AssetManager>();
+    let window          = game.get::<&Window>();
+
+    // Spawns a paragraph in the middle of the window, that wraps to fit
+    let font = assets.load(FONT_PATH);
+    let size = window.size();
+    let text = g2d::Text::new("The quick brown fox jumps over the lazy dog.\nSphinx of black quartz, judge my vow.", font, 24.0)
+        .with_max_width(size.x * 0.75);
+    let center = Transform::default().with_xyz(size.x / 2.0, size.y / 2.0, 0.0);
+    world.spawn((texts.insert(text), center, Bob { origin: center.translation.y, angle: 0.0 }));
+}
+
+struct Bob {
+    origin: f32,
+    angle: f32,
+}
+
+/// Bobs texts up and down.
+fn bob_texts(game: &mut Game, ctx: RunContext) {
+    let mut world = game.get::<&mut World>();
+    let delta = ctx.delta_secs();
+    for (_, (transform, bob)) in world.query_mut::<(&mut Transform, &mut Bob)>() {
+        bob.angle += 2.0 * delta;
+        transform.translation.y = bob.origin + bob.angle.sin() * 16.0;
+    }
+}
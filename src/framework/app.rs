@@ -1,9 +1,12 @@
-use std::collections::VecDeque;
+use std::any::{type_name, TypeId};
+use std::collections::{BTreeMap, VecDeque};
 use std::time::Duration;
-use log::warn;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{Receiver, Sender};
+use log::{error, warn};
 use tracing::instrument;
-use vecmap::VecSet;
-use crate::{DynEvent, Event, EventBus, EventHandler, Game, HashMap, Script, StartEvent};
+use derive_more::{Display, Error};
+use crate::{ConsumingEventHandler, DynEvent, Event, EventBus, EventHandler, Game, HashMap, HashSet, Script, StartEvent, TicksDroppedEvent};
     
 /**
  * Adds logic to a [`Game`] by executing [`System`]s across it.
@@ -12,14 +15,22 @@ use crate::{DynEvent, Event, EventBus, EventHandler, Game, HashMap, Script, Star
 pub struct App {
     pub game: Game,                                     // Game to update state via systems.
     pub(crate) quit_requested: bool,                    // If true, app has requested that it quit.
+    shut_down: bool,                                    // If true, shutdown stage has already run.
     tick: u64,                                          // Current tick.
+    time_since_startup: Duration,                       // Sum of all frame deltas.
+    tick_elapsed: Duration,                             // Sum of the durations of all completed ticks.
     tick_accum: Duration,                               // Time accumulated for current tick.
     tick_duration: Duration,                            // Length of time for a single game tick.
+    max_ticks_per_frame: u32,                           // Most ticks that can run in a single frame.
     systems: HashMap<System, SystemMeta>,               // Systems that manipulate the state of the Game.
-    enabled_systems: HashMap<Stage, VecSet<System>>,    // Subset of systems that are enabled.
+    enabled_systems: HashMap<Stage, BTreeMap<usize, System>>,   // Subset of systems that are enabled, keyed by rank.
+    startup_systems: Vec<System>,                       // Systems that run once, before the first stage.
     scripts: HashMap<Stage, Vec<Script>>,               // Scripts.
     event_queue: VecDeque<DynEvent>,                    // Enqueued events
+    event_sender: Sender<DynEvent>,                     // Sends events from outside of systems, such as from the AssetManager.
+    event_receiver: Receiver<DynEvent>,                 // Receives events sent with event_sender, moved to event_queue before handling events.
     event_bus: EventBus,                                // Place to fire events, and attach event handlers.
+    event_updates: HashMap<TypeId, fn(&mut Game)>,      // Updates the Events domain of each event type fired so far.
     commands: VecDeque<Box<dyn Command>>,
     app_requests: VecDeque<AppRequest>,
 }
@@ -27,42 +38,83 @@ pub struct App {
 impl App {
 
     pub fn builder() -> AppBuilder {
+        let mut game = Game::new();
+        game.add(Time::default());
+        let (event_sender, event_receiver) = std::sync::mpsc::channel();
         AppBuilder {
             app: Self {
-                game: Game::new(),
+                game,
                 quit_requested: false,
+                shut_down: false,
                 tick: 1,
+                time_since_startup: Duration::ZERO,
+                tick_elapsed: Duration::ZERO,
                 tick_accum: Duration::ZERO,
                 tick_duration: Duration::from_secs_f64(1.0/60.0),
+                max_ticks_per_frame: 5,
                 systems: HashMap::default(),
                 enabled_systems: HashMap::default(),
+                startup_systems: Vec::new(),
                 scripts: HashMap::default(),
                 event_queue: VecDeque::default(),
+                event_sender,
+                event_receiver,
                 event_bus: EventBus::default(),
+                event_updates: HashMap::default(),
                 commands: VecDeque::new(),
                 app_requests: VecDeque::new(),
             },
             runner: None,
+            default_stage: None,
+            orderings: Vec::new(),
+            plugins: HashSet::default(),
         }
     }
 
     pub fn tick_duration(&self) -> Duration { self.tick_duration }
 
+    pub fn max_ticks_per_frame(&self) -> u32 { self.max_ticks_per_frame }
+
+    /**
+     * Time elapsed since the app started.
+     * Accumulated from frame deltas rather than the system clock, so simulated runs are deterministic.
+     */
+    pub fn time_since_startup(&self) -> Duration { self.time_since_startup }
+
     /**
      * Advances the game logic by a frame.
      * Runs all per-frame stages.
      * Runs all per-tick stages if enough time has accumulated.
+     * Runs at most [`App::max_ticks_per_frame`] ticks, discarding the time of any ticks beyond that.
+     * Fires a [`TicksDroppedEvent`] when ticks are discarded.
      */
     #[instrument(skip(self))]
     pub fn run_frame(&mut self, delta: Duration) {
+
+        // Runs startup systems if this is the first frame
+        if !self.startup_systems.is_empty() {
+            self.run_startup_systems();
+        }
+
+        // Drops events that readers had two frames to see
+        for update in self.event_updates.values() {
+            update(&mut self.game);
+        }
         
         // Determines how many times to run per-tick stages
+        self.time_since_startup += delta;
         self.tick_accum += delta;
         let mut num_ticks = 0;
         while self.tick_accum >= self.tick_duration {
             self.tick_accum -= self.tick_duration;
             num_ticks += 1;
         }
+        if num_ticks > self.max_ticks_per_frame {
+            let dropped = num_ticks - self.max_ticks_per_frame;
+            num_ticks = self.max_ticks_per_frame;
+            warn!("Dropped {dropped} tick(s) that could not keep up");
+            self.event_queue.push_back(DynEvent::new(TicksDroppedEvent { dropped }));
+        }
         let partial_ticks = self.tick_accum.as_secs_f32() / self.tick_duration.as_secs_f32();
 
         // Fires StartEvent if this is the first tick
@@ -73,17 +125,90 @@ impl App {
 
         // Runs per-tick stages
         for _ in 0..num_ticks {
+            self.sync_time();
             self.run_stage(Stage::PreUpdate, self.tick_duration, true, partial_ticks);
             self.run_stage(Stage::Update, self.tick_duration, true, partial_ticks);
             self.run_stage(Stage::UpdatePhysics, self.tick_duration, true, partial_ticks);
             self.run_stage(Stage::PostUpdate, self.tick_duration, true, partial_ticks);
             self.run_stage(Stage::Cleanup, self.tick_duration, true, partial_ticks);
             self.tick += 1; 
+            self.tick_elapsed += self.tick_duration;
         }
 
         // Runs per-frame stages
+        self.sync_time();
         self.run_stage(Stage::Asset, delta, is_tick, partial_ticks);
+        self.run_stage(Stage::PreRender, delta, is_tick, partial_ticks);
         self.run_stage(Stage::Render, delta, is_tick, partial_ticks);
+
+        // Runs shutdown stage if a system requested that the app quit
+        if self.quit_requested {
+            self.shutdown();
+        }
+    }
+
+    /**
+     * Runs the [`Stage::Shutdown`] stage.
+     * Does nothing if it has already run.
+     * Invoked automatically at the end of the frame in which a quit was requested.
+     * Runners should also invoke this when exiting for other reasons, IE: the window was closed.
+     */
+    pub fn shutdown(&mut self) {
+        if self.shut_down {
+            return;
+        }
+        self.shut_down = true;
+        self.run_stage(Stage::Shutdown, Duration::ZERO, false, 0.0);
+    }
+
+    /// Runs all startup systems once, then executes enqueued tasks.
+    fn run_startup_systems(&mut self) {
+        for system in std::mem::take(&mut self.startup_systems) {
+            let ctx = RunContext {
+                commands: &mut self.commands,
+                app_requests: &mut self.app_requests,
+                event_queue: &mut self.event_queue,
+                delta: Duration::ZERO,
+                is_tick: false,
+                partial_ticks: 0.0,
+                time_since_startup: self.time_since_startup,
+                tick_elapsed: self.tick_elapsed,
+                tick: self.tick,
+                tick_accum: self.tick_accum,
+            };
+            let game = &mut self.game;
+            let result = panic::catch_unwind(AssertUnwindSafe(|| system(game, ctx)));
+            if let Err(payload) = result {
+                error!("Startup system {system:?} panicked");
+                panic::resume_unwind(payload);
+            }
+        }
+        self.run_tasks(Duration::ZERO, false, 0.0);
+    }
+
+    /**
+     * Queues an event to be handled at the end of the next stage that runs.
+     * Useful for runners that receive input from outside of the game.
+     */
+    pub fn fire<E: Event>(&mut self, event: E) {
+        self.event_queue.push_back(DynEvent::new(event));
+    }
+
+    /// Updates the [`Time`] domain, if present.
+    fn sync_time(&mut self) {
+        let Some(mut time) = self.game.try_get::<&mut Time>() else { return };
+        time.elapsed = self.time_since_startup;
+        time.tick = self.tick;
+    }
+
+    /**
+     * Runs n frames, each with the same simulated delta.
+     * Useful for deterministic tests which run without a window.
+     */
+    pub fn run_n_frames_simulated(&mut self, n: u64, frame_time: Duration) {
+        for _ in 0..n {
+            self.run_frame(frame_time);
+        }
     }
 
     /**
@@ -94,7 +219,13 @@ impl App {
 
         // Runs systems for stage specified.
         if let Some(systems) = self.enabled_systems.get_mut(&stage) {
-            for system in systems.iter().copied() {
+            for system in systems.values().copied() {
+                let condition = self.systems.get(&system).and_then(|meta| meta.condition);
+                if let Some(condition) = condition {
+                    if !condition(&self.game) {
+                        continue;
+                    }
+                }
                 let ctx = RunContext {
                     commands: &mut self.commands,
                     app_requests: &mut self.app_requests,
@@ -102,6 +233,10 @@ impl App {
                     delta,
                     is_tick,
                     partial_ticks,
+                    time_since_startup: self.time_since_startup,
+                    tick_elapsed: self.tick_elapsed,
+                    tick: self.tick,
+                    tick_accum: self.tick_accum,
                 };
                 system(&mut self.game, ctx);
             }
@@ -117,12 +252,24 @@ impl App {
                     delta,
                     is_tick,
                     partial_ticks,
+                    time_since_startup: self.time_since_startup,
+                    tick_elapsed: self.tick_elapsed,
+                    tick: self.tick,
+                    tick_accum: self.tick_accum,
                 };
                 let finished = script.run(&mut self.game, ctx);
                 !finished
             });
         }
 
+        self.run_tasks(delta, is_tick, partial_ticks);
+    }
+
+    /**
+     * Executes app requests, commands and events enqueued by systems and scripts.
+     */
+    fn run_tasks(&mut self, delta: Duration, is_tick: bool, partial_ticks: f32) {
+
         // Handles app requests emitted by systems and scripts.
         while let Some(app_request) = self.app_requests.pop_front() {
             match app_request {
@@ -138,7 +285,8 @@ impl App {
             command.run(&mut self.game);
         }
 
-        // Runs event bus for all queued events
+        // Runs event bus for all queued events, including those sent from outside of systems
+        self.event_queue.extend(self.event_receiver.try_iter());
         while !self.event_queue.is_empty() {
             let mut event_queue = std::mem::take(&mut self.event_queue);
             let mut ctx = RunContext {
@@ -148,8 +296,14 @@ impl App {
                 delta,
                 is_tick,
                 partial_ticks,
+                time_since_startup: self.time_since_startup,
+                tick_elapsed: self.tick_elapsed,
+                tick: self.tick,
+                tick_accum: self.tick_accum,
             };
             while let Some(event) = event_queue.pop_front() {
+                self.event_updates.entry(event.type_id).or_insert(event.update);
+                (event.send)(&mut self.game, &*event.event);
                 self.event_bus.handle_event(&mut self.game, event, &mut ctx);
             }
         }
@@ -165,7 +319,7 @@ impl App {
             self.enabled_systems
                 .entry(system_meta.stage)
                 .or_default()
-                .insert(system);
+                .insert(system_meta.rank, system);
         }
     }
 
@@ -179,7 +333,7 @@ impl App {
             self.enabled_systems
                 .entry(system_meta.stage)
                 .or_default()
-                .remove(&system);
+                .remove(&system_meta.rank);
         }
     }
 
@@ -195,6 +349,9 @@ impl App {
 pub struct AppBuilder {
     app: App,
     runner: Option<Box<dyn AppRunner>>,
+    default_stage: Option<Stage>,
+    orderings: Vec<(System, SystemOrder)>,
+    plugins: HashSet<TypeId>,
 }
 
 impl AppBuilder {
@@ -210,32 +367,205 @@ impl AppBuilder {
         self
     }
 
+    /// Adds a system to the stage specified with a priority.
+    /// Within a stage, systems with lower priorities run first.
+    /// Systems with equal priorities run in the order they were added.
+    /// [`AppBuilder::system`] uses a priority of 0.
+    pub fn system_with_priority(&mut self, stage: Stage, system: System, priority: i32) -> &mut Self {
+        self.add_system(stage, system, true, priority);
+        self
+    }
+
+    /// Adds a system to the stage specified that only runs when the condition returns true.
+    /// The condition is checked every time the stage runs, before the system would be invoked.
+    /// It is checked in addition to, not instead of, the system being enabled.
+    pub fn system_with_condition(&mut self, stage: Stage, system: System, condition: SystemCondition) -> &mut Self {
+        self.add_system(stage, system, true, 0);
+        self.app.systems.get_mut(&system).unwrap().condition = Some(condition);
+        self
+    }
+
+    /// Adds a system to the stage specified, ordered relative to other systems in the same stage.
+    /// Ordering constraints take precedence over priorities.
+    /// Panics when the app is built if the constraints form a cycle.
+    ///
+    /// ```ignore
+    /// builder.system_ordered(Stage::Update, move_player, &[after(read_input), before(follow_player)]);
+    /// ```
+    pub fn system_ordered(&mut self, stage: Stage, system: System, orders: &[SystemOrder]) -> &mut Self {
+        self.add_system(stage, system, true, 0);
+        for order in orders {
+            self.orderings.push((system, *order));
+        }
+        self
+    }
+
+    /// Adds a system that runs exactly once, after all plugins are installed and before the first stage runs.
+    /// Startup systems run in the order they were added.
+    pub fn startup(&mut self, system: System) -> &mut Self {
+        self.app.startup_systems.push(system);
+        self
+    }
+
+    /// Adds a system that runs once when the app shuts down.
+    /// Useful for flushing writes, or saving data.
+    /// See [`App::shutdown`].
+    pub fn shutdown(&mut self, system: System) -> &mut Self {
+        self.system(Stage::Shutdown, system)
+    }
+
+    /// Adds a system to the default stage.
+    /// Panics if no default stage was set with [`AppBuilder::set_default_stage`].
+    pub fn system_default(&mut self, system: System) -> &mut Self {
+        let stage = self.default_stage.expect("Default stage not set");
+        self.system(stage, system)
+    }
+
+    /// Sets the stage that [`AppBuilder::system_default`] adds systems to.
+    pub fn set_default_stage(&mut self, stage: Stage) -> &mut Self {
+        self.default_stage = Some(stage);
+        self
+    }
+
+    /// Unsets the default stage.
+    pub fn clear_default_stage(&mut self) -> &mut Self {
+        self.default_stage = None;
+        self
+    }
+
     /// Adds a system to the stage specified.
     pub fn system_enabled(&mut self, stage: Stage, system: System, enabled: bool) -> &mut Self {
+        self.add_system(stage, system, enabled, 0);
+        self
+    }
+
+    fn add_system(&mut self, stage: Stage, system: System, enabled: bool, priority: i32) {
         if self.app.systems.contains_key(&system) {
             panic!("Duplicate system {system:?}");
         }
         let enabled_counter = if enabled { 1 } else { 0 };
-        self.app.systems.insert(system, SystemMeta { enabled_counter, stage });
-        if enabled {
-            self.app.enabled_systems
-                .entry(stage)
-                .or_default()
-                .insert(system);
+        let index = self.app.systems.len();
+        self.app.systems.insert(system, SystemMeta { enabled_counter, stage, priority, index, rank: 0, condition: None });
+    }
+
+    /**
+     * Ranks the systems of each stage by their ordering constraints, then their priorities, then the order they were added.
+     * Panics if the ordering constraints of a stage form a cycle.
+     */
+    fn schedule_systems(&mut self) {
+        let app = &mut self.app;
+
+        // Groups systems by stage
+        let mut stages: HashMap<Stage, Vec<System>> = HashMap::default();
+        for (system, meta) in &app.systems {
+            stages.entry(meta.stage).or_default().push(*system);
         }
-        self
+
+        // Collects which systems must run after which
+        let mut successors: HashMap<System, Vec<System>> = HashMap::default();
+        let mut predecessor_counts: HashMap<System, usize> = HashMap::default();
+        for (system, order) in self.orderings.iter().copied() {
+            let (first, second) = match order {
+                SystemOrder::Before(other) => (system, other),
+                SystemOrder::After(other) => (other, system),
+            };
+            let Some(other_meta) = app.systems.get(&order.other()) else {
+                warn!("Ordering of system {system:?} refers to unregistered system {:?}", order.other());
+                continue;
+            };
+            if other_meta.stage != app.systems[&system].stage {
+                warn!("Ordering of system {system:?} refers to system {:?} in another stage", order.other());
+                continue;
+            }
+            successors.entry(first).or_default().push(second);
+            *predecessor_counts.entry(second).or_default() += 1;
+        }
+
+        // Ranks systems within each stage
+        for (stage, mut remaining) in stages {
+            remaining.sort_by_key(|system| {
+                let meta = &app.systems[system];
+                (meta.priority, meta.index)
+            });
+            let mut rank = 0;
+            while let Some(pos) = remaining.iter().position(|system| predecessor_counts.get(system).copied().unwrap_or(0) == 0) {
+                let system = remaining.remove(pos);
+                for successor in successors.get(&system).into_iter().flatten() {
+                    *predecessor_counts.get_mut(successor).unwrap() -= 1;
+                }
+                let meta = app.systems.get_mut(&system).unwrap();
+                meta.rank = rank;
+                if meta.enabled_counter > 0 {
+                    app.enabled_systems
+                        .entry(stage)
+                        .or_default()
+                        .insert(rank, system);
+                }
+                rank += 1;
+            }
+            if !remaining.is_empty() {
+                panic!("Ordering of systems in stage {stage:?} forms a cycle: {remaining:?}");
+            }
+        }
+    }
+
+    /**
+     * Sender of events fired from outside of systems, such as from background threads.
+     * Sent events are handled at the end of the next stage that runs.
+     */
+    pub(crate) fn event_sender(&self) -> Sender<DynEvent> {
+        self.app.event_sender.clone()
     }
 
+    /// Adds an event handler of priority 0.
     pub fn event_handler<E: Event>(&mut self, handler: EventHandler<E>) -> &mut Self {
-        self.app.event_bus.add_handler(handler);
+        self.event_handler_with_priority(handler, 0)
+    }
+
+    /**
+     * Adds an event handler.
+     * Handlers of an event run by descending priority, and handlers of the same priority run in the order they were added.
+     */
+    pub fn event_handler_with_priority<E: Event>(&mut self, handler: EventHandler<E>, priority: i32) -> &mut Self {
+        self.app.event_bus.add_handler::<E>(handler, priority);
         self
     }
 
-    pub fn plugin(&mut self, mut plugin: impl Plugin) -> &mut Self {
-        plugin.install(self);
+    /**
+     * Adds an event handler that can consume the events it handles, so that handlers of lower priority do not see them.
+     * See [`AppBuilder::event_handler_with_priority`].
+     */
+    pub fn consuming_event_handler<E: Event>(&mut self, handler: ConsumingEventHandler<E>, priority: i32) -> &mut Self {
+        self.app.event_bus.add_handler::<E>(handler, priority);
+        self
+    }
+
+    /// Installs a plugin.
+    /// Panics if a plugin of the same type was already installed.
+    pub fn plugin<P: Plugin + 'static>(&mut self, plugin: P) -> &mut Self {
+        if let Err(err) = self.try_plugin(plugin) {
+            panic!("{err}");
+        }
+        self
+    }
+
+    /// Installs a plugin, unless a plugin of the same type was already installed.
+    /// Useful for optional plugins that other plugins may also install.
+    pub fn plugin_if_absent<P: Plugin + 'static>(&mut self, plugin: P) -> &mut Self {
+        let _ = self.try_plugin(plugin);
         self
     }
 
+    /// Installs a plugin.
+    /// Fails if a plugin of the same type was already installed.
+    pub fn try_plugin<P: Plugin + 'static>(&mut self, mut plugin: P) -> Result<&mut Self, PluginError> {
+        if !self.plugins.insert(TypeId::of::<P>()) {
+            return Err(PluginError::AlreadyInstalled { plugin: type_name::<P>() });
+        }
+        plugin.install(self);
+        Ok(self)
+    }
+
     pub fn tick_duration(&mut self, tick_duration: Duration) -> &mut Self {
         self.app.tick_duration = tick_duration;
         self
@@ -246,12 +576,26 @@ impl AppBuilder {
         self
     }
 
+    /// Most ticks that can run in a single frame, so that a long hitch does not snowball into ever longer frames.
+    /// Defaults to 5.
+    pub fn max_ticks_per_frame(&mut self, max_ticks_per_frame: u32) -> &mut Self {
+        self.app.max_ticks_per_frame = max_ticks_per_frame.max(1);
+        self
+    }
+
     pub fn runner(&mut self, runner: impl AppRunner + 'static) {
         self.runner = Some(Box::new(runner));
     }
 
+    /// Finishes building [`App`] without running it.
+    pub fn build(mut self) -> App {
+        self.schedule_systems();
+        self.app
+    }
+
     /// Finishes building [`App`] and immediately runs it.
     pub fn run(mut self) {
+        self.schedule_systems();
         
         #[cfg(feature = "profile")]
         {
@@ -291,6 +635,12 @@ where F: FnMut(&mut AppBuilder)
     }
 }
 
+#[derive(Error, Copy, Clone, Eq, PartialEq, Display, Debug)]
+pub enum PluginError {
+    #[display(fmt="Plugin {plugin} already installed")]
+    AlreadyInstalled { plugin: &'static str },
+}
+
 pub struct RunContext<'a> {
     commands: &'a mut VecDeque<Box<dyn Command>>,
     app_requests: &'a mut VecDeque<AppRequest>,
@@ -298,6 +648,10 @@ pub struct RunContext<'a> {
     delta: Duration,
     is_tick: bool,
     partial_ticks: f32,
+    time_since_startup: Duration,
+    tick_elapsed: Duration,
+    tick: u64,
+    tick_accum: Duration,
 }
 
 impl<'a> RunContext<'a> {
@@ -324,6 +678,79 @@ impl<'a> RunContext<'a> {
         self.partial_ticks
     }
 
+    /**
+     * Time elapsed since the app started.
+     * See [`App::time_since_startup`].
+     */
+    pub fn time_since_startup(&self) -> Duration {
+        self.time_since_startup
+    }
+
+    /**
+     * Same as [`RunContext::total_frame_elapsed`].
+     */
+    pub fn total_elapsed(&self) -> Duration {
+        self.time_since_startup
+    }
+
+    /**
+     * Sum of all frame deltas since the app started.
+     * Includes the current frame.
+     */
+    pub fn total_frame_elapsed(&self) -> Duration {
+        self.time_since_startup
+    }
+
+    /**
+     * Tick duration multiplied by the number of ticks completed.
+     * Excludes the current tick.
+     * Unlike [`RunContext::total_frame_elapsed`], it only advances in whole ticks.
+     */
+    pub fn total_tick_elapsed(&self) -> Duration {
+        self.tick_elapsed
+    }
+
+    /**
+     * Current tick, starting at 1.
+     * During per-frame stages, this is the tick that will run next.
+     */
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /**
+     * Same as [`RunContext::tick`].
+     */
+    pub fn tick_number(&self) -> u64 {
+        self.tick
+    }
+
+    /**
+     * Time accumulated towards the next tick.
+     * Always less than the tick duration, including after ticks were dropped.
+     */
+    pub fn accumulated(&self) -> Duration {
+        self.tick_accum
+    }
+
+    /**
+     * Shorter-lived context with the same timing, for invoking other systems from within a system.
+     */
+    pub(crate) fn reborrow(&mut self) -> RunContext<'_> {
+        RunContext {
+            commands: self.commands,
+            app_requests: self.app_requests,
+            event_queue: self.event_queue,
+            delta: self.delta,
+            is_tick: self.is_tick,
+            partial_ticks: self.partial_ticks,
+            time_since_startup: self.time_since_startup,
+            tick_elapsed: self.tick_elapsed,
+            tick: self.tick,
+            tick_accum: self.tick_accum,
+        }
+    }
+
     /**
      * Requests that the following [`Command`] be executed at the end of the current [`Stage`](crate::Stage).
      */
@@ -365,13 +792,58 @@ impl<'a> RunContext<'a> {
     }
 }
 
+/// Domain that stores the app's time, updated before every tick and frame.
+/// Allows systems to read the time without a [`RunContext`].
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub struct Time {
+    /// Time elapsed since the app started.
+    pub elapsed: Duration,
+    /// Current tick.
+    pub tick: u64,
+}
+
 /// Function that runs over a [`Game`] and updates its state.
 pub type System = fn(&mut Game, ctx: RunContext);
 
+/// Cheap check of whether a [`System`] should run.
+/// Takes no [`RunContext`], so it cannot have side effects.
+pub type SystemCondition = fn(&Game) -> bool;
+
 /// Metadata for a [`System`].
 pub(crate) struct SystemMeta {
     pub enabled_counter: i32,
     pub stage: Stage,
+    pub priority: i32,
+    pub index: usize,       // Order the system was added in.
+    pub rank: usize,        // Position the system runs at within its stage.
+    pub condition: Option<SystemCondition>,
+}
+
+/// Constrains when a [`System`] runs relative to another in the same [`Stage`].
+/// See [`AppBuilder::system_ordered`].
+#[derive(Copy, Clone, Debug)]
+pub enum SystemOrder {
+    Before(System),
+    After(System),
+}
+
+impl SystemOrder {
+    fn other(self) -> System {
+        match self {
+            Self::Before(other) => other,
+            Self::After(other) => other,
+        }
+    }
+}
+
+/// Runs a system before another system.
+pub fn before(system: System) -> SystemOrder {
+    SystemOrder::Before(system)
+}
+
+/// Runs a system after another system.
+pub fn after(system: System) -> SystemOrder {
+    SystemOrder::After(system)
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
@@ -399,8 +871,14 @@ pub enum Stage {
     /// Runs logic pertaining to asset management.
     Asset,
     /// Per frame.
+    /// CPU-side render preparation, after the game state is final for the frame.
+    /// IE: Syncing renderables, preparing materials.
+    PreRender,
+    /// Per frame.
     /// Updates animations and renders.
     Render,
+    /// Runs once, when the app shuts down.
+    Shutdown,
 }
 
 
@@ -431,4 +909,443 @@ pub(crate) enum AppRequest {
         script: Script,
     },
     Quit,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use crate::{after, before, App, AppBuilder, DynEvent, EventFlow, EventReader, Events, Game, PluginError, RunContext, Stage, StartEvent, TicksDroppedEvent, Time};
+
+    #[derive(Default)]
+    struct Counter {
+        ticks: u32,
+        frames: u32,
+    }
+
+    fn count_ticks(game: &mut Game, _ctx: RunContext) {
+        game.get::<&mut Counter>().ticks += 1;
+    }
+
+    fn count_frames(game: &mut Game, _ctx: RunContext) {
+        game.get::<&mut Counter>().frames += 1;
+    }
+
+    fn counter_app() -> App {
+        let mut builder = App::builder();
+        builder
+            .tick_rate(60.0)
+            .system(Stage::Update, count_ticks)
+            .system(Stage::Render, count_frames);
+        builder.game().add(Counter::default());
+        builder.build()
+    }
+
+    #[test]
+    fn run_n_frames_simulated() {
+        let mut app = counter_app();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(10, tick_duration);
+        let counter = app.game.get::<&Counter>();
+        assert_eq!(10, counter.ticks);
+        assert_eq!(10, counter.frames);
+    }
+
+    #[test]
+    fn run_zero_frames_simulated() {
+        let mut app = counter_app();
+        app.run_n_frames_simulated(0, Duration::from_secs(1));
+        let counter = app.game.get::<&Counter>();
+        assert_eq!(0, counter.ticks);
+        assert_eq!(0, counter.frames);
+    }
+
+    #[test]
+    fn time_since_startup() {
+        let mut app = counter_app();
+        let frame_time = app.tick_duration();
+        app.run_n_frames_simulated(5, frame_time);
+        assert_eq!(frame_time * 5, app.time_since_startup());
+        let time = *app.game.get::<&Time>();
+        assert_eq!(frame_time * 5, time.elapsed);
+        assert_eq!(6, time.tick);
+    }
+
+    #[derive(Default)]
+    struct StageLog(Vec<Stage>);
+
+    fn log_asset(game: &mut Game, _ctx: RunContext) { game.get::<&mut StageLog>().0.push(Stage::Asset); }
+    fn log_pre_render(game: &mut Game, _ctx: RunContext) { game.get::<&mut StageLog>().0.push(Stage::PreRender); }
+    fn log_render(game: &mut Game, _ctx: RunContext) { game.get::<&mut StageLog>().0.push(Stage::Render); }
+
+    #[test]
+    fn pre_render_stage() {
+        let mut builder = App::builder();
+        builder
+            .system(Stage::Render, log_render)
+            .system(Stage::PreRender, log_pre_render)
+            .system(Stage::Asset, log_asset);
+        builder.game().add(StageLog::default());
+        let mut app = builder.build();
+        app.run_n_frames_simulated(2, Duration::ZERO);
+        let log = app.game.get::<&StageLog>();
+        let expected = [Stage::Asset, Stage::PreRender, Stage::Render];
+        assert_eq!(expected.repeat(2), log.0);
+    }
+
+    #[test]
+    fn system_default() {
+        let mut builder = App::builder();
+        builder
+            .set_default_stage(Stage::Update)
+            .system_default(count_ticks)
+            .set_default_stage(Stage::Render)
+            .system_default(count_frames)
+            .clear_default_stage();
+        builder.game().add(Counter::default());
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(2, tick_duration);
+        let counter = app.game.get::<&Counter>();
+        assert_eq!(2, counter.ticks);
+        assert_eq!(2, counter.frames);
+    }
+
+    #[derive(Default)]
+    struct Handoff {
+        written: bool,
+        observed: bool,
+    }
+
+    fn write_handoff(game: &mut Game, _ctx: RunContext) {
+        game.get::<&mut Handoff>().written = true;
+    }
+
+    fn read_handoff(game: &mut Game, _ctx: RunContext) {
+        let mut handoff = game.get::<&mut Handoff>();
+        handoff.observed = handoff.written;
+    }
+
+    #[test]
+    fn system_with_priority() {
+        let mut builder = App::builder();
+        builder
+            .system_with_priority(Stage::Update, read_handoff, 1)
+            .system_with_priority(Stage::Update, write_handoff, -1);
+        builder.game().add(Handoff::default());
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(1, tick_duration);
+        assert!(app.game.get::<&Handoff>().observed);
+    }
+
+    fn startup_counter(game: &mut Game, mut ctx: RunContext) {
+        game.add(Counter::default());
+        ctx.run_command(|game: &mut Game| game.get::<&mut Counter>().frames += 100);
+    }
+
+    #[test]
+    fn startup() {
+        let mut builder = App::builder();
+        builder
+            .startup(startup_counter)
+            .system(Stage::Update, count_ticks)
+            .system(Stage::Render, count_frames);
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(2, tick_duration);
+        let counter = app.game.get::<&Counter>();
+        assert_eq!(2, counter.ticks);
+        assert_eq!(102, counter.frames);
+    }
+
+    #[test]
+    fn system_ordered() {
+        let mut builder = App::builder();
+        builder
+            .system_ordered(Stage::Update, read_handoff, &[after(write_handoff)])
+            .system_with_priority(Stage::Update, write_handoff, 1);
+        builder.game().add(Handoff::default());
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(1, tick_duration);
+        assert!(app.game.get::<&Handoff>().observed);
+    }
+
+    #[test]
+    #[should_panic]
+    fn system_ordered_cycle() {
+        let mut builder = App::builder();
+        builder
+            .system_ordered(Stage::Update, read_handoff, &[before(write_handoff)])
+            .system_ordered(Stage::Update, write_handoff, &[before(read_handoff)]);
+        builder.build();
+    }
+
+    #[derive(Default)]
+    struct FinalCount(Option<u32>);
+
+    fn quit_on_second_tick(game: &mut Game, mut ctx: RunContext) {
+        if game.get::<&Counter>().ticks == 2 {
+            ctx.quit();
+        }
+    }
+
+    fn record_final_count(game: &mut Game, _ctx: RunContext) {
+        let ticks = game.get::<&Counter>().ticks;
+        let mut final_count = game.get::<&mut FinalCount>();
+        assert!(final_count.0.is_none(), "Shutdown ran more than once");
+        final_count.0 = Some(ticks);
+    }
+
+    #[test]
+    fn shutdown() {
+        let mut builder = App::builder();
+        builder
+            .system(Stage::Update, count_ticks)
+            .system(Stage::PostUpdate, quit_on_second_tick)
+            .shutdown(record_final_count);
+        builder.game().add(Counter::default());
+        builder.game().add(FinalCount::default());
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(1, tick_duration);
+        assert_eq!(None, app.game.get::<&FinalCount>().0);
+        app.run_n_frames_simulated(1, tick_duration);
+        assert_eq!(Some(2), app.game.get::<&FinalCount>().0);
+        app.shutdown();
+    }
+
+    #[derive(Default)]
+    struct TickLog(Vec<u64>);
+
+    fn log_tick(game: &mut Game, ctx: RunContext) {
+        game.get::<&mut TickLog>().0.push(ctx.tick());
+    }
+
+    #[test]
+    fn tick() {
+        let mut builder = App::builder();
+        builder.system(Stage::Update, log_tick);
+        builder.game().add(TickLog::default());
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(3, tick_duration);
+        assert_eq!(vec![1, 2, 3], app.game.get::<&TickLog>().0);
+    }
+
+    #[derive(Default)]
+    struct ElapsedLog(Vec<(Duration, Duration)>);
+
+    fn log_elapsed(game: &mut Game, ctx: RunContext) {
+        game.get::<&mut ElapsedLog>().0.push((ctx.total_frame_elapsed(), ctx.total_tick_elapsed()));
+    }
+
+    #[test]
+    fn total_elapsed() {
+        let mut builder = App::builder();
+        builder.tick_duration(Duration::from_millis(10));
+        builder.system(Stage::Render, log_elapsed);
+        builder.game().add(ElapsedLog::default());
+        let mut app = builder.build();
+        app.run_n_frames_simulated(3, Duration::from_millis(15));
+        let expected = vec![
+            (Duration::from_millis(15), Duration::from_millis(10)),
+            (Duration::from_millis(30), Duration::from_millis(30)),
+            (Duration::from_millis(45), Duration::from_millis(40)),
+        ];
+        assert_eq!(expected, app.game.get::<&ElapsedLog>().0);
+    }
+
+    fn counter_plugin(builder: &mut AppBuilder) {
+        builder.system(Stage::Update, count_ticks);
+        builder.game().add(Counter::default());
+    }
+
+    #[test]
+    fn plugin_if_absent() {
+        let mut builder = App::builder();
+        builder
+            .plugin(counter_plugin)
+            .plugin_if_absent(counter_plugin);
+        let result = builder.try_plugin(counter_plugin).err();
+        assert!(matches!(result, Some(PluginError::AlreadyInstalled { .. })));
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(1, tick_duration);
+        assert_eq!(1, app.game.get::<&Counter>().ticks);
+    }
+
+    #[test]
+    #[should_panic(expected = "already installed")]
+    fn plugin_duplicate() {
+        App::builder()
+            .plugin(counter_plugin)
+            .plugin(counter_plugin);
+    }
+
+    fn ticks_below_two(game: &Game) -> bool {
+        game.get::<&Counter>().ticks < 2
+    }
+
+    #[test]
+    fn system_with_condition() {
+        let mut builder = App::builder();
+        builder.system_with_condition(Stage::Update, count_ticks, ticks_below_two);
+        builder.game().add(Counter::default());
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(5, tick_duration);
+        assert_eq!(2, app.game.get::<&Counter>().ticks);
+
+        // Re-enabling a disabled system does not bypass its condition.
+        app.disable_system(count_ticks);
+        app.run_n_frames_simulated(2, tick_duration);
+        app.enable_system(count_ticks);
+        app.run_n_frames_simulated(2, tick_duration);
+        assert_eq!(2, app.game.get::<&Counter>().ticks);
+    }
+
+    #[derive(Default)]
+    struct Dropped(Vec<u32>);
+
+    fn log_dropped(game: &mut Game, event: &TicksDroppedEvent, _ctx: &mut RunContext) {
+        game.get::<&mut Dropped>().0.push(event.dropped);
+    }
+
+    #[test]
+    fn max_ticks_per_frame() {
+        let mut builder = App::builder();
+        builder
+            .tick_duration(Duration::from_millis(10))
+            .max_ticks_per_frame(5)
+            .system(Stage::Update, count_ticks)
+            .event_handler(log_dropped);
+        builder.game()
+            .add(Counter::default())
+            .add(Dropped::default());
+        let mut app = builder.build();
+        app.run_frame(Duration::from_secs(2));
+        assert_eq!(5, app.game.get::<&Counter>().ticks);
+        assert_eq!(vec![195], app.game.get::<&Dropped>().0);
+
+        // Frames that keep up do not drop ticks.
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(3, tick_duration);
+        assert_eq!(8, app.game.get::<&Counter>().ticks);
+        assert_eq!(1, app.game.get::<&Dropped>().0.len());
+    }
+
+    #[derive(Clone)]
+    struct Click;
+
+    #[derive(Default)]
+    struct Clicks(Vec<&'static str>);
+
+    fn start_clicking(_game: &mut Game, _event: &StartEvent, ctx: &mut RunContext) {
+        ctx.fire(Click);
+    }
+
+    fn click_gameplay(game: &mut Game, _event: &Click, _ctx: &mut RunContext) {
+        game.get::<&mut Clicks>().0.push("gameplay");
+    }
+
+    fn click_hud(game: &mut Game, _event: &Click, _ctx: &mut RunContext) {
+        game.get::<&mut Clicks>().0.push("hud");
+    }
+
+    fn click_ui(game: &mut Game, _event: &Click, _ctx: &mut RunContext) -> EventFlow {
+        game.get::<&mut Clicks>().0.push("ui");
+        EventFlow::Consume
+    }
+
+    #[test]
+    fn event_handler_priorities() {
+        let mut builder = App::builder();
+        builder
+            .event_handler(start_clicking)
+            .event_handler(click_gameplay)
+            .event_handler_with_priority(click_hud, 10);
+        builder.game().add(Clicks::default());
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_frame(tick_duration);
+        assert_eq!(vec!["hud", "gameplay"], app.game.get::<&Clicks>().0);
+    }
+
+    #[test]
+    fn event_handler_consumes() {
+        let mut builder = App::builder();
+        builder
+            .event_handler(start_clicking)
+            .event_handler(click_gameplay)
+            .consuming_event_handler(click_ui, 5)
+            .event_handler_with_priority(click_hud, 10);
+        builder.game().add(Clicks::default());
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_frame(tick_duration);
+        assert_eq!(vec!["hud", "ui"], app.game.get::<&Clicks>().0);
+    }
+
+    #[test]
+    fn sent_events_are_handled() {
+        let mut builder = App::builder();
+        builder.event_handler(click_gameplay);
+        builder.game().add(Clicks::default());
+        let event_sender = builder.event_sender();
+        let mut app = builder.build();
+        std::thread::spawn(move || event_sender.send(DynEvent::new(Click)).unwrap()).join().unwrap();
+        let tick_duration = app.tick_duration();
+        app.run_frame(tick_duration);
+        assert_eq!(vec!["gameplay"], app.game.get::<&Clicks>().0);
+    }
+
+    #[derive(Clone)]
+    struct Shot(u32);
+
+    fn fire_shots(game: &mut Game, mut ctx: RunContext) {
+        let mut counter = game.get::<&mut Counter>();
+        ctx.fire(Shot(counter.ticks));
+        counter.ticks += 1;
+    }
+
+    #[derive(Default)]
+    struct ShotLog {
+        reader: EventReader<Shot>,
+        frames: u32,
+        shots: Vec<u32>,
+    }
+
+    fn read_shots_every_other_frame(game: &mut Game, _ctx: RunContext) {
+        let mut log = game.get::<&mut ShotLog>();
+        log.frames += 1;
+        if log.frames % 2 == 1 { return }
+        let events = game.get::<&Events<Shot>>();
+        let ShotLog { reader, shots, .. } = &mut *log;
+        shots.extend(events.read(reader).map(|shot| shot.0));
+    }
+
+    #[test]
+    fn event_reader_reads_every_other_frame() {
+        let mut builder = App::builder();
+        builder
+            .system(Stage::Update, fire_shots)
+            .system(Stage::PostUpdate, read_shots_every_other_frame);
+        builder.game()
+            .add(Counter::default())
+            .add(ShotLog::default());
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(6, tick_duration);
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], app.game.get::<&ShotLog>().shots);
+
+        // Events older than the previous frame are dropped.
+        assert_eq!(vec![4, 5], app.game.get::<&Events<Shot>>().iter().map(|shot| shot.0).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[should_panic]
+    fn system_default_unset() {
+        App::builder().system_default(count_ticks);
+    }
+}
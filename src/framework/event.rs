@@ -1,10 +1,19 @@
 use std::any::{Any, TypeId};
+use std::marker::PhantomData;
 use crate::{Game, HashMap, RunContext};
 
 /// Event that is fired the first frame the game starts.
 #[derive(Clone)]
 pub struct StartEvent;
 
+/// Event that is fired when a frame accumulated more ticks than it was allowed to run.
+/// See [`AppBuilder::max_ticks_per_frame`](crate::AppBuilder::max_ticks_per_frame).
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct TicksDroppedEvent {
+    /// Number of ticks that did not run.
+    pub dropped: u32,
+}
+
 /**
  * Represents someting that happened in the [`Game`] to be reacted to.
  */
@@ -12,8 +21,10 @@ pub trait Event: Any + Send + Sync + Clone {}
 impl<E: Any + Send + Sync + Clone> Event for E {}
 
 pub(crate) struct DynEvent {
-    pub event: Box<dyn Any>,
+    pub event: Box<dyn Any + Send + Sync>,
     pub type_id: TypeId,
+    pub send: fn(&mut Game, &dyn Any),     // Sends a copy of the event to the Events domain of its type
+    pub update: fn(&mut Game),              // Updates the Events domain of the event's type
 }
 
 impl DynEvent {
@@ -21,6 +32,102 @@ impl DynEvent {
         Self {
             event: Box::new(event),
             type_id: TypeId::of::<E>(),
+            send: send_event::<E>,
+            update: update_events::<E>,
+        }
+    }
+}
+
+fn send_event<E: Event>(game: &mut Game, event: &dyn Any) {
+    let event = event.downcast_ref::<E>().unwrap();
+    game.init(|_| Events::<E>::default());
+    game.get::<&mut Events<E>>().send(event.clone());
+}
+
+fn update_events<E: Event>(game: &mut Game) {
+    if let Some(mut events) = game.try_get::<&mut Events<E>>() {
+        events.update();
+    }
+}
+
+/**
+ * Domain that stores the events of a single type fired in the current and previous frames.
+ * Lets systems read events without an [`EventHandler`], each with its own [`EventReader`].
+ * Created the first time an event of its type is fired, after which the app updates it at the start of every frame.
+ * A reader that reads at least every other frame, after the events of that frame were fired, sees every event.
+ */
+pub struct Events<E> {
+    previous: Vec<(u64, E)>,    // Events sent last frame, with their ids
+    current: Vec<(u64, E)>,     // Events sent this frame, with their ids
+    next_id: u64,
+}
+
+impl<E> Default for Events<E> {
+    fn default() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<E> Events<E> {
+
+    /// Adds an event for readers to see.
+    /// Events fired with [`RunContext::fire`] are sent automatically once they are handled.
+    pub fn send(&mut self, event: E) {
+        self.current.push((self.next_id, event));
+        self.next_id += 1;
+    }
+
+    /// Events the reader has not seen yet, oldest first.
+    /// Events that were dropped before the reader saw them are skipped.
+    pub fn read<'a>(&'a self, reader: &mut EventReader<E>) -> impl Iterator<Item = &'a E> + 'a {
+        let cursor = reader.cursor;
+        reader.cursor = self.next_id;
+        self.previous
+            .iter()
+            .chain(&self.current)
+            .filter(move |(id, _)| *id >= cursor)
+            .map(|(_, event)| event)
+    }
+
+    /// All events stored, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &E> {
+        self.previous.iter().chain(&self.current).map(|(_, event)| event)
+    }
+
+    /// Number of events stored.
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops the events of the previous frame, and makes the events of this frame the previous ones.
+    pub fn update(&mut self) {
+        self.previous.clear();
+        std::mem::swap(&mut self.previous, &mut self.current);
+    }
+}
+
+/**
+ * Cursor into an [`Events`] domain, so that whatever owns it sees each event exactly once.
+ * Each system that reads events should have its own.
+ */
+pub struct EventReader<E> {
+    cursor: u64,                    // Id of the next event to read
+    phantom: PhantomData<fn() -> E>,
+}
+
+impl<E> Default for EventReader<E> {
+    fn default() -> Self {
+        Self {
+            cursor: 0,
+            phantom: PhantomData,
         }
     }
 }
@@ -28,38 +135,67 @@ impl DynEvent {
 
 /// Callback that handles an event.
 pub type EventHandler<E> = fn(&mut Game, &E, &mut RunContext);
- 
+
+/// Callback that handles an event, and decides whether handlers after it see the event.
+pub type ConsumingEventHandler<E> = fn(&mut Game, &E, &mut RunContext) -> EventFlow;
+
+/// Returned by a [`ConsumingEventHandler`] to decide whether the event keeps propagating.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub enum EventFlow {
+    /// Handlers of lower priority also handle the event.
+    #[default]
+    Continue,
+    /// Handlers of lower priority do not see the event.
+    /// IE: A UI click handler consuming a click, so that gameplay does not see it.
+    Consume,
+}
 
 pub(crate) trait DynEventHandler {
-    fn handle_dyn(&self, game: &mut Game, event: &DynEvent, ctx: &mut RunContext);
+    fn handle_dyn(&self, game: &mut Game, event: &DynEvent, ctx: &mut RunContext) -> EventFlow;
 }
 
 impl<E: Event> DynEventHandler for EventHandler<E> {
-    fn handle_dyn(&self, game: &mut Game, event: &DynEvent, ctx: &mut RunContext) {
+    fn handle_dyn(&self, game: &mut Game, event: &DynEvent, ctx: &mut RunContext) -> EventFlow {
         let event = event.event.downcast_ref::<E>().unwrap();
         self(game, event, ctx);
+        EventFlow::Continue
+    }
+}
+
+impl<E: Event> DynEventHandler for ConsumingEventHandler<E> {
+    fn handle_dyn(&self, game: &mut Game, event: &DynEvent, ctx: &mut RunContext) -> EventFlow {
+        let event = event.event.downcast_ref::<E>().unwrap();
+        self(game, event, ctx)
     }
 }
 
 /// Collection of event handlers for a particular stage.
+/// Handlers of each event are sorted by descending priority.
 #[derive(Default)]
 pub(crate) struct EventBus {
-    handlers: HashMap<TypeId, Vec<Box<dyn DynEventHandler>>>
+    handlers: HashMap<TypeId, Vec<PrioritizedHandler>>
 }
 
+type PrioritizedHandler = (i32, Box<dyn DynEventHandler>);
+
 impl EventBus {
     
     /// Adds an event handler.
-    pub fn add_handler<E: Event>(&mut self, handler: EventHandler<E>) {
+    /// Runs after handlers of a higher or equal priority, and before those of a lower priority.
+    pub fn add_handler<E: Event>(&mut self, handler: impl DynEventHandler + 'static, priority: i32) {
         let event_type = TypeId::of::<E>();
         let handlers_for_event = self.handlers.entry(event_type).or_default();
-        handlers_for_event.push(Box::new(handler));
+        let index = handlers_for_event.partition_point(|(other_priority, _)| *other_priority >= priority);
+        handlers_for_event.insert(index, (priority, Box::new(handler)));
     }
 
+    /// Runs the handlers of an event until one consumes it.
     pub fn handle_event(&self, game: &mut Game, event: DynEvent, ctx: &mut RunContext) {
         let Some(handlers_for_event) = self.handlers.get(&event.type_id) else { return };
-        for handler in handlers_for_event {
-            handler.handle_dyn(game, &event, ctx);
+        for (_, handler) in handlers_for_event {
+            if handler.handle_dyn(game, &event, ctx) == EventFlow::Consume {
+                break;
+            }
         }
     }
 }
\ No newline at end of file
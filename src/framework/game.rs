@@ -1,24 +1,28 @@
-use std::any::{TypeId, Any};
+use std::any::{type_name, TypeId, Any};
 use std::cell::{RefCell, Ref, RefMut};
 use std::collections::HashMap;
+use derive_more::{Display, Error};
 
 /// Game structure, which acts as a simple container of [`Domain`]s.
 /// Contains no logic on its own.
 pub struct Game {
     domains: HashMap<TypeId, Box<dyn Any>>,
+    domain_names: HashMap<TypeId, &'static str>,   // Type names of domains, for error messages
 }
 
 impl Game {
 
     pub fn new() -> Self {
         Self {
-            domains: HashMap::new()
+            domains: HashMap::new(),
+            domain_names: HashMap::new(),
         }
     }
 
     /// Adds a domain to the game.
     pub fn add<D: Domain>(&mut self, domain: D) -> &mut Self {
         self.domains.insert(TypeId::of::<D>(), Box::new(RefCell::new(domain)));
+        self.domain_names.insert(TypeId::of::<D>(), type_name::<D>());
         self
     }
 
@@ -28,31 +32,53 @@ impl Game {
         if !self.domains.contains_key(&type_id) {
             let domain = producer(self);
             self.domains.insert(type_id, Box::new(RefCell::new(domain)));
+            self.domain_names.insert(type_id, type_name::<D>());
         }
         self
     }
 
+    /// Fetches a domain by type.
+    /// Panics with the name of the domain, and the names of those registered, if not found.
     pub fn get<'a, E0: DomainExtractor<'a>>(&'a self) -> E0::Data {
-        E0::extract(self).unwrap()
+        E0::extract(self).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Fetches several domains at once, IE: `game.all::<(&mut World, &GraphicsState)>()`.
+    /// Panics with the name of the first domain not found, and the names of those registered.
+    pub fn all<'a, E: DomainExtractor<'a>>(&'a self) -> E::Data {
+        E::extract(self).unwrap_or_else(|err| panic!("{err}"))
     }
 
     pub fn remove<D: Domain>(&mut self) -> D {
-        self.try_remove().unwrap()
+        match self.try_remove() {
+            Some(domain) => domain,
+            None => panic!("{}", self.missing::<D>()),
+        }
     }
 
     pub fn take<D: Domain + Default>(&mut self) -> D {
-        self.try_take().unwrap()
+        match self.try_take() {
+            Some(domain) => domain,
+            None => panic!("{}", self.missing::<D>()),
+        }
     }
 
-    pub fn contains<D: Domain>(&mut self) -> bool {
+    pub fn contains<D: Domain>(&self) -> bool {
         self.domains.contains_key(&TypeId::of::<D>())
     }
 
     pub fn try_get<'a, E0: DomainExtractor<'a>>(&'a self) -> Option<E0::Data> {
-        E0::extract(self)
+        E0::extract(self).ok()
+    }
+
+    /// Fetches several domains at once.
+    /// Fails with the name of the first domain not found.
+    pub fn try_all<'a, E: DomainExtractor<'a>>(&'a self) -> Result<E::Data, MissingDomain> {
+        E::extract(self)
     }
 
     pub fn try_remove<D: Domain>(&mut self) -> Option<D> {
+        self.domain_names.remove(&TypeId::of::<D>());
         let domain = self.domains
             .remove(&TypeId::of::<D>())?
             .downcast::<RefCell<D>>()
@@ -76,7 +102,7 @@ impl Game {
 
     /// Fetches a domain by type.
     pub fn get_cell<D: Domain>(&self) -> &RefCell<D> {
-        self.try_get_cell().unwrap()
+        self.try_get_cell().unwrap_or_else(|| panic!("{}", self.missing::<D>()))
     }
 
     /// Fetches a domain by type.
@@ -85,6 +111,20 @@ impl Game {
         let any = self.domains.get(&domain_id)?;
         any.downcast_ref::<RefCell<D>>()
     }
+
+    /// Type names of all domains, sorted.
+    pub fn domain_names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.domain_names.values().copied().collect();
+        names.sort_unstable();
+        names
+    }
+
+    fn missing<D: Domain>(&self) -> MissingDomain {
+        MissingDomain {
+            domain: type_name::<D>(),
+            registered: self.domain_names(),
+        }
+    }
 }
 
 /**
@@ -95,25 +135,97 @@ pub trait Domain: Any {}
 impl<D: Any> Domain for D {}
 
 
+/// Error returned when a [`Domain`] was not found in a [`Game`].
+/// IE: When a plugin was installed before the plugin that adds the domain it depends on.
+#[derive(Error, Clone, Eq, PartialEq, Display, Debug)]
+#[display(fmt="Domain {domain} not found. Registered domains: [{}]", "registered.join(\", \")")]
+pub struct MissingDomain {
+    /// Type name of the domain not found.
+    pub domain: &'static str,
+    /// Type names of the domains that were registered at the time.
+    pub registered: Vec<&'static str>,
+}
+
 pub trait DomainExtractor<'a> {
     type Data;
-    fn extract(game: &'a Game) -> Option<Self::Data>;
+    fn extract(game: &'a Game) -> Result<Self::Data, MissingDomain>;
 }
 
 impl<'a, D0> DomainExtractor<'a> for &'a D0
 where D0: Domain {
     type Data = Ref<'a, D0>;
-    fn extract(game: &'a Game) -> Option<Self::Data> {
-        let d0 = game.try_get_cell::<D0>()?;
-        Some(d0.borrow())
+    fn extract(game: &'a Game) -> Result<Self::Data, MissingDomain> {
+        let d0 = game.try_get_cell::<D0>().ok_or_else(|| game.missing::<D0>())?;
+        Ok(d0.borrow())
     }
 }
 
 impl<'a, D0> DomainExtractor<'a> for &'a mut D0
 where D0: Domain {
     type Data = RefMut<'a, D0>;
-    fn extract(game: &'a Game) -> Option<Self::Data> {
-        let d0 = game.try_get_cell::<D0>()?;
-        Some(d0.borrow_mut())
+    fn extract(game: &'a Game) -> Result<Self::Data, MissingDomain> {
+        let d0 = game.try_get_cell::<D0>().ok_or_else(|| game.missing::<D0>())?;
+        Ok(d0.borrow_mut())
+    }
+}
+
+macro_rules! impl_domain_extractor {
+    ($($e:ident),*) => {
+        impl<'a, $($e: DomainExtractor<'a>),*> DomainExtractor<'a> for ($($e,)*) {
+            type Data = ($($e::Data,)*);
+            fn extract(game: &'a Game) -> Result<Self::Data, MissingDomain> {
+                Ok(($($e::extract(game)?,)*))
+            }
+        }
+    };
+}
+
+impl_domain_extractor!(E0);
+impl_domain_extractor!(E0, E1);
+impl_domain_extractor!(E0, E1, E2);
+impl_domain_extractor!(E0, E1, E2, E3);
+impl_domain_extractor!(E0, E1, E2, E3, E4);
+impl_domain_extractor!(E0, E1, E2, E3, E4, E5);
+impl_domain_extractor!(E0, E1, E2, E3, E4, E5, E6);
+impl_domain_extractor!(E0, E1, E2, E3, E4, E5, E6, E7);
+
+#[cfg(test)]
+mod test {
+    use crate::Game;
+
+    #[derive(Default)]
+    struct Counter(u32);
+    #[derive(Default)]
+    struct Name(&'static str);
+
+    #[test]
+    fn extracts_several_domains() {
+        let mut game = Game::new();
+        game.add(Counter(1)).add(Name("game"));
+        assert!(game.contains::<Counter>());
+        let (mut counter, name) = game.all::<(&mut Counter, &Name)>();
+        counter.0 += 1;
+        assert_eq!(2, counter.0);
+        assert_eq!("game", name.0);
+    }
+
+    #[test]
+    fn names_missing_domain() {
+        let mut game = Game::new();
+        game.add(Counter(1));
+        let err = game.try_all::<(&Counter, &Name)>().err().unwrap();
+        assert_eq!(std::any::type_name::<Name>(), err.domain);
+        assert_eq!(vec![std::any::type_name::<Counter>()], err.registered);
+        assert!(game.try_get::<&Name>().is_none());
+        game.remove::<Counter>();
+        assert!(!game.contains::<Counter>());
+        assert!(game.domain_names().is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[should_panic(expected = "Domain hecs_game::framework::game::test::Name not found")]
+    fn panics_with_missing_domain() {
+        let game = Game::new();
+        game.get::<&Name>();
+    }
+}
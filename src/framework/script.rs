@@ -9,13 +9,13 @@ use crate::{Game, RunContext, HashMap};
 pub struct Script {
     current: Option<Box<dyn Instruction>>,
     instructions: VecDeque<Box<dyn Instruction>>,
-    variables: HashMap<VarKey, Box<dyn Any>>,
+    variables: HashMap<VarKey, Box<dyn Any + Send + Sync>>,
     stopped: bool,
 }
 
 impl Script {
 
-    pub(crate) fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             current: None,
             instructions: VecDeque::new(),
@@ -32,29 +32,47 @@ impl Script {
         self
     }
 
+    /**
+     * Adds an instruction to the end of the script.
+     */
+    pub fn with(mut self, instruction: impl Instruction) -> Self {
+        self.add(instruction);
+        self
+    }
+
     /**
      * Advances by a single instruction. Re-runs instruction next tick if not finished.
-     * Returns true if all instructions are consumed.
+     * Returns true if all instructions are consumed, or if the script was stopped.
      */
     pub(crate) fn run(&mut self, game: &mut Game, mut run_context: RunContext) -> bool {
-        if self.stopped { return false }
+        if self.stopped {
+            if let Some(mut current_ins) = self.current.take() {
+                current_ins.on_cancel(game, &mut ScriptContext::new(&mut run_context.reborrow(), self));
+            }
+            return true;
+        }
         let mut current_ins = match self.current.take() {
             Some(current_ins) => current_ins,
             None => {
                 let Some(mut current_ins) = self.instructions.pop_front() else { return true };
-                current_ins.start(game, &mut ScriptContext::new(&mut run_context, self));
+                current_ins.start(game, &mut ScriptContext::new(&mut run_context.reborrow(), self));
                 current_ins
             },
         };
 
         loop {
-            let finished = current_ins.run(game, &mut ScriptContext::new(&mut run_context, self));
+            if self.stopped {
+                current_ins.on_cancel(game, &mut ScriptContext::new(&mut run_context.reborrow(), self));
+                return true;
+            }
+            let finished = current_ins.run(game, &mut ScriptContext::new(&mut run_context.reborrow(), self));
             if finished {
+                if self.stopped { return true }
                 current_ins = match self.instructions.pop_front() {
                     Some(current) => current,
                     None => return true,
                 };
-                current_ins.start(game, &mut ScriptContext::new(&mut run_context, self));
+                current_ins.start(game, &mut ScriptContext::new(&mut run_context.reborrow(), self));
             }
             else {
                 self.current = Some(current_ins);
@@ -64,6 +82,12 @@ impl Script {
     }
 }
 
+impl Default for Script {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<I: Instruction> From<I> for Script {
     fn from(instruction: I) -> Self {
         let mut script = Self::new();
@@ -72,6 +96,14 @@ impl<I: Instruction> From<I> for Script {
     }
 }
 
+impl From<Vec<Box<dyn Instruction>>> for Script {
+    fn from(instructions: Vec<Box<dyn Instruction>>) -> Self {
+        let mut script = Self::new();
+        script.instructions.extend(instructions);
+        script
+    }
+}
+
 /**
  * Value of a variable stored in a [`Script`].
  */
@@ -103,20 +135,26 @@ pub trait Instruction: Send + Sync + 'static {
      * Returns true if instruction is finished.
      */
     fn run(&mut self, _game: &mut Game, _ctx: &mut ScriptContext) -> bool { true }
+
+    /**
+     * Executed if the [`Script`] is stopped after start() was invoked, but before the instruction finished.
+     * Useful for releasing resources acquired in start().
+     */
+    fn on_cancel(&mut self, _game: &mut Game, _ctx: &mut ScriptContext) {}
 }
 
 /**
  * Parameters passed into the various methods belonging to [`Task`].
  */
 pub struct ScriptContext<'a> {
-    pub run_context: &'a RunContext<'a>,
+    pub run_context: &'a mut RunContext<'a>,
     script: &'a mut Script,
     insert_index: usize,
 }
 
 impl<'a> ScriptContext<'a> {
 
-    fn new(run_context: &'a RunContext<'a>, script: &'a mut Script) -> Self {
+    fn new(run_context: &'a mut RunContext<'a>, script: &'a mut Script) -> Self {
         Self {
             run_context,
             script,
@@ -130,6 +168,14 @@ impl<'a> ScriptContext<'a> {
         self
     }
 
+    /**
+     * Stops the script.
+     * The current instruction is cancelled, and the remaining instructions are discarded.
+     */
+    pub fn stop_script(&mut self) {
+        self.script.stopped = true;
+    }
+
     /**
      * Sets a variable.
      */
@@ -201,8 +247,320 @@ impl<'a> ScriptContext<'a> {
     }
 }
 
+impl Script {
+
+    /**
+     * Runs as part of the script of ctx, sharing its variables.
+     * Returns true if all instructions are consumed, or if this script was stopped.
+     */
+    fn run_nested(&mut self, game: &mut Game, ctx: &mut ScriptContext) -> bool {
+        std::mem::swap(&mut self.variables, &mut ctx.script.variables);
+        let finished = self.run(game, ctx.run_context.reborrow());
+        std::mem::swap(&mut self.variables, &mut ctx.script.variables);
+        finished
+    }
+
+    /**
+     * Like run_nested(), but stops the script of ctx if this script was stopped.
+     * Used for bodies, which are part of the script they are in.
+     */
+    fn run_body(&mut self, game: &mut Game, ctx: &mut ScriptContext) -> bool {
+        let finished = self.run_nested(game, ctx);
+        if self.stopped {
+            ctx.stop_script();
+        }
+        finished
+    }
+
+    /**
+     * Stops this script, cancelling its current instruction.
+     */
+    fn cancel_nested(&mut self, game: &mut Game, ctx: &mut ScriptContext) {
+        self.stopped = true;
+        self.run_nested(game, ctx);
+    }
+}
+
+/**
+ * Runs a body a number of times.
+ * A new body is created for each repetition, so that its instructions start fresh.
+ * Stopping the script from within the body stops the script the [`Repeat`] is in.
+ */
+pub struct Repeat<B> {
+    remaining: u32,
+    make_body: B,
+    body: Option<Script>,
+}
+
+impl<B, S> Repeat<B>
+where
+    B: FnMut() -> S + Send + Sync + 'static,
+    S: Into<Script>,
+{
+    pub fn new(times: u32, make_body: B) -> Self {
+        Self { remaining: times, make_body, body: None }
+    }
+}
+
+impl<B, S> Instruction for Repeat<B>
+where
+    B: FnMut() -> S + Send + Sync + 'static,
+    S: Into<Script>,
+{
+    fn run(&mut self, game: &mut Game, ctx: &mut ScriptContext) -> bool {
+        loop {
+            let body = match &mut self.body {
+                Some(body) => body,
+                None => {
+                    if self.remaining == 0 { return true }
+                    self.remaining -= 1;
+                    self.body.insert((self.make_body)().into())
+                },
+            };
+            if !body.run_body(game, ctx) { return false }
+            self.body = None;
+            if ctx.script.stopped { return true }
+        }
+    }
+
+    fn on_cancel(&mut self, game: &mut Game, ctx: &mut ScriptContext) {
+        if let Some(mut body) = self.body.take() {
+            body.cancel_nested(game, ctx);
+        }
+    }
+}
+
+/**
+ * Runs a body for as long as a condition holds.
+ * The condition is checked before each repetition, and a new body is created for each one.
+ * Bodies that finish instantly repeat within the same tick, so the condition must eventually fail.
+ * Stopping the script from within the body stops the script the [`While`] is in.
+ */
+pub struct While<C, B> {
+    condition: C,
+    make_body: B,
+    body: Option<Script>,
+}
+
+impl<C, B, S> While<C, B>
+where
+    C: FnMut(&Game, &ScriptContext) -> bool + Send + Sync + 'static,
+    B: FnMut() -> S + Send + Sync + 'static,
+    S: Into<Script>,
+{
+    pub fn new(condition: C, make_body: B) -> Self {
+        Self { condition, make_body, body: None }
+    }
+}
+
+impl<C, B, S> Instruction for While<C, B>
+where
+    C: FnMut(&Game, &ScriptContext) -> bool + Send + Sync + 'static,
+    B: FnMut() -> S + Send + Sync + 'static,
+    S: Into<Script>,
+{
+    fn run(&mut self, game: &mut Game, ctx: &mut ScriptContext) -> bool {
+        loop {
+            let body = match &mut self.body {
+                Some(body) => body,
+                None => {
+                    if !(self.condition)(game, ctx) { return true }
+                    self.body.insert((self.make_body)().into())
+                },
+            };
+            if !body.run_body(game, ctx) { return false }
+            self.body = None;
+            if ctx.script.stopped { return true }
+        }
+    }
+
+    fn on_cancel(&mut self, game: &mut Game, ctx: &mut ScriptContext) {
+        if let Some(mut body) = self.body.take() {
+            body.cancel_nested(game, ctx);
+        }
+    }
+}
+
+/**
+ * Runs one of two bodies, depending on a condition checked when the instruction starts.
+ * Stopping the script from within a body stops the script the [`If`] is in.
+ */
+pub struct If<C> {
+    condition: C,
+    then_body: Option<Script>,
+    else_body: Option<Script>,
+}
+
+impl<C> If<C>
+where
+    C: FnMut(&Game, &ScriptContext) -> bool + Send + Sync + 'static,
+{
+    pub fn new(condition: C, then_body: impl Into<Script>, else_body: impl Into<Script>) -> Self {
+        Self {
+            condition,
+            then_body: Some(then_body.into()),
+            else_body: Some(else_body.into()),
+        }
+    }
+
+    /// Runs then_body if the condition holds, and nothing otherwise.
+    pub fn then(condition: C, then_body: impl Into<Script>) -> Self {
+        Self::new(condition, then_body, Script::new())
+    }
+}
+
+impl<C> Instruction for If<C>
+where
+    C: FnMut(&Game, &ScriptContext) -> bool + Send + Sync + 'static,
+{
+    fn start(&mut self, game: &mut Game, ctx: &mut ScriptContext) {
+        if (self.condition)(game, ctx) {
+            self.else_body = None;
+        }
+        else {
+            self.then_body = None;
+        }
+    }
+
+    fn run(&mut self, game: &mut Game, ctx: &mut ScriptContext) -> bool {
+        match self.then_body.as_mut().or(self.else_body.as_mut()) {
+            Some(body) => body.run_body(game, ctx),
+            None => true,
+        }
+    }
+
+    fn on_cancel(&mut self, game: &mut Game, ctx: &mut ScriptContext) {
+        if let Some(body) = self.then_body.as_mut().or(self.else_body.as_mut()) {
+            body.cancel_nested(game, ctx);
+        }
+    }
+}
+
+/**
+ * Runs scripts side by side, each advancing once per tick, and finishes when all of them have.
+ * Each script shares the variables of the script the [`Parallel`] is in,
+ * but stopping one only stops that one.
+ */
+pub struct Parallel(pub Vec<Script>);
+
+impl Instruction for Parallel {
+    fn run(&mut self, game: &mut Game, ctx: &mut ScriptContext) -> bool {
+        self.0.retain_mut(|script| !script.run_nested(game, ctx));
+        self.0.is_empty()
+    }
+
+    fn on_cancel(&mut self, game: &mut Game, ctx: &mut ScriptContext) {
+        for mut script in self.0.drain(..) {
+            script.cancel_nested(game, ctx);
+        }
+    }
+}
+
 #[derive(Error, Display, Debug)]
 pub enum ScriptError {
     VariableNotFound,
     IncorrectVariableType,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{App, Game, If, Inline, Instruction, Parallel, Repeat, RunContext, Script, ScriptContext, Stage, StartEvent, WaitFrames, WaitUntil, While};
+
+    #[derive(Default)]
+    struct Log {
+        runs: u32,
+        cancelled: bool,
+        finished: bool,
+    }
+
+    /// Runs until stopping its own script on its third run.
+    struct StopOnThirdRun;
+    impl Instruction for StopOnThirdRun {
+        fn run(&mut self, game: &mut Game, ctx: &mut ScriptContext) -> bool {
+            let mut log = game.get::<&mut Log>();
+            log.runs += 1;
+            if log.runs == 3 {
+                ctx.stop_script();
+            }
+            false
+        }
+        fn on_cancel(&mut self, game: &mut Game, _ctx: &mut ScriptContext) {
+            game.get::<&mut Log>().cancelled = true;
+        }
+    }
+
+    fn start_script(_game: &mut Game, _event: &StartEvent, ctx: &mut RunContext) {
+        let mut script = Script::new();
+        script
+            .add(StopOnThirdRun)
+            .add(Inline(|game: &mut Game, _ctx: &mut ScriptContext| {
+                game.get::<&mut Log>().finished = true;
+            }));
+        ctx.start_script(Stage::Update, script);
+    }
+
+    #[test]
+    fn on_cancel() {
+        let mut builder = App::builder();
+        builder.event_handler(start_script);
+        builder.game().add(Log::default());
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(10, tick_duration);
+        let log = app.game.get::<&Log>();
+        assert_eq!(3, log.runs);
+        assert!(log.cancelled);
+        assert!(!log.finished);
+    }
+
+    #[derive(Default)]
+    struct Steps(Vec<String>);
+
+    fn step(name: &'static str) -> Inline<impl FnMut(&mut Game, &mut ScriptContext) + Send + Sync + 'static> {
+        Inline(move |game: &mut Game, ctx: &mut ScriptContext| {
+            let count = *ctx.var::<u32>("count");
+            game.get::<&mut Steps>().0.push(format!("{name} {count}"));
+        })
+    }
+
+    fn increment(_game: &mut Game, ctx: &mut ScriptContext) {
+        *ctx.var_mut::<u32>("count") += 1;
+    }
+
+    fn start_control_flow_script(_game: &mut Game, _event: &StartEvent, ctx: &mut RunContext) {
+        let counting = Repeat::new(3, || Script::new()
+            .with(Inline(increment))
+            .with(step("repeat"))
+            .with(WaitFrames { remaining: 1 })
+        );
+        let watching = Script::new()
+            .with(WaitUntil(|_game: &Game, ctx: &ScriptContext| *ctx.var::<u32>("count") >= 2))
+            .with(step("until"));
+        let script = Script::new()
+            .with(Inline(|_game: &mut Game, ctx: &mut ScriptContext| ctx.set_var("count", 0_u32)))
+            .with(If::new(
+                |_game: &Game, ctx: &ScriptContext| *ctx.var::<u32>("count") == 0,
+                Parallel(vec![Script::from(counting), watching]),
+                step("else"),
+            ))
+            .with(step("joined"))
+            .with(While::new(
+                |_game: &Game, ctx: &ScriptContext| *ctx.var::<u32>("count") < 5,
+                || Inline(increment),
+            ))
+            .with(If::then(|_game: &Game, ctx: &ScriptContext| *ctx.var::<u32>("count") == 5, step("while")));
+        ctx.start_script(Stage::Update, script);
+    }
+
+    #[test]
+    fn control_flow() {
+        let mut builder = App::builder();
+        builder.event_handler(start_control_flow_script);
+        builder.game().add(Steps::default());
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(10, tick_duration);
+        let expected = ["repeat 1", "repeat 2", "until 2", "repeat 3", "joined 3", "while 5"];
+        assert_eq!(expected.as_slice(), app.game.get::<&Steps>().0);
+    }
+}
@@ -9,27 +9,49 @@ pub trait HasId: Send + Sync + 'static {
 /// A "tracking" handle to an object in some domain (Trackee).
 /// When the tracker drops, the object it references will be scheduled for removal.
 /// Commonly stored in an ECS Entity to keep some external object alive until the entity is despawned.
+/// Each object should have at most one tracker, as every tracker schedules its removal.
 pub struct Tracker<T: HasId> {
     id: T::Id,
-    sender: TrackerSender<T>,
+    sender: Option<TrackerSender<T>>,   // None once detached
 }
 
 impl<T: HasId> Tracker<T> {
     pub fn new(id: T::Id, sender: TrackerSender<T>) -> Self {
         Self {
             id,
-            sender,
+            sender: Some(sender),
         }
     }
 
     pub fn id(&self) -> T::Id {
         self.id
     }
+
+    /// Consumes the tracker without scheduling the removal of its object.
+    /// The object stays alive and untracked until it is removed some other way, or adopted by a new tracker.
+    pub fn detach(mut self) -> T::Id {
+        self.sender = None;
+        self.id
+    }
+
+    /// Schedules the removal of the current object, and tracks new_id instead.
+    /// new_id should not already be tracked, unless by this tracker, in which case nothing happens.
+    pub fn retarget(&mut self, new_id: T::Id) {
+        if new_id == self.id {
+            return;
+        }
+        if let Some(sender) = &self.sender {
+            let _ = sender.0.send(self.id);
+        }
+        self.id = new_id;
+    }
 }
 
 impl<T: HasId> Drop for Tracker<T> {
     fn drop(&mut self) {
-        let _ = self.sender.0.send(self.id);
+        if let Some(sender) = &self.sender {
+            let _ = sender.0.send(self.id);
+        }
     }
 }
 
@@ -1,4 +1,4 @@
-use glam::{Vec3, Mat4, Vec4, Vec4Swizzles};
+use glam::{Mat3, Mat4, Quat, Vec3, Vec4, Vec4Swizzles};
 use derive_more::*;
 
 /// A 3D shape that can be one of many.
@@ -24,18 +24,57 @@ impl Sphere {
         radius: 1.0
     };
 
+    /// Sphere that bounds nothing, and that frustums never contain.
+    pub const EMPTY: Self = Sphere {
+        center: Vec3::ZERO,
+        radius: -1.0,
+    };
+
     pub fn new(center: Vec3, radius: f32) -> Self {
         Self { center, radius }
     }
 
+    /**
+     * Sphere that bounds every point, using Ritter's algorithm.
+     * Not the smallest such sphere, but usually within a few percent of it.
+     * [`Sphere::EMPTY`] if there are no points.
+     */
+    pub fn from_points(points: &[Vec3]) -> Self {
+        let Some(&first) = points.first() else { return Self::EMPTY };
+        let farthest_from = |from: Vec3| points.iter().copied().fold(from, |farthest, point| {
+            if point.distance_squared(from) > farthest.distance_squared(from) { point } else { farthest }
+        });
+
+        // Starts with the sphere between two points that are far apart, then grows it to fit the points outside of it
+        let a = farthest_from(first);
+        let b = farthest_from(a);
+        let mut center = (a + b) / 2.0;
+        let mut radius = a.distance(b) / 2.0;
+        for &point in points {
+            let distance = point.distance(center);
+            if distance > radius {
+                let new_radius = (radius + distance) / 2.0;
+                center += (point - center) * ((new_radius - radius) / distance);
+                radius = new_radius;
+            }
+        }
+        Self { center, radius }
+    }
+
+    /// True if the sphere has a negative radius, as [`Sphere::EMPTY`] does.
+    pub fn is_empty(&self) -> bool {
+        self.radius < 0.0
+    }
+
     pub fn transform(self, mat: Mat4) -> Self {
+        if self.is_empty() { return self }
         let right = mat.col(0).xyz();
         let up = mat.col(1).xyz();
         let back = mat.col(2).xyz();
         let max_scale = max_len(right, up, back);
         Self {
             center: mat.transform_point3(self.center),
-            radius: self.radius * max_scale * 0.5
+            radius: self.radius * max_scale
         }
     }
 }
@@ -44,22 +83,7 @@ pub fn max_len(a: Vec3, b: Vec3, c: Vec3) -> f32 {
     let a_len_sq = a.length_squared();
     let b_len_sq = b.length_squared();
     let c_len_sq = c.length_squared();
-    if a_len_sq > b_len_sq {
-        if a_len_sq > c_len_sq {
-            a_len_sq.sqrt()
-        }
-        else {
-            b_len_sq.sqrt()
-        }
-    }
-    else {
-        if b_len_sq > c_len_sq {
-            b_len_sq.sqrt()
-        }
-        else {
-            c_len_sq.sqrt()
-        }
-    }
+    a_len_sq.max(b_len_sq).max(c_len_sq).sqrt()
 }
 
 
@@ -90,7 +114,31 @@ impl AABB {
         extents: Vec3::splat(0.5),
     };
 
+    /// Box that bounds nothing, and that frustums never contain.
+    pub const EMPTY: Self = AABB {
+        center: Vec3::ZERO,
+        extents: Vec3::NEG_ONE,
+    };
+
+    /// Smallest box that bounds every point, or [`AABB::EMPTY`] if there are none.
+    pub fn from_points(points: &[Vec3]) -> Self {
+        if points.is_empty() { return Self::EMPTY }
+        let (min, max) = points.iter().fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), point| {
+            (min.min(*point), max.max(*point))
+        });
+        Self {
+            center: (min + max) / 2.0,
+            extents: (max - min) / 2.0,
+        }
+    }
+
+    /// True if the box has a negative extent, as [`AABB::EMPTY`] does.
+    pub fn is_empty(&self) -> bool {
+        self.extents.cmplt(Vec3::ZERO).any()
+    }
+
     pub fn transform(self, mat: Mat4) -> Self {
+        if self.is_empty() { return self }
         let right = mat.col(0).xyz() * self.extents.x;
         let up = mat.col(1).xyz() * self.extents.y;
         let forward = -mat.col(2).xyz() * self.extents.z;
@@ -113,10 +161,105 @@ impl AABB {
     }
 }
 
+/**
+ * Oriented bounding box.
+ * Fits rotated objects far more tightly than an [`AABB`], at the cost of a more expensive frustum test.
+ */
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct OBB {
+    pub center: Vec3,
+    pub half_extents: Vec3,
+    pub rotation: Quat,
+}
+
+impl OBB {
+
+    pub const UNIT: Self = OBB {
+        center: Vec3::ZERO,
+        half_extents: Vec3::splat(0.5),
+        rotation: Quat::IDENTITY,
+    };
+
+    pub fn new(center: Vec3, half_extents: Vec3, rotation: Quat) -> Self {
+        Self { center, half_extents, rotation }
+    }
+
+    /// Local x, y and z axes of the box, normalized.
+    pub fn axes(&self) -> [Vec3; 3] {
+        [
+            self.rotation * Vec3::X,
+            self.rotation * Vec3::Y,
+            self.rotation * Vec3::Z,
+        ]
+    }
+
+    /// Half of the length of the box's shadow on the axis.
+    pub fn projection_interval(&self, axis: Vec3) -> f32 {
+        let [x, y, z] = self.axes();
+        self.half_extents.x * x.dot(axis).abs() +
+        self.half_extents.y * y.dot(axis).abs() +
+        self.half_extents.z * z.dot(axis).abs()
+    }
+
+    /**
+     * Box transformed by mat.
+     * The rotation of the new box is the rotational part of the polar decomposition of mat's scaled axes.
+     * Its half extents are the projections of those axes onto the new rotation, so a shearing mat yields a box that still bounds the original.
+     */
+    pub fn transform(self, mat: Mat4) -> Self {
+        let half_axes = Mat3::from_mat4(mat) * Mat3::from_quat(self.rotation) * Mat3::from_diagonal(self.half_extents);
+        let rotation = polar_rotation(half_axes);
+        let [x, y, z] = [rotation.x_axis, rotation.y_axis, rotation.z_axis];
+        let extent = |axis: Vec3| {
+            axis.dot(half_axes.x_axis).abs() +
+            axis.dot(half_axes.y_axis).abs() +
+            axis.dot(half_axes.z_axis).abs()
+        };
+        Self {
+            center: mat.transform_point3(self.center),
+            half_extents: Vec3::new(extent(x), extent(y), extent(z)),
+            rotation: Quat::from_mat3(&rotation).normalize(),
+        }
+    }
+}
+
+impl Default for OBB {
+    fn default() -> Self { Self::UNIT }
+}
+
+impl From<AABB> for OBB {
+    fn from(aabb: AABB) -> Self {
+        Self::new(aabb.center, aabb.extents, Quat::IDENTITY)
+    }
+}
+
+/**
+ * Rotation closest to mat, found with Newton's iteration for the polar decomposition.
+ * Reflections are flipped back into rotations, since boxes are symmetric.
+ * Identity if mat is degenerate.
+ */
+fn polar_rotation(mat: Mat3) -> Mat3 {
+    const MAX_ITERATIONS: usize = 16;
+    const TOLERANCE: f32 = 1e-6;
+    let mut rotation = match mat.determinant() {
+        det if det.abs() <= f32::EPSILON => return Mat3::IDENTITY,
+        det if det < 0.0 => Mat3::from_cols(-mat.x_axis, mat.y_axis, mat.z_axis),
+        _ => mat,
+    };
+    for _ in 0..MAX_ITERATIONS {
+        let next = (rotation + rotation.inverse().transpose()) * 0.5;
+        let converged = (next - rotation).to_cols_array().iter().all(|delta| delta.abs() <= TOLERANCE);
+        rotation = next;
+        if converged { break }
+    }
+    rotation
+}
+
 #[derive(Copy, Clone, PartialEq, From, Debug)]
 pub enum Volume {
     Sphere(Sphere),
     AABB(AABB),
+    OBB(OBB),
 }
 
 impl Volume {
@@ -126,6 +269,9 @@ impl Volume {
     pub fn aabb(center: Vec3, extents: Vec3) -> Self {
         Self::AABB(AABB { center, extents })
     }
+    pub fn obb(center: Vec3, half_extents: Vec3, rotation: Quat) -> Self {
+        Self::OBB(OBB { center, half_extents, rotation })
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -198,6 +344,7 @@ impl Frustum {
     /// False if outside of sphere sits precisely on the plane.
     pub fn contains_sphere(&self, sphere: Sphere) -> bool {
         let (point, radius) = (sphere.center, sphere.radius);
+        !sphere.is_empty() &&
         self.left.signed_distance(point) > -radius &&
         self.right.signed_distance(point) > -radius &&
         self.bottom.signed_distance(point) > -radius &&
@@ -209,6 +356,7 @@ impl Frustum {
     /// Checks if aabb is completely, or partially inside the frustum.
     /// False if outside of aabb sits precisely on the plane.
     pub fn contains_aabb(&self, aabb: AABB) -> bool {
+        !aabb.is_empty() &&
         -self.left.projection_interval(aabb) < self.left.signed_distance(aabb.center) &&
         -self.right.projection_interval(aabb) < self.right.signed_distance(aabb.center) &&
         -self.bottom.projection_interval(aabb) < self.bottom.signed_distance(aabb.center) &&
@@ -216,9 +364,78 @@ impl Frustum {
         -self.near.projection_interval(aabb) < self.near.signed_distance(aabb.center) &&
         -self.far.projection_interval(aabb) < self.far.signed_distance(aabb.center)
     }
+
+    /**
+     * Checks if obb is completely, or partially inside the frustum, using the separating axis theorem.
+     * Tests the planes first, then the obb's axes and the cross products of the frustum's edges with them.
+     * That catches boxes near the frustum's edges and corners that the planes alone would keep.
+     * False if outside of obb sits precisely on a separating axis.
+     */
+    pub fn contains_obb(&self, obb: OBB) -> bool {
+        if !self.planes().iter().all(|plane| -obb.projection_interval(plane.normal) < plane.signed_distance(obb.center)) {
+            return false;
+        }
+        let corners = self.corners();
+        let [lbn, rbn, ltn, rtn, lbf, rbf, ltf, rtf] = corners;
+        let frustum_edges = [
+            rbn - lbn,
+            ltn - lbn,
+            lbf - lbn,
+            rbf - rbn,
+            ltf - ltn,
+            rtf - rtn,
+        ];
+        let obb_axes = obb.axes();
+        let cross_axes = frustum_edges.iter().flat_map(|edge| obb_axes.map(|axis| edge.cross(axis)));
+        obb_axes.into_iter().chain(cross_axes)
+            .filter(|axis| axis.length_squared() > f32::EPSILON)
+            .all(|axis| {
+                let (min, max) = corners.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), corner| {
+                    let projected = corner.dot(axis);
+                    (min.min(projected), max.max(projected))
+                });
+                let center = obb.center.dot(axis);
+                let interval = obb.projection_interval(axis);
+                center - interval < max && min < center + interval
+            })
+    }
+
+    /// All six planes, in declaration order.
+    pub fn planes(&self) -> [Plane; 6] {
+        [self.left, self.right, self.bottom, self.top, self.near, self.far]
+    }
+
+    /**
+     * Eight corners where the planes meet.
+     * Ordered left before right, bottom before top, then near before far.
+     */
+    pub fn corners(&self) -> [Vec3; 8] {
+        let mut corners = [Vec3::ZERO; 8];
+        let mut index = 0;
+        for depth in [self.near, self.far] {
+            for vertical in [self.bottom, self.top] {
+                for horizontal in [self.left, self.right] {
+                    corners[index] = intersect_planes(horizontal, vertical, depth);
+                    index += 1;
+                }
+            }
+        }
+        corners
+    }
+}
+
+/// Point where three planes meet, assuming that no two are parallel.
+fn intersect_planes(a: Plane, b: Plane, c: Plane) -> Vec3 {
+    let bc = b.normal.cross(c.normal);
+    let ca = c.normal.cross(a.normal);
+    let ab = a.normal.cross(b.normal);
+    (bc * a.distance + ca * b.distance + ab * c.distance) / a.normal.dot(bc)
 }
 
 impl From<Mat4> for Frustum {
+
+    /// Extracts the planes of a projection-view matrix that maps depth to 0..1, as wgpu and glam's `_rh` projections do.
+    /// Planes point inwards.
     fn from(proj_view: Mat4) -> Self {
         let row1 = proj_view.row(0);
         let row2 = proj_view.row(1);
@@ -235,11 +452,102 @@ impl From<Mat4> for Frustum {
     }
 }
 
+/**
+ * A half-line in 3D space.
+ * Useful for picking objects under the cursor.
+ */
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// Normalized direction.
+    pub direction: Vec3,
+}
+
+impl Ray {
+
+    /// Creates a ray. Direction gets normalized.
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self { origin, direction: direction.normalize() }
+    }
+
+    /// Point at the distance specified along the ray.
+    pub fn at(self, distance: f32) -> Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    /// Distance along the ray to where it first hits the aabb.
+    /// Zero if the ray starts inside the aabb.
+    pub fn intersects_aabb(&self, aabb: AABB) -> Option<f32> {
+        let inv_dir = self.direction.recip();
+        let t1 = (aabb.center - aabb.extents - self.origin) * inv_dir;
+        let t2 = (aabb.center + aabb.extents - self.origin) * inv_dir;
+        let t_near = t1.min(t2).max_element();
+        let t_far = t1.max(t2).min_element();
+        if t_near > t_far || t_far < 0.0 {
+            return None;
+        }
+        Some(t_near.max(0.0))
+    }
+
+    /// Distance along the ray to where it first hits the obb.
+    /// Zero if the ray starts inside the obb.
+    pub fn intersects_obb(&self, obb: OBB) -> Option<f32> {
+        let inv_rotation = obb.rotation.inverse();
+        let local = Ray {
+            origin: inv_rotation * (self.origin - obb.center),
+            direction: inv_rotation * self.direction,
+        };
+        local.intersects_aabb(AABB::new(Vec3::ZERO, obb.half_extents))
+    }
+
+    /// Distance along the ray to where it first hits the sphere.
+    /// Zero if the ray starts inside the sphere.
+    pub fn intersects_sphere(&self, sphere: Sphere) -> Option<f32> {
+        let to_center = sphere.center - self.origin;
+        let projected = to_center.dot(self.direction);
+        let dist_sq = to_center.length_squared() - projected * projected;
+        let radius_sq = sphere.radius * sphere.radius;
+        if dist_sq > radius_sq {
+            return None;
+        }
+        let half_chord = (radius_sq - dist_sq).sqrt();
+        let t_near = projected - half_chord;
+        let t_far = projected + half_chord;
+        if t_far < 0.0 {
+            return None;
+        }
+        Some(t_near.max(0.0))
+    }
+
+    /// Distance along the ray to where it crosses the plane, from either side.
+    /// None if the ray is parallel to the plane.
+    pub fn intersects_plane(&self, plane: Plane) -> Option<f32> {
+        let facing = plane.normal.dot(self.direction);
+        if facing.abs() <= f32::EPSILON {
+            return None;
+        }
+        let t = -plane.signed_distance(self.origin) / facing;
+        if t < 0.0 {
+            return None;
+        }
+        Some(t)
+    }
+
+    /// Distance along the ray to where it first hits the volume.
+    pub fn intersects_volume(&self, volume: Volume) -> Option<f32> {
+        match volume {
+            Volume::Sphere(sphere) => self.intersects_sphere(sphere),
+            Volume::AABB(aabb) => self.intersects_aabb(aabb),
+            Volume::OBB(obb) => self.intersects_obb(obb),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
 
-    use glam::{Mat4, Vec3};
-    use crate::math::Frustum;
+    use glam::{Mat4, Quat, Vec3};
+    use crate::math::{max_len, Frustum, Plane, Ray, Sphere, AABB, OBB};
 
     #[test]
     fn signed_dist() {
@@ -272,4 +580,265 @@ mod test {
         let actual_dist = frustum.far.signed_distance(center);
         assert_eq!(expected_dist, actual_dist);
     }
+
+    #[test]
+    fn volumes_from_points() {
+        let points = [Vec3::new(-1.0, 0.0, 2.0), Vec3::new(3.0, -2.0, 0.0), Vec3::new(0.0, 4.0, 1.0), Vec3::new(1.0, 1.0, 1.0)];
+        let aabb = AABB::from_points(&points);
+        assert_eq!(AABB::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(2.0, 3.0, 1.0)), aabb);
+
+        // Ritter's sphere bounds every point, and is no bigger than the sphere around the box.
+        let sphere = Sphere::from_points(&points);
+        for point in points {
+            assert!(point.distance(sphere.center) <= sphere.radius + 1e-5);
+        }
+        assert!(sphere.radius <= aabb.extents.length());
+
+        // A single point gets a volume of no size.
+        assert_eq!(AABB::new(Vec3::ONE, Vec3::ZERO), AABB::from_points(&[Vec3::ONE]));
+        assert_eq!(Sphere::new(Vec3::ONE, 0.0), Sphere::from_points(&[Vec3::ONE]));
+    }
+
+    #[test]
+    fn empty_volumes_are_never_contained() {
+        let frustum = Frustum::from(Mat4::perspective_rh(1.0, 1.0, 0.1, 100.0));
+        let aabb = AABB::from_points(&[]);
+        let sphere = Sphere::from_points(&[]);
+        assert!(aabb.is_empty() && sphere.is_empty());
+        assert!(!frustum.contains_aabb(aabb));
+        assert!(!frustum.contains_sphere(sphere));
+
+        // Transforming them keeps them empty, without producing NaNs.
+        let mat = Mat4::from_scale_rotation_translation(Vec3::splat(2.0), Quat::from_rotation_y(1.0), Vec3::new(0.0, 0.0, -5.0));
+        assert_eq!(AABB::EMPTY, aabb.transform(mat));
+        assert_eq!(Sphere::EMPTY, sphere.transform(mat));
+        assert!(!frustum.contains_aabb(aabb.transform(mat)));
+    }
+
+    #[test]
+    fn ray_intersects_aabb() {
+        let aabb = AABB::new(Vec3::new(0.0, 0.0, -5.0), Vec3::splat(1.0));
+        let ray = Ray::new(Vec3::ZERO, Vec3::NEG_Z);
+        assert_eq!(Some(4.0), ray.intersects_aabb(aabb));
+
+        let ray = Ray::new(Vec3::ZERO, Vec3::Z);
+        assert_eq!(None, ray.intersects_aabb(aabb));
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::X);
+        assert_eq!(Some(0.0), ray.intersects_aabb(aabb));
+
+        // Rays parallel to a slab miss unless they start between its planes.
+        let ray = Ray::new(Vec3::new(0.0, 2.0, 0.0), Vec3::NEG_Z);
+        assert_eq!(None, ray.intersects_aabb(aabb));
+
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, -1.0));
+        let t = ray.intersects_aabb(aabb).unwrap();
+        assert!((t - 4.0 * 2.0_f32.sqrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn ray_intersects_sphere() {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -5.0), 2.0);
+        let ray = Ray::new(Vec3::ZERO, Vec3::NEG_Z);
+        assert_eq!(Some(3.0), ray.intersects_sphere(sphere));
+
+        let ray = Ray::new(Vec3::new(3.0, 0.0, 0.0), Vec3::NEG_Z);
+        assert_eq!(None, ray.intersects_sphere(sphere));
+
+        let ray = Ray::new(Vec3::ZERO, Vec3::Z);
+        assert_eq!(None, ray.intersects_sphere(sphere));
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -4.0), Vec3::NEG_Z);
+        assert_eq!(Some(0.0), ray.intersects_sphere(sphere));
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -10.0), Vec3::NEG_Z);
+        assert_eq!(None, ray.intersects_sphere(sphere));
+    }
+
+    #[test]
+    fn ray_intersects_plane() {
+        let floor = Plane { normal: Vec3::Y, distance: -2.0 };
+        let ray = Ray::new(Vec3::ZERO, Vec3::NEG_Y);
+        assert_eq!(Some(2.0), ray.intersects_plane(floor));
+
+        // Planes are hit from behind too.
+        let ray = Ray::new(Vec3::new(0.0, -5.0, 0.0), Vec3::Y);
+        assert_eq!(Some(3.0), ray.intersects_plane(floor));
+
+        let ray = Ray::new(Vec3::ZERO, Vec3::Y);
+        assert_eq!(None, ray.intersects_plane(floor));
+
+        let ray = Ray::new(Vec3::ZERO, Vec3::X);
+        assert_eq!(None, ray.intersects_plane(floor));
+
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let t = ray.intersects_plane(floor).unwrap();
+        assert!(ray.at(t).abs_diff_eq(Vec3::new(3.0, -2.0, 0.0), 1e-5));
+    }
+
+    /// Offset that puts a point just inside or just outside of a plane.
+    const EPSILON: f32 = 0.01;
+
+    /// Orthographic frustum spanning -1 to 1 on x and y, and 0 to 10 in front of the camera.
+    fn ortho_frustum() -> Frustum {
+        Frustum::from(Mat4::orthographic_rh(-1.0, 1.0, -1.0, 1.0, 0.0, 10.0))
+    }
+
+    /// Perspective frustum with a 90 degree FOV from 1 to 100 in front of the camera.
+    /// At a distance d, it spans -d to d on x and y.
+    fn persp_frustum() -> Frustum {
+        Frustum::from(Mat4::perspective_rh(90.0_f32.to_radians(), 1.0, 1.0, 100.0))
+    }
+
+    /// Points that sit on each of the six planes, and the outward normals of those planes.
+    /// Side planes lean outwards by the slope, which is 0 for orthographic frustums.
+    fn plane_points(half_width: f32, slope: f32, near: f32, far: f32, depth: f32) -> [(Vec3, Vec3); 6] {
+        let lean = Vec3::Z * slope;
+        [
+            (Vec3::new(-half_width, 0.0, -depth), (Vec3::NEG_X + lean).normalize()),
+            (Vec3::new(half_width, 0.0, -depth), (Vec3::X + lean).normalize()),
+            (Vec3::new(0.0, -half_width, -depth), (Vec3::NEG_Y + lean).normalize()),
+            (Vec3::new(0.0, half_width, -depth), (Vec3::Y + lean).normalize()),
+            (Vec3::new(0.0, 0.0, -near), Vec3::Z),
+            (Vec3::new(0.0, 0.0, -far), Vec3::NEG_Z),
+        ]
+    }
+
+    fn assert_planes(frustum: &Frustum, points: [(Vec3, Vec3); 6]) {
+        for (point, outward) in points {
+            let inside = point - outward * EPSILON;
+            let outside = point + outward * EPSILON;
+            assert!(frustum.contains_point(inside), "{inside} should be inside");
+            assert!(!frustum.contains_point(outside), "{outside} should be outside");
+
+            // Spheres and aabbs that poke into the frustum are kept.
+            // Aabb extents are scaled so that their projection onto the normal matches the sphere's radius.
+            let center = point + outward * 0.5;
+            let projected_extent = outward.abs().dot(Vec3::ONE);
+            assert!(frustum.contains_sphere(Sphere::new(center, 0.5 + EPSILON)), "sphere at {center} should be kept");
+            assert!(!frustum.contains_sphere(Sphere::new(center, 0.5 - EPSILON)), "sphere at {center} should be culled");
+            let kept = AABB::new(center, Vec3::splat((0.5 + EPSILON) / projected_extent));
+            let culled = AABB::new(center, Vec3::splat((0.5 - EPSILON) / projected_extent));
+            assert!(frustum.contains_aabb(kept), "aabb at {center} should be kept");
+            assert!(!frustum.contains_aabb(culled), "aabb at {center} should be culled");
+            assert!(frustum.contains_obb(OBB::from(kept)), "obb at {center} should be kept");
+            assert!(!frustum.contains_obb(OBB::from(culled)), "obb at {center} should be culled");
+        }
+    }
+
+    #[test]
+    fn ortho_frustum_planes() {
+        assert_planes(&ortho_frustum(), plane_points(1.0, 0.0, 0.0, 10.0, 5.0));
+    }
+
+    #[test]
+    fn persp_frustum_planes() {
+        let frustum = persp_frustum();
+        assert_planes(&frustum, plane_points(50.0, 1.0, 1.0, 100.0, 50.0));
+
+        // Side planes are slanted, so objects near the far plane are only kept if they are within its wider bounds.
+        assert!(frustum.contains_point(Vec3::new(99.0, 0.0, -99.5)));
+        assert!(!frustum.contains_point(Vec3::new(99.0, 0.0, -98.5)));
+    }
+
+    #[test]
+    fn shapes_straddling_corners_are_kept() {
+        let frustum = persp_frustum();
+        let corner = Vec3::new(100.0, 100.0, -100.0);
+        assert!(frustum.contains_sphere(Sphere::new(corner + Vec3::splat(0.5), 1.0)));
+        assert!(frustum.contains_aabb(AABB::new(corner + Vec3::splat(0.5), Vec3::splat(1.0))));
+    }
+
+    #[test]
+    fn rotated_obbs_near_corners_are_culled() {
+        let frustum = ortho_frustum();
+        let rotation = Quat::from_rotation_z(45.0_f32.to_radians());
+
+        // Diamond just outside the top right edge, whose corners overlap both side planes.
+        let outside = OBB::new(Vec3::new(1.6, 1.6, -5.0), Vec3::new(0.5, 0.5, 1.0), rotation);
+        assert!(frustum.contains_aabb(AABB::new(outside.center, Vec3::splat(outside.projection_interval(Vec3::X)))));
+        assert!(!frustum.contains_obb(outside));
+
+        let inside = OBB::new(Vec3::new(1.3, 1.3, -5.0), Vec3::new(0.5, 0.5, 1.0), rotation);
+        assert!(frustum.contains_obb(inside));
+    }
+
+    #[test]
+    fn frustum_corners() {
+        let corners = persp_frustum().corners();
+        assert!(corners[0].abs_diff_eq(Vec3::new(-1.0, -1.0, -1.0), 1e-4));
+        assert!(corners[3].abs_diff_eq(Vec3::new(1.0, 1.0, -1.0), 1e-4));
+        assert!(corners[4].abs_diff_eq(Vec3::new(-100.0, -100.0, -100.0), 1e-2));
+        assert!(corners[7].abs_diff_eq(Vec3::new(100.0, 100.0, -100.0), 1e-2));
+    }
+
+    #[test]
+    fn obb_transform() {
+        let obb = OBB::new(Vec3::X, Vec3::new(1.0, 2.0, 3.0), Quat::from_rotation_y(0.5));
+        let rotation = Quat::from_rotation_x(1.0);
+        let mat = Mat4::from_scale_rotation_translation(Vec3::splat(2.0), rotation, Vec3::Y);
+        let transformed = obb.transform(mat);
+        assert!(transformed.center.abs_diff_eq(Vec3::new(2.0, 1.0, 0.0), 1e-5));
+        assert!(transformed.half_extents.abs_diff_eq(Vec3::new(2.0, 4.0, 6.0), 1e-4));
+        assert!(transformed.rotation.angle_between(rotation * obb.rotation) < 1e-3);
+
+        // Non-uniform scale along the box's own axes stretches its extents.
+        let obb = OBB::new(Vec3::ZERO, Vec3::ONE, Quat::from_rotation_z(1.0));
+        let mat = Mat4::from_quat(obb.rotation) * Mat4::from_scale(Vec3::new(1.0, 3.0, 1.0)) * Mat4::from_quat(obb.rotation.inverse());
+        let transformed = obb.transform(mat);
+        assert!(transformed.half_extents.abs_diff_eq(Vec3::new(1.0, 3.0, 1.0), 1e-4));
+        assert!(transformed.rotation.angle_between(obb.rotation) < 1e-3);
+
+        // Reflections keep a valid rotation.
+        let transformed = OBB::UNIT.transform(Mat4::from_scale(Vec3::new(-2.0, 1.0, 1.0)));
+        assert!(transformed.rotation.is_normalized());
+        assert!(transformed.half_extents.abs_diff_eq(Vec3::new(1.0, 0.5, 0.5), 1e-5));
+
+        // Sheared boxes are still bounded by the result.
+        let shear = Mat4::from_cols_array(&[1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+        let transformed = OBB::UNIT.transform(shear);
+        let inv_rotation = transformed.rotation.inverse();
+        for x in [-0.5, 0.5] {
+            for y in [-0.5, 0.5] {
+                let corner = inv_rotation * shear.transform_point3(Vec3::new(x, y, 0.5));
+                assert!(corner.abs().cmple(transformed.half_extents + 1e-5).all(), "{corner} should be bounded");
+            }
+        }
+    }
+
+    #[test]
+    fn ray_intersects_obb() {
+        let obb = OBB::new(Vec3::new(0.0, 0.0, -5.0), Vec3::splat(1.0), Quat::from_rotation_y(45.0_f32.to_radians()));
+        let ray = Ray::new(Vec3::ZERO, Vec3::NEG_Z);
+        let t = ray.intersects_obb(obb).unwrap();
+        assert!((t - (5.0 - 2.0_f32.sqrt())).abs() < 1e-4);
+
+        assert_eq!(Some(0.0), Ray::new(obb.center, Vec3::X).intersects_obb(obb));
+
+        // Would hit the unrotated box, but passes beside the diamond.
+        let obb = OBB::new(obb.center, obb.half_extents, Quat::from_rotation_z(45.0_f32.to_radians()));
+        let ray = Ray::new(Vec3::new(0.9, 0.9, 0.0), Vec3::NEG_Z);
+        assert!(ray.intersects_aabb(AABB::new(obb.center, obb.half_extents)).is_some());
+        assert_eq!(None, ray.intersects_obb(obb));
+    }
+
+    #[test]
+    fn sphere_transform_scales_radius() {
+        let sphere = Sphere::new(Vec3::new(1.0, 0.0, 0.0), 2.0);
+        let transformed = sphere.transform(Mat4::from_scale(Vec3::splat(2.0)));
+        assert_eq!(Vec3::new(2.0, 0.0, 0.0), transformed.center);
+        assert_eq!(4.0, transformed.radius);
+
+        // Non-uniform scale picks the largest axis.
+        let transformed = sphere.transform(Mat4::from_scale(Vec3::new(1.0, 2.0, 3.0)));
+        assert_eq!(6.0, transformed.radius);
+    }
+
+    #[test]
+    fn max_len_of_each_axis() {
+        assert_eq!(3.0, max_len(Vec3::X * 3.0, Vec3::Y, Vec3::Z));
+        assert_eq!(3.0, max_len(Vec3::X * 2.0, Vec3::Y, Vec3::Z * 3.0));
+        assert_eq!(3.0, max_len(Vec3::X, Vec3::Y * 3.0, Vec3::Z * 2.0));
+        assert_eq!(3.0, max_len(Vec3::X, Vec3::Y * 2.0, Vec3::Z * 3.0));
+    }
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
-use glam::{Vec3, Quat, Affine3A, Mat4, EulerRot};
+use std::ops::Mul;
+use glam::{Vec3, Quat, Affine3A, Mat3, Mat4, EulerRot};
 
 
 /**
@@ -19,6 +20,22 @@ impl Transform {
         scale: Vec3::new(1.0, 1.0, 1.0),
     };
 
+    /**
+     * Transform at eye, rotated so that its forward axis (-Z) points at target, and its up axis (+Y) is as close to up as possible.
+     * Returns [`Transform::IDENTITY`] if eye and target are the same point.
+     * If up is parallel to the direction of target, an arbitrary perpendicular up is used instead.
+     */
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        let Some(forward) = (target - eye).try_normalize() else { return Self::IDENTITY };
+        let up = match forward.cross(up).length_squared() > f32::EPSILON {
+            true => up,
+            false => forward.any_orthogonal_vector(),
+        };
+        let view = Mat4::look_at_rh(eye, target, up);
+        let rotation = Quat::from_mat3(&Mat3::from_mat4(view).transpose()).normalize();
+        Self { translation: eye, rotation, ..Self::IDENTITY }
+    }
+
     pub fn with_translation(mut self, translation: Vec3) -> Self {
         self.translation = translation;
         self
@@ -49,10 +66,37 @@ impl Transform {
         self
     }
 
+    /// Rotates the transform to look at target, as in [`Transform::look_at`], keeping its translation and scale.
+    pub fn with_look_at(mut self, target: Vec3, up: Vec3) -> Self {
+        self.rotation = Self::look_at(self.translation, target, up).rotation;
+        self
+    }
+
+    /**
+     * Decomposes a matrix into translation, rotation and scale, as received from other libraries.
+     * Shear and projection cannot be represented, and are lost.
+     */
+    pub fn from_mat4(mat: Mat4) -> Self {
+        let (scale, rotation, translation) = mat.to_scale_rotation_translation();
+        Self { translation, rotation, scale }
+    }
+
+    /// Matrix that scales, then rotates, then translates.
+    pub fn to_mat4(self) -> Mat4 {
+        Mat4::from(self)
+    }
+
+    /// Same transform with its rotation normalized, undoing drift accumulated by repeatedly composing rotations.
+    pub fn renormalize(mut self) -> Self {
+        self.rotation = self.rotation.normalize();
+        self
+    }
+
+    /// Interpolates translation and scale linearly, and rotation spherically.
     pub fn lerp(self, other: Transform, s: f32) -> Transform {
         Transform {
             translation: self.translation.lerp(other.translation, s),
-            rotation: self.rotation.lerp(other.rotation, s),
+            rotation: self.rotation.slerp(other.rotation, s),
             scale: self.scale.lerp(other.scale, s),
         }
     }
@@ -68,6 +112,17 @@ impl Default for Transform {
     }
 }
 
+/// Composes two transforms, so that rhs is applied first. IE: parent * child.
+/// Shear, which non-uniform scales can produce under rotation, is lost.
+impl Mul for Transform {
+    type Output = Transform;
+    fn mul(self, rhs: Transform) -> Transform {
+        let affine = Affine3A::from(self) * Affine3A::from(rhs);
+        let (scale, rotation, translation) = affine.to_scale_rotation_translation();
+        Transform { translation, rotation, scale }
+    }
+}
+
 impl From<Transform> for Affine3A {
     fn from(transform: Transform) -> Self {
         Self::from_scale_rotation_translation(
@@ -96,3 +151,88 @@ pub fn lerp_matrices(a: Mat4, b: Mat4, t: f32) -> Mat4 {
     let col3 = a.col(3).lerp(b.col(3), t);
     Mat4::from_cols(col0, col1, col2, col3)
 }
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+    use glam::{Mat4, Quat, Vec3};
+    use super::Transform;
+
+    #[test]
+    fn lerp_rotates_at_constant_speed() {
+        let a = Transform::default();
+        let b = Transform::default().with_rotation(Quat::from_rotation_y(PI * 0.9));
+        let quarter = a.lerp(b, 0.25).rotation;
+        assert!(quarter.is_normalized());
+        assert!(quarter.angle_between(Quat::from_rotation_y(PI * 0.225)) < 1e-3);
+    }
+
+    #[test]
+    fn decomposes_matrices() {
+        let rotation = Quat::from_rotation_y(PI / 2.0);
+        let mat = Mat4::from_translation(Vec3::new(1.0, 2.0, 3.0)) * Mat4::from_quat(rotation) * Mat4::from_scale(Vec3::new(2.0, 3.0, 4.0));
+        let transform = Transform::from_mat4(mat);
+        assert!(transform.translation.abs_diff_eq(Vec3::new(1.0, 2.0, 3.0), 1e-5));
+        assert!(transform.rotation.angle_between(rotation) < 1e-3);
+        assert!(transform.scale.abs_diff_eq(Vec3::new(2.0, 3.0, 4.0), 1e-5));
+        assert!(transform.to_mat4().abs_diff_eq(mat, 1e-5));
+
+        // Round trips through a matrix keep the transform.
+        let transform = Transform::default().with_xyz(-4.0, 0.5, 8.0).with_rotation(Quat::from_rotation_x(0.3)).with_scale_xyz(1.0, 2.0, 0.5);
+        let round_trip = Transform::from_mat4(transform.to_mat4());
+        assert!(round_trip.translation.abs_diff_eq(transform.translation, 1e-5));
+        assert!(round_trip.rotation.angle_between(transform.rotation) < 1e-3);
+        assert!(round_trip.scale.abs_diff_eq(transform.scale, 1e-5));
+        assert_eq!(Transform::IDENTITY, Transform::from_mat4(Mat4::IDENTITY));
+    }
+
+    #[test]
+    fn renormalize_undoes_drift() {
+        let drifted = Transform::default().with_rotation(Quat::from_xyzw(0.0, 0.5, 0.0, 0.9));
+        assert!(!drifted.rotation.is_normalized());
+        let renormalized = drifted.renormalize();
+        assert!(renormalized.rotation.is_normalized());
+        assert!(renormalized.rotation.angle_between(drifted.rotation.normalize()) < 1e-3);
+    }
+
+    #[test]
+    fn look_at_points_forward_at_target() {
+        let eye = Vec3::new(1.0, 2.0, 3.0);
+        let target = Vec3::new(-4.0, 0.0, 1.0);
+        let transform = Transform::look_at(eye, target, Vec3::Y);
+        assert_eq!(eye, transform.translation);
+        assert!(transform.rotation.is_normalized());
+        let forward = transform.rotation * Vec3::NEG_Z;
+        assert!(forward.distance((target - eye).normalize()) < 1e-5);
+
+        // Right stays level, and up leans towards the up specified.
+        assert!((transform.rotation * Vec3::X).y.abs() < 1e-5);
+        assert!((transform.rotation * Vec3::Y).y > 0.0);
+
+        // Looking down -Z with +Y up needs no rotation.
+        let transform = Transform::look_at(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y);
+        assert!(transform.rotation.angle_between(Quat::IDENTITY) < 1e-5);
+    }
+
+    #[test]
+    fn look_at_degenerate_inputs() {
+        let eye = Vec3::new(1.0, 2.0, 3.0);
+        assert_eq!(Transform::IDENTITY, Transform::look_at(eye, eye, Vec3::Y));
+
+        // Looking straight up still points at the target.
+        let transform = Transform::look_at(Vec3::ZERO, Vec3::Y * 5.0, Vec3::Y);
+        assert!(transform.rotation.is_finite() && transform.rotation.is_normalized());
+        assert!((transform.rotation * Vec3::NEG_Z).distance(Vec3::Y) < 1e-5);
+    }
+
+    #[test]
+    fn with_look_at_keeps_translation_and_scale() {
+        let transform = Transform::default()
+            .with_xyz(0.0, 10.0, 0.0)
+            .with_scale_xyz(2.0, 2.0, 2.0)
+            .with_look_at(Vec3::ZERO, Vec3::Z);
+        assert_eq!(Vec3::new(0.0, 10.0, 0.0), transform.translation);
+        assert_eq!(Vec3::splat(2.0), transform.scale);
+        assert!((transform.rotation * Vec3::NEG_Z).distance(Vec3::NEG_Y) < 1e-5);
+    }
+}
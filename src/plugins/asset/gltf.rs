@@ -0,0 +1,607 @@
+use std::sync::Arc;
+use base64::Engine;
+use derive_more::*;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use wgpu::{Device, Face, Queue};
+use crate::g3d::{BlendMode, Material, Mesh, MeshData};
+use crate::math::Transform;
+use crate::{Asset, AssetLoader, AssetManager, AssetPath, AppBuilder, Color, Game, GraphicsState, Handle, Plugin, Readiness, RunContext, Stage, Texture, TextureLoader};
+
+/// Registers a [`GltfLoader`], and the storages of the assets it produces.
+/// Must be installed after the asset and graphics plugins.
+pub struct GltfPlugin;
+
+impl Plugin for GltfPlugin {
+    fn install(&mut self, builder: &mut AppBuilder) {
+        builder.system(Stage::Asset, insert_gltf_scenes);
+        let game = builder.game();
+        assert!(game.contains::<AssetManager>(), "AssetPlugin must be installed before GltfPlugin");
+        assert!(game.contains::<GraphicsState>(), "GraphicsPlugin must be installed before GltfPlugin");
+        let (device, queue) = {
+            let state = game.get::<&GraphicsState>();
+            (state.device.clone(), state.queue.clone())
+        };
+        let mut assets = game.get::<&mut AssetManager>();
+        assets.add_storage::<GltfScene>();
+        assets.add_storage::<Mesh>();
+        assets.add_storage::<Material>();
+        assets.add_storage::<Texture>();
+        assets.add_loader(GltfLoader { device, queue }).unwrap();
+    }
+}
+
+/**
+ * Loads a [`GltfScene`] from a .gltf or .glb file.
+ * Buffers must either be embedded as data URIs, or stored in the binary chunk of a .glb file.
+ * Only base color, roughness and metalness factors, and base color textures are read from materials. Skins and animations are ignored.
+ * Images embedded in the file are decoded on the loader thread. Those in files of their own are loaded relative to the glTF file.
+ */
+pub struct GltfLoader {
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+}
+
+impl AssetLoader for GltfLoader {
+
+    type AssetType = GltfScene;
+
+    fn load(&self, bytes: &[u8], path: &AssetPath) -> anyhow::Result<Self::AssetType> {
+        let data = parse_gltf(bytes)?;
+        let meshes = data.meshes
+            .iter()
+            .map(|mesh_data| Mesh::from_data(mesh_data, &self.device))
+            .collect();
+        let texture_loader = TextureLoader::new(self.device.clone(), self.queue.clone());
+        let textures = data.images
+            .into_iter()
+            .enumerate()
+            .map(|(image_idx, image)| match image {
+                GltfImage::Uri(uri) => Ok(PendingTexture::Path(path.resolve(&uri))),
+                GltfImage::Embedded { bytes, extension } => {
+                    let image_path = AssetPath {
+                        protocol: path.protocol.clone(),
+                        body: format!("{}#image{image_idx}", path.body),
+                        extension,
+                    };
+                    Ok(PendingTexture::Embedded(texture_loader.load(&bytes, &image_path)?))
+                },
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(GltfScene {
+            primitives: Vec::new(),
+            nodes: Vec::new(),
+            roots: data.roots,
+            textures: Vec::new(),
+            pending: Some(PendingScene {
+                meshes,
+                materials: data.materials,
+                base_color_textures: data.base_color_textures,
+                textures,
+                nodes: data.nodes,
+                primitives: data.primitives,
+            }),
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["gltf", "glb"]
+    }
+}
+
+/**
+ * Default scene of a glTF file, or its first scene if it has no default.
+ * Meshes, materials and textures are inserted into their storages on the first asset stage after loading.
+ * Until then, the scene has no nodes or primitives, and is not ready.
+ * It is then ready once its textures are.
+ * Spawn it with [`crate::g3d::spawn_gltf`].
+ */
+pub struct GltfScene {
+    /// Mesh, material and global transform of each primitive, in the order of the scene's nodes.
+    /// A mesh instanced by several nodes shares its handle.
+    pub primitives: Vec<(Handle<Mesh>, Handle<Material>, Transform)>,
+    /// Every node of the file, in the file's order, with meshes and materials as handles.
+    pub nodes: Vec<GltfNode>,
+    /// Indices of the nodes at the root of the scene.
+    pub roots: Vec<usize>,
+    /// Textures of the file's images, which its materials refer to.
+    /// Images that could not be loaded are left out.
+    pub textures: Vec<Handle<Texture>>,
+    pending: Option<PendingScene>,
+}
+
+impl Asset for GltfScene {
+    fn readiness(&self, assets: &AssetManager) -> Readiness {
+        if self.pending.is_some() {
+            return Readiness::NotReady;
+        }
+        self.textures
+            .iter()
+            .map(|texture| assets.dyn_readiness(texture.id()))
+            .fold(Readiness::Ready, Readiness::merge)
+    }
+}
+
+/**
+ * Node of a glTF file.
+ * Primitives are (mesh, material) handles in a [`GltfScene`], and indices in [`GltfData`].
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct GltfNode<P = (Handle<Mesh>, Handle<Material>)> {
+    pub name: Option<String>,
+    /// Relative to the parent node, if any.
+    pub transform: Transform,
+    /// Mesh and material of each primitive of the node's mesh. Empty if the node has no mesh.
+    pub primitives: Vec<P>,
+    /// Indices of child nodes.
+    pub children: Vec<usize>,
+}
+
+/// Assets created on a loader thread, awaiting insertion into their storages.
+struct PendingScene {
+    meshes: Vec<Mesh>,
+    materials: Vec<Material>,
+    base_color_textures: Vec<Option<usize>>,
+    textures: Vec<PendingTexture>,
+    nodes: Vec<GltfNode<(usize, usize)>>,
+    primitives: Vec<(usize, usize, Transform)>,
+}
+
+/// Texture of an image, decoded if it was embedded.
+enum PendingTexture {
+    Path(String),
+    Embedded(Texture),
+}
+
+fn insert_gltf_scenes(game: &mut Game, _ctx: RunContext) {
+    let mut assets = game.get::<&mut AssetManager>();
+    insert_gltf_assets(&mut assets);
+}
+
+/// Inserts the meshes, materials and textures of loaded [`GltfScene`]s into their storages.
+/// Starts loading textures stored in files of their own.
+pub(crate) fn insert_gltf_assets(assets: &mut AssetManager) {
+
+    // Takes what scenes loaded since the last call are waiting on
+    let mut loaded = Vec::new();
+    {
+        let mut scenes = assets.storage::<GltfScene>().unwrap();
+        for (index, scene) in scenes.inner.iter_mut() {
+            let Some(scene) = scene.as_loaded_mut() else { continue };
+            let Some(pending) = scene.pending.take() else { continue };
+            loaded.push((index, pending));
+        }
+    }
+    if loaded.is_empty() { return }
+
+    // Inserts or starts loading textures
+    let loaded: Vec<_> = loaded
+        .into_iter()
+        .map(|(index, mut pending)| {
+            let textures: Vec<Option<Handle<Texture>>> = std::mem::take(&mut pending.textures)
+                .into_iter()
+                .map(|texture| match texture {
+                    PendingTexture::Path(path) => {
+                        let handle = assets.try_load::<Texture, _>(&path);
+                        if let Err(err) = &handle {
+                            log::error!("Failed to load texture {path}: {err}");
+                        }
+                        handle.ok()
+                    },
+                    PendingTexture::Embedded(texture) => Some(assets.insert(texture)),
+                })
+                .collect();
+            (index, pending, textures)
+        })
+        .collect();
+
+    // Inserts meshes and materials
+    let mut scenes = assets.storage::<GltfScene>().unwrap();
+    let mut meshes = assets.storage::<Mesh>().unwrap();
+    let mut materials = assets.storage::<Material>().unwrap();
+    for (index, pending, textures) in loaded {
+        let Some(scene) = scenes.inner.get_mut(index).and_then(|scene| scene.as_loaded_mut()) else { continue };
+        let mesh_handles: Vec<Handle<Mesh>> = pending.meshes
+            .into_iter()
+            .map(|mesh| meshes.insert(mesh))
+            .collect();
+        let material_handles: Vec<Handle<Material>> = pending.materials
+            .into_iter()
+            .zip(pending.base_color_textures)
+            .map(|(mut material, image_idx)| {
+                material.base_color_texture = image_idx.and_then(|image_idx| textures[image_idx].clone());
+                materials.insert(material)
+            })
+            .collect();
+        let handles = |(mesh_idx, material_idx): (usize, usize)| {
+            (mesh_handles[mesh_idx].clone(), material_handles[material_idx].clone())
+        };
+        scene.nodes = pending.nodes
+            .into_iter()
+            .map(|node| GltfNode {
+                name: node.name,
+                transform: node.transform,
+                primitives: node.primitives.into_iter().map(handles).collect(),
+                children: node.children,
+            })
+            .collect();
+        scene.primitives = pending.primitives
+            .into_iter()
+            .map(|(mesh_idx, material_idx, transform)| {
+                let (mesh, material) = handles((mesh_idx, material_idx));
+                (mesh, material, transform)
+            })
+            .collect();
+        scene.textures = textures.into_iter().flatten().collect();
+    }
+}
+
+/// Source of an image of a glTF file.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum GltfImage {
+    /// Path of an image file, relative to the glTF file.
+    Uri(String),
+    /// Encoded image stored in a buffer or a data URI, and the file extension of its format.
+    Embedded { bytes: Vec<u8>, extension: String },
+}
+
+/// Contents of a glTF file, before any GPU resources are created.
+pub struct GltfData {
+    /// One per primitive of each glTF mesh.
+    pub meshes: Vec<MeshData>,
+    /// One per glTF material, followed by a default material for primitives that have none.
+    /// Textures are left unset, as they only exist once inserted into their storage.
+    pub materials: Vec<Material>,
+    /// Index in images of the base color texture of each material, if it has one.
+    pub base_color_textures: Vec<Option<usize>>,
+    /// One per glTF image.
+    pub images: Vec<GltfImage>,
+    /// Index of the mesh, index of the material, and global transform of each primitive in the scene.
+    pub primitives: Vec<(usize, usize, Transform)>,
+    /// Every node of the file, with the indices of the mesh and material of each primitive.
+    pub nodes: Vec<GltfNode<(usize, usize)>>,
+    /// Indices of the nodes at the root of the scene.
+    pub roots: Vec<usize>,
+}
+
+/**
+ * Parses the bytes of a .gltf or .glb file into [`GltfData`].
+ * Only triangle list primitives are read. Other primitives are skipped.
+ * Primitives without indices are indexed in vertex order.
+ */
+pub fn parse_gltf(bytes: &[u8]) -> Result<GltfData, GltfError> {
+
+    let gltf::Gltf { document, blob } = gltf::Gltf::from_slice(bytes).map_err(GltfError::Parse)?;
+    let buffers = read_buffers(&document, blob)?;
+
+    // Reads each primitive of each mesh, remembering where each mesh's primitives start
+    let mut meshes = Vec::new();
+    let mut mesh_primitives: Vec<Vec<(usize, usize)>> = Vec::new();
+    let default_material = document.materials().len();
+    for mesh in document.meshes() {
+        let mut primitives = Vec::new();
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                log::warn!("Skipping primitive of mesh {} with unsupported mode {:?}", mesh.index(), primitive.mode());
+                continue;
+            }
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+            let Some(positions) = reader.read_positions() else { continue };
+            let positions: Vec<Vec3> = positions.map(Vec3::from).collect();
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            let mesh_data = MeshData {
+                indices,
+                positions,
+                colors: reader.read_colors(0).map(|colors| colors
+                    .into_rgba_f32()
+                    .map(|[r, g, b, a]| Color::new(r, g, b, a))
+                    .collect()
+                ),
+                normals: reader.read_normals().map(|normals| normals.map(Vec3::from).collect()),
+                uvs: reader.read_tex_coords(0).map(|uvs| uvs.into_f32().map(Vec2::from).collect()),
+                tangents: reader.read_tangents().map(|tangents| tangents.map(Vec4::from).collect()),
+                bone_indices: None,
+                bone_weights: None,
+            };
+            let material = primitive.material().index().unwrap_or(default_material);
+            primitives.push((meshes.len(), material));
+            meshes.push(mesh_data);
+        }
+        mesh_primitives.push(primitives);
+    }
+
+    // Converts materials, and the images of their textures
+    let base_texture_image = |info: gltf::texture::Info| info.texture().source().index();
+    let mut base_color_textures: Vec<Option<usize>> = document.materials()
+        .map(|material| material.pbr_metallic_roughness().base_color_texture().map(base_texture_image))
+        .collect();
+    base_color_textures.push(None);
+    let images = document.images()
+        .map(|image| read_image(image, &buffers))
+        .collect::<Result<_, _>>()?;
+    let mut materials: Vec<Material> = document.materials().map(|material| {
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, a] = pbr.base_color_factor();
+        Material {
+            base_color: Color::new(r, g, b, a),
+            cull_mode: if material.double_sided() { None } else { Some(Face::Back) },
+            blend_mode: match material.alpha_mode() {
+                gltf::material::AlphaMode::Blend => BlendMode::AlphaBlend,
+                _ => BlendMode::Opaque,
+            },
+            alpha_cutoff: match material.alpha_mode() {
+                gltf::material::AlphaMode::Mask => Some(material.alpha_cutoff().unwrap_or(0.5)),
+                _ => None,
+            },
+            pbr: true,
+            roughness: pbr.roughness_factor(),
+            metalness: pbr.metallic_factor(),
+            ..Default::default()
+        }
+    }).collect();
+    materials.push(Material::from(Color::WHITE));
+
+    // Walks the scene's nodes, accumulating transforms
+    let mut primitives = Vec::new();
+    let mut roots = Vec::new();
+    let scene = document.default_scene().or_else(|| document.scenes().next());
+    if let Some(scene) = scene {
+        for node in scene.nodes() {
+            roots.push(node.index());
+            collect_primitives(node, Mat4::IDENTITY, &mesh_primitives, &mut primitives);
+        }
+    }
+    let nodes = document.nodes()
+        .map(|node| GltfNode {
+            name: node.name().map(String::from),
+            transform: Transform::from_mat4(Mat4::from_cols_array_2d(&node.transform().matrix())),
+            primitives: node.mesh().map_or(Vec::new(), |mesh| mesh_primitives[mesh.index()].clone()),
+            children: node.children().map(|child| child.index()).collect(),
+        })
+        .collect();
+    Ok(GltfData { meshes, materials, base_color_textures, images, primitives, nodes, roots })
+}
+
+fn read_image(image: gltf::Image, buffers: &[Vec<u8>]) -> Result<GltfImage, GltfError> {
+    let extension = |mime_type: &str| match mime_type {
+        "image/png" => Ok(String::from("png")),
+        "image/jpeg" => Ok(String::from("jpg")),
+        _ => Err(GltfError::UnsupportedImageType(String::from(mime_type))),
+    };
+    match image.source() {
+        gltf::image::Source::View { view, mime_type } => {
+            let buffer = buffers.get(view.buffer().index()).ok_or(GltfError::BufferTooShort)?;
+            let bytes = buffer.get(view.offset()..view.offset() + view.length()).ok_or(GltfError::BufferTooShort)?;
+            Ok(GltfImage::Embedded { bytes: bytes.to_vec(), extension: extension(mime_type)? })
+        },
+        gltf::image::Source::Uri { uri, mime_type } if uri.starts_with("data:") => {
+            let mime_type = mime_type.or_else(|| uri.strip_prefix("data:")?.split_once(';').map(|(mime_type, _)| mime_type));
+            let extension = extension(mime_type.unwrap_or_default())?;
+            Ok(GltfImage::Embedded { bytes: decode_data_uri(uri)?, extension })
+        },
+        gltf::image::Source::Uri { uri, .. } => Ok(GltfImage::Uri(String::from(uri))),
+    }
+}
+
+fn collect_primitives(
+    node: gltf::Node,
+    parent_transform: Mat4,
+    mesh_primitives: &[Vec<(usize, usize)>],
+    primitives: &mut Vec<(usize, usize, Transform)>,
+) {
+    let global_transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        let (scale, rotation, translation) = global_transform.to_scale_rotation_translation();
+        let transform = Transform { translation, rotation, scale };
+        for (mesh_idx, material_idx) in &mesh_primitives[mesh.index()] {
+            primitives.push((*mesh_idx, *material_idx, transform));
+        }
+    }
+    for child in node.children() {
+        collect_primitives(child, global_transform, mesh_primitives, primitives);
+    }
+}
+
+/// Bytes of every buffer in the document, in order.
+fn read_buffers(document: &gltf::Document, mut blob: Option<Vec<u8>>) -> Result<Vec<Vec<u8>>, GltfError> {
+    document.buffers().map(|buffer| {
+        let mut bytes = match buffer.source() {
+            gltf::buffer::Source::Bin => blob.take().ok_or(GltfError::MissingBinaryChunk)?,
+            gltf::buffer::Source::Uri(uri) => decode_data_uri(uri)?,
+        };
+        if bytes.len() < buffer.length() {
+            return Err(GltfError::BufferTooShort);
+        }
+        bytes.truncate(buffer.length());
+        Ok(bytes)
+    }).collect()
+}
+
+fn decode_data_uri(uri: &str) -> Result<Vec<u8>, GltfError> {
+    let Some(data) = uri.strip_prefix("data:") else {
+        return Err(GltfError::ExternalBuffer(String::from(uri)));
+    };
+    let Some((_mime_type, data)) = data.split_once(";base64,") else {
+        return Err(GltfError::InvalidDataUri);
+    };
+    base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|_| GltfError::InvalidDataUri)
+}
+
+#[derive(Error, Display, Debug)]
+pub enum GltfError {
+    #[display(fmt="Invalid glTF: {_0}")]
+    Parse(gltf::Error),
+    #[display(fmt="External buffers are not supported: {_0}")]
+    #[error(ignore)]
+    ExternalBuffer(String),
+    #[display(fmt="Invalid data URI")]
+    InvalidDataUri,
+    #[display(fmt="Buffer refers to a missing binary chunk")]
+    MissingBinaryChunk,
+    #[display(fmt="Buffer is shorter than its declared length")]
+    BufferTooShort,
+    #[display(fmt="Unsupported image type: {_0}")]
+    #[error(ignore)]
+    UnsupportedImageType(String),
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::time::Duration;
+    use base64::Engine;
+    use glam::{Quat, Vec3};
+    use hecs::World;
+    use image::{DynamicImage, ImageFormat, RgbImage};
+    use wgpu::Face;
+    use crate::g3d::{spawn_gltf, BlendMode, Material, Mesh, Renderable};
+    use crate::math::Transform;
+    use crate::plugins::graphics::headless;
+    use crate::{parse_gltf, AssetManager, Color, EmbeddedProtocol, GltfError, GltfImage, GltfLoader, GltfScene, Readiness, Scene, Texture, TextureLoader, Tracker};
+    use super::insert_gltf_assets;
+
+    /// A textured triangle with normals, instanced by a parent node and its child.
+    fn triangle_gltf(buffer_uri: &str) -> String {
+        format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scene": 0,
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": [
+                {{ "name": "parent", "mesh": 0, "translation": [1, 2, 3], "children": [1] }},
+                {{ "mesh": 0, "scale": [2, 2, 2] }}
+            ],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0, "NORMAL": 1 }}, "material": 0 }}] }}],
+            "materials": [{{
+                "pbrMetallicRoughness": {{ "baseColorFactor": [1, 0, 0, 0.5], "roughnessFactor": 0.25, "baseColorTexture": {{ "index": 0 }} }},
+                "alphaMode": "BLEND"
+            }}],
+            "textures": [{{ "source": 0 }}],
+            "images": [{{ "uri": "checker.png" }}],
+            "buffers": [{{ "byteLength": 72, "uri": "{buffer_uri}" }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 36 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+                {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" }}
+            ]
+        }}"#)
+    }
+
+    fn triangle_buffer() -> Vec<u8> {
+        let floats: [f32; 18] = [
+            0.0, 0.0, 0.0,  1.0, 0.0, 0.0,  0.0, 1.0, 0.0,
+            0.0, 0.0, 1.0,  0.0, 0.0, 1.0,  0.0, 0.0, 1.0,
+        ];
+        bytemuck::cast_slice(&floats).to_vec()
+    }
+
+    #[test]
+    fn embedded_triangle() {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(triangle_buffer());
+        let gltf = triangle_gltf(&format!("data:application/octet-stream;base64,{encoded}"));
+        let data = parse_gltf(gltf.as_bytes()).unwrap();
+
+        assert_eq!(1, data.meshes.len());
+        let mesh = &data.meshes[0];
+        assert_eq!(vec![0, 1, 2], mesh.indices);
+        assert_eq!(vec![Vec3::ZERO, Vec3::X, Vec3::Y], mesh.positions);
+        assert_eq!(Some(vec![Vec3::Z; 3]), mesh.normals);
+        assert!(mesh.uvs.is_none());
+        assert!(mesh.colors.is_none());
+
+        assert_eq!(2, data.materials.len());
+        assert_eq!(Color::new(1.0, 0.0, 0.0, 0.5), data.materials[0].base_color);
+        assert_eq!(BlendMode::AlphaBlend, data.materials[0].blend_mode);
+        assert_eq!(None, data.materials[0].alpha_cutoff);
+        assert_eq!(Some(Face::Back), data.materials[0].cull_mode);
+        assert!(data.materials[0].pbr);
+        assert_eq!(0.25, data.materials[0].roughness);
+        assert_eq!(1.0, data.materials[0].metalness);
+
+        assert_eq!(2, data.primitives.len());
+        let (parent_mesh, parent_material, parent) = data.primitives[0];
+        let (child_mesh, _, child) = data.primitives[1];
+        assert_eq!((0, 0, 0), (parent_mesh, parent_material, child_mesh));
+        assert_eq!(Vec3::new(1.0, 2.0, 3.0), parent.translation);
+        assert_eq!(Vec3::new(1.0, 2.0, 3.0), child.translation);
+        assert!(child.scale.abs_diff_eq(Vec3::splat(2.0), 1e-6));
+        assert!(child.rotation.abs_diff_eq(Quat::IDENTITY, 1e-6));
+
+        assert_eq!(vec![GltfImage::Uri(String::from("checker.png"))], data.images);
+        assert_eq!(vec![Some(0), None], data.base_color_textures);
+        assert_eq!(vec![0], data.roots);
+        assert_eq!(2, data.nodes.len());
+        assert_eq!(Some("parent"), data.nodes[0].name.as_deref());
+        assert_eq!(Vec3::new(1.0, 2.0, 3.0), data.nodes[0].transform.translation);
+        assert_eq!((vec![(0, 0)], vec![1]), (data.nodes[0].primitives.clone(), data.nodes[0].children.clone()));
+        assert_eq!(Vec3::ZERO, data.nodes[1].transform.translation);
+        assert!(data.nodes[1].transform.scale.abs_diff_eq(Vec3::splat(2.0), 1e-6));
+    }
+
+    #[test]
+    fn scene_waits_for_textures_and_spawns_hierarchy() {
+        let Some((device, queue)) = headless::device() else { return };
+        let encoded = base64::engine::general_purpose::STANDARD.encode(triangle_buffer());
+        let gltf = triangle_gltf(&format!("data:application/octet-stream;base64,{encoded}"));
+        let mut checker = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(2, 2)).write_to(&mut Cursor::new(&mut checker), ImageFormat::Png).unwrap();
+        let mut assets = AssetManager::new();
+        assets.add_protocol(EmbeddedProtocol::new()
+            .with("models/triangle.gltf", Box::leak(gltf.into_bytes().into_boxed_slice()))
+            .with("models/checker.png", Box::leak(checker.into_boxed_slice())),
+            true,
+        );
+        assets.add_storage::<GltfScene>();
+        assets.add_storage::<Mesh>();
+        assets.add_storage::<Material>();
+        assets.add_storage::<Texture>();
+        assets.add_loader(GltfLoader { device: device.clone(), queue: queue.clone() }).unwrap();
+        assets.add_loader(TextureLoader::new(device, queue)).unwrap();
+        let handle = assets.load::<GltfScene, _>("models/triangle.gltf");
+        let mut waited_for_texture = false;
+        for _ in 0..100 {
+            assets.try_handle_messages();
+            insert_gltf_assets(&mut assets);
+            let is_inserted = assets.storage::<GltfScene>().unwrap().get(&handle).as_loaded().is_some_and(|scene| !scene.nodes.is_empty());
+            match assets.dyn_readiness(handle.id()) {
+                Readiness::NotReady if is_inserted => waited_for_texture = true,
+                Readiness::NotReady => {},
+                Readiness::Ready => break,
+                Readiness::Failed => panic!("Scene failed to load"),
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(waited_for_texture, "Scene was ready before its texture loaded");
+        let scenes = assets.storage::<GltfScene>().unwrap();
+        let scene = scenes.get(&handle).unwrap();
+        let materials = assets.storage::<Material>().unwrap();
+        let (_, material) = &scene.nodes[0].primitives[0];
+        assert_eq!(Some(scene.textures[0].id()), materials.get(material).unwrap().base_color_texture.as_ref().map(|texture| texture.id()));
+
+        // Only the parent is at the root of the scene graph
+        let mut scene_graph = Scene::<Renderable>::new();
+        let mut world = World::new();
+        let entities = spawn_gltf(scene, &mut scene_graph, &mut world);
+        assert_eq!(2, entities.len());
+        assert_eq!(2, scene_graph.len());
+        assert_eq!(1, scene_graph.root_ids().len());
+        let child = world.get::<&Tracker<Renderable>>(entities[1]).unwrap().id();
+        assert!(!scene_graph.root_ids().contains(&child));
+        assert_eq!(Vec3::new(1.0, 2.0, 3.0), world.get::<&Transform>(entities[0]).unwrap().translation);
+        assert!(scene_graph.get(child).unwrap().transform().scale.abs_diff_eq(Vec3::splat(2.0), 1e-6));
+    }
+
+    #[test]
+    fn external_buffers_are_rejected() {
+        let gltf = triangle_gltf("triangle.bin");
+        let result = parse_gltf(gltf.as_bytes());
+        assert!(matches!(result, Err(GltfError::ExternalBuffer(uri)) if uri == "triangle.bin"));
+    }
+}
@@ -0,0 +1,60 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/**
+ * Watches a directory, and all of its subdirectories, for files that were created or modified.
+ * Events are collected on the watcher's thread and drained with [`FileWatcher::changed_paths`].
+ */
+pub(crate) struct FileWatcher {
+    _watcher: RecommendedWatcher,
+    root: PathBuf,
+    receiver: Receiver<PathBuf>,
+}
+
+impl FileWatcher {
+
+    pub fn new(root: impl AsRef<Path>) -> notify::Result<Self> {
+        let root = root.as_ref().canonicalize()?;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+            let event = match result {
+                Ok(event) => event,
+                Err(err) => {
+                    log::error!("{err}");
+                    return;
+                },
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                return;
+            }
+            for path in event.paths {
+                let _ = sender.send(path);
+            }
+        })?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+        Ok(Self {
+            _watcher: watcher,
+            root,
+            receiver,
+        })
+    }
+
+    /// Paths of files that changed since the last call, relative to the root and separated by '/'.
+    /// A file that changed several times is only included once.
+    pub fn changed_paths(&self) -> Vec<String> {
+        let mut paths = Vec::new();
+        for path in self.receiver.try_iter() {
+            let Ok(relative) = path.strip_prefix(&self.root) else { continue };
+            let relative: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect();
+            let relative = relative.join("/");
+            if !paths.contains(&relative) {
+                paths.push(relative);
+            }
+        }
+        paths
+    }
+}
@@ -5,6 +5,8 @@ use crate::{Asset, AssetPath};
 /// Takes the contents of a file, and converts them into an asset.
 pub trait AssetLoader: Send + Sync + 'static {
     type AssetType: Asset;
+    /// Path is the one the asset was loaded with, without the path prefix of the [`AssetManager`](crate::AssetManager).
+    /// Paths resolved against it can be loaded as is.
     fn load(&self, bytes: &[u8], path: &AssetPath) -> anyhow::Result<Self::AssetType>;
     fn extensions(&self) -> &[&str];
 }
@@ -1,4 +1,4 @@
-use crate::HashMap;
+use crate::{HashMap, HashSet};
 use derive_more::*;
 use std::any::{Any, TypeId};
 use std::cell::RefCell;
@@ -6,20 +6,35 @@ use std::collections::hash_map::Entry;
 use std::marker::PhantomData;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
-use crate::{Asset, AssetId, AssetLoader, AssetPath, AssetStorage, AssetStorageMut, DynLoader, DynStorage, Handle, InnerAssetStorage, PathHash, Protocol};
+use crate::{Asset, AssetId, AssetIndex, AssetLoader, AssetPath, AssetStorage, AssetStorageMut, DynEvent, DynLoader, DynStorage, FolderHandle, Handle, InnerAssetStorage, PathHash, Protocol, Readiness};
+use super::workers::LoadWorkers;
+#[cfg(feature = "hot_reload")]
+use super::hot_reload::FileWatcher;
 
-/// Responsible for loading assets in a background thread and storing them in relevant storages.
+/// Priority of loads that were not given one.
+pub const DEFAULT_LOAD_PRIORITY: u8 = 128;
+
+/// Responsible for loading assets in background threads and storing them in relevant storages.
 pub struct AssetManager {
     path_prefix: Option<String>,
     protocols: HashMap<String, Arc<dyn Protocol>>,
     default_protocol: Option<String>,
     loaders: Vec<Arc<dyn DynLoader>>,
-    extension_to_loader: HashMap<String, usize>,
+    extension_to_loader: HashMap<String, Vec<usize>>,
+    fallback_loader: Option<Arc<dyn DynLoader>>,
+    fallbacks: HashMap<TypeId, (AssetIndex, Box<dyn Any>)>,  // Stand-ins for failed assets by type, with handles that keep them alive
     asset_storages: HashMap<TypeId, Box<dyn DynStorage>>,
     asset_metas: HashMap<AssetId, AssetMeta>,
     path_to_asset: HashMap<PathHash, AssetId>,
+    load_sources: HashMap<AssetId, LoadSource>,
+    reloading: HashSet<AssetId>,
+    event_sender: Option<Sender<DynEvent>>,             // Where events of finished loads are sent
     sender: Sender<AssetMessage>,
     receiver: Receiver<AssetMessage>,
+    workers: LoadWorkers,
+    pending_loads: usize,
+    #[cfg(feature = "hot_reload")]
+    file_watcher: Option<FileWatcher>,
 }
 
 impl AssetManager {
@@ -32,18 +47,60 @@ impl AssetManager {
             default_protocol: None,
             loaders: Vec::default(),
             extension_to_loader: HashMap::default(),
+            fallback_loader: None,
+            fallbacks: HashMap::default(),
             asset_storages: HashMap::default(),
             asset_metas: HashMap::default(),
             path_to_asset: HashMap::default(),
+            load_sources: HashMap::default(),
+            reloading: HashSet::default(),
+            event_sender: None,
             sender,
             receiver,
+            workers: LoadWorkers::new(),
+            pending_loads: 0,
+            #[cfg(feature = "hot_reload")]
+            file_watcher: None,
         }
     }
 
+    /// Sets the number of threads that assets are loaded on.
+    /// Defaults to 4.
+    pub fn set_worker_count(&mut self, worker_count: usize) {
+        self.workers.set_worker_count(worker_count);
+    }
+
+    pub fn worker_count(&self) -> usize {
+        self.workers.worker_count()
+    }
+
+    /// Number of assets that have started loading, but have not finished or failed.
+    /// An asset stops pending when its message is handled in [`AssetManager::try_handle_messages`].
+    /// Useful for showing progress on a loading screen.
+    pub fn pending_loads(&self) -> usize {
+        self.pending_loads
+    }
+
     pub fn set_path_prefix<S: Into<String>>(&mut self, prefix: Option<S>) {
         self.path_prefix = prefix.map(|s| s.into());
     }
 
+    pub fn path_prefix(&self) -> Option<&str> {
+        self.path_prefix.as_deref()
+    }
+
+    /**
+     * Watches the directory of the path prefix, or the working directory if there is none.
+     * Assets loaded from files that change are reloaded in [`AssetManager::try_handle_messages`].
+     * Stops watching the previous directory, if any.
+     */
+    #[cfg(feature = "hot_reload")]
+    pub fn watch_for_changes(&mut self) -> notify::Result<()> {
+        let root = self.path_prefix.as_deref().unwrap_or(".");
+        self.file_watcher = Some(FileWatcher::new(root)?);
+        Ok(())
+    }
+
     /// Adds an asset storage for the specified asset type.
     pub fn add_storage<A: Asset>(&mut self) {
         let asset_type = TypeId::of::<A>();
@@ -62,25 +119,71 @@ impl AssetManager {
     }
 
     /// Adds a loader for transforming file bytes into assets.
-    pub fn add_loader(&mut self, loader: impl AssetLoader) -> Result<(), LoadError> {
+    /// Several loaders may share an extension, as long as they load different asset types.
+    /// The loader used is the one whose asset type matches the type being loaded.
+    pub fn add_loader<L: AssetLoader>(&mut self, loader: L) -> Result<(), LoadError> {
+        let asset_type = TypeId::of::<L::AssetType>();
         for extension in loader.extensions() {
-            if self.extension_to_loader.contains_key(*extension) {
+            let Some(loader_indices) = self.extension_to_loader.get(*extension) else { continue };
+            if loader_indices.iter().any(|idx| self.loaders[*idx].asset_type() == asset_type) {
                 return Err(LoadError::ExtensionOverlaps);
             }
         }
         let loader_index = self.loaders.len();
         for extension in loader.extensions() {
-            self.extension_to_loader.insert(String::from(*extension), loader_index);
+            self.extension_to_loader
+                .entry(String::from(*extension))
+                .or_default()
+                .push(loader_index);
         }
         self.loaders.push(Arc::new(loader));
         Ok(())
     }
 
+    /// Sets a loader to use when no loader matches a path's extension.
+    /// Useful for loaders that inspect file contents rather than extensions.
+    /// Only used if its asset type matches the type being loaded.
+    /// Its extensions are ignored, so it never overlaps with other loaders.
+    pub fn set_fallback_loader(&mut self, loader: impl AssetLoader) {
+        self.fallback_loader = Some(Arc::new(loader));
+    }
+
+    /**
+     * Sets an asset to stand in for assets of type A that failed to load.
+     * Storages return the fallback instead of [`AssetState::Failed`](crate::AssetState::Failed), so that failures are visible rather than missing.
+     * Typically an asset loaded with the [`EmbeddedProtocol`](crate::EmbeddedProtocol), like an error texture.
+     */
+    pub fn fallback_for<A: Asset>(&mut self, handle: Handle<A>) {
+        self.fallbacks.insert(TypeId::of::<A>(), (handle.id().index, Box::new(handle)));
+    }
+
+    /// Fallback of assets of type A, if one was set.
+    pub fn fallback<A: Asset>(&self) -> Option<&Handle<A>> {
+        let (_, handle) = self.fallbacks.get(&TypeId::of::<A>())?;
+        handle.downcast_ref::<Handle<A>>()
+    }
+
+    /// Loader registered for an extension that loads the specified asset type.
+    fn loader_of(&self, extension: &str, asset_type: TypeId) -> Option<&Arc<dyn DynLoader>> {
+        self.extension_to_loader
+            .get(extension)?
+            .iter()
+            .map(|loader_idx| &self.loaders[*loader_idx])
+            .find(|loader| loader.asset_type() == asset_type)
+    }
+
     /// Inserts an asset manually, and returns a handle to it.
     pub fn insert<A: Asset>(&mut self, asset: A) -> Handle<A> {
         self.storage_mut::<A>().unwrap().insert(asset)
     }
 
+    /// Shares a handle, intended for use across a thread boundary.
+    /// Equivalent to handle.clone(), as the ref count is updated via the channel either way.
+    /// The name only signals intent.
+    pub fn share_handle<A: Asset>(&self, handle: &Handle<A>) -> Handle<A> {
+        handle.clone()
+    }
+
     /// Gets asset storage
     pub fn storage<A: Asset>(&self) -> Option<AssetStorage<A>> {
         let asset_type = TypeId::of::<A>();
@@ -92,6 +195,7 @@ impl AssetManager {
         Some(AssetStorage {
             inner: inner_cell.borrow_mut(),
             sender: &self.sender,
+            fallback: self.fallbacks.get(&asset_type).map(|(index, _)| *index),
         })
     }
 
@@ -105,11 +209,35 @@ impl AssetManager {
             .unwrap();
         Some(AssetStorageMut {
             inner: inner_cell.borrow_mut(),
+            fallback: self.fallbacks.get(&asset_type).map(|(index, _)| *index),
             metas: &mut self.asset_metas,
             sender: &mut self.sender,
         })
     }
 
+    /// Borrows the storage of the asset, and invokes f if the asset is loaded.
+    /// Storage is released before returning.
+    /// Returns None if the asset is not loaded, or if there is no storage for the asset type.
+    pub fn try_loaded_with<A, B, F>(&self, handle: &Handle<A>, f: F) -> Option<B>
+    where
+        A: Asset,
+        F: Fn(&A) -> B,
+    {
+        let storage = self.storage::<A>()?;
+        let asset = storage.get(handle).as_loaded().copied()?;
+        Some(f(asset))
+    }
+
+    /// Same as try_loaded_with, but returns the default value of B if the asset is not loaded.
+    pub fn loaded_or<A, B, F>(&self, handle: &Handle<A>, f: F) -> B
+    where
+        A: Asset,
+        B: Default,
+        F: Fn(&A) -> B,
+    {
+        self.try_loaded_with(handle, f).unwrap_or_default()
+    }
+
     /// Loads an asset in the background, and returns a handle.
     /// Contents of handle can be fetched from underlying storage once loading finishes.
     pub fn load<A, P>(&mut self, path: P) -> Handle<A>
@@ -120,6 +248,20 @@ impl AssetManager {
         self.try_load(path).unwrap()
     }
 
+    /**
+     * Loads an asset in the background, and returns a handle.
+     * Lower priority values load first, so 0 is used for essential assets, and 255 for background decorations.
+     * Loads that use no priority have a priority of [`DEFAULT_LOAD_PRIORITY`].
+     * Does nothing to the priority of an asset that is already loading.
+     */
+    pub fn load_with_priority<A, P>(&mut self, path: P, priority: u8) -> Handle<A>
+    where
+        A: Asset,
+        P: AsRef<str>,
+    {
+        self.try_load_with_priority(path, priority).unwrap()
+    }
+
     /// Loads an asset in the background, and returns a handle.
     /// Contents of handle can be fetched from underlying storage once loading finishes.
     /// Assumes that path_hash is the correct hash of path.
@@ -139,34 +281,61 @@ impl AssetManager {
         self.try_fast_load(path, path_hash)
     }
 
+    /// Same as load_with_priority, but returns an error instead of panicking.
+    pub fn try_load_with_priority<A, P>(&mut self, path: P, priority: u8) -> Result<Handle<A>, LoadError>
+    where
+        A: Asset,
+        P: AsRef<str>,
+    {
+        let path = path.as_ref();
+        let path_hash = PathHash::of(path);
+        self.try_fast_load_with_priority(path, path_hash, priority)
+    }
+
     /// Loads an asset in the background, and returns a handle.
     /// Contents of handle can be fetched from underlying storage once loading finishes.
     /// Assumes that path_hash is the hash of path.
     pub fn try_fast_load<A: Asset>(&mut self, path: &str, path_hash: PathHash) -> Result<Handle<A>, LoadError> {
+        self.try_fast_load_with_priority(path, path_hash, DEFAULT_LOAD_PRIORITY)
+    }
+
+    /// Same as try_fast_load, but with a priority.
+    /// See [`AssetManager::load_with_priority`].
+    pub fn try_fast_load_with_priority<A: Asset>(&mut self, path: &str, path_hash: PathHash, priority: u8) -> Result<Handle<A>, LoadError> {
+        let asset_id = self.dyn_load(path, path_hash, TypeId::of::<A>(), priority)?;
+        Ok(Handle::new(asset_id, self.sender.clone()))
+    }
+
+    /// Loads an asset of the type specified, and counts a reference to it.
+    /// The caller is responsible for wrapping the id in something that releases the reference.
+    fn dyn_load(&mut self, path: &str, path_hash: PathHash, asset_type: TypeId, priority: u8) -> Result<AssetId, LoadError> {
         
-        // Returns cloned handle if already stored.
-        let asset_type = TypeId::of::<A>();
+        // Counts a reference if already stored.
         if let Some(asset_id) = self.path_to_asset.get(&path_hash) {
             if asset_id.asset_type != asset_type {
                 return Err(LoadError::IncorrectAssetType);
             }
             let asset_meta = self.asset_metas.get_mut(asset_id).unwrap();
             asset_meta.ref_count += 1;
-            return Ok(Handle::new(*asset_id, self.sender.clone()));
+            return Ok(*asset_id);
         }
 
         // Parses path, and uses it to fetch protocol and loader.
-        let mut path = AssetPath::parse(path, self.default_protocol.as_deref())?;
-        if let Some(path_prefix) = &self.path_prefix {
-            path.body = format!("{}/{}", path_prefix, path.body);
-        }
+        let asset_path = AssetPath::parse(path, self.default_protocol.as_deref())?;
+        let mut path = asset_path.clone();
         let protocol = match self.protocols.get_mut(&path.protocol) {
             Some(protocol) => protocol.clone(),
             None => return Err(LoadError::NoSuchProtocol),
         };
-        let loader = match self.extension_to_loader.get(&path.extension) {
-            Some(loader_idx) => self.loaders[*loader_idx].clone(),
-            None => return Err(LoadError::NoSuchLoader),
+        if let (Some(path_prefix), true) = (&self.path_prefix, protocol.uses_path_prefix()) {
+            path.body = format!("{}/{}", path_prefix, path.body);
+        }
+        let loader = match self.loader_of(&path.extension, asset_type) {
+            Some(loader) => loader.clone(),
+            None => match &self.fallback_loader {
+                Some(loader) if loader.asset_type() == asset_type => loader.clone(),
+                _ => return Err(LoadError::NoSuchLoader),
+            },
         };
         
         // Inserts new handle in "loading" state.
@@ -180,34 +349,191 @@ impl AssetManager {
             path_hash: Some(path_hash),
             ref_count: 1,
         });
+        let source = LoadSource { path, asset_path, protocol, loader, priority };
+        self.load_sources.insert(asset_id, source.clone());
+        self.load_in_background(asset_id, source);
+        Ok(asset_id)
+    }
+
+    /// Loads every file in a directory that a loader of A supports, and returns their handles.
+    /// Handles are typically stored in a [`PreloadSet`](crate::PreloadSet) to keep the assets alive until needed.
+    pub fn preload_dir<A: Asset>(&mut self, dir: &str) -> Vec<Handle<A>> {
+        self.try_preload_dir(dir).unwrap()
+    }
+
+    /**
+     * Same as preload_dir, but returns an error instead of panicking.
+     * Files in subdirectories are not loaded.
+     */
+    pub fn try_preload_dir<A: Asset>(&mut self, dir: &str) -> Result<Vec<Handle<A>>, LoadError> {
+        self.load_folder(dir, false)
+    }
+
+    /**
+     * Loads every file in a directory that a loader of A supports, and returns their handles.
+     * The directory is listed with the protocol of its path, or the default protocol if it has none.
+     * If recursive, files in subdirectories are loaded too, if the protocol can list them.
+     */
+    pub fn load_folder<A: Asset>(&mut self, dir: &str, recursive: bool) -> Result<Vec<Handle<A>>, LoadError> {
+        let asset_type = TypeId::of::<A>();
+        let mut handles = Vec::new();
+        for (path, extension) in self.list_folder(dir, recursive)? {
+            if self.loader_of(&extension, asset_type).is_none() { continue }
+            handles.push(self.try_load(path)?);
+        }
+        Ok(handles)
+    }
+
+    /**
+     * Loads every file in a directory that any loader supports, each into the storage of its loader's asset type.
+     * When several loaders share an extension, the one added first is used.
+     * Returns a single handle to all of them, which is useful for waiting on a loading screen.
+     * See [`AssetManager::load_folder`].
+     */
+    pub fn load_mixed_folder(&mut self, dir: &str, recursive: bool) -> Result<FolderHandle, LoadError> {
+        let mut ids = Vec::new();
+        for (path, extension) in self.list_folder(dir, recursive)? {
+            let path_hash = PathHash::of(&path);
+            let asset_type = match self.path_to_asset.get(&path_hash) {
+                Some(asset_id) => asset_id.asset_type,
+                None => {
+                    let Some(loader_indices) = self.extension_to_loader.get(&extension) else { continue };
+                    self.loaders[loader_indices[0]].asset_type()
+                },
+            };
+            ids.push(self.dyn_load(&path, path_hash, asset_type, DEFAULT_LOAD_PRIORITY)?);
+        }
+        Ok(FolderHandle::new(ids, self.sender.clone()))
+    }
+
+    /// Paths and extensions of files in a directory, listed with the protocol of its path.
+    /// Paths keep the directory's protocol, and do not include the path prefix.
+    fn list_folder(&self, dir: &str, recursive: bool) -> Result<Vec<(String, String)>, LoadError> {
+        let (protocol_prefix, body) = match dir.split_once("://") {
+            Some((protocol, body)) => (Some(protocol), body),
+            None => (None, dir),
+        };
+        let protocol_name = match protocol_prefix.or(self.default_protocol.as_deref()) {
+            Some(protocol_name) => protocol_name,
+            None => return Err(LoadError::NoDefaultProtocol),
+        };
+        let protocol = match self.protocols.get(protocol_name) {
+            Some(protocol) => protocol.as_ref(),
+            None => return Err(LoadError::NoSuchProtocol),
+        };
+        let mut files = Vec::new();
+        let mut dirs = vec![String::from(body.trim_end_matches('/'))];
+        while let Some(body) = dirs.pop() {
+            let path_prefix = self.path_prefix.as_ref().filter(|_| protocol.uses_path_prefix());
+            let listed_dir = match path_prefix {
+                Some(path_prefix) if body.is_empty() => path_prefix.clone(),
+                Some(path_prefix) => format!("{path_prefix}/{body}"),
+                None => body.clone(),
+            };
+            let listed = protocol.list(&listed_dir).and_then(|names| match recursive {
+                true => Ok((names, protocol.list_dirs(&listed_dir)?)),
+                false => Ok((names, Vec::new())),
+            });
+            let (names, dir_names) = match listed {
+                Ok(listed) => listed,
+                Err(err) => {
+                    log::error!("{err}");
+                    return Err(LoadError::ListFailed);
+                },
+            };
+            let join = |name: &str| match body.is_empty() {
+                true => String::from(name),
+                false => format!("{body}/{name}"),
+            };
+            for name in names {
+                let Some((_, extension)) = name.split_once('.') else { continue };
+                let path = match protocol_prefix {
+                    Some(protocol) => format!("{protocol}://{}", join(&name)),
+                    None => join(&name),
+                };
+                files.push((path, String::from(extension)));
+            }
+            dirs.extend(dir_names.iter().rev().map(|dir_name| join(dir_name)));
+        }
+        Ok(files)
+    }
+
+    /// Readiness of any asset, or failed if there is no such asset.
+    pub(crate) fn dyn_readiness(&self, asset_id: AssetId) -> Readiness {
+        match self.asset_storages.get(&asset_id.asset_type) {
+            Some(storage) => storage.readiness(asset_id.index, self),
+            None => Readiness::Failed,
+        }
+    }
 
-        // Loads asset in background thread.
-        let sender = self.sender.clone();
-        std::thread::spawn(move || {
+    /**
+     * Reloads the asset that was loaded from the path in the background, and returns true.
+     * The asset goes back to the loading state, and existing handles see the new value once it finishes.
+     * Returns false if no asset was loaded from the path.
+     */
+    pub fn reload(&mut self, path_hash: PathHash) -> bool {
+        let Some(asset_id) = self.path_to_asset.get(&path_hash).copied() else { return false };
+        let Some(source) = self.load_sources.get(&asset_id).cloned() else { return false };
+        let storage = self.asset_storages.get_mut(&asset_id.asset_type).unwrap();
+        storage.start_loading(asset_id.index);
+        self.reloading.insert(asset_id);
+        self.load_in_background(asset_id, source);
+        true
+    }
+
+    /// Reads and loads an asset on a worker thread.
+    /// The load counts as pending until the worker reports how it ended, which it does even if the protocol or loader panics.
+    fn load_in_background(&mut self, asset_id: AssetId, source: LoadSource) {
+        let LoadSource { path, asset_path, protocol, loader, priority } = source;
+        let guard = LoadGuard { asset_id, sender: Some(self.sender.clone()) };
+        self.pending_loads += 1;
+        self.workers.submit(Box::new(move || {
             let bytes = match protocol.read(&path) {
                 Ok(asset_bytes) => asset_bytes,
                 Err(err) => {
                     log::error!("{err}");
-                    let _ = sender.send(AssetMessage::AssetFailedLoading(asset_id));
+                    guard.send(AssetMessage::AssetFailedLoading(asset_id));
                     return;
                 },
             };
-            let dyn_asset = match loader.dyn_load(&bytes, &path) {
+            let dyn_asset = match loader.dyn_load(&bytes, &asset_path) {
                 Ok(dyn_asset) => dyn_asset,
                 Err(err) => {
                     log::error!("{err}");
-                    let _ = sender.send(AssetMessage::AssetFailedLoading(asset_id));
+                    guard.send(AssetMessage::AssetFailedLoading(asset_id));
                     return;
                 },
             };
-            let _ = sender.send(AssetMessage::AssetFinishedLoading(asset_id, dyn_asset));
-        });
+            guard.send(AssetMessage::AssetFinishedLoading(asset_id, dyn_asset));
+        }), priority);
+    }
 
-        Ok(Handle {
-            id: asset_id,
-            sender: self.sender.clone(),
-            phantom: PhantomData,
-        })
+    /// Reloads assets whose files changed since the last call.
+    #[cfg(feature = "hot_reload")]
+    fn reload_changed_files(&mut self) {
+        let Some(file_watcher) = &self.file_watcher else { return };
+        for path in file_watcher.changed_paths() {
+            if self.reload(PathHash::of(&path)) {
+                log::info!("Reloading asset {path}");
+            }
+        }
+    }
+
+    /**
+     * Sets where events are sent when assets finish loading, fail loading or finish reloading.
+     * These are [`AssetLoadedEvent`](crate::AssetLoadedEvent), [`AssetFailedEvent`](crate::AssetFailedEvent) and [`AssetReloadedEvent`](crate::AssetReloadedEvent) of the asset's type.
+     */
+    pub(crate) fn set_event_sender(&mut self, event_sender: Sender<DynEvent>) {
+        self.event_sender = Some(event_sender);
+    }
+
+    /// Sends the event of a load's outcome, if there is an event sender.
+    fn send_event(&self, asset_id: AssetId, outcome: LoadOutcome) {
+        let Some(event_sender) = &self.event_sender else { return };
+        let storage = &self.asset_storages[&asset_id.asset_type];
+        if event_sender.send(storage.event(asset_id, outcome)).is_err() {
+            log::warn!("Dropped event of asset {asset_id:?}, as its receiver is gone");
+        }
     }
 
     /// Handles messages enqueued in storages.
@@ -215,6 +541,8 @@ impl AssetManager {
     /// This discards assets that have no more references.
     /// Acts as a sort of "garbage-collection" phase where the the user specifies when it runs.
     pub fn try_handle_messages(&mut self) -> u32 {
+        #[cfg(feature = "hot_reload")]
+        self.reload_changed_files();
         let mut count = 0;
         for message in self.receiver.try_iter() {
             count += 1;
@@ -242,16 +570,28 @@ impl AssetManager {
                         if let Some(path_hash) = asset_meta.path_hash {
                             self.path_to_asset.remove(&path_hash);
                         }
+                        self.load_sources.remove(&asset_id);
                         asset_meta_entry.remove();
                     }
                 },
                 AssetMessage::AssetFinishedLoading(asset_id, dyn_asset) => {
+                    self.pending_loads -= 1;
+                    if !self.asset_metas.contains_key(&asset_id) { continue }
                     let storage = self.asset_storages.get_mut(&asset_id.asset_type).unwrap();
                     storage.finish_loading(asset_id.index, dyn_asset);
+                    let outcome = match self.reloading.remove(&asset_id) {
+                        true => LoadOutcome::Reloaded,
+                        false => LoadOutcome::Loaded,
+                    };
+                    self.send_event(asset_id, outcome);
                 },
                 AssetMessage::AssetFailedLoading(asset_id) => {
+                    self.pending_loads -= 1;
+                    self.reloading.remove(&asset_id);
+                    if !self.asset_metas.contains_key(&asset_id) { continue }
                     let storage = self.asset_storages.get_mut(&asset_id.asset_type).unwrap();
                     storage.fail_loading(asset_id.index);
+                    self.send_event(asset_id, LoadOutcome::Failed);
                 },
             }
         }
@@ -274,6 +614,86 @@ pub(crate) enum AssetMessage {
     AssetFinishedLoading(AssetId, Box<dyn Any + Send + Sync + 'static>),
 }
 
+/// Reports a background load as failed if it is dropped before reporting how it ended.
+/// This happens when the protocol or loader panics, or when the job never runs.
+struct LoadGuard {
+    asset_id: AssetId,
+    sender: Option<Sender<AssetMessage>>,   // None once the outcome was sent
+}
+
+impl LoadGuard {
+    fn send(mut self, message: AssetMessage) {
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.send(message);
+        }
+    }
+}
+
+impl Drop for LoadGuard {
+    fn drop(&mut self) {
+        let Some(sender) = self.sender.take() else { return };
+        log::error!("Loading of asset {:?} ended without an outcome", self.asset_id);
+        let _ = sender.send(AssetMessage::AssetFailedLoading(self.asset_id));
+    }
+}
+
+/// Everything needed to load an asset again.
+#[derive(Clone)]
+struct LoadSource {
+    path: AssetPath,            // Path read by the protocol, with the path prefix if it uses one
+    asset_path: AssetPath,      // Path given to the loader, as it was loaded
+    protocol: Arc<dyn Protocol>,
+    loader: Arc<dyn DynLoader>,
+    priority: u8,
+}
+
+/// How a background load ended.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub(crate) enum LoadOutcome { Loaded, Failed, Reloaded }
+
+/// Fired when an asset of type A finishes loading for the first time.
+pub struct AssetLoadedEvent<A> {
+    pub id: AssetId,
+    phantom: PhantomData<A>,
+}
+
+/// Fired when an asset of type A fails to load or reload.
+pub struct AssetFailedEvent<A> {
+    pub id: AssetId,
+    phantom: PhantomData<A>,
+}
+
+/// Fired when an asset of type A finishes reloading, after its file changed or [`AssetManager::reload`] was called.
+pub struct AssetReloadedEvent<A> {
+    pub id: AssetId,
+    phantom: PhantomData<A>,
+}
+
+macro_rules! impl_asset_event {
+    ($event:ident) => {
+        impl<A> $event<A> {
+            pub(crate) fn new(id: AssetId) -> Self {
+                Self { id, phantom: PhantomData }
+            }
+
+            /// True if the asset is the one the handle points to.
+            pub fn is(&self, handle: &Handle<A>) -> bool {
+                self.id == handle.id()
+            }
+        }
+
+        impl<A> Clone for $event<A> {
+            fn clone(&self) -> Self {
+                Self::new(self.id)
+            }
+        }
+    };
+}
+
+impl_asset_event!(AssetLoadedEvent);
+impl_asset_event!(AssetFailedEvent);
+impl_asset_event!(AssetReloadedEvent);
+
 #[derive(Error, Debug, Display, Clone, Eq, PartialEq)]
 pub enum LoadError {
     #[display(fmt="Incorrect asset type")]
@@ -290,6 +710,8 @@ pub enum LoadError {
     PathMissingExtension,
     #[display(fmt="Supported extension of one loader overlaps with another")]
     ExtensionOverlaps,
+    #[display(fmt="Failed to list directory")]
+    ListFailed,
 }
 
 #[derive(Debug)]
@@ -298,6 +720,410 @@ pub(crate) struct AssetMeta {
     pub ref_count: u32,
 }
 
+#[cfg(test)]
+mod test_accessors {
+    use std::any::TypeId;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+    use crate::{Asset, AssetFailedEvent, AssetLoadedEvent, AssetLoader, AssetManager, AssetPath, AssetReloadedEvent, AssetState, DynEvent, EmbeddedProtocol, Handle, PathHash, Protocol, RawProtocol, Readiness};
+    use super::LoadError;
+
+    struct Number(u32);
+    impl Asset for Number {}
+
+    struct NumberLoader;
+    impl AssetLoader for NumberLoader {
+        type AssetType = Number;
+        fn load(&self, bytes: &[u8], _path: &AssetPath) -> anyhow::Result<Self::AssetType> {
+            Ok(Number(std::str::from_utf8(bytes)?.parse()?))
+        }
+        fn extensions(&self) -> &[&str] {
+            &["num"]
+        }
+    }
+
+    #[test]
+    fn try_loaded_with() {
+        let mut manager = AssetManager::new();
+        manager.add_storage::<Number>();
+        let handle = manager.insert(Number(5));
+        assert_eq!(Some(10), manager.try_loaded_with(&handle, |number| number.0 * 2));
+        assert_eq!(6, manager.loaded_or(&handle, |number| number.0 + 1));
+    }
+
+    #[test]
+    fn try_loaded_with_loading() {
+        let mut manager = AssetManager::new();
+        manager.add_storage::<Number>();
+        let handle = manager.insert(Number(5));
+        {
+            let mut storage = manager.storage_mut::<Number>().unwrap();
+            *storage.inner.get_mut(handle.id().index).unwrap() = AssetState::Loading;
+        }
+        assert_eq!(None, manager.try_loaded_with(&handle, |number| number.0 * 2));
+        assert_eq!(0, manager.loaded_or(&handle, |number| number.0 + 1));
+    }
+
+    #[test]
+    fn fallback_loader() {
+        let mut manager = AssetManager::new();
+        manager.add_storage::<Number>();
+        manager.add_protocol(RawProtocol::from("7"), true);
+        assert_eq!(Some(LoadError::NoSuchLoader), manager.try_load::<Number, _>("seven.bin").err());
+
+        // Fallback does not overlap with loaders of the same extensions.
+        manager.set_fallback_loader(NumberLoader);
+        manager.add_loader(NumberLoader).unwrap();
+        let handle = manager.try_load::<Number, _>("seven.bin").unwrap();
+        for _ in 0..100 {
+            manager.try_handle_messages();
+            if let Some(number) = manager.try_loaded_with(&handle, |number| number.0) {
+                assert_eq!(7, number);
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Asset did not finish loading");
+    }
+
+    struct Word(String);
+    impl Asset for Word {}
+
+    struct WordLoader;
+    impl AssetLoader for WordLoader {
+        type AssetType = Word;
+        fn load(&self, bytes: &[u8], _path: &AssetPath) -> anyhow::Result<Self::AssetType> {
+            Ok(Word(String::from(std::str::from_utf8(bytes)?)))
+        }
+        fn extensions(&self) -> &[&str] {
+            &["num", "word"]
+        }
+    }
+
+    #[test]
+    fn loaders_share_extension() {
+        let mut manager = AssetManager::new();
+        manager.add_storage::<Number>();
+        manager.add_storage::<Word>();
+        manager.add_protocol(RawProtocol::from("7"), true);
+        manager.add_loader(NumberLoader).unwrap();
+        manager.add_loader(WordLoader).unwrap();
+        assert_eq!(Some(LoadError::ExtensionOverlaps), manager.add_loader(NumberLoader).err());
+
+        // Each type is loaded by its own loader.
+        let number = manager.load::<Number, _>("number.num");
+        let word = manager.load::<Word, _>("word.num");
+        for _ in 0..100 {
+            manager.try_handle_messages();
+            let loaded_number = manager.try_loaded_with(&number, |number| number.0);
+            let loaded_word = manager.try_loaded_with(&word, |word| word.0.clone());
+            if let (Some(loaded_number), Some(loaded_word)) = (loaded_number, loaded_word) {
+                assert_eq!(7, loaded_number);
+                assert_eq!("7", loaded_word);
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Assets did not finish loading");
+    }
+
+    /// Echoes the body of the path, after a delay that varies by path.
+    struct EchoProtocol;
+    impl Protocol for EchoProtocol {
+        fn name(&self) -> &str { "echo" }
+        fn read(&self, path: &AssetPath) -> anyhow::Result<Vec<u8>> {
+            let number: u64 = path.body.parse()?;
+            std::thread::sleep(Duration::from_millis(number * 7 % 13));
+            Ok(path.body.clone().into_bytes())
+        }
+    }
+
+    #[test]
+    fn many_concurrent_loads() {
+        let mut manager = AssetManager::new();
+        manager.add_storage::<Number>();
+        manager.add_protocol(EchoProtocol, true);
+        manager.add_loader(NumberLoader).unwrap();
+        manager.set_worker_count(3);
+        let handles: Vec<_> = (0..50)
+            .map(|i| manager.load::<Number, _>(format!("{i}.num")))
+            .collect();
+        assert_eq!(50, manager.pending_loads());
+        for _ in 0..500 {
+            manager.try_handle_messages();
+            if manager.pending_loads() == 0 {
+                for (i, handle) in handles.iter().enumerate() {
+                    assert_eq!(Some(i as u32), manager.try_loaded_with(handle, |number| number.0));
+                }
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Assets did not finish loading");
+    }
+
+    /// Lists a fixed set of files, and reads the number in each file's name.
+    struct ListingProtocol;
+    impl Protocol for ListingProtocol {
+        fn name(&self) -> &str { "listing" }
+        fn read(&self, path: &AssetPath) -> anyhow::Result<Vec<u8>> {
+            let (_, name) = path.body.rsplit_once('/').unwrap();
+            Ok(name.to_owned().into_bytes())
+        }
+        fn list(&self, dir: &str) -> anyhow::Result<Vec<String>> {
+            match dir {
+                "assets/numbers" => Ok(vec!["1.num".into(), "2.num".into(), "readme.txt".into(), "3".into()]),
+                "assets/numbers/more" => Ok(vec!["4.num".into(), "hello.word".into()]),
+                _ => anyhow::bail!("No such directory"),
+            }
+        }
+        fn list_dirs(&self, dir: &str) -> anyhow::Result<Vec<String>> {
+            match dir {
+                "assets/numbers" => Ok(vec!["more".into()]),
+                _ => Ok(Vec::new()),
+            }
+        }
+    }
+
+    #[test]
+    fn preload_dir() {
+        let mut manager = AssetManager::new();
+        manager.add_storage::<Number>();
+        manager.add_protocol(ListingProtocol, true);
+        manager.add_protocol(RawProtocol::from("1"), false);
+        manager.add_loader(NumberLoader).unwrap();
+        manager.set_path_prefix(Some("assets"));
+        assert_eq!(Some(LoadError::ListFailed), manager.try_preload_dir::<Number>("missing").err());
+        assert_eq!(Some(LoadError::ListFailed), manager.try_preload_dir::<Number>("raw://numbers").err());
+
+        let handles = manager.preload_dir::<Number>("numbers/");
+        assert_eq!(2, handles.len());
+        for _ in 0..100 {
+            manager.try_handle_messages();
+            if manager.pending_loads() == 0 {
+                assert_eq!(Some(1), manager.try_loaded_with(&handles[0], |number| number.0));
+                assert_eq!(Some(2), manager.try_loaded_with(&handles[1], |number| number.0));
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Assets did not finish loading");
+    }
+
+    #[test]
+    fn load_folder_recursive() {
+        let mut manager = AssetManager::new();
+        manager.add_storage::<Number>();
+        manager.add_protocol(ListingProtocol, true);
+        manager.add_loader(NumberLoader).unwrap();
+        manager.set_path_prefix(Some("assets"));
+        let handles = manager.load_folder::<Number>("listing://numbers", true).unwrap();
+        assert_eq!(3, handles.len());
+        for _ in 0..100 {
+            manager.try_handle_messages();
+            if manager.pending_loads() == 0 {
+                let numbers: Vec<_> = handles.iter()
+                    .map(|handle| manager.try_loaded_with(handle, |number| number.0))
+                    .collect();
+                assert_eq!(vec![Some(1), Some(2), Some(4)], numbers);
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Assets did not finish loading");
+    }
+
+    #[test]
+    fn load_mixed_folder() {
+        let mut manager = AssetManager::new();
+        manager.add_storage::<Number>();
+        manager.add_storage::<Word>();
+        manager.add_protocol(ListingProtocol, true);
+        manager.add_loader(NumberLoader).unwrap();
+        manager.add_loader(WordLoader).unwrap();
+        manager.set_path_prefix(Some("assets"));
+        let folder = manager.load_mixed_folder("numbers", true).unwrap();
+        assert_eq!(4, folder.ids().len());
+        assert_eq!(Readiness::NotReady, folder.readiness(&manager));
+        for _ in 0..100 {
+            manager.try_handle_messages();
+            if folder.readiness(&manager) == Readiness::Ready {
+                assert_eq!(3, folder.handles::<Number>().len());
+                let words = folder.handles::<Word>();
+                assert_eq!(Some(String::from("hello")), manager.try_loaded_with(&words[0], |word| word.0.clone()));
+
+                // Dropping every handle frees the assets
+                drop(words);
+                drop(folder);
+                manager.try_handle_messages();
+                assert_eq!(0, manager.storage::<Number>().unwrap().len());
+                assert_eq!(0, manager.storage::<Word>().unwrap().len());
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Assets did not finish loading");
+    }
+
+    #[test]
+    fn embedded_protocol() {
+        let mut manager = AssetManager::new();
+        manager.add_storage::<Number>();
+        manager.add_protocol(FailingProtocol, true);
+        manager.add_protocol(EmbeddedProtocol::new()
+            .with("numbers/1.num", b"1")
+            .with("numbers/more/2.num", b"2"), false);
+        manager.add_loader(NumberLoader).unwrap();
+        manager.set_path_prefix(Some("assets"));
+
+        // Ignores the path prefix
+        let handles = manager.load_folder::<Number>("embedded://numbers", true).unwrap();
+        assert_eq!(2, handles.len());
+        for _ in 0..100 {
+            manager.try_handle_messages();
+            if manager.pending_loads() == 0 {
+                assert_eq!(Some(1), manager.try_loaded_with(&handles[0], |number| number.0));
+                assert_eq!(Some(2), manager.try_loaded_with(&handles[1], |number| number.0));
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Assets did not finish loading");
+    }
+
+    #[test]
+    fn fallback() {
+        let mut manager = AssetManager::new();
+        manager.add_storage::<Number>();
+        manager.add_protocol(FailingProtocol, true);
+        manager.add_loader(NumberLoader).unwrap();
+        let missing = manager.load::<Number, _>("missing.num");
+        let fallback = manager.insert(Number(404));
+        manager.fallback_for::<Number>(fallback);
+        assert_eq!(Some(404), manager.fallback::<Number>().and_then(|handle| manager.try_loaded_with(handle, |number| number.0)));
+        for _ in 0..100 {
+            manager.try_handle_messages();
+            if manager.pending_loads() == 0 {
+                assert_eq!(Some(404), manager.try_loaded_with(&missing, |number| number.0));
+                let mut storage = manager.storage_mut::<Number>().unwrap();
+                assert!(storage.get_mut(&missing).is_failed());
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Asset did not fail loading");
+    }
+
+    /// Reads a number that goes up by one on every read.
+    struct CounterProtocol(AtomicU32);
+    impl Protocol for CounterProtocol {
+        fn name(&self) -> &str { "counter" }
+        fn read(&self, _path: &AssetPath) -> anyhow::Result<Vec<u8>> {
+            let number = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(number.to_string().into_bytes())
+        }
+    }
+
+    fn wait_for_number(manager: &mut AssetManager, handle: &Handle<Number>) -> u32 {
+        for _ in 0..100 {
+            manager.try_handle_messages();
+            if let Some(number) = manager.try_loaded_with(handle, |number| number.0) {
+                return number;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Asset did not finish loading");
+    }
+
+    #[test]
+    fn reload() {
+        let mut manager = AssetManager::new();
+        manager.add_storage::<Number>();
+        manager.add_protocol(CounterProtocol(AtomicU32::new(0)), true);
+        manager.add_loader(NumberLoader).unwrap();
+        let (event_sender, event_receiver) = std::sync::mpsc::channel();
+        manager.set_event_sender(event_sender);
+        let handle = manager.load::<Number, _>("count.num");
+        assert_eq!(1, wait_for_number(&mut manager, &handle));
+        let events: Vec<DynEvent> = event_receiver.try_iter().collect();
+        assert_eq!(1, events.len());
+        assert_eq!(TypeId::of::<AssetLoadedEvent<Number>>(), events[0].type_id);
+
+        assert!(!manager.reload(PathHash::of("other.num")));
+        assert!(manager.reload(PathHash::of("count.num")));
+        assert_eq!(None, manager.try_loaded_with(&handle, |number| number.0));
+        assert_eq!(2, wait_for_number(&mut manager, &handle));
+        let events: Vec<DynEvent> = event_receiver.try_iter().collect();
+        assert_eq!(1, events.len());
+        assert_eq!(TypeId::of::<AssetReloadedEvent<Number>>(), events[0].type_id);
+    }
+
+    /// Protocol that fails every read.
+    struct FailingProtocol;
+    impl Protocol for FailingProtocol {
+        fn name(&self) -> &str { "failing" }
+        fn read(&self, _path: &AssetPath) -> anyhow::Result<Vec<u8>> {
+            anyhow::bail!("Read failed")
+        }
+    }
+
+    #[test]
+    fn failed_event() {
+        let mut manager = AssetManager::new();
+        manager.add_storage::<Number>();
+        manager.add_protocol(FailingProtocol, true);
+        manager.add_loader(NumberLoader).unwrap();
+        let (event_sender, event_receiver) = std::sync::mpsc::channel();
+        manager.set_event_sender(event_sender);
+        let handle = manager.load::<Number, _>("missing.num");
+        for _ in 0..100 {
+            manager.try_handle_messages();
+            if let Ok(event) = event_receiver.try_recv() {
+                let event = event.event.downcast_ref::<AssetFailedEvent<Number>>().unwrap();
+                assert!(event.is(&handle));
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Asset did not fail loading");
+    }
+
+    struct PanickingLoader;
+    impl AssetLoader for PanickingLoader {
+        type AssetType = Number;
+        fn load(&self, _bytes: &[u8], _path: &AssetPath) -> anyhow::Result<Self::AssetType> {
+            panic!("Loader panicked")
+        }
+        fn extensions(&self) -> &[&str] {
+            &["num"]
+        }
+    }
+
+    #[test]
+    fn panicking_loader_fails() {
+        let mut manager = AssetManager::new();
+        manager.add_storage::<Number>();
+        manager.add_protocol(RawProtocol::from("7"), true);
+        manager.add_loader(PanickingLoader).unwrap();
+        let (event_sender, event_receiver) = std::sync::mpsc::channel();
+        manager.set_event_sender(event_sender);
+        let handle = manager.load::<Number, _>("seven.num");
+        assert_eq!(1, manager.pending_loads());
+        for _ in 0..100 {
+            manager.try_handle_messages();
+            if manager.pending_loads() == 0 {
+                assert!(manager.storage::<Number>().unwrap().get(&handle).is_failed());
+                let events: Vec<DynEvent> = event_receiver.try_iter().collect();
+                assert_eq!(1, events.len());
+                assert!(events[0].event.downcast_ref::<AssetFailedEvent<Number>>().unwrap().is(&handle));
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Load is still pending");
+    }
+}
+
 // #[cfg(test)]
 // mod test {
 //     use std::time::Duration;
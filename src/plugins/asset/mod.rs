@@ -4,6 +4,11 @@ mod protocol;
 mod path_parts;
 mod loader;
 mod manager;
+mod workers;
+mod preload;
+mod gltf;
+#[cfg(feature = "hot_reload")]
+mod hot_reload;
 
 pub use storage::*;
 pub use asset::*;
@@ -11,22 +16,71 @@ pub use protocol::*;
 pub use path_parts::*;
 pub use loader::*;
 pub use manager::*;
+pub use preload::*;
+pub use self::gltf::*;
 
 use crate::{AppBuilder, Game, Plugin, RunContext, Stage};
 
 
-pub struct AssetPlugin;
+/// Adds an [`AssetManager`] that loads files from the "assets" directory, and embedded files from the [`EmbeddedProtocol`].
+/// Fires an [`AssetLoadedEvent`] or [`AssetFailedEvent`] when a load finishes.
+/// Also adds an empty [`PreloadSet`].
+#[derive(Default)]
+pub struct AssetPlugin {
+    /// Number of threads that assets are loaded on.
+    /// If None, uses the default of [`AssetManager`].
+    pub worker_count: Option<usize>,
+    /// Files compiled into the executable, loaded with paths like "embedded://textures/missing.png".
+    pub embedded: EmbeddedProtocol,
+    /// If true, assets are reloaded when their files change.
+    /// Fires an [`AssetReloadedEvent`] when a reload finishes.
+    #[cfg(feature = "hot_reload")]
+    pub hot_reload: bool,
+}
+
+impl AssetPlugin {
+    pub fn with_worker_count(mut self, worker_count: usize) -> Self {
+        self.worker_count = Some(worker_count);
+        self
+    }
+
+    /// Embeds a file. See [`EmbeddedProtocol::register`].
+    pub fn with_embedded(mut self, path: impl Into<String>, bytes: &'static [u8]) -> Self {
+        self.embedded.register(path, bytes);
+        self
+    }
+
+    #[cfg(feature = "hot_reload")]
+    pub fn with_hot_reload(mut self, hot_reload: bool) -> Self {
+        self.hot_reload = hot_reload;
+        self
+    }
+}
+
 impl Plugin for AssetPlugin {
     fn install(&mut self, builder: &mut AppBuilder) {
         let mut manager = AssetManager::new();
         manager.add_protocol(FileProtocol, true);
-        builder.game().add(manager);
+        manager.add_protocol(std::mem::take(&mut self.embedded), false);
+        manager.set_path_prefix(Some("assets"));
+        if let Some(worker_count) = self.worker_count {
+            manager.set_worker_count(worker_count);
+        }
+        #[cfg(feature = "hot_reload")]
+        if self.hot_reload {
+            if let Err(err) = manager.watch_for_changes() {
+                log::error!("Failed to watch assets for changes: {err}");
+            }
+        }
+        manager.set_event_sender(builder.event_sender());
+        builder.game()
+            .add(manager)
+            .init(|_| PreloadSet::default());
         builder.system(Stage::Asset, handle_asset_messages);
     }
 }
 
 fn handle_asset_messages(game: &mut Game, _ctx: RunContext) {
     let mut assets = game.get::<&mut AssetManager>();
-    assets.set_path_prefix(Some("assets"));
     assets.try_handle_messages();
 }
\ No newline at end of file
@@ -51,6 +51,14 @@ impl AssetPath {
     pub fn without_protocol(&self) -> String {
         format!("{}.{}", self.body, self.extension)
     }
+
+    /// Path of a file relative to the directory of this one, with the same protocol.
+    pub fn resolve(&self, relative: &str) -> String {
+        match self.body.rsplit_once('/') {
+            Some((dir, _)) => format!("{}://{dir}/{relative}", self.protocol),
+            None => format!("{}://{relative}", self.protocol),
+        }
+    }
 }
 
 impl fmt::Display for AssetPath {
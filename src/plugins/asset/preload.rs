@@ -0,0 +1,41 @@
+use std::any::{Any, TypeId};
+use crate::{Asset, Handle, HashMap};
+
+/**
+ * Domain that keeps handles alive, so that preloaded assets are not discarded before they are used.
+ * Typically filled with the handles returned by [`AssetManager::preload_dir`](crate::AssetManager::preload_dir) during a loading screen.
+ */
+#[derive(Default)]
+pub struct PreloadSet {
+    handles: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl PreloadSet {
+
+    /// Keeps handles alive until they are cleared.
+    pub fn insert<A: Asset>(&mut self, handles: impl IntoIterator<Item = Handle<A>>) {
+        let stored = self.handles
+            .entry(TypeId::of::<A>())
+            .or_insert_with(|| Box::new(Vec::<Handle<A>>::new()));
+        let stored = stored.downcast_mut::<Vec<Handle<A>>>().unwrap();
+        stored.extend(handles);
+    }
+
+    /// Handles of type A kept alive so far.
+    pub fn get<A: Asset>(&self) -> &[Handle<A>] {
+        match self.handles.get(&TypeId::of::<A>()) {
+            Some(stored) => stored.downcast_ref::<Vec<Handle<A>>>().unwrap(),
+            None => &[],
+        }
+    }
+
+    /// Drops handles of type A, freeing assets that have no other handles.
+    pub fn clear<A: Asset>(&mut self) {
+        self.handles.remove(&TypeId::of::<A>());
+    }
+
+    /// Drops all handles.
+    pub fn clear_all(&mut self) {
+        self.handles.clear();
+    }
+}
@@ -1,4 +1,4 @@
-use crate::AssetPath;
+use crate::{AssetPath, HashMap, HashSet};
 
 /**
  * A method of receiving bytes from files.
@@ -14,6 +14,25 @@ pub trait Protocol: Send + Sync + 'static {
      * Retrieves raw bytes from the path specified.
      */
     fn read(&self, path: &AssetPath) -> anyhow::Result<Vec<u8>>;
+    /**
+     * Names of the files directly inside a directory, without the directory itself.
+     * Protocols that cannot enumerate their contents keep the default, which returns an error.
+     */
+    fn list(&self, _dir: &str) -> anyhow::Result<Vec<String>> {
+        anyhow::bail!("Protocol \"{}\" cannot list directories", self.name())
+    }
+    /**
+     * Names of the directories directly inside a directory, without the directory itself.
+     * Used when loading folders recursively. By default, reports no subdirectories.
+     */
+    fn list_dirs(&self, _dir: &str) -> anyhow::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+    /**
+     * If true, paths read by this protocol begin with the path prefix of the [`AssetManager`](crate::AssetManager).
+     * Protocols that do not read from the file system typically return false.
+     */
+    fn uses_path_prefix(&self) -> bool { true }
 }
 
 /**
@@ -27,6 +46,90 @@ impl Protocol for FileProtocol {
         let bytes = std::fs::read(path.without_protocol())?;
         Ok(bytes)
     }
+    fn list(&self, dir: &str) -> anyhow::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() { continue }
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(names)
+    }
+    fn list_dirs(&self, dir: &str) -> anyhow::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() { continue }
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+/**
+ * An implementation of [`Protocol`] that serves bytes compiled into the executable, under the "embedded" scheme.
+ * Useful for shipping fallback assets, which are available even if the assets directory is missing.
+ * Paths ignore the path prefix of the [`AssetManager`](crate::AssetManager).
+ */
+#[derive(Clone, Default, Debug)]
+pub struct EmbeddedProtocol {
+    files: HashMap<String, &'static [u8]>,
+}
+
+impl EmbeddedProtocol {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves bytes from a path, such as "textures/missing.png", which is loaded as "embedded://textures/missing.png".
+    /// Typically used with include_bytes!.
+    pub fn register(&mut self, path: impl Into<String>, bytes: &'static [u8]) {
+        self.files.insert(path.into(), bytes);
+    }
+
+    /// Same as register, but chains.
+    pub fn with(mut self, path: impl Into<String>, bytes: &'static [u8]) -> Self {
+        self.register(path, bytes);
+        self
+    }
+
+    /// Paths of files directly inside a directory, relative to the directory.
+    fn entries<'a>(&'a self, dir: &'a str) -> impl Iterator<Item = &'a str> {
+        self.files.keys().filter_map(move |path| match dir.is_empty() {
+            true => Some(path.as_str()),
+            false => path.strip_prefix(dir)?.strip_prefix('/'),
+        })
+    }
+}
+
+impl Protocol for EmbeddedProtocol {
+    fn name(&self) -> &str { "embedded" }
+    fn read(&self, path: &AssetPath) -> anyhow::Result<Vec<u8>> {
+        match self.files.get(&path.without_protocol()) {
+            Some(bytes) => Ok(bytes.to_vec()),
+            None => anyhow::bail!("No embedded file at {path}"),
+        }
+    }
+    fn list(&self, dir: &str) -> anyhow::Result<Vec<String>> {
+        let mut names: Vec<String> = self.entries(dir)
+            .filter(|entry| !entry.contains('/'))
+            .map(String::from)
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+    fn list_dirs(&self, dir: &str) -> anyhow::Result<Vec<String>> {
+        let names: HashSet<&str> = self.entries(dir)
+            .filter_map(|entry| entry.split_once('/'))
+            .map(|(dir_name, _)| dir_name)
+            .collect();
+        let mut names: Vec<String> = names.into_iter().map(String::from).collect();
+        names.sort();
+        Ok(names)
+    }
+    fn uses_path_prefix(&self) -> bool { false }
 }
 
 /**
@@ -3,14 +3,17 @@ use std::cell::{RefCell, RefMut};
 use std::marker::PhantomData;
 use std::sync::mpsc::Sender;
 use slotmap::{new_key_type, SlotMap};
-use crate::{Asset, AssetId, AssetMessage, AssetMeta, HashMap, Readiness};
+use crate::{Asset, AssetFailedEvent, AssetId, AssetLoadedEvent, AssetManager, AssetMessage, AssetMeta, AssetReloadedEvent, DynEvent, HashMap, LoadOutcome, Readiness};
 
 /// Trait that [`AssetStorage`] must implement to be used dynamically by the [`AssetServer`].
 pub(crate) trait DynStorage {
     fn insert_loading(&mut self) -> AssetIndex;
+    fn start_loading(&mut self, index: AssetIndex);
     fn finish_loading(&mut self, index: AssetIndex, asset: Box<dyn Any>);
     fn fail_loading(&mut self, index: AssetIndex);
     fn remove(&mut self, index: AssetIndex);
+    fn event(&self, id: AssetId, outcome: LoadOutcome) -> DynEvent;
+    fn readiness(&self, index: AssetIndex, assets: &AssetManager) -> Readiness;
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
@@ -20,6 +23,7 @@ pub(crate) trait DynStorage {
 pub struct AssetStorage<'a, A> {
     pub(crate) inner: RefMut<'a, InnerAssetStorage<A>>,
     pub(crate) sender: &'a Sender<AssetMessage>,
+    pub(crate) fallback: Option<AssetIndex>,                // Asset returned in place of failed assets
 }
 
 impl<'a, A: Asset> AssetStorage<'a, A> {
@@ -38,12 +42,13 @@ impl<'a, A: Asset> AssetStorage<'a, A> {
         }
     }
 
+    /// State of the asset. If it failed, and a fallback was set, the state of the fallback instead.
     pub fn get(&self, handle: &Handle<A>) -> AssetState<&A> {
-        self.inner.get(handle.id.index).unwrap().as_ref()
+        get_or_fallback(&self.inner, handle.id.index, self.fallback)
     }
 
     pub fn get_mut(&mut self, handle: &Handle<A>) -> AssetState<&A> {
-        self.inner.get_mut(handle.id.index).unwrap().as_ref()
+        get_or_fallback(&self.inner, handle.id.index, self.fallback)
     }
 
     pub fn len(&self) -> usize {
@@ -70,6 +75,7 @@ pub struct AssetStorageMut<'a, A> {
     pub(crate) inner: RefMut<'a, InnerAssetStorage<A>>,     // Internal storage of assets
     pub(crate) metas: &'a mut HashMap<AssetId, AssetMeta>,  // Metadata of assets
     pub(crate) sender: &'a mut Sender<AssetMessage>,
+    pub(crate) fallback: Option<AssetIndex>,                // Asset returned in place of failed assets
 }
 
 impl<'a, A: Asset> AssetStorageMut<'a, A> {
@@ -91,10 +97,12 @@ impl<'a, A: Asset> AssetStorageMut<'a, A> {
         }
     }
 
+    /// State of the asset. If it failed, and a fallback was set, the state of the fallback instead.
     pub fn get(&self, handle: &Handle<A>) -> AssetState<&A> {
-        self.inner.get(handle.id.index).unwrap().as_ref()
+        get_or_fallback(&self.inner, handle.id.index, self.fallback)
     }
 
+    /// State of the asset. Unlike get, never returns the fallback, which is shared.
     pub fn get_mut(&mut self, handle: &Handle<A>) -> AssetState<&mut A> {
         self.inner.get_mut(handle.id.index).unwrap().as_mut()
     }
@@ -112,6 +120,14 @@ impl<'a, A: Asset> AssetStorageMut<'a, A> {
     }
 }
 
+fn get_or_fallback<A>(inner: &InnerAssetStorage<A>, index: AssetIndex, fallback: Option<AssetIndex>) -> AssetState<&A> {
+    let state = inner.get(index).unwrap().as_ref();
+    match (state, fallback.and_then(|fallback| inner.get(fallback))) {
+        (AssetState::Failed, Some(fallback)) => fallback.as_ref(),
+        _ => state,
+    }
+}
+
 /// Simple contiguous storage of assets.
 pub(crate) type InnerAssetStorage<A> = SlotMap<AssetIndex, AssetState<A>>;
 impl<A: Asset> DynStorage for RefCell<InnerAssetStorage<A>> {
@@ -121,6 +137,12 @@ impl<A: Asset> DynStorage for RefCell<InnerAssetStorage<A>> {
         slf.insert(AssetState::Loading)
     }
 
+    fn start_loading(&mut self, index: AssetIndex) {
+        let slf = self.get_mut();
+        let state = slf.get_mut(index).unwrap();
+        *state = AssetState::Loading;
+    }
+
     fn finish_loading(&mut self, index: AssetIndex, asset: Box<dyn Any>) {
         let slf = self.get_mut();
         let state = slf.get_mut(index).unwrap();
@@ -138,6 +160,20 @@ impl<A: Asset> DynStorage for RefCell<InnerAssetStorage<A>> {
         let slf = self.get_mut();
         slf.remove(index);
     }
+    fn event(&self, id: AssetId, outcome: LoadOutcome) -> DynEvent {
+        match outcome {
+            LoadOutcome::Loaded => DynEvent::new(AssetLoadedEvent::<A>::new(id)),
+            LoadOutcome::Failed => DynEvent::new(AssetFailedEvent::<A>::new(id)),
+            LoadOutcome::Reloaded => DynEvent::new(AssetReloadedEvent::<A>::new(id)),
+        }
+    }
+    fn readiness(&self, index: AssetIndex, assets: &AssetManager) -> Readiness {
+        match self.borrow().get(index) {
+            Some(AssetState::Loading) => Readiness::NotReady,
+            Some(AssetState::Loaded(asset)) => asset.readiness(assets),
+            Some(AssetState::Failed) | None => Readiness::Failed,
+        }
+    }
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -283,6 +319,62 @@ impl<A> Drop for Handle<A> {
     }
 }
 
+/**
+ * Handle to every asset loaded from a folder, which may be of different types.
+ * Keeps all of them alive, like a [`Handle`] to each.
+ * Returned by [`AssetManager::load_mixed_folder`].
+ */
+pub struct FolderHandle {
+    ids: Vec<AssetId>,
+    sender: Sender<AssetMessage>,
+}
+
+impl FolderHandle {
+    pub(crate) fn new(ids: Vec<AssetId>, sender: Sender<AssetMessage>) -> Self {
+        Self { ids, sender }
+    }
+
+    /// Ids of every asset in the folder.
+    pub fn ids(&self) -> &[AssetId] { &self.ids }
+
+    /// Handles to the assets in the folder of type A.
+    pub fn handles<A: Asset>(&self) -> Vec<Handle<A>> {
+        self.ids
+            .iter()
+            .filter(|id| id.asset_type == TypeId::of::<A>())
+            .map(|id| {
+                let _ = self.sender.send(AssetMessage::HandleCloned(*id));
+                Handle::new(*id, self.sender.clone())
+            })
+            .collect()
+    }
+
+    /// Merged readiness of every asset in the folder.
+    pub fn readiness(&self, assets: &AssetManager) -> Readiness {
+        self.ids
+            .iter()
+            .map(|id| assets.dyn_readiness(*id))
+            .fold(Readiness::Ready, Readiness::merge)
+    }
+}
+
+impl Clone for FolderHandle {
+    fn clone(&self) -> Self {
+        for id in &self.ids {
+            let _ = self.sender.send(AssetMessage::HandleCloned(*id));
+        }
+        Self::new(self.ids.clone(), self.sender.clone())
+    }
+}
+
+impl Drop for FolderHandle {
+    fn drop(&mut self) {
+        for id in &self.ids {
+            let _ = self.sender.send(AssetMessage::HandleDropped(*id));
+        }
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Hash, PartialOrd, Ord, Debug)]
 pub struct WeakHandle<A> {
     id: AssetId,
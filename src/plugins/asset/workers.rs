@@ -0,0 +1,185 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// A unit of work run on a worker thread.
+pub(crate) type LoadJob = Box<dyn FnOnce() + Send + 'static>;
+
+/// Number of workers in a pool, unless configured otherwise.
+pub(crate) const DEFAULT_WORKER_COUNT: usize = 4;
+
+/**
+ * Fixed-size pool of threads that run load jobs in priority order.
+ * Lower priority values run first. Jobs of equal priority run in the order they were submitted.
+ * Threads are spawned lazily, when the first job is submitted.
+ */
+pub(crate) struct LoadWorkers {
+    worker_count: usize,
+    queue: Option<Arc<JobQueue>>,
+    next_sequence: u64,
+}
+
+impl LoadWorkers {
+
+    /// Pool with [`DEFAULT_WORKER_COUNT`] workers.
+    pub fn new() -> Self {
+        Self::with_worker_count(DEFAULT_WORKER_COUNT)
+    }
+
+    pub fn with_worker_count(worker_count: usize) -> Self {
+        Self {
+            worker_count: worker_count.max(1),
+            queue: None,
+            next_sequence: 0,
+        }
+    }
+
+    pub fn worker_count(&self) -> usize {
+        self.worker_count
+    }
+
+    /// Changes the number of worker threads.
+    /// Existing workers finish the jobs already submitted, then exit.
+    pub fn set_worker_count(&mut self, worker_count: usize) {
+        self.worker_count = worker_count.max(1);
+        if let Some(queue) = self.queue.take() {
+            queue.close();
+        }
+    }
+
+    /// Runs a job on the next available worker, once all jobs of a lower priority value have started.
+    pub fn submit(&mut self, job: LoadJob, priority: u8) {
+        let queue = self.queue.get_or_insert_with(|| {
+            let queue = Arc::new(JobQueue::default());
+            for _ in 0..self.worker_count {
+                let queue = queue.clone();
+                std::thread::spawn(move || run_worker(&queue));
+            }
+            queue
+        });
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        queue.push(PrioritizedJob { priority, sequence, job });
+    }
+}
+
+impl Drop for LoadWorkers {
+    fn drop(&mut self) {
+        if let Some(queue) = self.queue.take() {
+            queue.close();
+        }
+    }
+}
+
+/// Runs jobs until the queue is closed and empty.
+/// A job that panics does not take its worker down with it.
+fn run_worker(queue: &JobQueue) {
+    while let Some(job) = queue.pop() {
+        let _ = std::panic::catch_unwind(AssertUnwindSafe(job));
+    }
+}
+
+/// Heap of jobs shared by workers.
+#[derive(Default)]
+struct JobQueue {
+    state: Mutex<JobQueueState>,
+    available: Condvar,
+}
+
+#[derive(Default)]
+struct JobQueueState {
+    jobs: BinaryHeap<PrioritizedJob>,
+    closed: bool,
+}
+
+impl JobQueue {
+
+    fn push(&self, job: PrioritizedJob) {
+        let Ok(mut state) = self.state.lock() else { return };
+        state.jobs.push(job);
+        self.available.notify_one();
+    }
+
+    /// Blocks until a job is available.
+    /// None if the queue was closed and has no jobs left.
+    fn pop(&self) -> Option<LoadJob> {
+        let mut state = self.state.lock().ok()?;
+        loop {
+            if let Some(job) = state.jobs.pop() {
+                return Some(job.job);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.available.wait(state).ok()?;
+        }
+    }
+
+    fn close(&self) {
+        let Ok(mut state) = self.state.lock() else { return };
+        state.closed = true;
+        self.available.notify_all();
+    }
+}
+
+/// Job ordered so that the max-heap pops the lowest priority value, then the lowest sequence.
+struct PrioritizedJob {
+    priority: u8,
+    sequence: u64,
+    job: LoadJob,
+}
+
+impl Ord for PrioritizedJob {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.priority
+            .cmp(&self.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for PrioritizedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for PrioritizedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PrioritizedJob {}
+
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use super::LoadWorkers;
+
+    #[test]
+    fn jobs_run_in_priority_order() {
+        let mut workers = LoadWorkers::with_worker_count(1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        // Occupies the only worker until every other job is queued.
+        let (release, released) = std::sync::mpsc::channel::<()>();
+        workers.submit(Box::new(move || { let _ = released.recv(); }), 0);
+        for (name, priority) in [("decoration", 255), ("hud", 0), ("terrain", 128), ("player", 0)] {
+            let order = order.clone();
+            workers.submit(Box::new(move || order.lock().unwrap().push(name)), priority);
+        }
+        release.send(()).unwrap();
+
+        for _ in 0..100 {
+            if order.lock().unwrap().len() == 4 {
+                assert_eq!(vec!["hud", "player", "terrain", "decoration"], *order.lock().unwrap());
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Jobs did not finish");
+    }
+}
@@ -1,17 +1,19 @@
-use std::f32::consts::PI;
-use glam::{Mat4, Quat, Vec2, Vec3};
-use hecs::World;
+use std::f32::consts::{FRAC_PI_2, PI};
+use glam::{EulerRot, Mat4, Quat, Vec2, Vec3};
+use hecs::{ComponentError, Entity, World};
 use winit::keyboard::KeyCode;
-use crate::math::{lerp_matrices, Transform};
-use crate::{Cursor, Game, Keyboard, Plugin, Rect, RunContext, Stage, Window, WindowRequests};
+use crate::math::{lerp_matrices, Ray, Transform};
+use crate::{g3d, Color, Cursor, Game, Keyboard, MouseButton, Plugin, Rect, RunContext, Stage, Window, WindowRequests};
 
 const SENSITIVITY_SCALE: f32 = 0.005;
 const SCROLL_SENSITIVITY_SCALE: f32 = 0.1;
+const MAX_ORBIT_PITCH: f32 = FRAC_PI_2 - 0.01;
 
 pub struct FlycamPlugin;
 impl Plugin for FlycamPlugin {
     fn install(&mut self, builder: &mut crate::AppBuilder) {
         builder.system(Stage::Update, control_flycams);
+        builder.system(Stage::Update, control_orbit_cams);
         builder.system(Stage::PostUpdate, set_cam_projections);
     }
 }
@@ -78,6 +80,67 @@ fn control_flycams(game: &mut Game, ctx: RunContext) {
     }
 }
 
+fn control_orbit_cams(game: &mut Game, ctx: RunContext) {
+
+    let Some(cursor)    = game.try_get::<&Cursor>() else { return };
+    let mut world       = game.get::<&mut World>();
+    let cursor_movement = cursor.movement();
+    let delta           = ctx.delta_secs();
+
+    for (_, (transform, camera, controller)) in world.query_mut::<(&mut Transform, &mut Camera, &mut OrbitController)>() {
+
+        // Zooms with scroll
+        let zoom = 1.0 - cursor.scroll().y * controller.scroll_sensitivity * SCROLL_SENSITIVITY_SCALE;
+        controller.distance = (controller.distance * zoom.max(0.0)).clamp(controller.min_distance, controller.max_distance);
+
+        // Rotates around focus and pans focus
+        if cursor.is_pressed(controller.rotate_button) {
+            controller.yaw -= cursor_movement.x * controller.sensitivity * SENSITIVITY_SCALE;
+            controller.pitch -= cursor_movement.y * controller.sensitivity * SENSITIVITY_SCALE;
+        }
+        else if cursor.is_pressed(controller.pan_button) {
+            let view_rotation = orbit_rotation(controller.yaw, controller.pitch);
+            let pan = Vec3::new(-cursor_movement.x, cursor_movement.y, 0.0) * controller.distance * controller.sensitivity * SENSITIVITY_SCALE;
+            controller.focus += view_rotation * pan;
+        }
+        controller.pitch = controller.pitch.clamp(-MAX_ORBIT_PITCH, MAX_ORBIT_PITCH);
+
+        controller.step(delta);
+        let view = controller.transform();
+        transform.translation = view.translation;
+        transform.rotation = view.rotation;
+        camera.projection = controller.perspective.compute_projection();
+    }
+}
+
+/**
+ * Switches an entity's camera between a [`CameraController`] and an [`OrbitController`], preserving its current view.
+ * When switching to orbit mode, the focus is placed orbit_distance in front of the camera,
+ * and the flycam's settings are kept so that they are restored when switching back.
+ * Cursor grab and visibility are left as they are.
+ */
+pub fn toggle_orbit(world: &mut World, entity: Entity, orbit_distance: f32) -> Result<(), ComponentError> {
+    let transform = *world.get::<&Transform>(entity)?;
+    if let Ok(flycam) = world.remove_one::<CameraController>(entity) {
+        let mut orbit = OrbitController::from_transform(&transform, orbit_distance);
+        orbit.perspective = flycam.perspective.clone();
+        orbit.flycam = Some(flycam);
+        world.insert_one(entity, orbit).unwrap();
+    }
+    else {
+        let mut orbit = world.remove_one::<OrbitController>(entity)?;
+        let view = orbit.transform();
+        let mut flycam = orbit.flycam.take().unwrap_or_default();
+        flycam.set_rotation(view.rotation);
+        let mut transform = world.get::<&mut Transform>(entity)?;
+        transform.translation = view.translation;
+        transform.rotation = flycam.rotation();
+        drop(transform);
+        world.insert_one(entity, flycam).unwrap();
+    }
+    Ok(())
+}
+
 fn set_cam_projections(game: &mut Game, _ctx: RunContext) {
     let mut world       = game.get::<&mut World>();
     let window          = game.get::<&Window>();
@@ -85,6 +148,7 @@ fn set_cam_projections(game: &mut Game, _ctx: RunContext) {
 
     for (_, (mut camera, controller)) in world.query_mut::<(&mut Camera, &mut CameraController)>() {
         camera.projection = controller.projection();
+        camera.layer_mask = controller.layer_mask;
         match controller.scaling_mode {
             ScalingMode::Stretch => {},
             ScalingMode::ScaleSmallest => scale_smallest_viewport(
@@ -114,10 +178,55 @@ fn scale_smallest_viewport(win_size: Vec2, aspect_ratio: f32, camera: &mut Camer
 }
 
 /// Camera projection component.
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, Debug)]
 pub struct Camera {
     pub projection: Mat4,
     pub viewport: Option<Rect>,
+    /// See [`crate::g3d::Camera::clear`].
+    pub clear: Option<Color>,
+    /// See [`crate::g3d::Camera::layer_mask`].
+    pub layer_mask: u32,
+    /// See [`crate::g3d::Camera::order`].
+    pub order: i32,
+    /// See [`crate::g3d::Camera::enabled`].
+    pub enabled: bool,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            projection: Mat4::default(),
+            viewport: None,
+            clear: None,
+            layer_mask: g3d::LAYER_ALL,
+            order: 0,
+            enabled: true,
+        }
+    }
+}
+
+impl Camera {
+
+    /**
+     * Converts a cursor position into a ray in world space, starting at the near plane.
+     * Works with any projection, including the interpolated one from [`CameraController::projection`].
+     * viewport_size is the size of the surface being rendered to, and is used when the camera has no viewport.
+     * None if the cursor is outside of the camera's viewport.
+     */
+    pub fn viewport_to_world(&self, camera_transform: &Transform, viewport_size: Vec2, cursor_pos: Vec2) -> Option<Ray> {
+        let viewport = self.viewport.unwrap_or(Rect { origin: Vec2::ZERO, size: viewport_size });
+        let local_pos = cursor_pos - viewport.origin;
+        if local_pos.x < 0.0 || local_pos.y < 0.0 || local_pos.x > viewport.size.x || local_pos.y > viewport.size.y {
+            return None;
+        }
+        let ndc = local_pos / viewport.size * 2.0 - 1.0;
+        let ndc = Vec2::new(ndc.x, -ndc.y);
+        let view = Mat4::from(*camera_transform).inverse();
+        let inv_proj_view = (self.projection * view).inverse();
+        let near = inv_proj_view.project_point3(ndc.extend(0.0));
+        let far = inv_proj_view.project_point3(ndc.extend(1.0));
+        Some(Ray::new(near, far - near))
+    }
 }
 
 pub struct CameraController {
@@ -131,6 +240,8 @@ pub struct CameraController {
     pub t: f32,
     pub scaling_mode: ScalingMode,
     pub flycam_mode: FlycamMode,
+    /// Layers the controlled camera sees. See [`crate::g3d::Renderable::layer_mask`].
+    pub layer_mask: u32,
 }
 
 impl Default for CameraController {
@@ -146,16 +257,27 @@ impl Default for CameraController {
             t: 1.0,
             scaling_mode: ScalingMode::default(),
             flycam_mode: FlycamMode::default(),
+            layer_mask: g3d::LAYER_ALL,
         }
     }
 }
 
 impl CameraController {
 
+    pub fn with_layer_mask(mut self, layer_mask: u32) -> Self {
+        self.layer_mask = layer_mask;
+        self
+    }
+
     pub fn rotation(&self) -> Quat {
         Quat::IDENTITY * Quat::from_rotation_y(self.yaw) * Quat::from_rotation_x(self.pitch)
     }
 
+    /// Sets yaw and pitch to face the same direction as rotation. Roll is discarded.
+    pub fn set_rotation(&mut self, rotation: Quat) {
+        (self.yaw, self.pitch) = yaw_pitch(rotation);
+    }
+
     pub fn axes(&self) -> (Vec3, Vec3, Vec3) {
         let rotation = self.rotation();
         let x = rotation * Vec3::X;
@@ -183,6 +305,107 @@ impl CameraController {
     }
 }
 
+/**
+ * Orbits a camera around a focal point, for inspecting a scene.
+ * Dragging with the rotate button orbits, dragging with the pan button moves the focus, and scrolling zooms.
+ * The fields describe the view being steered towards. The camera catches up to it smoothly, based on damping.
+ * An entity should have either this or a [`CameraController`], not both. See [`toggle_orbit`].
+ */
+pub struct OrbitController {
+    pub focus: Vec3,
+    /// Distance from the focus, kept within min_distance and max_distance.
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub yaw: f32,
+    /// Kept just short of straight up or down, so that the camera never flips over.
+    pub pitch: f32,
+    pub sensitivity: f32,
+    pub scroll_sensitivity: f32,
+    /// Fraction of the way to the steered view left to catch up on after one second.
+    /// 0.0 snaps the camera to it every frame.
+    pub damping: f32,
+    pub rotate_button: MouseButton,
+    pub pan_button: MouseButton,
+    pub perspective: PerspectiveProjector,
+    view: Option<OrbitView>,                // Current, smoothed view. None until first stepped
+    flycam: Option<CameraController>,       // Flycam to restore when toggled back
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        Self {
+            focus: Vec3::ZERO,
+            distance: 5.0,
+            min_distance: 0.1,
+            max_distance: 1000.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            sensitivity: 1.0,
+            scroll_sensitivity: 1.0,
+            damping: 0.001,
+            rotate_button: MouseButton::Left,
+            pan_button: MouseButton::Middle,
+            perspective: PerspectiveProjector::default(),
+            view: None,
+            flycam: None,
+        }
+    }
+}
+
+impl OrbitController {
+
+    /// Orbit controller with the same view as a camera transform, focusing on the point distance in front of it.
+    pub fn from_transform(transform: &Transform, distance: f32) -> Self {
+        let (yaw, pitch) = yaw_pitch(transform.rotation);
+        let pitch = pitch.clamp(-MAX_ORBIT_PITCH, MAX_ORBIT_PITCH);
+        let focus = transform.translation + orbit_rotation(yaw, pitch) * Vec3::NEG_Z * distance;
+        let view = OrbitView { focus, distance, yaw, pitch };
+        Self { focus, distance, yaw, pitch, view: Some(view), ..Default::default() }
+    }
+
+    /// Transform of the camera's current view.
+    pub fn transform(&self) -> Transform {
+        let view = self.view.unwrap_or(self.target());
+        let rotation = orbit_rotation(view.yaw, view.pitch);
+        let translation = view.focus + rotation * Vec3::Z * view.distance;
+        Transform { translation, rotation, ..Default::default() }
+    }
+
+    /// Moves the current view towards the steered one.
+    fn step(&mut self, delta: f32) {
+        let target = self.target();
+        let t = 1.0 - self.damping.clamp(0.0, 1.0).powf(delta);
+        let view = self.view.get_or_insert(target);
+        view.focus = view.focus.lerp(target.focus, t);
+        view.distance += (target.distance - view.distance) * t;
+        view.yaw += (target.yaw - view.yaw) * t;
+        view.pitch += (target.pitch - view.pitch) * t;
+    }
+
+    fn target(&self) -> OrbitView {
+        OrbitView { focus: self.focus, distance: self.distance, yaw: self.yaw, pitch: self.pitch }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct OrbitView {
+    focus: Vec3,
+    distance: f32,
+    yaw: f32,
+    pitch: f32,
+}
+
+fn orbit_rotation(yaw: f32, pitch: f32) -> Quat {
+    Quat::from_rotation_y(yaw) * Quat::from_rotation_x(pitch)
+}
+
+/// Yaw and pitch of a rotation, as applied by [`CameraController::rotation`].
+fn yaw_pitch(rotation: Quat) -> (f32, f32) {
+    let (yaw, pitch, _roll) = rotation.to_euler(EulerRot::YXZ);
+    (yaw, pitch)
+}
+
 /**
  * Toggle for flycam mode
  */
@@ -258,4 +481,75 @@ impl Default for PerspectiveProjector {
             far: 10000.0,
         }
     }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::{Mat4, Quat, Vec2, Vec3};
+    use hecs::World;
+    use crate::math::Transform;
+    use crate::{toggle_orbit, Camera, CameraController, OrbitController, Rect};
+
+    #[test]
+    fn viewport_to_world() {
+        let camera = Camera {
+            projection: Mat4::perspective_rh(90.0_f32.to_radians(), 1.0, 0.1, 100.0),
+            viewport: Some(Rect::new(100.0, 0.0, 200.0, 200.0)),
+            ..Default::default()
+        };
+        let transform = Transform::default().with_xyz(0.0, 0.0, 5.0);
+        let viewport_size = Vec2::new(400.0, 200.0);
+
+        let ray = camera.viewport_to_world(&transform, viewport_size, Vec2::new(200.0, 100.0)).unwrap();
+        assert!(ray.direction.abs_diff_eq(Vec3::NEG_Z, 0.0001));
+        assert!(ray.origin.abs_diff_eq(Vec3::new(0.0, 0.0, 4.9), 0.0001));
+
+        let ray = camera.viewport_to_world(&transform, viewport_size, Vec2::new(300.0, 0.0)).unwrap();
+        assert!(ray.direction.abs_diff_eq(Vec3::new(1.0, 1.0, -1.0).normalize(), 0.0001));
+
+        assert_eq!(None, camera.viewport_to_world(&transform, viewport_size, Vec2::new(50.0, 100.0)));
+    }
+
+    #[test]
+    fn orbit_from_transform_preserves_view() {
+        let rotation = Quat::from_rotation_y(0.5) * Quat::from_rotation_x(-0.3);
+        let transform = Transform { translation: Vec3::new(1.0, 2.0, 3.0), rotation, ..Default::default() };
+        let orbit = OrbitController::from_transform(&transform, 4.0);
+        let view = orbit.transform();
+        assert!(view.translation.abs_diff_eq(transform.translation, 0.0001));
+        assert!(view.rotation.abs_diff_eq(transform.rotation, 0.0001));
+        assert!(orbit.focus.abs_diff_eq(transform.translation + rotation * Vec3::NEG_Z * 4.0, 0.0001));
+    }
+
+    #[test]
+    fn orbit_damping() {
+        let mut orbit = OrbitController { damping: 0.25, ..Default::default() };
+        orbit.step(1.0);
+        orbit.distance = 9.0;
+        orbit.step(1.0);
+        assert!((orbit.transform().translation.z - 8.0).abs() < 0.0001);
+        orbit.damping = 0.0;
+        orbit.step(1.0);
+        assert!((orbit.transform().translation.z - 9.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn toggle_orbit_preserves_view() {
+        let mut world = World::new();
+        let mut flycam = CameraController { speed: 7.0, ..Default::default() };
+        flycam.set_rotation(Quat::from_rotation_y(1.0) * Quat::from_rotation_x(0.2));
+        let transform = Transform { rotation: flycam.rotation(), ..Default::default() };
+        let entity = world.spawn((transform, Camera::default(), flycam));
+
+        toggle_orbit(&mut world, entity, 2.0).unwrap();
+        assert!(world.get::<&CameraController>(entity).is_err());
+        assert!(world.get::<&OrbitController>(entity).unwrap().transform().rotation.abs_diff_eq(transform.rotation, 0.0001));
+
+        toggle_orbit(&mut world, entity, 2.0).unwrap();
+        assert!(world.get::<&OrbitController>(entity).is_err());
+        let flycam = world.get::<&CameraController>(entity).unwrap();
+        assert_eq!(7.0, flycam.speed);
+        assert!(flycam.rotation().abs_diff_eq(transform.rotation, 0.0001));
+        assert!(world.get::<&Transform>(entity).unwrap().translation.abs_diff_eq(Vec3::ZERO, 0.0001));
+    }
 }
\ No newline at end of file
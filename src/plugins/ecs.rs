@@ -1,9 +1,235 @@
-use hecs::World;
-use crate::Plugin;
+use std::sync::{Arc, OnceLock};
+use hecs::{Bundle, Component, DynamicBundle, Entity, Query, World};
+use crate::{propagate_transforms, Game, Plugin, RunContext, Stage};
 
 pub struct EcsPlugin;
 impl Plugin for EcsPlugin {
     fn install(&mut self, builder: &mut crate::AppBuilder) {
         builder.game().init(|_| World::new());
+        builder.system(Stage::PostUpdate, propagate_transforms);
+    }
+}
+
+/// Convenience methods for common [`World`] patterns.
+pub trait WorldExt {
+
+    /// Invokes a function on every entity matching the query.
+    fn for_each_mut<Q, F>(&mut self, function: F)
+    where
+        Q: Query,
+        F: FnMut(Entity, Q::Item<'_>);
+
+    /// Gets the only entity matching the query.
+    /// Returns None if no entities match, or if more than one matches.
+    fn single<Q: Query>(&mut self) -> Option<(Entity, Q::Item<'_>)>;
+
+    /// Spawns an entity for every bundle of components, and returns them in order.
+    fn spawn_many<I>(&mut self, bundles: I) -> Vec<Entity>
+    where
+        I: IntoIterator,
+        I::Item: Bundle + 'static;
+}
+
+impl WorldExt for World {
+
+    fn for_each_mut<Q, F>(&mut self, mut function: F)
+    where
+        Q: Query,
+        F: FnMut(Entity, Q::Item<'_>)
+    {
+        for (entity, item) in self.query_mut::<Q>() {
+            function(entity, item);
+        }
+    }
+
+    fn single<Q: Query>(&mut self) -> Option<(Entity, Q::Item<'_>)> {
+        let mut iter = self.query_mut::<Q>().into_iter();
+        let first = iter.next()?;
+        match iter.next() {
+            Some(_) => None,
+            None => Some(first),
+        }
+    }
+
+    fn spawn_many<I>(&mut self, bundles: I) -> Vec<Entity>
+    where
+        I: IntoIterator,
+        I::Item: Bundle + 'static
+    {
+        self.spawn_batch(bundles).collect()
+    }
+}
+
+/// Commands that change the structure of the [`World`] at the end of the current [`Stage`](crate::Stage).
+/// Useful when the world is already borrowed, or from within an [`Instruction`](crate::Instruction).
+impl<'a> RunContext<'a> {
+
+    /// Spawns an entity at the end of the stage.
+    /// The placeholder returned can be used in later commands of the same stage.
+    pub fn spawn(&mut self, bundle: impl DynamicBundle + Send + Sync + 'static) -> EntityPlaceholder {
+        let placeholder = EntityPlaceholder::default();
+        let spawned = placeholder.clone();
+        let mut bundle = Some(bundle);
+        self.run_command(move |game: &mut Game| {
+            let Some(bundle) = bundle.take() else { return };
+            let Some(mut world) = game.try_get::<&mut World>() else {
+                log::warn!("Failed to spawn entity: World not found");
+                return;
+            };
+            let _ = spawned.entity.set(world.spawn(bundle));
+        });
+        placeholder
+    }
+
+    /// Inserts components into an entity at the end of the stage, replacing those of the same types.
+    pub fn insert(&mut self, entity: impl EntityTarget, bundle: impl DynamicBundle + Send + Sync + 'static) {
+        let mut bundle = Some(bundle);
+        self.run_entity_command(entity, "insert into", move |world, entity| {
+            let Some(bundle) = bundle.take() else { return Ok(()) };
+            world.insert(entity, bundle)
+        });
+    }
+
+    /// Removes a component from an entity at the end of the stage.
+    pub fn remove<T: Component>(&mut self, entity: impl EntityTarget) {
+        self.run_entity_command(entity, "remove from", |world, entity| {
+            match world.remove_one::<T>(entity) {
+                Ok(_) | Err(hecs::ComponentError::MissingComponent(_)) => Ok(()),
+                Err(hecs::ComponentError::NoSuchEntity) => Err(hecs::NoSuchEntity),
+            }
+        });
+    }
+
+    /// Despawns an entity at the end of the stage.
+    /// Nodes of a [`crate::SceneGraph`] tracked by its components are pruned on the next frame.
+    pub fn despawn(&mut self, entity: impl EntityTarget) {
+        self.run_entity_command(entity, "despawn", |world, entity| world.despawn(entity));
+    }
+
+    fn run_entity_command<F>(&mut self, target: impl EntityTarget, action: &'static str, mut command: F)
+    where
+        F: FnMut(&mut World, Entity) -> Result<(), hecs::NoSuchEntity> + Send + Sync + 'static
+    {
+        self.run_command(move |game: &mut Game| {
+            let Some(entity) = target.resolve() else {
+                log::warn!("Failed to {action} entity: placeholder was never spawned");
+                return;
+            };
+            let Some(mut world) = game.try_get::<&mut World>() else {
+                log::warn!("Failed to {action} entity {entity:?}: World not found");
+                return;
+            };
+            if command(&mut world, entity).is_err() {
+                log::warn!("Failed to {action} entity {entity:?}: no such entity");
+            }
+        });
+    }
+}
+
+/**
+ * Entity spawned by [`RunContext::spawn`].
+ * Is resolved once the spawn command runs, so it can be targeted by commands queued after it.
+ */
+#[derive(Clone, Default, Debug)]
+pub struct EntityPlaceholder {
+    entity: Arc<OnceLock<Entity>>,
+}
+
+impl EntityPlaceholder {
+    /// The entity spawned, or None if the spawn command has not run yet.
+    pub fn entity(&self) -> Option<Entity> {
+        self.entity.get().copied()
+    }
+}
+
+/// Entity that a command targets.
+/// Either an [`Entity`], or an [`EntityPlaceholder`] resolved when the command runs.
+pub trait EntityTarget: Send + Sync + 'static {
+    fn resolve(&self) -> Option<Entity>;
+}
+
+impl EntityTarget for Entity {
+    fn resolve(&self) -> Option<Entity> { Some(*self) }
+}
+
+impl EntityTarget for EntityPlaceholder {
+    fn resolve(&self) -> Option<Entity> { self.entity() }
+}
+
+#[cfg(test)]
+mod test {
+    use hecs::{Entity, World};
+    use crate::{App, EcsPlugin, Game, RunContext, Stage, WorldExt};
+
+    struct Health(u32);
+    struct Player;
+
+    #[test]
+    fn for_each_mut() {
+        let mut world = World::new();
+        world.spawn_many((0..3).map(|i| (Health(i),)));
+        world.for_each_mut::<&mut Health, _>(|_, health| health.0 += 10);
+        let mut healths: Vec<u32> = world.query_mut::<&Health>().into_iter().map(|(_, h)| h.0).collect();
+        healths.sort();
+        assert_eq!(vec![10, 11, 12], healths);
+    }
+
+    #[test]
+    fn single() {
+        let mut world = World::new();
+        assert!(world.single::<&Player>().is_none());
+        let player = world.spawn((Player, Health(3)));
+        let (entity, (_, health)) = world.single::<(&Player, &Health)>().unwrap();
+        assert_eq!(player, entity);
+        assert_eq!(3, health.0);
+        world.spawn((Player,));
+        assert!(world.single::<&Player>().is_none());
+    }
+
+    #[test]
+    fn spawn_many() {
+        let mut world = World::new();
+        let entities = world.spawn_many((0..4).map(|i| (Health(i),)));
+        assert_eq!(4, entities.len());
+        assert_eq!(2, world.get::<&Health>(entities[2]).unwrap().0);
+    }
+
+    #[derive(Default)]
+    struct Spawned(Option<Entity>);
+
+    fn spawn_player(game: &mut Game, mut ctx: RunContext) {
+        let spawned = game.get::<&mut Spawned>();
+        match spawned.0 {
+            None => {
+                let player = ctx.spawn((Player,));
+                ctx.insert(player.clone(), (Health(3),));
+                ctx.run_command(move |game: &mut Game| {
+                    game.get::<&mut Spawned>().0 = player.entity();
+                });
+            },
+            Some(player) => {
+                ctx.remove::<Health>(player);
+                ctx.despawn(player);
+            },
+        }
+    }
+
+    #[test]
+    fn entity_commands() {
+        let mut builder = App::builder();
+        builder
+            .plugin(EcsPlugin)
+            .system(Stage::Update, spawn_player);
+        builder.game().add(Spawned::default());
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_frame(tick_duration);
+        let player = app.game.get::<&Spawned>().0.unwrap();
+        {
+            let world = app.game.get::<&World>();
+            assert!(world.satisfies::<(&Player, &Health)>(player).unwrap());
+        }
+        app.run_frame(tick_duration);
+        assert!(!app.game.get::<&World>().contains(player));
     }
 }
\ No newline at end of file
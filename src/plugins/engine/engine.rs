@@ -3,7 +3,7 @@ use winit::keyboard::KeyCode;
 use winit::monitor::{MonitorHandle, VideoMode};
 use winit::window::Fullscreen;
 use crate::g3d::{Material, Mesh};
-use crate::{AppBuilder, AssetManager, AssetPlugin, EcsPlugin, Game, GraphicsPlugin, InputPlugin, Keyboard, Plugin, RunContext, Stage, Texture, Window, WindowFeatures, WindowPlugin, WindowRequests};
+use crate::{AppBuilder, AssetManager, AssetPlugin, EcsPlugin, Game, GraphicsPlugin, InputPlugin, Keyboard, Plugin, RunContext, Stage, Texture, Window, WindowPlugin, WindowRequests};
 
 /**
  * Main game engine plugin.
@@ -11,13 +11,18 @@ use crate::{AppBuilder, AssetManager, AssetPlugin, EcsPlugin, Game, GraphicsPlug
 pub struct EnginePlugin {
     pub window_width: u32,
     pub window_height: u32,
+    /// Samples per pixel when rendering. 1 disables MSAA.
+    pub msaa_samples: u32,
+    pub lighting: bool,
 }
 
 impl Default for EnginePlugin {
     fn default() -> Self {
         Self {
             window_width: 512,
-            window_height: 512
+            window_height: 512,
+            msaa_samples: 1,
+            lighting: false,
         }
     }
 }
@@ -29,11 +34,12 @@ impl Plugin for EnginePlugin {
             .plugin(WindowPlugin {
                 window_width: self.window_width,
                 window_height: self.window_height,
-                features: WindowFeatures::default(),
+                msaa_samples: self.msaa_samples,
+                ..WindowPlugin::default()
             })
             .plugin(EcsPlugin)
-            .plugin(AssetPlugin)
-            .plugin(GraphicsPlugin)
+            .plugin(AssetPlugin::default())
+            .plugin(GraphicsPlugin::default().with_lighting(self.lighting))
             .tick_duration(Duration::from_secs_f64(1.0/60.0));
         builder.system(Stage::PreUpdate, toggle_fullscreen);
 
@@ -25,6 +25,93 @@ impl Instruction for Wait {
     }
 }
 
+/// Waits for a number of runs of the script's stage.
+/// Counts down once per run, and finishes when it reaches zero.
+pub struct WaitFrames {
+    pub remaining: u32,
+}
+impl Instruction for WaitFrames {
+    fn run(&mut self, _game: &mut Game, _ctx: &mut ScriptContext) -> bool {
+        if self.remaining == 0 {
+            return true;
+        }
+        self.remaining -= 1;
+        false
+    }
+}
+
+/// Waits for a number of seconds.
+/// Accumulates the script stage's delta each run.
+pub struct WaitSeconds {
+    pub secs: f32,
+    pub elapsed: f32,
+}
+impl WaitSeconds {
+    pub fn new(secs: f32) -> Self {
+        Self { secs, elapsed: 0.0 }
+    }
+}
+impl Instruction for WaitSeconds {
+    fn run(&mut self, _game: &mut Game, ctx: &mut ScriptContext) -> bool {
+        self.elapsed += ctx.run_context.delta_secs();
+        self.elapsed >= self.secs
+    }
+}
+
+/// Waits until a condition holds, checking it once per run of the script's stage.
+pub struct WaitUntil<C>(pub C);
+impl<C> Instruction for WaitUntil<C>
+where
+    C: FnMut(&Game, &ScriptContext) -> bool + Send + Sync + 'static
+{
+    fn run(&mut self, game: &mut Game, ctx: &mut ScriptContext) -> bool {
+        self.0(game, ctx)
+    }
+}
+
+/**
+ * Methods of a [`ScriptContext`] that add wait instructions.
+ * Instructions are added like with [`ScriptContext::add`].
+ */
+pub trait WaitExt {
+
+    /// Adds a [`Wait`] instruction, which waits for a period of time.
+    fn wait(&mut self, duration: Duration) -> &mut Self;
+
+    /**
+     * Adds a [`WaitFrames`] instruction.
+     * Waits for a number of runs of the script's stage, which are ticks for per-tick stages.
+     */
+    fn wait_frames(&mut self, frames: u32) -> &mut Self;
+
+    /// Adds a [`WaitSeconds`] instruction.
+    fn wait_seconds(&mut self, secs: f32) -> &mut Self;
+
+    /// Adds a [`WaitUntil`] instruction.
+    fn wait_until<C>(&mut self, condition: C) -> &mut Self
+    where C: FnMut(&Game, &ScriptContext) -> bool + Send + Sync + 'static;
+}
+
+impl<'a> WaitExt for ScriptContext<'a> {
+
+    fn wait(&mut self, duration: Duration) -> &mut Self {
+        self.add(Wait(duration))
+    }
+
+    fn wait_frames(&mut self, frames: u32) -> &mut Self {
+        self.add(WaitFrames { remaining: frames })
+    }
+
+    fn wait_seconds(&mut self, secs: f32) -> &mut Self {
+        self.add(WaitSeconds::new(secs))
+    }
+
+    fn wait_until<C>(&mut self, condition: C) -> &mut Self
+    where C: FnMut(&Game, &ScriptContext) -> bool + Send + Sync + 'static {
+        self.add(WaitUntil(condition))
+    }
+}
+
 /**
  * Inline instruction that runs a block of code once during its start() invocation.
 */
@@ -50,22 +137,41 @@ impl<'a, 'b> Instructor<'a, 'b> {
     
     /// Waits for a period of time.
     pub fn wait_secs(&mut self, secs: u64) -> &mut Self {
-        self.0.add(Wait(Duration::from_secs(secs)));
+        self.0.wait(Duration::from_secs(secs));
         self
     }
     
     /// Waits for a period of time.
     pub fn wait_secs_f32(&mut self, secs: f32) -> &mut Self {
-        self.0.add(Wait(Duration::from_secs_f32(secs)));
+        self.0.wait(Duration::from_secs_f32(secs));
         self
     }
     
     /// Waits for a period of time.
     pub fn wait_millis(&mut self, millis: u64) -> &mut Self {
-        self.0.add(Wait(Duration::from_millis(millis)));
+        self.0.wait(Duration::from_millis(millis));
+        self
+    }
+    
+    /// Waits for a number of runs of the script's stage.
+    pub fn wait_frames(&mut self, frames: u32) -> &mut Self {
+        self.0.wait_frames(frames);
+        self
+    }
+
+    /// Waits for a number of seconds.
+    pub fn wait_seconds(&mut self, secs: f32) -> &mut Self {
+        self.0.wait_seconds(secs);
         self
     }
     
+    /// Waits until a condition holds.
+    pub fn wait_until<C>(&mut self, condition: C) -> &mut Self
+    where C: FnMut(&Game, &ScriptContext) -> bool + Send + Sync + 'static {
+        self.0.wait_until(condition);
+        self
+    }
+
     /// Performs some inline task that completes immediately.
     pub fn inline<F>(&mut self, callback: F) -> &mut Self
     where F: FnMut(&mut Game, &mut ScriptContext) + Send + Sync + 'static {
@@ -106,3 +212,59 @@ impl<'a, 'b> Instructor<'a, 'b> {
         self
     }
 }
+
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use crate::{App, EventHandler, Game, Inline, RunContext, ScriptContext, Stage, StartEvent};
+    use super::WaitExt;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    #[derive(Default)]
+    struct Finished(bool);
+
+    fn finish(game: &mut Game, _ctx: &mut ScriptContext) {
+        game.get::<&mut Finished>().0 = true;
+    }
+
+    fn start_frames_script(_game: &mut Game, _event: &StartEvent, ctx: &mut RunContext) {
+        ctx.start_script(Stage::Update, Inline(|_game: &mut Game, ctx: &mut ScriptContext| {
+            ctx.wait_frames(3).add(Inline(finish));
+        }));
+    }
+
+    fn start_duration_script(_game: &mut Game, _event: &StartEvent, ctx: &mut RunContext) {
+        ctx.start_script(Stage::Update, Inline(|_game: &mut Game, ctx: &mut ScriptContext| {
+            ctx.wait(TICK * 3).add(Inline(finish));
+        }));
+    }
+
+    /// Number of ticks until a script started by the handler finishes.
+    fn ticks_to_finish(start_script: EventHandler<StartEvent>) -> u32 {
+        let mut builder = App::builder();
+        builder.tick_duration(TICK).event_handler(start_script);
+        builder.game().add(Finished::default());
+        let mut app = builder.build();
+        for ticks in 1..=10 {
+            app.run_n_frames_simulated(1, TICK);
+            if app.game.get::<&Finished>().0 {
+                return ticks;
+            }
+        }
+        panic!("Script did not finish");
+    }
+
+    #[test]
+    fn wait_frames() {
+
+        // Script starts on the second tick, after StartEvent is handled.
+        assert_eq!(5, ticks_to_finish(start_frames_script));
+    }
+
+    #[test]
+    fn wait_duration() {
+        assert_eq!(5, ticks_to_finish(start_duration_script));
+    }
+}
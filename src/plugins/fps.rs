@@ -0,0 +1,130 @@
+use std::time::Duration;
+use crate::math::Transform;
+use crate::{g2d, AppBuilder, AssetManager, Color, Game, Plugin, RunContext, Scene, Stage, Tracker};
+
+/**
+ * Draws the number of frames rendered per second in the top-left corner of the screen, as [`g2d::Text`].
+ * Averaged over each update interval.
+ * [`crate::GraphicsPlugin`] must be installed first.
+ */
+pub struct FpsCounterPlugin {
+    /// Path of the font that the counter is drawn with.
+    pub font_path: String,
+    /// Height of the counter's text, in pixels.
+    pub size: f32,
+    pub color: Color,
+    /// How often the counter is updated.
+    pub update_interval: Duration,
+}
+
+impl FpsCounterPlugin {
+
+    pub fn new(font_path: impl Into<String>) -> Self {
+        Self {
+            font_path: font_path.into(),
+            size: 16.0,
+            color: Color::WHITE,
+            update_interval: Duration::from_millis(500),
+        }
+    }
+
+    pub fn with_size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_update_interval(mut self, update_interval: Duration) -> Self {
+        self.update_interval = update_interval;
+        self
+    }
+}
+
+impl Plugin for FpsCounterPlugin {
+    fn install(&mut self, builder: &mut AppBuilder) {
+        builder.system(Stage::PreRender, update_fps_counter);
+        let game = builder.game();
+        assert!(game.contains::<Scene<g2d::Text>>(), "GraphicsPlugin must be installed before FpsCounterPlugin");
+        let font = game.get::<&mut AssetManager>().load(&self.font_path);
+        let text = g2d::Text::new("", font, self.size)
+            .with_color(self.color)
+            .with_anchor(g2d::Anchor::TopLeft)
+            .with_layer(i32::MAX)
+            .with_transform(Transform::default().with_xyz(8.0, 8.0, 0.0));
+        let tracker = game.get::<&mut Scene<g2d::Text>>().insert(text);
+        game.add(FpsCounter::new(self.update_interval));
+        game.add(FpsCounterText(tracker));
+    }
+}
+
+/// Domain that counts frames for the [`FpsCounterPlugin`].
+pub struct FpsCounter {
+    /// Average frames per second over the last update interval, if one has elapsed.
+    pub fps: Option<f32>,
+    pub update_interval: Duration,
+    frames: u32,
+    elapsed: Duration,
+}
+
+impl FpsCounter {
+
+    pub fn new(update_interval: Duration) -> Self {
+        Self { fps: None, update_interval, frames: 0, elapsed: Duration::ZERO }
+    }
+
+    /// Counts a frame that took delta.
+    /// Updates the frames per second once an update interval has elapsed, and starts counting the next one.
+    /// Returns true if it was updated.
+    pub fn count_frame(&mut self, delta: Duration) -> bool {
+        self.frames += 1;
+        self.elapsed += delta;
+        if self.elapsed < self.update_interval {
+            return false;
+        }
+        self.fps = Some(self.frames as f32 / self.elapsed.as_secs_f32());
+        self.frames = 0;
+        self.elapsed = Duration::ZERO;
+        true
+    }
+}
+
+/// Domain tracking the text that the [`FpsCounterPlugin`] draws with.
+struct FpsCounterText(Tracker<g2d::Text>);
+
+fn update_fps_counter(game: &mut Game, ctx: RunContext) {
+    let mut counter = game.get::<&mut FpsCounter>();
+    if !counter.count_frame(ctx.delta()) { return }
+    let Some(fps) = counter.fps else { return };
+    let tracker = game.get::<&FpsCounterText>();
+    let mut texts = game.get::<&mut Scene<g2d::Text>>();
+    let Some(text) = texts.get_mut(tracker.0.id()) else { return };
+    text.string = format!("{fps:.0} FPS");
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use super::FpsCounter;
+
+    #[test]
+    fn averages_frames_over_interval() {
+        let mut counter = FpsCounter::new(Duration::from_millis(500));
+        let frame = Duration::from_millis(100);
+        for _ in 0..4 {
+            assert!(!counter.count_frame(frame));
+        }
+        assert_eq!(None, counter.fps);
+        assert!(counter.count_frame(frame));
+        assert_eq!(Some(10.0), counter.fps);
+
+        // Starts over after each update.
+        assert!(!counter.count_frame(Duration::from_millis(250)));
+        assert_eq!(Some(10.0), counter.fps);
+        assert!(counter.count_frame(Duration::from_millis(250)));
+        assert_eq!(Some(4.0), counter.fps);
+    }
+}
@@ -32,6 +32,80 @@ impl Color {
     pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
         Self { r, g, b, a }
     }
+
+    /// Color of a black-body radiator at the temperature specified, in linear space.
+    /// Uses Tanner Helland's approximation, which is valid from 1000K to 40000K.
+    /// Temperatures outside of that range are clamped.
+    pub fn from_temperature_kelvin(k: f32) -> Color {
+        let temp = k.clamp(1000.0, 40000.0) as f64 / 100.0;
+        let r = if temp <= 66.0 {
+            255.0
+        }
+        else {
+            329.698727446 * (temp - 60.0).powf(-0.1332047592)
+        };
+        let g = if temp <= 66.0 {
+            99.4708025861 * temp.ln() - 161.1195681661
+        }
+        else {
+            288.1221695283 * (temp - 60.0).powf(-0.0755148492)
+        };
+        let b = if temp >= 66.0 {
+            255.0
+        }
+        else if temp <= 19.0 {
+            0.0
+        }
+        else {
+            138.5177312231 * (temp - 10.0).ln() - 305.0447927307
+        };
+        let srgb = Color::new(
+            (r.clamp(0.0, 255.0) / 255.0) as f32,
+            (g.clamp(0.0, 255.0) / 255.0) as f32,
+            (b.clamp(0.0, 255.0) / 255.0) as f32,
+            1.0,
+        );
+        srgb.to_linear()
+    }
+
+    /// Converts from sRGB to linear space.
+    /// Alpha is left unchanged.
+    pub fn to_linear(self) -> Color {
+        fn to_linear(c: f32) -> f32 {
+            if c <= 0.04045 {
+                c / 12.92
+            }
+            else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        }
+        Color::new(to_linear(self.r), to_linear(self.g), to_linear(self.b), self.a)
+    }
+
+    /// Converts from linear to sRGB space.
+    /// Alpha is left unchanged.
+    pub fn to_srgb(self) -> Color {
+        fn to_srgb(c: f32) -> f32 {
+            if c <= 0.0031308 {
+                c * 12.92
+            }
+            else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            }
+        }
+        Color::new(to_srgb(self.r), to_srgb(self.g), to_srgb(self.b), self.a)
+    }
+}
+
+impl From<Color> for wgpu::Color {
+    fn from(color: Color) -> Self {
+        Self {
+            r: color.r as f64,
+            g: color.g as f64,
+            b: color.b as f64,
+            a: color.a as f64,
+        }
+    }
 }
 
 impl From<Color> for Material {
@@ -50,4 +124,33 @@ impl From<Handle<Texture>> for Material {
             ..Default::default()
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Color;
+
+    fn assert_close(a: Color, b: Color) {
+        let close = |x: f32, y: f32| (x - y).abs() < 0.001;
+        assert!(close(a.r, b.r) && close(a.g, b.g) && close(a.b, b.b) && close(a.a, b.a), "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn srgb_round_trip() {
+        let color = Color::new(0.0, 0.02, 0.5, 0.25);
+        assert_close(color, color.to_linear().to_srgb());
+        assert_close(Color::WHITE, Color::WHITE.to_linear());
+        assert_close(Color::new(0.214, 0.214, 0.214, 1.0), Color::GRAY.to_linear());
+    }
+
+    #[test]
+    fn from_temperature_kelvin() {
+        let candle = Color::from_temperature_kelvin(1900.0);
+        assert_eq!(1.0, candle.r);
+        assert_eq!(0.0, candle.b);
+        let daylight = Color::from_temperature_kelvin(6600.0);
+        assert_close(Color::WHITE, daylight);
+        let sky = Color::from_temperature_kelvin(20000.0);
+        assert!(sky.b > sky.r);
+    }
+}
@@ -0,0 +1,569 @@
+use std::f32::consts::PI;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use glam::{Vec3, Vec4};
+use half::f16;
+use image::{DynamicImage, ImageFormat};
+use image::io::Reader as ImageReader;
+use wgpu::{AddressMode, Device, Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, SamplerDescriptor, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureViewDescriptor, TextureViewDimension};
+use derive_more::*;
+use crate::{Asset, AssetLoader, AssetManager, AssetPath, AssetState, Color, Handle, Readiness};
+
+/// Format of every cube map. Filterable, and able to store HDR colors.
+const CUBE_MAP_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// File names of the faces of a cube map directory, in the order of the cube's layers.
+pub const CUBE_MAP_FACES: [&str; 6] = ["+x", "-x", "+y", "-y", "+z", "-z"];
+
+/**
+ * Loads a [`CubeMap`] from a single image, or from a .cubemap manifest.
+ * Images are read as equirectangular if twice as wide as they are tall, and as a cross if 4x3 or 3x4 faces in size.
+ * See [`CubeMap::from_cross`] for the layout of crosses.
+ *
+ * A manifest is a YAML mapping of each name in [`CUBE_MAP_FACES`] to an image, relative to the manifest. IE:
+ * ```yaml
+ * +x: right.png
+ * -x: left.png
+ * +y: top.png
+ * -y: bottom.png
+ * +z: front.png
+ * -z: back.png
+ * ```
+ * Faces of a manifest are loaded as [`CubeMapFace`]s through the [`AssetManager`], with the manifest's protocol.
+ * Until they all finish loading, the cube map is black, and not ready.
+ */
+pub struct CubeMapLoader {
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+}
+
+impl CubeMapLoader {
+
+    /**
+     * Loads a cube map from a directory holding one image per face, named after [`CUBE_MAP_FACES`].
+     * IE: +x.png, -x.png, +y.png, -y.png, +z.png, -z.png.
+     * Every face must be square, and of the same size.
+     */
+    pub fn load_dir(&self, dir: impl AsRef<Path>, extension: &str) -> anyhow::Result<CubeMap> {
+        let dir = dir.as_ref();
+        let face_paths = CUBE_MAP_FACES.map(|face| dir.join(format!("{face}.{extension}")));
+        self.load_faces(&face_paths)
+    }
+
+    /// Black cube map, whose faces are loaded from those listed in a manifest by [`insert_cube_map_faces`].
+    fn load_manifest(&self, bytes: &[u8], path: &AssetPath) -> anyhow::Result<CubeMap> {
+        let text = std::str::from_utf8(bytes)?;
+        let face_paths = parse_manifest(text)?.map(|face| path.resolve(&face));
+        Ok(CubeMap {
+            pending: Some(PendingFaces { face_paths, faces: None }),
+            ..CubeMap::solid(Color::BLACK, &self.device, &self.queue)
+        })
+    }
+
+    fn load_faces(&self, face_paths: &[PathBuf; 6]) -> anyhow::Result<CubeMap> {
+        let mut faces = Vec::with_capacity(6);
+        for face_path in face_paths {
+            faces.push(image::open(face_path)?);
+        }
+        let faces: [DynamicImage; 6] = faces.try_into().unwrap();
+        CubeMap::from_faces(&faces, &self.device, &self.queue)
+    }
+}
+
+impl AssetLoader for CubeMapLoader {
+
+    type AssetType = CubeMap;
+
+    fn load(&self, bytes: &[u8], path: &AssetPath) -> anyhow::Result<Self::AssetType> {
+        if path.extension == "cubemap" {
+            return self.load_manifest(bytes, path);
+        }
+        let image = decode_image(bytes, &path.extension)?;
+        CubeMap::from_image(&image, &self.device, &self.queue)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["hdr", "png", "jpg", "jpeg", "cubemap"]
+    }
+}
+
+/// Loads a [`CubeMapFace`], for the faces of cube maps loaded from manifests.
+pub struct CubeMapFaceLoader;
+
+impl AssetLoader for CubeMapFaceLoader {
+
+    type AssetType = CubeMapFace;
+
+    fn load(&self, bytes: &[u8], path: &AssetPath) -> anyhow::Result<Self::AssetType> {
+        Ok(CubeMapFace(decode_image(bytes, &path.extension)?))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["hdr", "png", "jpg", "jpeg"]
+    }
+}
+
+/// Decoded image of a single face of a cube map.
+pub struct CubeMapFace(pub DynamicImage);
+
+impl Asset for CubeMapFace {}
+
+fn decode_image(bytes: &[u8], extension: &str) -> anyhow::Result<DynamicImage> {
+    let Some(format) = ImageFormat::from_extension(extension) else {
+        return Err(CubeMapError::UnsupportedFileExtension.into());
+    };
+    let mut reader = ImageReader::new(Cursor::new(bytes));
+    reader.set_format(format);
+    Ok(reader.decode()?)
+}
+
+/**
+ * A cube texture with an associated sampler, sampled by direction.
+ * Colors are stored linearly, with a full mip chain.
+ * Used by skyboxes, and as the environment map of physically-based materials.
+ */
+pub struct CubeMap {
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    /// Width and height of each face in texels.
+    pub size: u32,
+    /// Number of mip levels.
+    pub mip_level_count: u32,
+    pending: Option<PendingFaces>,
+}
+
+impl CubeMap {
+
+    /// Cube map from six square faces of the same size, ordered like [`CUBE_MAP_FACES`].
+    /// Colors of 8-bit images are decoded from sRGB.
+    pub fn from_faces(faces: &[DynamicImage; 6], device: &Device, queue: &Queue) -> anyhow::Result<Self> {
+        Self::from_face_refs(faces.each_ref(), device, queue)
+    }
+
+    fn from_face_refs(faces: [&DynamicImage; 6], device: &Device, queue: &Queue) -> anyhow::Result<Self> {
+        let size = faces[0].width();
+        if faces.iter().any(|face| face.width() != size || face.height() != size) {
+            return Err(CubeMapError::InvalidFaceSize.into());
+        }
+        let is_srgb = faces.iter().all(|face| face.color().bytes_per_pixel() / face.color().channel_count() == 1);
+        let faces: Vec<Vec<Vec4>> = faces.iter().map(|face| linear_texels(face, is_srgb)).collect();
+        Ok(Self::from_texels(&faces, size, device, queue))
+    }
+
+    /// Cube map from an equirectangular or cross-shaped image, depending on its aspect ratio.
+    pub fn from_image(image: &DynamicImage, device: &Device, queue: &Queue) -> anyhow::Result<Self> {
+        match image.width() == image.height() * 2 {
+            true => Self::from_equirectangular(image, device, queue),
+            false => Self::from_cross(image, device, queue),
+        }
+    }
+
+    /**
+     * Cube map from an image of six faces laid out in a cross.
+     * Horizontal crosses are 4 faces wide and 3 tall. Vertical crosses are 3 wide and 4 tall, with -z upside down at the bottom.
+     * ```text
+     *       +y                  +y
+     *   -x  +z  +x  -z      -x  +z  +x
+     *       -y                  -y
+     *                           -z
+     * ```
+     */
+    pub fn from_cross(image: &DynamicImage, device: &Device, queue: &Queue) -> anyhow::Result<Self> {
+        let faces = cross_faces(image).ok_or(CubeMapError::InvalidCrossSize)?;
+        Self::from_faces(&faces, device, queue)
+    }
+
+    /// Cube map from an equirectangular image, whose width is twice its height.
+    /// Each face is a quarter of the image's width.
+    pub fn from_equirectangular(image: &DynamicImage, device: &Device, queue: &Queue) -> anyhow::Result<Self> {
+        let (width, height) = (image.width(), image.height());
+        if width == 0 || width != height * 2 {
+            return Err(CubeMapError::InvalidEquirectangularSize.into());
+        }
+        let texels = linear_texels(image, false);
+        let size = (width / 4).max(1);
+        let faces: Vec<Vec<Vec4>> = (0..6)
+            .map(|face| face_texels(face, size, |direction| {
+                let (u, v) = equirectangular_uv(direction);
+                let x = ((u * width as f32) as u32).min(width - 1);
+                let y = ((v * height as f32) as u32).min(height - 1);
+                texels[(y * width + x) as usize]
+            }))
+            .collect();
+        Ok(Self::from_texels(&faces, size, device, queue))
+    }
+
+    /// 1x1 cube map of a single color.
+    pub fn solid(color: Color, device: &Device, queue: &Queue) -> Self {
+        let Color { r, g, b, a } = color;
+        let faces = vec![vec![Vec4::new(r, g, b, a)]; 6];
+        Self::from_texels(&faces, 1, device, queue)
+    }
+
+    /// Uploads six faces of linear colors, and their mip chains.
+    fn from_texels(faces: &[Vec<Vec4>], size: u32, device: &Device, queue: &Queue) -> Self {
+        let mip_level_count = u32::BITS - size.leading_zeros();
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("cube_map"),
+            size: Extent3d { width: size, height: size, depth_or_array_layers: 6 },
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: CUBE_MAP_FORMAT,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        for (layer, face) in faces.iter().enumerate() {
+            let mut mip = face.clone();
+            let mut mip_size = size;
+            for mip_level in 0..mip_level_count {
+                let bytes: Vec<u8> = mip
+                    .iter()
+                    .flat_map(|texel| texel.to_array())
+                    .flat_map(|channel| f16::from_f32(channel).to_le_bytes())
+                    .collect();
+                queue.write_texture(
+                    ImageCopyTexture {
+                        texture: &texture,
+                        mip_level,
+                        origin: Origin3d { x: 0, y: 0, z: layer as u32 },
+                        aspect: TextureAspect::All,
+                    },
+                    &bytes,
+                    ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(mip_size * 8),
+                        rows_per_image: None,
+                    },
+                    Extent3d { width: mip_size, height: mip_size, depth_or_array_layers: 1 },
+                );
+                mip = downsample(&mip, mip_size);
+                mip_size = (mip_size / 2).max(1);
+            }
+        }
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("cube_map_sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        Self { view, sampler, size, mip_level_count, pending: None }
+    }
+
+    /// True if loaded from a manifest whose faces have not all finished loading.
+    pub(crate) fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+}
+
+impl Asset for CubeMap {
+    fn readiness(&self, _assets: &AssetManager) -> Readiness {
+        match self.pending {
+            Some(_) => Readiness::NotReady,
+            None => Readiness::Ready,
+        }
+    }
+}
+
+/// Faces of a cube map loaded from a manifest, awaiting loading.
+struct PendingFaces {
+    face_paths: [String; 6],
+    faces: Option<Vec<Handle<CubeMapFace>>>,
+}
+
+/**
+ * Starts loading the faces of [`CubeMap`]s loaded from manifests, and uploads them once they all finish loading.
+ * If a face fails to load, the cube map stays black.
+ */
+pub(crate) fn insert_cube_map_faces(assets: &mut AssetManager, device: &Device, queue: &Queue) {
+
+    // Starts loading faces of newly loaded cube maps
+    let mut face_loads = Vec::new();
+    {
+        let mut cube_maps = assets.storage::<CubeMap>().unwrap();
+        for (index, cube_map) in cube_maps.iter() {
+            let Some(pending) = cube_map.as_loaded().and_then(|cube_map| cube_map.pending.as_ref()) else { continue };
+            if pending.faces.is_some() { continue }
+            face_loads.push((index, pending.face_paths.clone()));
+        }
+    }
+    let face_handles: Vec<_> = face_loads
+        .into_iter()
+        .map(|(index, face_paths)| {
+            let handles: Result<Vec<_>, _> = face_paths
+                .iter()
+                .map(|face_path| assets.try_load::<CubeMapFace, _>(face_path))
+                .collect();
+            if let Err(err) = &handles {
+                log::error!("Failed to load faces of cube map: {err}");
+            }
+            (index, handles.ok())
+        })
+        .collect();
+
+    // Uploads cube maps whose faces all finished loading
+    let mut cube_maps = assets.storage::<CubeMap>().unwrap();
+    let faces = assets.storage::<CubeMapFace>().unwrap();
+    for (index, handles) in face_handles {
+        let Some(cube_map) = cube_maps.inner.get_mut(index).and_then(|cube_map| cube_map.as_loaded_mut()) else { continue };
+        let Some(pending) = &mut cube_map.pending else { continue };
+        match handles {
+            Some(handles) => pending.faces = Some(handles),
+            None => cube_map.pending = None,
+        }
+    }
+    for cube_map in cube_maps.values_mut() {
+        let Some(cube_map) = cube_map.as_loaded_mut() else { continue };
+        let Some(handles) = cube_map.pending.as_ref().and_then(|pending| pending.faces.as_ref()) else { continue };
+        let states: Vec<AssetState<&CubeMapFace>> = handles.iter().map(|handle| faces.get(handle)).collect();
+        if states.iter().any(AssetState::is_loading) { continue }
+        let images: Option<Vec<&DynamicImage>> = states
+            .iter()
+            .map(|state| state.as_loaded().map(|face| &face.0))
+            .collect();
+        let Some(images) = images else {
+            log::error!("Failed to load faces of cube map");
+            cube_map.pending = None;
+            continue;
+        };
+        match CubeMap::from_face_refs(images.try_into().unwrap(), device, queue) {
+            Ok(loaded) => *cube_map = loaded,
+            Err(err) => {
+                log::error!("{err}");
+                cube_map.pending = None;
+            },
+        }
+    }
+}
+
+#[derive(Error, Debug, Display)]
+pub enum CubeMapError {
+    #[display(fmt="Faces of a cube map must be square, and of the same size")]
+    InvalidFaceSize,
+    #[display(fmt="Equirectangular images must be twice as wide as they are tall")]
+    InvalidEquirectangularSize,
+    #[display(fmt="Cross images must be 4x3 or 3x4 square faces in size")]
+    InvalidCrossSize,
+    #[display(fmt="Unsupported file extension")]
+    UnsupportedFileExtension,
+    #[display(fmt="Cube map manifest is missing face {_0}")]
+    #[error(ignore)]
+    MissingFace(&'static str),
+}
+
+/// Paths of the faces listed in a manifest, ordered like [`CUBE_MAP_FACES`].
+fn parse_manifest(text: &str) -> anyhow::Result<[String; 6]> {
+    let mut faces: HashMap<String, String> = serde_yaml::from_str(text)?;
+    let mut face_paths = Vec::with_capacity(6);
+    for face in CUBE_MAP_FACES {
+        let face_path = faces.remove(face).ok_or(CubeMapError::MissingFace(face))?;
+        face_paths.push(face_path);
+    }
+    Ok(face_paths.try_into().unwrap())
+}
+
+/// Faces of a cross-shaped image, ordered like [`CUBE_MAP_FACES`].
+/// None if the image is not 4x3 or 3x4 square faces in size.
+fn cross_faces(image: &DynamicImage) -> Option<[DynamicImage; 6]> {
+    let (width, height) = (image.width(), image.height());
+    let (horizontal, size) = match (width * 3 == height * 4, width * 4 == height * 3) {
+        (true, _) => (true, width / 4),
+        (_, true) => (false, width / 3),
+        _ => return None,
+    };
+    if size == 0 { return None }
+
+    // Column and row of each face
+    let cells = match horizontal {
+        true => [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)],
+        false => [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (1, 3)],
+    };
+    let faces = cells.map(|(column, row)| image.crop_imm(column * size, row * size, size, size));
+    let [px, nx, py, ny, pz, nz] = faces;
+    let nz = match horizontal {
+        true => nz,
+        false => nz.rotate180(),
+    };
+    Some([px, nx, py, ny, pz, nz])
+}
+
+/// Colors of an image as linear RGBA, row by row.
+fn linear_texels(image: &DynamicImage, is_srgb: bool) -> Vec<Vec4> {
+    image
+        .to_rgba32f()
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            match is_srgb {
+                true => Vec4::new(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a),
+                false => Vec4::new(r, g, b, a),
+            }
+        })
+        .collect()
+}
+
+fn srgb_to_linear(channel: f32) -> f32 {
+    match channel <= 0.04045 {
+        true => channel / 12.92,
+        false => ((channel + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+/// Texels of a face, where sample returns the color seen in a direction.
+fn face_texels(face: usize, size: u32, sample: impl Fn(Vec3) -> Vec4) -> Vec<Vec4> {
+    let mut texels = Vec::with_capacity((size * size) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let s = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+            let t = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+            texels.push(sample(face_direction(face, s, t).normalize()));
+        }
+    }
+    texels
+}
+
+/**
+ * Direction that a point on a face of the cube faces, like wgpu samples it.
+ * s and t are in the -1..1 range, from left to right, and from top to bottom.
+ */
+fn face_direction(face: usize, s: f32, t: f32) -> Vec3 {
+    match face {
+        0 => Vec3::new(1.0, -t, -s),
+        1 => Vec3::new(-1.0, -t, s),
+        2 => Vec3::new(s, 1.0, t),
+        3 => Vec3::new(s, -1.0, -t),
+        4 => Vec3::new(s, -t, 1.0),
+        _ => Vec3::new(-s, -t, -1.0),
+    }
+}
+
+/// UV of a direction in an equirectangular image, whose center faces -Z.
+fn equirectangular_uv(direction: Vec3) -> (f32, f32) {
+    let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * PI);
+    let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+    (u, v)
+}
+
+/// Averages each 2x2 block of texels of a square image.
+fn downsample(texels: &[Vec4], size: u32) -> Vec<Vec4> {
+    let half_size = (size / 2).max(1);
+    let texel = |x: u32, y: u32| texels[(y.min(size - 1) * size + x.min(size - 1)) as usize];
+    let mut result = Vec::with_capacity((half_size * half_size) as usize);
+    for y in 0..half_size {
+        for x in 0..half_size {
+            let (x, y) = (x * 2, y * 2);
+            result.push((texel(x, y) + texel(x + 1, y) + texel(x, y + 1) + texel(x + 1, y + 1)) / 4.0);
+        }
+    }
+    result
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::time::Duration;
+    use glam::{Vec3, Vec4};
+    use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+    use crate::{AssetManager, CubeMap, CubeMapFace, CubeMapFaceLoader, CubeMapLoader, EmbeddedProtocol, Readiness};
+    use crate::plugins::graphics::headless;
+    use super::{cross_faces, downsample, equirectangular_uv, face_direction, insert_cube_map_faces, parse_manifest};
+
+    #[test]
+    fn face_centers_face_axes() {
+        let axes = [Vec3::X, Vec3::NEG_X, Vec3::Y, Vec3::NEG_Y, Vec3::Z, Vec3::NEG_Z];
+        for (face, axis) in axes.into_iter().enumerate() {
+            assert_eq!(axis, face_direction(face, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn equirectangular_center_faces_forward() {
+        assert_eq!((0.5, 0.5), equirectangular_uv(Vec3::NEG_Z));
+        assert_eq!(0.0, equirectangular_uv(Vec3::Y).1);
+        assert_eq!(0.75, equirectangular_uv(Vec3::X).0);
+    }
+
+    #[test]
+    fn downsample_averages_blocks() {
+        let texels = [Vec4::ZERO, Vec4::ONE, Vec4::ONE, Vec4::ZERO];
+        assert_eq!(vec![Vec4::splat(0.5)], downsample(&texels, 2));
+    }
+
+    /// Cross of 1x1 faces, where each cell's red channel is its column and green channel is its row.
+    fn cross(columns: u32, rows: u32) -> DynamicImage {
+        let image = RgbImage::from_fn(columns, rows, |x, y| Rgb([x as u8, y as u8, 0]));
+        DynamicImage::ImageRgb8(image)
+    }
+
+    fn cells(faces: &[DynamicImage; 6]) -> Vec<(u8, u8)> {
+        faces
+            .iter()
+            .map(|face| {
+                let [column, row, _] = face.to_rgb8().get_pixel(0, 0).0;
+                (column, row)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cross_faces_horizontal() {
+        let faces = cross_faces(&cross(4, 3)).unwrap();
+        assert_eq!(vec![(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)], cells(&faces));
+    }
+
+    #[test]
+    fn cross_faces_vertical() {
+        let faces = cross_faces(&cross(3, 4)).unwrap();
+        assert_eq!(vec![(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (1, 3)], cells(&faces));
+        assert!(cross_faces(&cross(4, 4)).is_none());
+    }
+
+    #[test]
+    fn manifest_orders_faces() {
+        let text = "-z: back.png\n+z: front.png\n-y: down.png\n+y: up.png\n-x: left.png\n+x: right.png\n";
+        let faces = parse_manifest(text).unwrap();
+        assert_eq!(["right.png", "left.png", "up.png", "down.png", "front.png", "back.png"], faces);
+        assert!(parse_manifest("+x: right.png").is_err());
+    }
+
+    #[test]
+    fn manifest_faces_load_as_assets() {
+        let Some((device, queue)) = headless::device() else { return };
+        let mut face = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(4, 4)).write_to(&mut Cursor::new(&mut face), ImageFormat::Png).unwrap();
+        let face: &'static [u8] = Box::leak(face.into_boxed_slice());
+        let mut embedded = EmbeddedProtocol::new().with("sky/sky.cubemap", b"+x: px.png\n-x: nx.png\n+y: py.png\n-y: ny.png\n+z: pz.png\n-z: nz.png\n");
+        for name in ["px", "nx", "py", "ny", "pz", "nz"] {
+            embedded.register(format!("sky/{name}.png"), face);
+        }
+        let mut assets = AssetManager::new();
+        assets.add_protocol(embedded, true);
+        assets.add_storage::<CubeMap>();
+        assets.add_storage::<CubeMapFace>();
+        assets.add_loader(CubeMapLoader { device: device.clone(), queue: queue.clone() }).unwrap();
+        assets.add_loader(CubeMapFaceLoader).unwrap();
+        let cube_map = assets.load::<CubeMap, _>("sky/sky.cubemap");
+        for _ in 0..100 {
+            assets.try_handle_messages();
+            insert_cube_map_faces(&mut assets, &device, &queue);
+            if assets.dyn_readiness(cube_map.id()) == Readiness::Ready {
+                let cube_maps = assets.storage::<CubeMap>().unwrap();
+                assert_eq!(4, cube_maps.get(&cube_map).unwrap().size);
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Cube map did not finish loading");
+    }
+}
@@ -0,0 +1,243 @@
+use ab_glyph::{Font as _, FontArc, GlyphId, PxScale};
+use glam::{UVec2, Vec2};
+use wgpu::{AddressMode, Device, Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, SamplerDescriptor, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureViewDescriptor};
+use crate::{HashMap, Rect, Texture};
+
+/// Width and height of a new atlas, in texels.
+const INITIAL_SIZE: u32 = 256;
+
+/// Empty texels to the right of and below each glyph, so that filtering does not bleed neighbors into it.
+const PADDING: u32 = 1;
+
+/// Where a glyph was rasterized in a [`GlyphAtlas`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct AtlasGlyph {
+    /// Region of the atlas, in texels.
+    pub rect: Rect,
+    /// Offset from the glyph's pen position on the baseline to the top-left corner of its region, in pixels.
+    pub offset: Vec2,
+}
+
+/**
+ * Texture that glyphs of a single font and size are rasterized into as they are first drawn.
+ * Glyphs are packed in rows, and the atlas doubles in size when a glyph does not fit.
+ * Texels are white, with coverage stored as alpha.
+ */
+pub(crate) struct GlyphAtlas {
+    scale: PxScale,
+    glyphs: HashMap<GlyphId, Option<AtlasGlyph>>,  // None for glyphs without an outline, IE: spaces
+    pixels: Vec<u8>,
+    size: UVec2,
+    cursor: UVec2,          // Top-left corner of the next glyph on the current row
+    row_height: u32,        // Height of the tallest glyph on the current row, padding included
+    texture: Option<(wgpu::Texture, Texture)>,  // GPU copy of the pixels, and how it is sampled
+    dirty: bool,
+}
+
+impl GlyphAtlas {
+
+    /// Empty atlas for glyphs that are size pixels tall.
+    pub fn new(size: f32) -> Self {
+        Self {
+            scale: PxScale::from(size),
+            glyphs: HashMap::default(),
+            pixels: vec![0; (INITIAL_SIZE * INITIAL_SIZE * 4) as usize],
+            size: UVec2::splat(INITIAL_SIZE),
+            cursor: UVec2::ZERO,
+            row_height: 0,
+            texture: None,
+            dirty: true,
+        }
+    }
+
+    /// Width and height in texels.
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    /// Region of the glyph, rasterizing it first if this is the first time it is requested.
+    /// None if the glyph has no outline.
+    pub fn glyph(&mut self, font: &FontArc, id: GlyphId) -> Option<AtlasGlyph> {
+        if let Some(glyph) = self.glyphs.get(&id) {
+            return *glyph;
+        }
+        let glyph = self.rasterize(font, id);
+        self.glyphs.insert(id, glyph);
+        glyph
+    }
+
+    /**
+     * Texture of the atlas, uploaded with every glyph rasterized so far.
+     * Recreated if the atlas grew since the last upload.
+     */
+    pub fn upload(&mut self, device: &Device, queue: &Queue) -> &Texture {
+        let (raw_texture, texture) = match self.texture.take() {
+            Some((raw_texture, texture)) if texture.size == self.size => (raw_texture, texture),
+            _ => {
+                self.dirty = true;
+                create_texture(self.size, device)
+            },
+        };
+        if self.dirty {
+            self.write_pixels(&raw_texture, queue);
+            self.dirty = false;
+        }
+        let (_, texture) = self.texture.insert((raw_texture, texture));
+        texture
+    }
+
+    fn rasterize(&mut self, font: &FontArc, id: GlyphId) -> Option<AtlasGlyph> {
+        let outlined = font.outline_glyph(id.with_scale(self.scale))?;
+        let bounds = outlined.px_bounds();
+        let glyph_size = UVec2::new(bounds.width() as u32, bounds.height() as u32);
+        let origin = self.allocate(glyph_size);
+        let atlas_width = self.size.x;
+        outlined.draw(|x, y, coverage| {
+            let index = (((origin.y + y) * atlas_width + origin.x + x) * 4) as usize;
+            let alpha = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+            self.pixels[index..index+4].copy_from_slice(&[255, 255, 255, alpha]);
+        });
+        self.dirty = true;
+        Some(AtlasGlyph {
+            rect: Rect { origin: origin.as_vec2(), size: glyph_size.as_vec2() },
+            offset: Vec2::new(bounds.min.x, bounds.min.y),
+        })
+    }
+
+    /// Top-left corner of a free region of the size specified, growing the atlas until one is found.
+    fn allocate(&mut self, size: UVec2) -> UVec2 {
+        let padded = size + PADDING;
+        loop {
+            if self.cursor.x + padded.x > self.size.x {
+                self.cursor = UVec2::new(0, self.cursor.y + self.row_height);
+                self.row_height = 0;
+            }
+            if self.cursor.x + padded.x <= self.size.x && self.cursor.y + padded.y <= self.size.y {
+                let origin = self.cursor;
+                self.cursor.x += padded.x;
+                self.row_height = self.row_height.max(padded.y);
+                return origin;
+            }
+            self.grow();
+        }
+    }
+
+    /// Doubles the width and height of the atlas, keeping glyphs where they are.
+    fn grow(&mut self) {
+        let new_size = self.size * 2;
+        let mut pixels = vec![0; (new_size.x * new_size.y * 4) as usize];
+        let row_len = (self.size.x * 4) as usize;
+        let new_row_len = (new_size.x * 4) as usize;
+        for (row, new_row) in self.pixels.chunks_exact(row_len).zip(pixels.chunks_exact_mut(new_row_len)) {
+            new_row[..row_len].copy_from_slice(row);
+        }
+        self.pixels = pixels;
+        self.size = new_size;
+        self.dirty = true;
+    }
+
+    fn write_pixels(&self, texture: &wgpu::Texture, queue: &Queue) {
+        queue.write_texture(
+            ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            &self.pixels,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.size.x * 4),
+                rows_per_image: None,
+            },
+            Extent3d { width: self.size.x, height: self.size.y, depth_or_array_layers: 1 },
+        );
+    }
+}
+
+fn create_texture(size: UVec2, device: &Device) -> (wgpu::Texture, Texture) {
+    let raw_texture = device.create_texture(&TextureDescriptor {
+        label: Some("glyph_atlas"),
+        size: Extent3d { width: size.x, height: size.y, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let sampler = device.create_sampler(&SamplerDescriptor {
+        label: Some("glyph_atlas_sampler"),
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        address_mode_w: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        mipmap_filter: FilterMode::Nearest,
+        ..Default::default()
+    });
+    let texture = Texture {
+        view: raw_texture.create_view(&TextureViewDescriptor::default()),
+        sampler,
+        size,
+        srgb: raw_texture.format().is_srgb(),
+    };
+    (raw_texture, texture)
+}
+
+#[cfg(test)]
+mod test {
+    use ab_glyph::{Font as _, FontArc};
+    use glam::UVec2;
+    use super::{GlyphAtlas, INITIAL_SIZE};
+
+    fn font() -> FontArc {
+        FontArc::try_from_slice(include_bytes!("../../../../assets/fonts/DejaVuSansMono.ttf")).unwrap()
+    }
+
+    #[test]
+    fn glyphs_are_rasterized_once() {
+        let font = font();
+        let mut atlas = GlyphAtlas::new(20.0);
+        let a = atlas.glyph(&font, font.glyph_id('a')).unwrap();
+        let b = atlas.glyph(&font, font.glyph_id('b')).unwrap();
+        assert_ne!(a.rect.origin, b.rect.origin);
+        assert_eq!(Some(a), atlas.glyph(&font, font.glyph_id('a')));
+        assert_eq!(None, atlas.glyph(&font, font.glyph_id(' ')));
+
+        // Glyphs rise above the baseline.
+        assert!(a.offset.y < 0.0);
+    }
+
+    #[test]
+    fn atlas_grows_and_keeps_glyphs() {
+        let font = font();
+        let mut atlas = GlyphAtlas::new(100.0);
+        let first = atlas.glyph(&font, font.glyph_id('A')).unwrap();
+        let first_texel = |atlas: &GlyphAtlas| {
+            let origin = first.rect.origin.as_uvec2();
+            let (x, y) = (origin.x, origin.y + first.rect.size.y as u32 - 1);
+            let index = ((y * atlas.size.x + x) * 4) as usize;
+            atlas.pixels[index..index+4].to_vec()
+        };
+        let before = first_texel(&atlas);
+        assert!(before[3] > 0);
+        for c in 'B'..='Z' {
+            atlas.glyph(&font, font.glyph_id(c)).unwrap();
+        }
+        assert!(atlas.size().x > INITIAL_SIZE);
+        assert_eq!(UVec2::splat(atlas.size().x), atlas.size());
+        assert_eq!(Some(first), atlas.glyph(&font, font.glyph_id('A')));
+        assert_eq!(before, first_texel(&atlas));
+
+        // Glyphs never overlap, padding included.
+        let rects: Vec<_> = ('A'..='Z').map(|c| atlas.glyph(&font, font.glyph_id(c)).unwrap().rect).collect();
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i+1..] {
+                let overlap_x = a.origin.x < b.origin.x + b.size.x + 1.0 && b.origin.x < a.origin.x + a.size.x + 1.0;
+                let overlap_y = a.origin.y < b.origin.y + b.size.y + 1.0 && b.origin.y < a.origin.y + a.size.y + 1.0;
+                assert!(!(overlap_x && overlap_y), "{a:?} overlaps {b:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use ab_glyph::FontArc;
+use crate::{Asset, AssetLoader, AssetPath};
+
+/**
+ * A TrueType or OpenType font that [`super::Text`] is drawn with.
+ * Glyphs are rasterized lazily by the [`G2D`](super::G2D) engine, into one atlas per size that the font is drawn at.
+ */
+#[derive(Clone)]
+pub struct Font {
+    pub(crate) font: FontArc,
+}
+
+impl Font {
+
+    /// Parses a font from the contents of a .ttf or .otf file.
+    pub fn from_bytes(bytes: Vec<u8>) -> anyhow::Result<Self> {
+        let font = FontArc::try_from_vec(bytes)?;
+        Ok(Self { font })
+    }
+}
+
+impl Asset for Font {}
+
+/// Loads [`Font`]s from .ttf and .otf files.
+pub struct FontLoader;
+
+impl AssetLoader for FontLoader {
+
+    type AssetType = Font;
+
+    fn load(&self, bytes: &[u8], _path: &AssetPath) -> anyhow::Result<Self::AssetType> {
+        Font::from_bytes(bytes.to_vec())
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ttf", "otf"]
+    }
+}
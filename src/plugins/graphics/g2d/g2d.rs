@@ -0,0 +1,453 @@
+use std::mem::size_of;
+use std::ops::Range;
+use std::sync::Arc;
+use bytemuck::{Pod, Zeroable};
+use glam::{Affine3A, Mat4, Vec2, Vec4};
+use tracing::instrument;
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupLayout, BindGroupLayoutDescriptor, BlendState, Buffer, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, CommandEncoder, Device, FragmentState, FrontFace, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, Queue, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, StoreOp, TextureFormat, TextureView, VertexAttribute, VertexBufferLayout, VertexFormat, VertexState, VertexStepMode};
+use crate::{reserve_buffer, AssetId, AssetState, AssetStorage, Color, HashMap, Scene, Texture};
+use super::{layout_text, Font, GlyphAtlas, Sprite, Text};
+
+const INSTANCE_LAYOUT: VertexBufferLayout<'static> = VertexBufferLayout {
+    array_stride: size_of::<SpriteInstance>() as u64,
+    step_mode: VertexStepMode::Instance,
+    attributes: &[
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 0,
+            shader_location: 0,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 1*4*4,
+            shader_location: 1,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 2*4*4,
+            shader_location: 2,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 3*4*4,
+            shader_location: 3,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 4*4*4,
+            shader_location: 4,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 5*4*4,
+            shader_location: 5,
+        },
+    ],
+};
+
+/**
+ * 2D graphics engine.
+ * Draws sprites and the glyphs of text as instanced quads, in a render pass that runs after the 3D engine's.
+ * Quads are sorted by layer, and consecutive quads that share a texture are drawn with a single draw call.
+ */
+pub(crate) struct G2D {
+    pipelines: HashMap<(TextureFormat, u32), RenderPipeline>,   // Cache of pipelines by target format and sample count
+    atlases: HashMap<AtlasKey, GlyphAtlas>,                     // Glyph atlases by font and size, created as text is first drawn
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    instances: Buffer,
+    texture_layout: BindGroupLayout,
+}
+
+/// Sprites and text to draw in a frame, with the storages of the textures and fonts they reference.
+pub(crate) struct G2DScene<'a> {
+    pub sprites: &'a mut Scene<Sprite>,
+    pub texts: &'a mut Scene<Text>,
+    pub textures: &'a AssetStorage<'a, Texture>,
+    pub fonts: &'a AssetStorage<'a, Font>,
+}
+
+/// Color target that a [`G2D`] draws over.
+pub(crate) struct G2DTarget<'a> {
+    pub color_view: &'a TextureView,
+    pub resolve_view: Option<&'a TextureView>,     // Receives the resolved image if color_view is multisampled
+    pub format: TextureFormat,
+    pub sample_count: u32,
+    pub size: Vec2,                                 // Size in pixels
+}
+
+/// Font id and size bits of a glyph atlas.
+type AtlasKey = (AssetId, u32);
+
+/// Texture that a batch of quads samples.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum BatchTexture {
+    Texture(AssetId),
+    Atlas(AtlasKey),
+}
+
+impl G2D {
+
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>) -> Self {
+        let texture_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("g2d_texture_layout"),
+            entries: &texture_layout_entries(),
+        });
+        Self {
+            pipelines: HashMap::default(),
+            atlases: HashMap::default(),
+            device: device.clone(),
+            queue,
+            instances: device.create_buffer(&BufferDescriptor {
+                label: Some("g2d_instances"),
+                size: 0,
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            texture_layout,
+        }
+    }
+
+    /**
+     * Draws all sprites whose textures are loaded, and all text whose fonts are loaded, over the target in a single render pass.
+     * Sprites and text are positioned in pixels, with the origin at the top-left corner of the target.
+     * Does nothing if there is nothing to draw.
+     */
+    #[instrument(skip_all)]
+    pub fn render(&mut self, scene: G2DScene, target: G2DTarget, encoder: &mut CommandEncoder) {
+
+        // Collects quads of sprites and glyphs, and groups them into batches
+        let G2DScene { sprites, texts, textures, fonts } = scene;
+        let projection = pixel_projection(target.size);
+        let mut quads = Vec::new();
+        let mut sprite_textures = HashMap::default();
+        for flat_sprite in flatten_sprites(sprites) {
+            let AssetState::Loaded(texture) = textures.get(&flat_sprite.sprite.texture) else { continue };
+            let texture_id = flat_sprite.sprite.texture.id();
+            quads.push((flat_sprite.sprite.layer, BatchTexture::Texture(texture_id), SpriteInstance::new(&flat_sprite, texture, projection)));
+            sprite_textures.insert(texture_id, texture);
+        }
+        let flat_texts = flatten_texts(texts);
+        quads.extend(self.glyph_quads(&flat_texts, fonts, projection));
+        if quads.is_empty() {
+            return;
+        }
+        quads.sort_by_key(|(layer, _, _)| *layer);
+        let batches = batch_ranges(quads.iter().map(|(_, batch_texture, _)| *batch_texture));
+
+        // Uploads instances and atlases, and creates a bind group for each batch's texture
+        let instances: Vec<SpriteInstance> = quads.iter().map(|(_, _, instance)| *instance).collect();
+        let instance_bytes: &[u8] = bytemuck::cast_slice(&instances);
+        reserve_buffer(&mut self.instances, instance_bytes.len() as u64, &self.device);
+        self.queue.write_buffer(&self.instances, 0, instance_bytes);
+        let mut atlas_textures = HashMap::default();
+        for (key, atlas) in &mut self.atlases {
+            atlas_textures.insert(*key, atlas.upload(&self.device, &self.queue));
+        }
+        let bind_groups: Vec<BindGroup> = batches
+            .iter()
+            .map(|batch| {
+                let texture = match quads[batch.start as usize].1 {
+                    BatchTexture::Texture(texture_id) => sprite_textures[&texture_id],
+                    BatchTexture::Atlas(key) => atlas_textures[&key],
+                };
+                let entries = texture.create_entries(0, 1);
+                self.device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("g2d_texture_bind_group"),
+                    layout: &self.texture_layout,
+                    entries: &[entries.group_texture_entry, entries.group_sampler_entry],
+                })
+            })
+            .collect();
+
+        // Draws batches over what was already rendered
+        let pipeline = self.pipelines
+            .entry((target.format, target.sample_count))
+            .or_insert_with(|| create_pipeline(target.format, target.sample_count, &self.texture_layout, &self.device));
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("g2d_pass"),
+            color_attachments: &[
+                Some(RenderPassColorAttachment {
+                    view: target.color_view,
+                    resolve_target: target.resolve_view,
+                    ops: Operations { load: LoadOp::Load, store: StoreOp::Store },
+                })
+            ],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_vertex_buffer(0, self.instances.slice(..));
+        for (batch, bind_group) in batches.into_iter().zip(&bind_groups) {
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.draw(0..6, batch);
+        }
+    }
+
+    /**
+     * Lays out text, and rasterizes glyphs that were not drawn before into the atlas of their font and size.
+     * Produces a quad for each visible glyph, sorted like the text it belongs to.
+     */
+    fn glyph_quads(&mut self, flat_texts: &[FlatText], fonts: &AssetStorage<Font>, projection: Mat4) -> Vec<(i32, BatchTexture, SpriteInstance)> {
+
+        // Places glyphs in atlases first, as atlases that grow change the texture coordinates of all of their glyphs
+        let mut glyphs = Vec::new();
+        for flat_text in flat_texts {
+            let text = flat_text.text;
+            let AssetState::Loaded(font) = fonts.get(&text.font) else { continue };
+            let key = (text.font.id(), text.size.to_bits());
+            let atlas = self.atlases.entry(key).or_insert_with(|| GlyphAtlas::new(text.size));
+            let anchor = text.anchor.fraction();
+            let layout = layout_text(&font.font, text.size, &text.string, text.max_width, anchor.x);
+            let origin = -layout.size * anchor;
+            for laid_glyph in layout.glyphs {
+                let Some(atlas_glyph) = atlas.glyph(&font.font, laid_glyph.id) else { continue };
+                glyphs.push((text, flat_text.global_transform, key, origin + laid_glyph.position, atlas_glyph));
+            }
+        }
+        glyphs
+            .into_iter()
+            .map(|(text, global_transform, key, position, atlas_glyph)| {
+                let atlas_size = self.atlases[&key].size().as_vec2();
+                let rect = atlas_glyph.rect;
+                let center = position + atlas_glyph.offset + rect.size * 0.5;
+                let model = Mat4::from(global_transform) * Mat4::from_translation(center.extend(0.0)) * Mat4::from_scale(rect.size.extend(1.0));
+                let min = rect.origin / atlas_size;
+                let max = (rect.origin + rect.size) / atlas_size;
+                let instance = SpriteInstance {
+                    transform: projection * model,
+                    uv_rect: Vec4::new(min.x, min.y, max.x, max.y),
+                    color: text.color,
+                };
+                (text.layer, BatchTexture::Atlas(key), instance)
+            })
+            .collect()
+    }
+}
+
+/// A sprite with its transform propagated.
+pub(crate) struct FlatSprite<'a> {
+    sprite: &'a Sprite,
+    global_transform: Affine3A,
+}
+
+/// Propagates transforms of all sprites, and sorts them by layer.
+/// Within a layer, sprites that share a texture are kept together so that they batch.
+#[instrument(skip_all)]
+pub(crate) fn flatten_sprites(scene: &mut Scene<Sprite>) -> Vec<FlatSprite<'_>> {
+    let mut flat_sprites = Vec::with_capacity(scene.len());
+    scene.graph.propagate_dirty(Affine3A::IDENTITY, |parent_transf, sprite, dirty| {
+        if dirty {
+            sprite.global_transform = parent_transf * Affine3A::from(sprite.transform);
+        }
+        let sprite: &Sprite = sprite;
+        flat_sprites.push(FlatSprite { sprite, global_transform: sprite.global_transform });
+        (sprite.global_transform, dirty)
+    });
+    flat_sprites.sort_by_key(|flat_sprite| (flat_sprite.sprite.layer, flat_sprite.sprite.texture.id()));
+    flat_sprites
+}
+
+/// Text with its transform propagated.
+pub(crate) struct FlatText<'a> {
+    text: &'a Text,
+    global_transform: Affine3A,
+}
+
+/// Propagates transforms of all text, and sorts it by layer.
+#[instrument(skip_all)]
+pub(crate) fn flatten_texts(scene: &mut Scene<Text>) -> Vec<FlatText<'_>> {
+    let mut flat_texts = Vec::with_capacity(scene.len());
+    scene.graph.propagate_dirty(Affine3A::IDENTITY, |parent_transf, text, dirty| {
+        if dirty {
+            text.global_transform = parent_transf * Affine3A::from(text.transform);
+        }
+        let text: &Text = text;
+        flat_texts.push(FlatText { text, global_transform: text.global_transform });
+        (text.global_transform, dirty)
+    });
+    flat_texts.sort_by_key(|flat_text| flat_text.text.layer);
+    flat_texts
+}
+
+/// Splits a sequence of texture ids into ranges of consecutive equal ids.
+fn batch_ranges<T: PartialEq>(texture_ids: impl Iterator<Item = T>) -> Vec<Range<u32>> {
+    let mut batches: Vec<(T, Range<u32>)> = Vec::new();
+    for (i, texture_id) in texture_ids.enumerate() {
+        let i = i as u32;
+        match batches.last_mut() {
+            Some((batch_texture_id, range)) if *batch_texture_id == texture_id => range.end = i + 1,
+            _ => batches.push((texture_id, i..i+1)),
+        }
+    }
+    batches.into_iter().map(|(_, range)| range).collect()
+}
+
+/// Orthographic projection where one unit is a pixel, the origin is the top-left corner, and y points down.
+pub(crate) fn pixel_projection(target_size: Vec2) -> Mat4 {
+    Mat4::orthographic_rh(0.0, target_size.x, target_size.y, 0.0, -1000.0, 1000.0)
+}
+
+/// Instance data of a single sprite.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct SpriteInstance {
+    transform: Mat4,
+    uv_rect: Vec4,
+    color: Color,
+}
+
+impl SpriteInstance {
+    fn new(flat_sprite: &FlatSprite, texture: &Texture, projection: Mat4) -> Self {
+        let sprite = flat_sprite.sprite;
+        let model = Mat4::from(flat_sprite.global_transform) * Mat4::from_scale(sprite.size.extend(1.0));
+        Self {
+            transform: projection * model,
+            uv_rect: sprite.uv_rect(texture.size.as_vec2()),
+            color: sprite.color,
+        }
+    }
+}
+
+fn texture_layout_entries() -> [wgpu::BindGroupLayoutEntry; 2] {
+    use wgpu::{BindGroupLayoutEntry, BindingType, SamplerBindingType, ShaderStages, TextureSampleType, TextureViewDimension};
+    [
+        BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        },
+    ]
+}
+
+fn create_pipeline(
+    texture_format: TextureFormat,
+    sample_count: u32,
+    texture_layout: &BindGroupLayout,
+    device: &Device,
+) -> RenderPipeline {
+    let module = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("g2d_module"),
+        source: ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+    });
+    let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("g2d_layout"),
+        bind_group_layouts: &[texture_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("g2d_pipeline"),
+        layout: Some(&layout),
+        vertex: VertexState {
+            module: &module,
+            entry_point: "vertex_main",
+            buffers: &[INSTANCE_LAYOUT],
+        },
+        fragment: Some(FragmentState {
+            module: &module,
+            entry_point: "fragment_main",
+            targets: &[Some(ColorTargetState {
+                format: texture_format,
+                blend: Some(BlendState::ALPHA_BLENDING),
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::any::TypeId;
+    use glam::{Vec2, Vec3, Vec4};
+    use slotmap::SlotMap;
+    use crate::{AssetId, AssetIndex, Handle, Rect, Scene, Texture};
+    use super::{batch_ranges, flatten_sprites, pixel_projection, Sprite};
+
+    fn texture_handles(count: usize) -> Vec<Handle<Texture>> {
+        let mut indices = SlotMap::<AssetIndex, ()>::with_key();
+        let (sender, _) = std::sync::mpsc::channel();
+        (0..count)
+            .map(|_| {
+                let id = AssetId { asset_type: TypeId::of::<Texture>(), index: indices.insert(()) };
+                Handle::new(id, sender.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pixel_projection_maps_corners() {
+        let projection = pixel_projection(Vec2::new(800.0, 600.0));
+        let top_left = projection.project_point3(Vec3::ZERO);
+        let bottom_right = projection.project_point3(Vec3::new(800.0, 600.0, 0.0));
+        assert!(top_left.truncate().abs_diff_eq(Vec2::new(-1.0, 1.0), 1e-6));
+        assert!(bottom_right.truncate().abs_diff_eq(Vec2::new(1.0, -1.0), 1e-6));
+    }
+
+    #[test]
+    fn sprites_sort_by_layer_then_texture() {
+        let textures = texture_handles(2);
+        let (a, b) = (&textures[0], &textures[1]);
+        let mut scene = Scene::new();
+        scene.insert(Sprite::new(b.clone(), Vec2::ONE).with_layer(1));
+        scene.insert(Sprite::new(a.clone(), Vec2::ONE).with_layer(1));
+        scene.insert(Sprite::new(b.clone(), Vec2::ONE).with_layer(0));
+        scene.insert(Sprite::new(a.clone(), Vec2::ONE).with_layer(1));
+        let flat_sprites = flatten_sprites(&mut scene);
+        let ids: Vec<(i32, AssetId)> = flat_sprites
+            .iter()
+            .map(|flat_sprite| (flat_sprite.sprite.layer, flat_sprite.sprite.texture.id()))
+            .collect();
+        assert_eq!(vec![(0, b.id()), (1, a.id()), (1, a.id()), (1, b.id())], ids);
+        assert_eq!(vec![0..1, 1..3, 3..4], batch_ranges(ids.into_iter().map(|(_, id)| id)));
+    }
+
+    #[test]
+    fn consecutive_textures_batch() {
+        let textures = texture_handles(2);
+        let (a, b) = (textures[0].id(), textures[1].id());
+        assert_eq!(vec![0..2, 2..3, 3..4], batch_ranges([a, a, b, a].into_iter()));
+        assert!(batch_ranges(std::iter::empty::<AssetId>()).is_empty());
+    }
+
+    #[test]
+    fn uv_rect_uses_source_and_flips() {
+        let textures = texture_handles(1);
+        let texture_size = Vec2::new(64.0, 32.0);
+        let sprite = Sprite::new(textures[0].clone(), Vec2::ONE);
+        assert_eq!(Vec4::new(0.0, 0.0, 1.0, 1.0), sprite.uv_rect(texture_size));
+        let sprite = sprite.with_source(Rect::new(16.0, 0.0, 16.0, 32.0));
+        assert_eq!(Vec4::new(0.25, 0.0, 0.5, 1.0), sprite.uv_rect(texture_size));
+        let sprite = sprite.with_flip(true, false);
+        assert_eq!(Vec4::new(0.5, 0.0, 0.25, 1.0), sprite.uv_rect(texture_size));
+        let sprite = sprite.with_flip(false, true);
+        assert_eq!(Vec4::new(0.25, 1.0, 0.5, 0.0), sprite.uv_rect(texture_size));
+    }
+}
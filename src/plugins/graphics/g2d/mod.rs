@@ -0,0 +1,11 @@
+mod g2d;
+mod sprite;
+mod font;
+mod atlas;
+mod text;
+
+pub(crate) use g2d::*;
+pub(crate) use atlas::*;
+pub use sprite::*;
+pub use font::*;
+pub use text::*;
\ No newline at end of file
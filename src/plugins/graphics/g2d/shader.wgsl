@@ -0,0 +1,50 @@
+struct InstanceIn {
+    @location(0) transform_0: vec4<f32>,
+    @location(1) transform_1: vec4<f32>,
+    @location(2) transform_2: vec4<f32>,
+    @location(3) transform_3: vec4<f32>,
+    @location(4) uv_rect: vec4<f32>,
+    @location(5) color: vec4<f32>,
+}
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var sprite_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var sprite_sampler: sampler;
+
+// Corners of a unit quad, centered on the origin, as two triangles.
+var<private> CORNERS: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+    vec2<f32>(-0.5, -0.5),
+    vec2<f32>(0.5, -0.5),
+    vec2<f32>(0.5, 0.5),
+    vec2<f32>(-0.5, -0.5),
+    vec2<f32>(0.5, 0.5),
+    vec2<f32>(-0.5, 0.5),
+);
+
+@vertex
+fn vertex_main(@builtin(vertex_index) vertex_index: u32, instance: InstanceIn) -> VertexOut {
+    let transform = mat4x4<f32>(
+        instance.transform_0,
+        instance.transform_1,
+        instance.transform_2,
+        instance.transform_3,
+    );
+    let corner = CORNERS[vertex_index];
+    var out: VertexOut;
+    out.position = transform * vec4<f32>(corner, 0.0, 1.0);
+    out.uv = mix(instance.uv_rect.xy, instance.uv_rect.zw, corner + 0.5);
+    out.color = instance.color;
+    return out;
+}
+
+@fragment
+fn fragment_main(in: VertexOut) -> @location(0) vec4<f32> {
+    return textureSample(sprite_texture, sprite_sampler, in.uv) * in.color;
+}
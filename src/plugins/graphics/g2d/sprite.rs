@@ -0,0 +1,97 @@
+use glam::{Affine3A, Vec2, Vec4};
+use crate::math::Transform;
+use crate::{Color, Handle, HasId, NodeId, Rect, Texture};
+
+/**
+ * A textured quad drawn in pixel space, centered on its transform.
+ * Stored in a [`Scene<Sprite>`](crate::Scene), and drawn by the [`G2D`](super::G2D) engine after 3D rendering.
+ */
+pub struct Sprite {
+    pub texture: Handle<Texture>,
+    /// Region of the texture to draw, in texels.
+    /// If None, the whole texture is drawn.
+    pub source: Option<Rect>,
+    /// Width and height in pixels, before the transform's scale is applied.
+    pub size: Vec2,
+    /// Multiplied with the texture's color.
+    pub color: Color,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Sprites on higher layers are drawn over sprites on lower layers.
+    pub layer: i32,
+    pub(crate) transform: Transform,
+    pub(crate) global_transform: Affine3A,
+}
+
+impl Sprite {
+
+    pub fn new(texture: Handle<Texture>, size: Vec2) -> Self {
+        Self {
+            texture,
+            source: None,
+            size,
+            color: Color::WHITE,
+            flip_x: false,
+            flip_y: false,
+            layer: 0,
+            transform: Transform::IDENTITY,
+            global_transform: Affine3A::IDENTITY,
+        }
+    }
+
+    pub fn with_source(mut self, source: Rect) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_flip(mut self, flip_x: bool, flip_y: bool) -> Self {
+        self.flip_x = flip_x;
+        self.flip_y = flip_y;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+    }
+
+    /// Texture coordinates of the top-left and bottom-right corners, packed as (u0, v0, u1, v1).
+    /// Corners are swapped on flipped axes.
+    pub(crate) fn uv_rect(&self, texture_size: Vec2) -> Vec4 {
+        let (min, max) = match self.source {
+            Some(source) => (source.origin / texture_size, (source.origin + source.size) / texture_size),
+            None => (Vec2::ZERO, Vec2::ONE),
+        };
+        let (u0, u1) = match self.flip_x {
+            false => (min.x, max.x),
+            true => (max.x, min.x),
+        };
+        let (v0, v1) = match self.flip_y {
+            false => (min.y, max.y),
+            true => (max.y, min.y),
+        };
+        Vec4::new(u0, v0, u1, v1)
+    }
+}
+
+impl HasId for Sprite {
+    type Id = NodeId;
+}
\ No newline at end of file
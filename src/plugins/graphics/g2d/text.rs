@@ -0,0 +1,274 @@
+use ab_glyph::{GlyphId, PxScale, ScaleFont};
+use glam::{Affine3A, Vec2};
+use crate::math::Transform;
+use crate::{Color, Handle, HasId, NodeId};
+use super::Font;
+
+/**
+ * A string drawn in pixel space, as one textured quad per glyph.
+ * Stored in a [`Scene<Text>`](crate::Scene), and drawn by the [`G2D`](super::G2D) engine along with sprites.
+ * Within a layer, text is drawn over sprites.
+ */
+pub struct Text {
+    pub string: String,
+    pub font: Handle<Font>,
+    /// Height of a line in pixels, from descent to ascent, before the transform's scale is applied.
+    /// Each size a font is drawn at gets its own glyph atlas.
+    pub size: f32,
+    /// Multiplied with the coverage of glyphs.
+    pub color: Color,
+    /// Point of the text's bounds that sits on its transform.
+    /// Also aligns lines within those bounds.
+    pub anchor: Anchor,
+    /// If set, lines wrap before words that would make them wider than this many pixels.
+    pub max_width: Option<f32>,
+    /// Text on higher layers is drawn over sprites and text on lower layers.
+    pub layer: i32,
+    pub(crate) transform: Transform,
+    pub(crate) global_transform: Affine3A,
+}
+
+impl Text {
+
+    pub fn new(string: impl Into<String>, font: Handle<Font>, size: f32) -> Self {
+        Self {
+            string: string.into(),
+            font,
+            size,
+            color: Color::WHITE,
+            anchor: Anchor::default(),
+            max_width: None,
+            layer: 0,
+            transform: Transform::IDENTITY,
+            global_transform: Affine3A::IDENTITY,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    pub fn with_max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+    }
+}
+
+impl HasId for Text {
+    type Id = NodeId;
+}
+
+/// Point of a [`Text`]'s bounds that sits on its transform.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+
+    /// Position of the point as a fraction of the bounds' size, from their top-left corner.
+    pub fn fraction(self) -> Vec2 {
+        match self {
+            Self::TopLeft => Vec2::new(0.0, 0.0),
+            Self::Top => Vec2::new(0.5, 0.0),
+            Self::TopRight => Vec2::new(1.0, 0.0),
+            Self::Left => Vec2::new(0.0, 0.5),
+            Self::Center => Vec2::new(0.5, 0.5),
+            Self::Right => Vec2::new(1.0, 0.5),
+            Self::BottomLeft => Vec2::new(0.0, 1.0),
+            Self::Bottom => Vec2::new(0.5, 1.0),
+            Self::BottomRight => Vec2::new(1.0, 1.0),
+        }
+    }
+}
+
+/// A glyph placed by [`layout_text`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct LaidGlyph {
+    pub id: GlyphId,
+    /// Pen position on the baseline, in pixels from the top-left corner of the text's bounds.
+    pub position: Vec2,
+}
+
+/// Glyphs of a string, and the size of the bounds they were laid out in.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct TextLayout {
+    pub glyphs: Vec<LaidGlyph>,
+    pub size: Vec2,
+}
+
+/**
+ * Lays out a string in lines that are size pixels tall, kerning each pair of glyphs.
+ * Lines break at newlines and, if max_width is set, before words that would overflow it. Words wider than max_width get a line of their own.
+ * align is the fraction of each line's leftover width that goes to its left: 0 aligns left, 0.5 centers, and 1 aligns right.
+ * Pen positions are rounded to whole pixels, so that glyphs are not blurred when drawn unscaled.
+ */
+pub(crate) fn layout_text(font: &impl ab_glyph::Font, size: f32, string: &str, max_width: Option<f32>, align: f32) -> TextLayout {
+    let font = font.as_scaled(PxScale::from(size));
+    let max_width = max_width.unwrap_or(f32::INFINITY);
+
+    // Breaks paragraphs into lines, each with the glyphs' horizontal positions and the width of the line
+    let mut lines: Vec<(Vec<(GlyphId, f32)>, f32)> = Vec::new();
+    for paragraph in string.split('\n') {
+        let mut line = Vec::new();
+        let mut line_width = 0.0;
+        let mut caret = 0.0;
+        let mut previous = None;
+        for word in paragraph.trim_end_matches('\r').split_inclusive(' ') {
+            let mut word_glyphs = Vec::new();
+            let mut word_caret = caret;
+            let mut word_previous = previous;
+            let mut word_end = caret;
+            for c in word.chars() {
+                let id = font.glyph_id(c);
+                if let Some(previous) = word_previous {
+                    word_caret += font.kern(previous, id);
+                }
+                word_glyphs.push((id, word_caret));
+                word_caret += font.h_advance(id);
+                word_previous = Some(id);
+                if c != ' ' {
+                    word_end = word_caret;
+                }
+            }
+
+            // Moves the word to a new line if it overflows the current one
+            if word_end > max_width && !line.is_empty() {
+                lines.push((std::mem::take(&mut line), line_width));
+                let shift = word_glyphs.first().map_or(0.0, |(_, x)| *x);
+                for (_, x) in &mut word_glyphs {
+                    *x -= shift;
+                }
+                word_caret -= shift;
+                word_end -= shift;
+                line_width = 0.0;
+            }
+            line.extend(word_glyphs);
+            line_width = f32::max(line_width, word_end);
+            caret = word_caret;
+            previous = word_previous;
+        }
+        lines.push((line, line_width));
+    }
+
+    // Positions lines one after another, aligned within the widest
+    let line_height = font.height() + font.line_gap();
+    let width = lines.iter().map(|(_, line_width)| *line_width).fold(0.0, f32::max);
+    let height = lines.len() as f32 * line_height - font.line_gap();
+    let glyphs = lines
+        .into_iter()
+        .enumerate()
+        .flat_map(|(i, (line, line_width))| {
+            let line_offset = (width - line_width) * align;
+            let baseline = i as f32 * line_height + font.ascent();
+            line.into_iter().map(move |(id, x)| LaidGlyph {
+                id,
+                position: Vec2::new(x + line_offset, baseline).round(),
+            })
+        })
+        .collect();
+    TextLayout { glyphs, size: Vec2::new(width, height) }
+}
+
+#[cfg(test)]
+mod test {
+    use ab_glyph::{Font as _, FontRef, PxScale, ScaleFont};
+    use glam::Vec2;
+    use super::{layout_text, Anchor, LaidGlyph};
+
+    const SIZE: f32 = 20.0;
+
+    fn font() -> FontRef<'static> {
+        FontRef::try_from_slice(include_bytes!("../../../../assets/fonts/DejaVuSansMono.ttf")).unwrap()
+    }
+
+    /// Horizontal advance and line height of the monospace test font.
+    fn metrics() -> (f32, f32) {
+        let font = font();
+        let font = font.as_scaled(PxScale::from(SIZE));
+        (font.h_advance(font.glyph_id('a')), font.height() + font.line_gap())
+    }
+
+    fn positions(glyphs: &[LaidGlyph]) -> Vec<Vec2> {
+        glyphs.iter().map(|glyph| glyph.position).collect()
+    }
+
+    #[test]
+    fn lays_out_lines() {
+        let (advance, line_height) = metrics();
+        let layout = layout_text(&font(), SIZE, "abc\nde", None, 0.0);
+        let glyphs = positions(&layout.glyphs);
+        assert_eq!(5, glyphs.len());
+        assert_eq!(Vec2::new(advance.round(), glyphs[0].y), glyphs[1]);
+        assert_eq!(Vec2::new(0.0, (glyphs[0].y + line_height).round()), glyphs[3]);
+        assert_eq!(Vec2::new(advance * 3.0, line_height + SIZE), layout.size);
+    }
+
+    #[test]
+    fn wraps_words() {
+        let (advance, _) = metrics();
+
+        // Trailing spaces do not count towards the width that lines wrap at.
+        let layout = layout_text(&font(), SIZE, "aaa bbb ccc", Some(advance * 7.0), 0.0);
+        let glyphs = positions(&layout.glyphs);
+        assert_eq!(glyphs[0].y, glyphs[6].y);
+        assert!(glyphs[8].y > glyphs[6].y);
+        assert_eq!(0.0, glyphs[8].x);
+        assert_eq!(advance * 7.0, layout.size.x);
+
+        // Words wider than the max width get a line of their own.
+        let layout = layout_text(&font(), SIZE, "a bbbbbb c", Some(advance * 3.0), 0.0);
+        let rows: Vec<f32> = positions(&layout.glyphs).iter().map(|glyph| glyph.y).collect();
+        assert!(rows[0] < rows[2] && rows[2] == rows[7] && rows[7] < rows[9]);
+        assert_eq!(advance * 6.0, layout.size.x);
+    }
+
+    #[test]
+    fn aligns_lines() {
+        let (advance, _) = metrics();
+        let layout = layout_text(&font(), SIZE, "aaaa\naa", None, Anchor::Center.fraction().x);
+        assert_eq!(advance.round(), layout.glyphs[4].position.x);
+        let layout = layout_text(&font(), SIZE, "aaaa\naa", None, Anchor::Right.fraction().x);
+        assert_eq!((advance * 2.0).round(), layout.glyphs[4].position.x);
+    }
+
+    #[test]
+    fn empty_text_is_one_line() {
+        let layout = layout_text(&font(), SIZE, "", Some(100.0), 0.0);
+        assert!(layout.glyphs.is_empty());
+        assert_eq!(Vec2::new(0.0, SIZE), layout.size);
+    }
+}
@@ -1,6 +1,7 @@
 use std::f32::consts::PI;
 use glam::Mat4;
-use crate::{InterpolationMode, Rect};
+use crate::{Color, Handle, InterpolationMode, Rect, RenderTexture};
+use super::LAYER_ALL;
 
 /**
  * Graphical camera which controls what can be seen and from what perspective.
@@ -10,7 +11,18 @@ pub struct Camera {
     pub(crate) projection: Mat4,
     pub(crate) previous_projection: Mat4,
     pub(crate) viewport: Option<Rect>,
+    /// Color the camera clears the whole target with before rendering, if no camera rendered to the target before it.
+    /// Later cameras on the same target always render over it, and so does the first one if None.
+    /// The surface does not keep its contents between frames, so it is cleared with the [`crate::ClearColor`] instead.
+    pub clear: Option<Color>,
     pub interpolation_mode: InterpolationMode,
+    /// Layers the camera sees. See [`crate::g3d::Renderable::layer_mask`].
+    pub layer_mask: u32,
+    /// Cameras render by ascending order. Cameras of the same order render in the order of the scene graph.
+    /// Cameras that render to textures always render before those on screen.
+    pub order: i32,
+    /// If false, the camera renders nothing.
+    pub enabled: bool,
 }
 
 impl Default for Camera {
@@ -21,6 +33,10 @@ impl Default for Camera {
             previous_projection: Mat4::IDENTITY,
             interpolation_mode: InterpolationMode::Skip,
             viewport: None,
+            clear: None,
+            layer_mask: LAYER_ALL,
+            order: 0,
+            enabled: true,
         }
     }
 }
@@ -83,6 +99,26 @@ impl Camera {
         self.interpolation_mode = interpolation_mode;
         self
     }
+
+    pub fn with_clear(mut self, clear: Option<Color>) -> Self {
+        self.clear = clear;
+        self
+    }
+
+    pub fn with_layer_mask(mut self, layer_mask: u32) -> Self {
+        self.layer_mask = layer_mask;
+        self
+    }
+
+    pub fn with_order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
 }
 
 /**
@@ -90,5 +126,5 @@ impl Camera {
  */
 pub enum CameraTarget {
     OnScreen,
-    OffScreen,
+    OffScreen(Handle<RenderTexture>),
 }
\ No newline at end of file
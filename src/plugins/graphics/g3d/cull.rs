@@ -0,0 +1,191 @@
+use std::mem::size_of;
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3, Vec4};
+use wgpu::{BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device, PipelineLayoutDescriptor, Queue, ShaderModuleDescriptor, ShaderSource, ShaderStages};
+use crate::math::{Frustum, Sphere, Volume};
+use crate::reserve_buffer;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Bounds of instances without a volume, which are never culled.
+pub(crate) const UNBOUNDED: Sphere = Sphere { center: Vec3::ZERO, radius: f32::MAX };
+
+/// Instance tested by the [`CullPass`].
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Debug, Pod, Zeroable)]
+pub(crate) struct CullInstance {
+    pub sphere: Vec4,       // World-space center, and radius
+    pub job: u32,           // Index of the job whose camera draws the instance
+    pub command: u32,       // Index of the indirect command of the instance's batch
+    pub first: u32,         // Offset of the batch's first instance in the instance buffer, in vec4s
+    pub source: u32,        // Offset of the instance in the instance buffer, in vec4s
+    pub stride: u32,        // Size of the data of each instance of the batch, in vec4s
+    pub _padding: [u32; 3],
+}
+
+/**
+ * Compute pass that frustum culls the instances of opaque batches on the GPU, in [`super::GpuDrivenMode::IndirectCulled`].
+ * Each batch's indirect command starts with no instances, and each instance that passes adds itself to it.
+ * Instances that pass are copied to the start of their batch's range in a buffer of their own, which batches then draw from.
+ * Instances within a batch end up in no particular order.
+ */
+pub(crate) struct CullPass {
+    layout: BindGroupLayout,
+    pipeline: ComputePipeline,
+    params: Buffer,
+    frustums: Buffer,       // Planes of each job's frustum
+    instances: Buffer,      // Instances to test
+    culled: Buffer,         // Instance data of the instances that passed, laid out like the instance buffer
+    count: u32,             // Number of instances uploaded by the last call to prepare
+}
+
+impl CullPass {
+
+    pub fn new(device: &Device) -> Self {
+        let storage_entry = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("g3d_cull_layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, false),
+                storage_entry(5, false),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("g3d_cull_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("g3d_cull_module"),
+            source: ShaderSource::Wgsl(include_str!("cull.wgsl").into()),
+        });
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("g3d_cull_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: "cull_main",
+        });
+        let buffer = |label, size, usage| device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+            mapped_at_creation: false,
+        });
+        Self {
+            layout,
+            pipeline,
+            params: buffer("g3d_cull_params", size_of::<[u32; 4]>() as u64, BufferUsages::UNIFORM | BufferUsages::COPY_DST),
+            frustums: buffer("g3d_cull_frustums", 0, BufferUsages::STORAGE | BufferUsages::COPY_DST),
+            instances: buffer("g3d_cull_instances", 0, BufferUsages::STORAGE | BufferUsages::COPY_DST),
+            culled: buffer("g3d_culled_instances", 0, BufferUsages::STORAGE | BufferUsages::VERTEX),
+            count: 0,
+        }
+    }
+
+    /// Instance data of the instances that passed, at the same offsets as the batches they belong to.
+    pub fn culled_instances(&self) -> &Buffer {
+        &self.culled
+    }
+
+    /**
+     * Uploads the frustum of each job and the instances to test.
+     * Instance data is instance_bytes for every batch.
+     */
+    pub fn prepare(&mut self, frustums: &[Frustum], instances: &[CullInstance], instance_bytes: u64, device: &Device, queue: &Queue) {
+        let planes: Vec<[Vec4; 6]> = frustums.iter()
+            .map(|frustum| frustum.planes().map(|plane| plane.normal.extend(-plane.distance)))
+            .collect();
+        let plane_bytes: &[u8] = bytemuck::cast_slice(&planes);
+        let tested_bytes: &[u8] = bytemuck::cast_slice(instances);
+        let params = [instances.len() as u32, 0, 0, 0];
+        reserve_buffer(&mut self.frustums, plane_bytes.len() as u64, device);
+        reserve_buffer(&mut self.instances, tested_bytes.len() as u64, device);
+        reserve_buffer(&mut self.culled, instance_bytes, device);
+        queue.write_buffer(&self.params, 0, bytemuck::bytes_of(&params));
+        queue.write_buffer(&self.frustums, 0, plane_bytes);
+        queue.write_buffer(&self.instances, 0, tested_bytes);
+        self.count = instances.len() as u32;
+    }
+
+    /**
+     * Tests the instances prepared, reading their data from source and adding those that pass to the commands.
+     * The instance counts of the commands of culled batches must have been uploaded as 0.
+     */
+    pub fn submit(&self, source: &Buffer, commands: &Buffer, encoder: &mut CommandEncoder, device: &Device) {
+        if self.count == 0 { return }
+        let buffers = [&self.params, &self.frustums, &self.instances, source, &self.culled, commands];
+        let entries: Vec<BindGroupEntry> = buffers.iter()
+            .enumerate()
+            .map(|(binding, buffer)| BindGroupEntry { binding: binding as u32, resource: buffer.as_entire_binding() })
+            .collect();
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("g3d_cull_bind_group"),
+            layout: &self.layout,
+            entries: &entries,
+        });
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("g3d_cull_pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(self.count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+}
+
+/// Sphere around a volume, once transformed by global_transform.
+/// Empty if the volume is.
+pub(crate) fn bounding_sphere(volume: Volume, global_transform: Mat4) -> Sphere {
+    match volume {
+        Volume::Sphere(sphere) => sphere.transform(global_transform),
+        Volume::AABB(aabb) => match aabb.transform(global_transform) {
+            aabb if aabb.is_empty() => Sphere::EMPTY,
+            aabb => Sphere::new(aabb.center, aabb.extents.length()),
+        },
+        Volume::OBB(obb) => {
+            let obb = obb.transform(global_transform);
+            Sphere::new(obb.center, obb.half_extents.length())
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::{Mat4, Vec3};
+    use crate::math::{Sphere, Volume, AABB};
+    use super::bounding_sphere;
+
+    #[test]
+    fn spheres_bound_volumes() {
+        let transform = Mat4::from_translation(Vec3::X) * Mat4::from_scale(Vec3::splat(2.0));
+        assert_eq!(Sphere::new(Vec3::X, 2.0), bounding_sphere(Volume::sphere(Vec3::ZERO, 1.0), transform));
+        let sphere = bounding_sphere(Volume::from(AABB::UNIT), transform);
+        assert_eq!(Vec3::X, sphere.center);
+        assert!((sphere.radius - 3f32.sqrt()).abs() < 1e-5);
+        let sphere = bounding_sphere(Volume::obb(Vec3::ZERO, Vec3::ONE, Default::default()), transform);
+        assert!((sphere.radius - 12f32.sqrt()).abs() < 1e-5);
+        assert!(bounding_sphere(Volume::from(AABB::EMPTY), transform).is_empty());
+    }
+}
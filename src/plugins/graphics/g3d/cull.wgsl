@@ -0,0 +1,66 @@
+struct Params {
+    count: u32,                 // Number of instances tested
+}
+
+struct Frustum {
+    planes: array<vec4<f32>, 6>,
+}
+
+struct CullInstance {
+    sphere: vec4<f32>,          // World-space center, and radius
+    job: u32,                   // Index of the frustum of the camera that draws the instance
+    command: u32,               // Index of the indirect command of the instance's batch
+    first: u32,                 // Offset of the batch's first instance, in vec4s
+    source: u32,                // Offset of the instance's data, in vec4s
+    stride: u32,                // Size of the data of each instance of the batch, in vec4s
+}
+
+struct DrawCommand {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@group(0) @binding(1)
+var<storage, read> frustums: array<Frustum>;
+
+@group(0) @binding(2)
+var<storage, read> instances: array<CullInstance>;
+
+@group(0) @binding(3)
+var<storage, read> source: array<vec4<f32>>;
+
+@group(0) @binding(4)
+var<storage, read_write> culled: array<vec4<f32>>;
+
+@group(0) @binding(5)
+var<storage, read_write> commands: array<DrawCommand>;
+
+@compute @workgroup_size(64)
+fn cull_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.count {
+        return;
+    }
+
+    // Keeps the instance if its sphere is at least partially inside every plane
+    let instance = instances[id.x];
+    for (var i = 0u; i < 6u; i++) {
+        let plane = frustums[instance.job].planes[i];
+        if dot(plane.xyz, instance.sphere.xyz) + plane.w <= -instance.sphere.w {
+            return;
+        }
+    }
+
+    // Appends the instance's data to those of its batch that were kept
+    let slot = atomicAdd(&commands[instance.command].instance_count, 1u);
+    let src = instance.source;
+    let dst = instance.first + slot * instance.stride;
+    for (var i = 0u; i < instance.stride; i++) {
+        culled[dst + i] = source[src + i];
+    }
+}
@@ -1,26 +1,53 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
 use std::mem::size_of;
+use std::ops::Range;
 use std::sync::Arc;
-use glam::{Mat4, Affine3A, Vec3};
+use glam::{Mat3, Mat4, Affine3A, Quat, Vec3, Vec4};
 use tracing::instrument;
-use derive_more::From;
-use wgpu::{BlendState, Buffer, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState, Device, Face, FragmentState, FrontFace, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, Queue, RenderPass, RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, StencilState, TextureFormat, VertexAttribute, VertexBufferLayout, VertexFormat, VertexState, VertexStepMode};
-use crate::math::{lerp_matrices, Frustum, Sphere, Transform, Volume, AABB};
-use crate::{reserve_buffer, AssetId, AssetState, AssetStorage, Handle, HasId, InterpolationMode, NodeId, Rect, Scene, ShaderPreprocessor, URect};
-use crate::g3d::{Material, Mesh, MeshKey, Camera, CameraTarget};
-use super::{MaterialKey, PreparedMaterial};
+use derive_more::{Display, Error, From};
+use wgpu::util::{BufferInitDescriptor, DeviceExt, DrawIndexedIndirect};
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, ColorTargetState, ColorWrites, CommandEncoder, CompareFunction, DepthBiasState, DepthStencilState, Device, ErrorFilter, FragmentState, MultisampleState, FrontFace, LoadOp, Operations, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, Queue, RenderPass, RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, SamplerBindingType, ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState, StoreOp, TextureFormat, TextureSampleType, TextureView, TextureViewDimension, VertexAttribute, VertexBufferLayout, VertexFormat, VertexState, VertexStepMode};
+use crate::math::{lerp_matrices, Frustum, Sphere, Transform, Volume, AABB, OBB};
+use crate::{reserve_buffer, AssetId, Color, AssetState, AssetStorage, CubeMap, Handle, HasId, InterpolationMode, NodeId, Rect, RenderTexture, Scene, ShaderDefError, ShaderPreprocessor, URect};
+use crate::g3d::{select_brightest_lights, AmbientLight, Material, Mesh, MeshKey, MeshLod, Camera, CameraTarget, DirectionalLight, FlatPointLight, FlatSpotLight, LightsUniform, PointLight, SpotLight};
+use super::{shadow_batches, light_view_proj, GizmoDraw, GizmoKey, GizmoPass, GizmoVertex, Gizmos, MaterialFlags, MaterialKey, PreparedMaterial, ShadowJob, ShadowPass};
+use super::skybox::{SkyboxDraw, SkyboxKey, SkyboxPass};
+use super::skin::SkinBindings;
+use super::cull::{bounding_sphere, CullInstance, CullPass, UNBOUNDED};
 
 const INSTANCE_SLOT: u32 = 0;
 const VERTEX_SLOT: u32 = 1;
 const MATERIAL_INDEX: u32 = 0;
+const LIGHTS_INDEX: u32 = 1;
+const SKIN_INDEX: u32 = 2;                              // Index of the bone matrices if lit. Takes the place of the lights otherwise
+const VIEW_BINDING: u32 = 3;
+const VIEW_SIZE: u64 = size_of::<Vec4>() as u64;
+const ENVIRONMENT_MAP_BINDING: u32 = 4;
+const ENVIRONMENT_SAMPLER_BINDING: u32 = 5;
 
-const INSTANCE_LAYOUT: VertexBufferLayout<'static> = VertexBufferLayout {
+/// Layer of ordinary renderables in the world.
+/// Layers 0 to 15 are reserved for the engine, and 16 to 31 are free for user code.
+pub const LAYER_DEFAULT: u32 = 1 << 0;
+/// Layer of user interface elements, IE: for rendering with a dedicated camera on top of the world.
+pub const LAYER_UI: u32 = 1 << 1;
+/// Layer of debug visuals, such as gizmos and bounding volumes.
+pub const LAYER_DEBUG: u32 = 1 << 2;
+/// Every layer. Default mask of both renderables and cameras.
+pub const LAYER_ALL: u32 = u32::MAX;
+
+/// Number of pipelines cached before unused ones are evicted, unless configured otherwise.
+pub const DEFAULT_MAX_PIPELINES: usize = 128;
+/// Number of frames a pipeline must go unused before it can be evicted, unless configured otherwise.
+pub const DEFAULT_PIPELINE_IDLE_FRAMES: u64 = 300;
+
+pub(crate) const INSTANCE_LAYOUT: VertexBufferLayout<'static> = VertexBufferLayout {
     array_stride: size_of::<Mat4>() as u64,
     step_mode: VertexStepMode::Instance,
     attributes: &[
         VertexAttribute {
             format: VertexFormat::Float32x4,
-            offset: 0*4*4,
+            offset: 0,
             shader_location: 0,
         },
         VertexAttribute {
@@ -41,29 +68,263 @@ const INSTANCE_LAYOUT: VertexBufferLayout<'static> = VertexBufferLayout {
     ],
 };
 
+/// Instance layout used when lighting is enabled.
+/// Same as [`INSTANCE_LAYOUT`], but followed by the world transform.
+const LIT_INSTANCE_LAYOUT: VertexBufferLayout<'static> = VertexBufferLayout {
+    array_stride: 2 * size_of::<Mat4>() as u64,
+    step_mode: VertexStepMode::Instance,
+    attributes: &[
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 0,
+            shader_location: 0,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 1*4*4,
+            shader_location: 1,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 2*4*4,
+            shader_location: 2,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 3*4*4,
+            shader_location: 3,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 4*4*4,
+            shader_location: 8,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 5*4*4,
+            shader_location: 9,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 6*4*4,
+            shader_location: 10,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 7*4*4,
+            shader_location: 11,
+        },
+    ],
+};
+
 /// A 3D graphics engine that stores its renderables in a scene graph.
 pub(crate) struct G3D {
-    pipelines: HashMap<PipelineKey, RenderPipeline>,    // Cache of render pipelines to use
+    pipelines: HashMap<PipelineKey, (RenderPipeline, u64)>, // Cache of render pipelines to use, and the frame each was last used
+    failed_pipelines: HashSet<PipelineKey>,             // Pipelines that failed to compile, so that they are not retried every frame
+    failed_render_targets: HashSet<AssetId>,            // Render textures that failed to load, so that their cameras are only reported once
+    max_pipelines: usize,                               // Pipelines are only evicted once there are more than this many
+    pipeline_idle_frames: u64,                          // Frames a pipeline must go unused before it can be evicted
+    frame: u64,                                         // Incremented every call to create_jobs
     device: Arc<Device>,
     queue: Arc<Queue>,
     instances: Buffer,
+    lighting: bool,                                     // If true, meshes with normals are lit by point lights
+    lights: Buffer,
+    lights_layout: BindGroupLayout,
+    lights_bind_group: BindGroup,
+    views: Buffer,                                      // Position of each camera, one aligned slot per camera, bound with a dynamic offset
+    view_stride: u64,                                   // Distance between slots in views
+    shadow_pass: Option<ShadowPass>,                    // Renders the shadow map of the directional light, if shadows are enabled
+    skybox_pass: SkyboxPass,
+    gizmo_pass: GizmoPass,
+    skin: SkinBindings,                                 // Bone matrices of skinned instances
+    default_environment: CubeMap,                       // Bound as the environment map when there is no skybox
+    environment_id: Option<AssetId>,                    // Cube map bound as the environment map, if it is not the default
+    gpu_driven_mode: GpuDrivenMode,
+    indirect: Buffer,                                   // Indirect draw commands, used in GpuDrivenMode::Indirect and IndirectCulled
+    cull_pass: Option<CullPass>,                        // Culls opaque instances on the GPU, in GpuDrivenMode::IndirectCulled
+    culling_stats: CullingStats,                        // Culling stats of the last call to create_jobs
 }
 
 impl G3D {
 
     /// New graphics engine with an empty scene graph.
-    pub fn new(device: Arc<Device>, queue: Arc<Queue>) -> Self {
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>, lighting: bool) -> Self {
+        let lights = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("g3d_lights"),
+            contents: bytemuck::bytes_of(&LightsUniform::new(&[])),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let view_stride = (device.limits().min_uniform_buffer_offset_alignment as u64).max(VIEW_SIZE);
+        let views = device.create_buffer(&BufferDescriptor {
+            label: Some("g3d_views"),
+            size: view_stride,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let default_environment = CubeMap::solid(Color::BLACK, &device, &queue);
+        let (lights_layout, lights_bind_group) = create_lights_bindings(&lights, &views, None, &default_environment, &device);
         Self {
             pipelines: HashMap::default(),
+            failed_pipelines: HashSet::default(),
+            failed_render_targets: HashSet::default(),
+            max_pipelines: DEFAULT_MAX_PIPELINES,
+            pipeline_idle_frames: DEFAULT_PIPELINE_IDLE_FRAMES,
+            frame: 0,
             device: device.clone(),
             queue,
             instances: device.create_buffer(&BufferDescriptor {
-                label: None,
+                label: Some("g3d_instances"),
                 size: 0,
                 usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
                 mapped_at_creation: false,
             }),
+            lighting,
+            lights,
+            lights_layout,
+            lights_bind_group,
+            views,
+            view_stride,
+            shadow_pass: None,
+            skybox_pass: SkyboxPass::new(&device),
+            gizmo_pass: GizmoPass::new(&device),
+            skin: SkinBindings::new(&device),
+            default_environment,
+            environment_id: None,
+            gpu_driven_mode: GpuDrivenMode::default(),
+            indirect: device.create_buffer(&BufferDescriptor {
+                label: Some("g3d_indirect"),
+                size: 0,
+                usage: BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            cull_pass: None,
+            culling_stats: CullingStats::default(),
+        }
+    }
+
+    /// Frustum culling statistics of the most recent frame.
+    pub fn culling_stats(&self) -> CullingStats {
+        self.culling_stats
+    }
+
+    /// Culling on the GPU binds instances and indirect commands as storage, so their buffers are recreated to allow it.
+    pub fn with_gpu_driven_mode(mut self, gpu_driven_mode: GpuDrivenMode) -> Self {
+        self.gpu_driven_mode = gpu_driven_mode;
+        self.cull_pass = None;
+        if gpu_driven_mode == GpuDrivenMode::IndirectCulled {
+            let buffer = |label, usage| self.device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: 0,
+                usage: usage | BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            self.instances = buffer("g3d_instances", BufferUsages::VERTEX);
+            self.indirect = buffer("g3d_indirect", BufferUsages::INDIRECT | BufferUsages::COPY_SRC);
+            self.cull_pass = Some(CullPass::new(&self.device));
+        }
+        self
+    }
+
+    /**
+     * Once more than max_pipelines are cached, pipelines unused for at least idle_frames frames are evicted,
+     * least recently used first, until max_pipelines remain.
+     * Pipelines used in the current frame are never evicted.
+     */
+    pub fn with_pipeline_eviction(mut self, max_pipelines: usize, idle_frames: u64) -> Self {
+        self.max_pipelines = max_pipelines;
+        self.pipeline_idle_frames = idle_frames.max(1);
+        self
+    }
+
+    /**
+     * Enables shadows from the [`DirectionalLight`], rendered to a square shadow map of the size specified.
+     * Only has an effect when lighting is enabled.
+     * Must be called before any pipelines are compiled.
+     */
+    pub fn with_shadows(mut self, shadow_map_size: u32) -> Self {
+        let shadow_pass = ShadowPass::new(shadow_map_size, &self.device);
+        let (lights_layout, lights_bind_group) = create_lights_bindings(&self.lights, &self.views, Some(&shadow_pass), &self.default_environment, &self.device);
+        self.lights_layout = lights_layout;
+        self.lights_bind_group = lights_bind_group;
+        self.shadow_pass = Some(shadow_pass);
+        self.environment_id = None;
+        self
+    }
+
+    /// Compiles a pipeline for every combination of mesh key and material key.
+    /// Combinations that were already compiled are skipped.
+    /// Returns the number of pipelines newly compiled.
+    ///
+    /// Pipelines are otherwise compiled the first time a material / mesh combination is rendered, which can cause a stutter.
+    /// Should be called once after initial asset loading, IE: during a loading screen.
+    pub fn precompile_pipelines(
+        &mut self,
+        mesh_keys: &[MeshKey],
+        material_keys: &[MaterialKey],
+        texture_format: TextureFormat,
+        depth_format: TextureFormat,
+        sample_count: u32,
+    ) -> usize {
+        let mut compiled = 0;
+        for &mesh_key in mesh_keys {
+            for &material_key in material_keys {
+                let pipeline_key = PipelineKey(mesh_key, material_key, sample_count);
+                if let Ok(true) = self.compile_pipeline(pipeline_key, texture_format, depth_format) {
+                    compiled += 1;
+                }
+            }
         }
+        compiled
+    }
+
+    /// Number of pipelines currently cached.
+    pub fn pipeline_count(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    /**
+     * Drops every cached pipeline, and forgets which ones failed to compile.
+     * Pipelines are compiled again the next time they are rendered.
+     * Fire [`ClearPipelines`](crate::ClearPipelines) to call this from outside of the render stage.
+     */
+    pub fn clear_pipelines(&mut self) {
+        self.pipelines.clear();
+        self.failed_pipelines.clear();
+    }
+
+    /**
+     * Compiles a pipeline if it is not cached, and marks it as used this frame.
+     * Returns true if it was compiled.
+     * Failures are logged once, and remembered so that the pipeline is not compiled again until [`Self::clear_pipelines`].
+     */
+    fn compile_pipeline(&mut self, pipeline_key: PipelineKey, texture_format: TextureFormat, depth_format: TextureFormat) -> Result<bool, PipelineError> {
+        if let Some((_, last_used)) = self.pipelines.get_mut(&pipeline_key) {
+            *last_used = self.frame;
+            return Ok(false);
+        }
+        if self.failed_pipelines.contains(&pipeline_key) {
+            return Err(PipelineError::PreviouslyFailed);
+        }
+        let lights_layout = self.is_lit(pipeline_key.0).then_some(&self.lights_layout);
+        let skin_layout = pipeline_key.0.contains(MeshKey::SKINNED).then_some(self.skin.layout());
+        let shadows = self.shadow_pass.is_some();
+        match create_pipeline(pipeline_key, texture_format, depth_format, lights_layout, skin_layout, shadows, &self.device) {
+            Ok(pipeline) => {
+                self.pipelines.insert(pipeline_key, (pipeline, self.frame));
+                Ok(true)
+            },
+            Err(err) => {
+                log::error!("Failed to compile pipeline {pipeline_key:?}: {err}");
+                self.failed_pipelines.insert(pipeline_key);
+                Err(err)
+            },
+        }
+    }
+
+    /// True if meshes with the key are affected by point lights.
+    fn is_lit(&self, mesh_key: MeshKey) -> bool {
+        self.lighting && mesh_key.contains(MeshKey::NORMAL)
     }
 
     /// Generates render jobs for every camera in the scene graph.
@@ -73,166 +334,648 @@ impl G3D {
         flat_scene: FlatScene<'s>,
         texture_format: TextureFormat,
         depth_format: TextureFormat,
+        sample_count: u32,
         materials: &'s AssetStorage<Material>,
         meshes: &'s AssetStorage<Mesh>,
     ) -> RenderJobs<'s> {
         
+        self.frame += 1;
+        let mut flat_scene = flat_scene;
         let mut jobs = Vec::new();
         let mut renderable_count = 0;
+        let mut batch_count = 0;
+        let mut culling_stats = CullingStats::default();
+        let gpu_culling = self.cull_pass.is_some();
+
+        // Reports cameras skipped because their render texture failed to load, once per texture
+        for render_texture_id in &flat_scene.failed_render_targets {
+            if self.failed_render_targets.insert(*render_texture_id) {
+                log::error!("Skipping camera whose render texture {render_texture_id:?} failed to load");
+            }
+        }
+
+        // The first camera of each target clears it.
+        sort_cameras(&mut flat_scene.flat_cams);
+        let firsts_on_target = firsts_on_target(&flat_scene.flat_cams);
+
+        // Skinned mat meshes get a slot of bone matrices the first time the shadow pass or a camera renders them.
+        let mut skin_slots: Vec<Option<usize>> = vec![None; flat_scene.flat_mat_meshes.len()];
+        let mut skin_poses: Vec<&[Mat4]> = Vec::new();
+
+        // Uploads lights, which are shared by all cameras.
+        // If there are too many point lights, keeps the ones that shine brightest on the first camera on screen.
+        let mut shadow_job = None;
+        if self.lighting {
+            let mut point_lights = std::mem::take(&mut flat_scene.point_lights);
+            let viewer = flat_scene.flat_cams
+                .iter()
+                .find(|flat_cam| flat_cam.render_texture.is_none())
+                .or(flat_scene.flat_cams.first())
+                .map(|flat_cam| flat_cam.global_transform.w_axis.truncate());
+            if let Some(viewer) = viewer {
+                select_brightest_lights(&mut point_lights, viewer);
+            }
+            let mut lights = LightsUniform::new(&point_lights)
+                .with_spots(&flat_scene.spot_lights)
+                .with_ambient(flat_scene.ambient_light)
+                .with_directional(flat_scene.directional_light);
+
+            // Collects shadow casters if the directional light casts shadows.
+            // Meshes outside of the cameras' frustums still cast shadows into them.
+            let shadow_light = flat_scene.directional_light.filter(|light| light.cast_shadows);
+            if let (Some(shadow_pass), Some(light)) = (&mut self.shadow_pass, shadow_light) {
+                let view_proj = light_view_proj(&light, viewer.unwrap_or(Vec3::ZERO));
+                let mut casters = Vec::new();
+                for (mat_mesh_index, flat_mat_mesh) in flat_scene.flat_mat_meshes.iter().enumerate() {
+                    let MatMesh(material_handle, mesh_handle) = flat_mat_mesh.mat_mesh;
+                    let AssetState::Loaded(mesh) = meshes.get(mesh_handle) else { continue };
+                    let AssetState::Loaded(material) = materials.get(material_handle) else { continue };
+                    if material.blend_mode.is_transparent() { continue };
+                    let skin_offset = mesh.key.contains(MeshKey::SKINNED).then(|| {
+                        let slot = *skin_slots[mat_mesh_index].get_or_insert_with(|| {
+                            skin_poses.push(flat_mat_mesh.pose);
+                            skin_poses.len() - 1
+                        });
+                        SkinBindings::offset(slot)
+                    });
+                    casters.push((mesh_handle.id(), mesh, flat_mat_mesh.global_transform, skin_offset));
+                }
+                let batches = shadow_batches(casters.into_iter());
+                for batch in &batches {
+                    shadow_pass.compile_pipeline(batch.mesh.key, self.skin.layout(), &self.device);
+                }
+                lights = lights.with_shadow(view_proj, shadow_pass.size());
+                shadow_job = Some(ShadowJob { view_proj, batches });
+            }
+            if let Some((_, cube_map)) = flat_scene.skybox {
+                lights = lights.with_environment(cube_map.mip_level_count);
+            }
+            self.queue.write_buffer(&self.lights, 0, bytemuck::bytes_of(&lights));
+
+            // Uploads the position of each camera to its own slot.
+            // Rebinds if the buffer had to grow, or if the skybox's cube map changed.
+            let mut view_bytes = vec![0; flat_scene.flat_cams.len() * self.view_stride as usize];
+            for (flat_cam, slot) in flat_scene.flat_cams.iter().zip(view_bytes.chunks_exact_mut(self.view_stride as usize)) {
+                let position: Vec4 = flat_cam.global_transform.w_axis;
+                slot[..VIEW_SIZE as usize].copy_from_slice(bytemuck::bytes_of(&position));
+            }
+            let views_size = self.views.size();
+            reserve_buffer(&mut self.views, view_bytes.len() as u64, &self.device);
+            let environment_id = flat_scene.skybox.map(|(cube_map_id, _)| cube_map_id);
+            if self.views.size() != views_size || self.environment_id != environment_id {
+                let environment = flat_scene.skybox.map_or(&self.default_environment, |(_, cube_map)| cube_map);
+                let (_, lights_bind_group) = create_lights_bindings(&self.lights, &self.views, self.shadow_pass.as_ref(), environment, &self.device);
+                self.lights_bind_group = lights_bind_group;
+                self.environment_id = environment_id;
+            }
+            self.queue.write_buffer(&self.views, 0, &view_bytes);
+        }
+
+        // Uploads the view of each camera that draws the skybox.
+        // Only the first camera on a target does, as the skybox would cover what previous cameras rendered.
+        let mut skybox_draws = vec![None; flat_scene.flat_cams.len()];
+        if let Some((cube_map_id, cube_map)) = flat_scene.skybox {
+            let (sky_cams, sky_view_projs): (Vec<usize>, Vec<Mat4>) = flat_scene.flat_cams
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| firsts_on_target[*i])
+                .map(|(i, flat_cam)| {
+                    let rotation = Mat4::from_mat3(Mat3::from_mat4(flat_cam.global_transform.inverse()));
+                    (i, flat_cam.projection * rotation)
+                })
+                .unzip();
+            let offsets = self.skybox_pass.prepare(cube_map_id, cube_map, &sky_view_projs, &self.device, &self.queue);
+            for (i, offset) in sky_cams.into_iter().zip(offsets) {
+                let sample_count = flat_scene.flat_cams[i].sample_count(sample_count);
+                let skybox_key = SkyboxKey { texture_format, depth_format, sample_count };
+                self.skybox_pass.compile_pipeline(skybox_key, &self.device);
+                skybox_draws[i] = Some(SkyboxDraw { key: skybox_key, offset });
+            }
+        }
+
+        // Uploads gizmos, and the view projection of each camera that sees the debug layer.
+        let mut gizmo_draws = vec![None; flat_scene.flat_cams.len()];
+        if let Some(gizmos) = flat_scene.gizmos.filter(|gizmos| gizmos.vertices().next().is_some()) {
+            let gizmo_vertices: Vec<GizmoVertex> = gizmos.vertices().copied().collect();
+            let (gizmo_cams, gizmo_view_projs): (Vec<usize>, Vec<Mat4>) = flat_scene.flat_cams
+                .iter()
+                .enumerate()
+                .filter(|(_, flat_cam)| flat_cam.sees(LAYER_DEBUG))
+                .map(|(i, flat_cam)| (i, flat_cam.projection * flat_cam.global_transform.inverse()))
+                .unzip();
+            let offsets = self.gizmo_pass.prepare(&gizmo_vertices, &gizmo_view_projs, &self.device, &self.queue);
+            for (i, offset) in gizmo_cams.into_iter().zip(offsets) {
+                let sample_count = flat_scene.flat_cams[i].sample_count(sample_count);
+                let gizmo_key = GizmoKey { texture_format, depth_format, sample_count, depth_test: gizmos.depth_test };
+                self.gizmo_pass.compile_pipeline(gizmo_key, &self.device);
+                gizmo_draws[i] = Some(GizmoDraw { key: gizmo_key, offset });
+            }
+        }
 
         // Collects N RenderJobs for N cameras.
-        for flat_cam in flat_scene.flat_cams {
+        let flat_cams = std::mem::take(&mut flat_scene.flat_cams);
+        for (((flat_cam, skybox), gizmos), is_first_on_target) in flat_cams.into_iter().zip(skybox_draws).zip(gizmo_draws).zip(firsts_on_target) {
+            let sample_count = flat_cam.sample_count(sample_count);
             let mut instance_batches: HashMap<InstanceKey, MatMeshInstances> = HashMap::default();
+            let mut skinned_batches: Vec<MatMeshInstances> = Vec::new();
+            let mut transparent_instances: Vec<(f32, InstanceKey, MatMeshInstances)> = Vec::new();
             let proj = flat_cam.projection;
+            let cam_position = flat_cam.global_transform.w_axis.truncate();
             let view = flat_cam.global_transform.inverse();
             let proj_view = proj * view;
             let frustum = Frustum::from(proj_view);
 
             // Renders mat meshes.
-            for flat_mat_mesh in &flat_scene.flat_mat_meshes {
+            for (mat_mesh_index, flat_mat_mesh) in flat_scene.flat_mat_meshes.iter().enumerate() {
+
+                // Skips mat mesh if it is on none of the camera's layers.
+                if !flat_cam.sees(flat_mat_mesh.layer_mask) { continue }
 
                 // Skips mat mesh if it has a bounding volume and it not in the frustum.
-                match flat_mat_mesh.volume {
-                    Some(Volume::Sphere(sphere)) => {
-                        let global_sphere = sphere.transform(flat_mat_mesh.global_transform);
-                        if !frustum.contains_sphere(global_sphere) {
-                            continue;
-                        }
-                    },
-                    Some(Volume::AABB(aabb)) => {
-                        let global_aabb = aabb.transform(flat_mat_mesh.global_transform);
-                        if !frustum.contains_aabb(global_aabb) {
-                            continue;
-                        }
-                    },
-                    None => {}
+                // Those with an automatic volume are tested once their mesh is known, and all of them are when culling on the GPU.
+                let breakdown = &mut culling_stats.breakdown;
+                match (flat_mat_mesh.volume, flat_mat_mesh.auto_volume) {
+                    _ if gpu_culling => {},
+                    (Some(volume), _) => if is_culled(volume, flat_mat_mesh.global_transform, &frustum, breakdown) { continue },
+                    (None, true) => {},
+                    (None, false) => breakdown.no_volume += 1,
                 }
 
-                // Extracts material and mesh from renderable.
+                // Extracts material and mesh from renderable, swapping the mesh for its level of detail at the camera's distance.
+                // Keeps the renderable's own mesh while that level's mesh has not done loading.
                 // Skips if material or mesh have not done loading.
                 // Skips if material has textures that are not done loading.
                 let MatMesh(material_handle, mesh_handle) = flat_mat_mesh.mat_mesh;
+                let lod_mesh_handle = flat_mat_mesh.lod
+                    .and_then(|lod| {
+                        let distance = flat_mat_mesh.global_transform.w_axis.truncate().distance(cam_position);
+                        lod.select(distance)
+                    })
+                    .filter(|lod_mesh_handle| meshes.get(lod_mesh_handle).is_loaded());
+                let mesh_handle = lod_mesh_handle.unwrap_or(mesh_handle);
                 let AssetState::Loaded(mesh) = meshes.get(mesh_handle) else { continue };
                 let AssetState::Loaded(material) = materials.get(material_handle) else { continue };
                 let Some(prepared_material) = &material.prepared else { continue };
+
+                // Skips mat mesh if its mesh's bounding box is not in the frustum.
+                // Skinned meshes move away from their bounding box when posed, so they are never culled automatically.
+                if !gpu_culling && flat_mat_mesh.volume.is_none() && flat_mat_mesh.auto_volume {
+                    let breakdown = &mut culling_stats.breakdown;
+                    match mesh.key.contains(MeshKey::SKINNED) {
+                        true => breakdown.no_volume += 1,
+                        false => if is_culled(Volume::AABB(mesh.aabb), flat_mat_mesh.global_transform, &frustum, breakdown) { continue },
+                    }
+                }
+
+                // When culling on the GPU, finds the sphere that opaque mat meshes are culled by, around their volume or their mesh's bounding box.
+                // Transparent ones are culled here instead, as culling them on the GPU would not keep them sorted.
+                // So are those whose volume is empty, as they are never visible.
+                let transparent = prepared_material.key.blend_mode.is_transparent();
+                let mut bounds = UNBOUNDED;
+                if gpu_culling {
+                    let breakdown = &mut culling_stats.breakdown;
+                    let volume = match (flat_mat_mesh.volume, flat_mat_mesh.auto_volume && !mesh.key.contains(MeshKey::SKINNED)) {
+                        (Some(volume), _) => Some(volume),
+                        (None, true) => Some(Volume::AABB(mesh.aabb)),
+                        (None, false) => None,
+                    };
+                    match volume {
+                        None => breakdown.no_volume += 1,
+                        Some(volume) => {
+                            let sphere = bounding_sphere(volume, flat_mat_mesh.global_transform);
+                            if transparent || sphere.is_empty() {
+                                if is_culled(volume, flat_mat_mesh.global_transform, &frustum, breakdown) { continue }
+                            }
+                            else {
+                                breakdown.gpu_tested += 1;
+                                bounds = sphere;
+                            }
+                        },
+                    }
+                }
                 
                 // Creates pipeline compatible with material and mesh.
                 // Does nothing if already cached.
-                let pipeline_key = PipelineKey(mesh.key, prepared_material.key);
-                self.pipelines
-                    .entry(pipeline_key)
-                    .or_insert_with(|| create_pipeline(
-                        &prepared_material,
-                        &mesh,
-                        prepared_material.key.cull_mode,
-                        texture_format,
-                        depth_format,
-                        &self.device
-                    ));
+                // Skips if it failed to compile.
+                let pipeline_key = PipelineKey(mesh.key, prepared_material.key, sample_count);
+                let lit = self.is_lit(mesh.key);
+                if self.compile_pipeline(pipeline_key, texture_format, depth_format).is_err() { continue };
 
                 // Fetches instance batch for material and mesh.
                 // Creates it if it does not exist.
+                // Transparent instances get a batch each, so they can be sorted by depth.
+                // Skinned instances get a batch each, so that each binds its own bone matrices.
                 let instance_key = InstanceKey { material_id: material_handle.id(), mesh_id: mesh_handle.id() };
-                let instance_batch = instance_batches
-                    .entry(instance_key)
-                    .or_insert_with(|| MatMeshInstances::new(prepared_material, mesh, pipeline_key));
+                let skin_offset = match mesh.key.contains(MeshKey::SKINNED) {
+                    true => {
+                        let slot = *skin_slots[mat_mesh_index].get_or_insert_with(|| {
+                            skin_poses.push(flat_mat_mesh.pose);
+                            skin_poses.len() - 1
+                        });
+                        Some(SkinBindings::offset(slot))
+                    },
+                    false => None,
+                };
+                let gpu_culled = gpu_culling && !transparent;
+                let new_batch = || MatMeshInstances::new(prepared_material, mesh, pipeline_key, lit, skin_offset, gpu_culled);
+                let instance_batch = match (transparent, skin_offset) {
+                    (false, None) => instance_batches
+                        .entry(instance_key)
+                        .or_insert_with(new_batch),
+                    (false, Some(_)) => {
+                        skinned_batches.push(new_batch());
+                        skinned_batches.last_mut().unwrap()
+                    },
+                    (true, _) => {
+                        let view_depth = (view * flat_mat_mesh.global_transform).w_axis.z;
+                        transparent_instances.push((view_depth, instance_key, new_batch()));
+                        &mut transparent_instances.last_mut().unwrap().2
+                    },
+                };
                 
                 // Inserts instance data into that batch.
+                // Lit instances also need their world transform.
                 instance_batch.instance_data.push(proj_view * flat_mat_mesh.global_transform);
+                if lit {
+                    instance_batch.instance_data.push(flat_mat_mesh.global_transform);
+                }
+                if gpu_culled {
+                    instance_batch.bounds.push(bounds.center.extend(bounds.radius));
+                }
+                instance_batch.num_instances += 1;
                 renderable_count += 1;
             }
+            let transparent_batches = sort_transparent_instances(transparent_instances);
+            batch_count += (instance_batches.len() + skinned_batches.len() + transparent_batches.len()) as u64;
             jobs.push(RenderJob {
                 camera: flat_cam,
-                instance_batches: instance_batches.into_values().collect(),
+                instance_batches: instance_batches.into_values().chain(skinned_batches).collect(),
+                transparent_batches,
+                frustum,
+                skybox,
+                gizmos,
+                is_first_on_target,
             });
         }
-        RenderJobs { jobs, renderable_count }
+        self.skin.prepare(&skin_poses, &self.device, &self.queue);
+        culling_stats.culled = culling_stats.breakdown.sphere_culled + culling_stats.breakdown.aabb_culled + culling_stats.breakdown.obb_culled;
+        self.culling_stats = culling_stats;
+        evict_least_recently_used(&mut self.pipelines, self.frame, self.max_pipelines, self.pipeline_idle_frames);
+        RenderJobs { jobs, shadow_job, renderable_count, batch_count }
     }
 
-    /// Renders a collection of RenderJobs.
+    /**
+     * Renders a collection of RenderJobs, beginning one render pass per camera.
+     * If the jobs include a shadow job, the shadow map is rendered first.
+     * The first camera of each target clears it with its clear color, or with clear_color if it has none.
+     * Subsequent cameras clear only if they have a clear color, and otherwise composite over the previous result.
+     * Cameras on screen render to color_view and depth_view. If color_view is multisampled, resolve_view receives the resolved image.
+     * Cameras with a [`RenderTexture`] render to it instead.
+     */
     #[instrument(skip_all)]
-    pub fn submit_jobs<'r>(&'r mut self, jobs: RenderJobs<'r>, pass: &mut RenderPass<'r>) {
+    pub fn submit_jobs<'r>(
+        &'r mut self,
+        jobs: RenderJobs<'r>,
+        encoder: &mut CommandEncoder,
+        color_view: &TextureView,
+        resolve_view: Option<&TextureView>,
+        depth_view: &TextureView,
+        clear_color: Color,
+    ) {
+
+        // Renders the shadow map, which the passes of every camera sample
+        if let (Some(shadow_pass), Some(shadow_job)) = (&mut self.shadow_pass, jobs.shadow_job) {
+            shadow_pass.submit(shadow_job, &self.skin, encoder, &self.device, &self.queue);
+        }
 
-        // Reserves just enough room to store all instance data across all instance batches.
-        reserve_buffer(
-            &mut self.instances,
-            jobs.renderable_count * size_of::<Mat4>() as u64,
-            &self.device
-        );
+        // Lays out instance data of every batch of every job, so that no two batches overlap.
+        let batch_sizes: Vec<Vec<u64>> = jobs.jobs
+            .iter()
+            .map(|job| job.batches().map(|batch| batch.instance_bytes().len() as u64).collect())
+            .collect();
+        let instance_ranges = instance_ranges(&batch_sizes);
 
-        for job in jobs.jobs {
-            self.submit_job(job, pass);
+        // Uploads all instance data, and indirect draw commands if any, before any draws are encoded.
+        let instance_size = match self.lighting {
+            true => LIT_INSTANCE_LAYOUT.array_stride,
+            false => INSTANCE_LAYOUT.array_stride,
+        };
+        let mut instance_bytes = Vec::with_capacity((jobs.renderable_count * instance_size) as usize);
+        let mut indirect_bytes = match self.gpu_driven_mode {
+            GpuDrivenMode::Disabled => Vec::new(),
+            GpuDrivenMode::Indirect | GpuDrivenMode::IndirectCulled => Vec::with_capacity(jobs.batch_count as usize * size_of::<DrawIndexedIndirect>()),
+        };
+        let mut cull_instances = Vec::new();
+        let batches = jobs.jobs.iter().enumerate().flat_map(|(i, job)| job.batches().map(move |batch| (i, batch)));
+        for (command_index, ((job_index, batch), instance_range)) in batches.zip(instance_ranges.iter().flatten()).enumerate() {
+            instance_bytes.extend_from_slice(batch.instance_bytes());
+            if self.gpu_driven_mode == GpuDrivenMode::Disabled { continue }
+
+            // Batches culled on the GPU start without instances, and get one for every instance that passes
+            let command = DrawIndexedIndirect {
+                vertex_count: batch.mesh.num_indices,
+                instance_count: if batch.gpu_culled { 0 } else { batch.num_instances },
+                base_index: 0,
+                vertex_offset: 0,
+                base_instance: 0,
+            };
+            indirect_bytes.extend_from_slice(command.as_bytes());
+            // Lit and unlit batches lay out instances with different strides, so each instance carries that of its batch
+            if batch.gpu_culled {
+                let stride = match batch.lit {
+                    true => LIT_INSTANCE_LAYOUT.array_stride,
+                    false => INSTANCE_LAYOUT.array_stride,
+                } / size_of::<Vec4>() as u64;
+                let first = (instance_range.start / size_of::<Vec4>() as u64) as u32;
+                let sources = (first..).step_by(stride as usize);
+                cull_instances.extend(batch.bounds.iter().zip(sources).map(|(bounds, source)| CullInstance {
+                    sphere: *bounds,
+                    job: job_index as u32,
+                    command: command_index as u32,
+                    first,
+                    source,
+                    stride: stride as u32,
+                    _padding: [0; 3],
+                }));
+            }
+        }
+        reserve_buffer(&mut self.instances, instance_bytes.len() as u64, &self.device);
+        self.queue.write_buffer(&self.instances, 0, &instance_bytes);
+        if !indirect_bytes.is_empty() {
+            reserve_buffer(&mut self.indirect, indirect_bytes.len() as u64, &self.device);
+            self.queue.write_buffer(&self.indirect, 0, &indirect_bytes);
+        }
+
+        // Culls instances of opaque batches by the frustum of their camera, before any of them are drawn
+        if let Some(cull_pass) = &mut self.cull_pass {
+            let frustums: Vec<Frustum> = jobs.jobs.iter().map(|job| job.frustum.clone()).collect();
+            cull_pass.prepare(&frustums, &cull_instances, instance_bytes.len() as u64, &self.device, &self.queue);
+            cull_pass.submit(&self.instances, &self.indirect, encoder, &self.device);
+        }
+
+        // Clears the target when no camera renders to it, so that it does not show the contents of a previous frame
+        if jobs.jobs.iter().all(|job| job.camera.render_texture.is_some()) {
+            encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("g3d_clear_pass"),
+                color_attachments: &[
+                    Some(RenderPassColorAttachment {
+                        view: color_view,
+                        resolve_target: resolve_view,
+                        ops: Operations { load: LoadOp::Clear(clear_color.into()), store: StoreOp::Store },
+                    })
+                ],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(Operations { load: LoadOp::Clear(1.0), store: StoreOp::Store }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+        }
+
+        let mut indirect_offset = 0;
+        for ((i, job), instance_ranges) in jobs.jobs.into_iter().enumerate().zip(instance_ranges) {
+
+            // Only the first camera on a target clears it. Without a clear color, it keeps what the target holds,
+            // except on the surface, whose contents do not persist between frames.
+            let clear = match (job.is_first_on_target, job.camera.clear, job.camera.render_texture) {
+                (false, _, _) => None,
+                (true, Some(camera_clear), _) => Some(camera_clear),
+                (true, None, None) => Some(clear_color),
+                (true, None, Some(_)) => None,
+            };
+            let (color_view, resolve_view, depth_view) = match job.camera.render_texture {
+                Some((_, render_texture)) => (&render_texture.view, None, render_texture.depth_view()),
+                None => (color_view, resolve_view, depth_view),
+            };
+            let color_load = match clear {
+                Some(clear) => LoadOp::Clear(clear.into()),
+                None => LoadOp::Load,
+            };
+            let depth_load = match job.is_first_on_target {
+                true => LoadOp::Clear(1.0),
+                false => LoadOp::Load,
+            };
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("g3d_pass"),
+                color_attachments: &[
+                    Some(RenderPassColorAttachment {
+                        view: color_view,
+                        resolve_target: resolve_view,
+                        ops: Operations { load: color_load, store: StoreOp::Store },
+                    })
+                ],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(Operations { load: depth_load, store: StoreOp::Store }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            let view_offset = (i as u64 * self.view_stride) as u32;
+            self.submit_job(job, &instance_ranges, view_offset, &mut indirect_offset, &mut pass);
         }
     }
 
     /// Renders a single RenderJob.
+    /// Instance data and indirect commands must have already been uploaded.
+    /// view_offset is the offset of the job's camera in the views buffer.
     fn submit_job<'r>(
         &'r self,
         job: RenderJob<'r>,
+        instance_ranges: &[Range<u64>],
+        view_offset: u32,
+        indirect_offset: &mut u64,
         pass: &mut RenderPass<'r>,
     ) {
-        let mut buffer_offset = 0;
-        let mut instance_bytes = Vec::new();
-
         if let Some(vp) = job.camera.viewport {
             let sc = URect::from(vp);
             pass.set_viewport(vp.origin.x, vp.origin.y, vp.size.x, vp.size.y, 0.0, 1.0);
             pass.set_scissor_rect(sc.origin.x, sc.origin.y, sc.size.x, sc.size.y);
         }
 
-        for instance_batch in job.instance_batches {
+        // The skybox is drawn after opaque batches, so that it only covers pixels they did not.
+        // Transparent batches are drawn next, so that what is behind them has been drawn.
+        // Gizmos are drawn last, over everything.
+        let (opaque_ranges, transparent_ranges) = instance_ranges.split_at(job.instance_batches.len());
+        for (instance_batch, instance_range) in job.instance_batches.into_iter().zip(opaque_ranges) {
+            self.submit_batch(instance_batch, instance_range, view_offset, indirect_offset, pass);
+        }
+        if let Some(skybox) = job.skybox {
+            self.skybox_pass.draw(skybox, pass);
+        }
+        for (instance_batch, instance_range) in job.transparent_batches.into_iter().zip(transparent_ranges) {
+            self.submit_batch(instance_batch, instance_range, view_offset, indirect_offset, pass);
+        }
+        if let Some(gizmos) = job.gizmos {
+            self.gizmo_pass.draw(gizmos, pass);
+        }
+    }
+
+    /// Draws instances of a single material / mesh.
+    fn submit_batch<'r>(
+        &'r self,
+        instance_batch: MatMeshInstances<'r>,
+        instance_range: &Range<u64>,
+        view_offset: u32,
+        indirect_offset: &mut u64,
+        pass: &mut RenderPass<'r>,
+    ) {
+        // Gets material, mesh and pipeline for rendering.
+        let (material, mesh) = (instance_batch.material, instance_batch.mesh);
+        let (pipeline, _) = self.pipelines.get(&instance_batch.pipeline_key).unwrap();
 
+        // Draws instances
+        let num_instances = instance_batch.num_instances;
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(MATERIAL_INDEX, &material.bind_group, &[]);                       // Material
+        if instance_batch.lit {
+            pass.set_bind_group(LIGHTS_INDEX, &self.lights_bind_group, &[view_offset]);       // Lights
+        }
+        if let Some(skin_offset) = instance_batch.skin_offset {
+            let skin_index = if instance_batch.lit { SKIN_INDEX } else { LIGHTS_INDEX };
+            pass.set_bind_group(skin_index, self.skin.bind_group(), &[skin_offset]);          // Bone matrices
+        }
+        let instances = match (&self.cull_pass, instance_batch.gpu_culled) {
+            (Some(cull_pass), true) => cull_pass.culled_instances(),
+            _ => &self.instances,
+        };
+        pass.set_vertex_buffer(INSTANCE_SLOT, instances.slice(instance_range.clone()));       // Instance data
+        pass.set_vertex_buffer(VERTEX_SLOT, mesh.vertices.slice(..));                         // Mesh vertices
+        pass.set_index_buffer(mesh.indices.slice(..), mesh.index_format);                     // Mesh indices
+        match self.gpu_driven_mode {
+            GpuDrivenMode::Disabled => pass.draw_indexed(0..mesh.num_indices, 0, 0..num_instances),
+            GpuDrivenMode::Indirect | GpuDrivenMode::IndirectCulled => {
+                pass.draw_indexed_indirect(&self.indirect, *indirect_offset);
+                *indirect_offset += size_of::<DrawIndexedIndirect>() as u64;
+            },
+        }
+    }
+}
 
-            // Gets material, mesh and pipeline for rendering.
-            let (material, mesh) = (instance_batch.material, instance_batch.mesh);
+/// True if the volume, transformed by global_transform, is outside of the frustum.
+/// Counts the outcome in breakdown.
+fn is_culled(volume: Volume, global_transform: Mat4, frustum: &Frustum, breakdown: &mut CullBreakdown) -> bool {
+    let (contained, culled) = match volume {
+        Volume::Sphere(sphere) => (frustum.contains_sphere(sphere.transform(global_transform)), &mut breakdown.sphere_culled),
+        Volume::AABB(aabb) => (frustum.contains_aabb(aabb.transform(global_transform)), &mut breakdown.aabb_culled),
+        Volume::OBB(obb) => (frustum.contains_obb(obb.transform(global_transform)), &mut breakdown.obb_culled),
+    };
+    match contained {
+        true => breakdown.passed += 1,
+        false => *culled += 1,
+    }
+    !contained
+}
 
-            // Collects instance bytes for this batch
-            let transform_bytes: &[u8] = bytemuck::cast_slice(&instance_batch.instance_data);
-            instance_bytes.extend_from_slice(transform_bytes);
+/**
+ * Once cache holds more than max_len entries, removes those unused for at least idle_frames frames,
+ * least recently used first, until max_len remain.
+ * Values are paired with the frame they were last used.
+ */
+fn evict_least_recently_used<K: Copy + Eq + Hash, V>(cache: &mut HashMap<K, (V, u64)>, frame: u64, max_len: usize, idle_frames: u64) {
+    if cache.len() <= max_len { return }
+    let mut evictable: Vec<(u64, K)> = cache
+        .iter()
+        .filter(|(_, (_, last_used))| frame.saturating_sub(*last_used) >= idle_frames)
+        .map(|(key, (_, last_used))| (*last_used, *key))
+        .collect();
+    evictable.sort_by_key(|(last_used, _)| *last_used);
+    let excess = cache.len() - max_len;
+    for (_, key) in evictable.into_iter().take(excess) {
+        cache.remove(&key);
+    }
+}
 
-            let pipeline = self.pipelines.get(&instance_batch.pipeline_key).unwrap();
+/// Byte ranges of each batch in a buffer shared by every job, in the order the batches are drawn.
+/// batch_sizes holds the size in bytes of each batch, per job.
+fn instance_ranges(batch_sizes: &[Vec<u64>]) -> Vec<Vec<Range<u64>>> {
+    let mut offset = 0;
+    batch_sizes
+        .iter()
+        .map(|job_sizes| job_sizes
+            .iter()
+            .map(|size| {
+                let range = offset..offset + size;
+                offset += size;
+                range
+            })
+            .collect()
+        )
+        .collect()
+}
 
-            // Draws instances of a single material / mesh
-            let instance_range = buffer_offset .. buffer_offset+transform_bytes.len() as u64;
-            let num_instances = instance_batch.instance_data.len() as u32;
-            pass.set_pipeline(pipeline);
-            pass.set_bind_group(MATERIAL_INDEX, &material.bind_group, &[]);               // Material
-            pass.set_vertex_buffer(INSTANCE_SLOT, self.instances.slice(instance_range));  // Instance data
-            pass.set_vertex_buffer(VERTEX_SLOT, mesh.vertices.slice(..));                 // Mesh vertices
-            pass.set_index_buffer(mesh.indices.slice(..), mesh.index_format);             // Mesh indices
-            pass.draw_indexed(0..mesh.num_indices, 0, 0..num_instances);
-            buffer_offset += transform_bytes.len() as u64;
+/// Sorts transparent instances back to front by view-space depth.
+/// Neighboring instances of the same material and mesh are merged into a single batch, unless skinned, as each binds its own bone matrices.
+fn sort_transparent_instances<'a>(mut instances: Vec<(f32, InstanceKey, MatMeshInstances<'a>)>) -> Vec<MatMeshInstances<'a>> {
+    instances.sort_by(|(a, _, _), (b, _, _)| a.total_cmp(b));
+    let mut batches: Vec<(InstanceKey, MatMeshInstances)> = Vec::new();
+    for (_, instance_key, instances) in instances {
+        match batches.last_mut() {
+            Some((last_key, last)) if *last_key == instance_key && last.skin_offset.is_none() => {
+                last.instance_data.extend(instances.instance_data);
+                last.num_instances += instances.num_instances;
+            },
+            _ => batches.push((instance_key, instances)),
         }
-        self.queue.write_buffer(&self.instances, 0, &instance_bytes);
     }
+    batches.into_iter().map(|(_, batch)| batch).collect()
 }
 
 /// Creates a "flattened" version of the scene.
 /// All renderables have their transforms propagated.
 /// All renderables are put into separate flat vecs.
 #[instrument(skip_all)]
-pub(crate) fn flatten_scene<'a>(scene: &'a Scene<Renderable>, t: f32) -> FlatScene<'a> {
+pub(crate) fn flatten_scene<'a>(scene: &'a mut Scene<Renderable>, t: f32) -> FlatScene<'a> {
     let mut flat_scene = FlatScene::with_capacities(scene.len(), 1);
     let init_transf = Mat4::IDENTITY;
-    scene.graph.propagate(init_transf, |parent_transf, renderable| {
-        let local_transform = renderable.previous_transform.lerp(renderable.transform, t);
-        let local_affine = Affine3A::from(local_transform);
-        let global_transform = parent_transf * local_affine;
+    scene.graph.propagate_dirty(init_transf, |parent_transf, renderable, dirty| {
+
+        // Only recomputes global transform if it could have changed since last frame.
+        let changed = dirty || renderable.is_interpolating();
+        if changed {
+            let local_transform = renderable.previous_transform.lerp(renderable.transform, t);
+            let local_affine = Affine3A::from(local_transform);
+            renderable.global_transform = parent_transf * local_affine;
+        }
+        let renderable: &'a Renderable = renderable;
+        let global_transform = renderable.global_transform;
         match &renderable.kind {
             RenderableKind::MatMesh(mat_mesh) => flat_scene.flat_mat_meshes.push(FlatMatMesh {
                 mat_mesh,
                 global_transform,
                 volume: renderable.volume,
+                auto_volume: renderable.auto_volume,
+                layer_mask: renderable.layer_mask,
+                pose: &renderable.pose,
+                lod: renderable.lod.as_ref(),
             }),
             RenderableKind::Camera(camera) => flat_scene.flat_cams.push(FlatCamera {
                 global_transform,
-                _target: &camera.target,
+                target: &camera.target,
+                render_texture: None,
                 projection: lerp_matrices(camera.previous_projection, camera.projection, t),
                 viewport: camera.viewport,
+                clear: camera.clear,
+                layer_mask: camera.layer_mask,
+                order: camera.order,
+                enabled: camera.enabled,
+            }),
+            RenderableKind::PointLight(light) => flat_scene.point_lights.push(FlatPointLight {
+                position: global_transform.transform_point3(light.position),
+                ..FlatPointLight::from(*light)
+            }),
+            RenderableKind::SpotLight(light) => flat_scene.spot_lights.push(FlatSpotLight {
+                position: global_transform.transform_point3(light.position),
+                direction: global_transform.transform_vector3(light.direction),
+                ..FlatSpotLight::from(*light)
             }),
+            RenderableKind::Skybox(cube_map) => flat_scene.skybox_handle = Some(cube_map),
             RenderableKind::Empty => {},
         }
-        global_transform
+        (global_transform, changed)
     });
     flat_scene
 }
@@ -240,7 +983,74 @@ pub(crate) fn flatten_scene<'a>(scene: &'a Scene<Renderable>, t: f32) -> FlatSce
 // Collection of render jobs to render later.
 pub struct RenderJobs<'a> {
     jobs: Vec<RenderJob<'a>>,
+    shadow_job: Option<ShadowJob<'a>>,
     renderable_count: u64,
+    batch_count: u64,
+}
+
+/// Frustum culling statistics, summed across all cameras.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub struct CullingStats {
+    /// Total number of mat meshes culled.
+    pub culled: u64,
+    /// Disposition of mat meshes by bounding volume type.
+    pub breakdown: CullBreakdown,
+}
+
+/// Number of mat meshes culled or kept, by bounding volume type.
+/// Useful for choosing whichever volume type is more effective for a scene.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub struct CullBreakdown {
+    /// Culled by a bounding sphere test.
+    pub sphere_culled: u64,
+    /// Culled by an AABB test.
+    pub aabb_culled: u64,
+    /// Culled by an OBB test.
+    pub obb_culled: u64,
+    /// Had no bounding volume, so were never culled.
+    pub no_volume: u64,
+    /// Had a bounding volume, and passed the frustum test.
+    pub passed: u64,
+    /// Had a bounding volume, and was tested on the GPU, whose results are not read back.
+    /// See [`GpuDrivenMode::IndirectCulled`].
+    pub gpu_tested: u64,
+}
+
+/// Pipelines to compile ahead of time, before they are first rendered.
+/// Drained by the [`crate::GraphicsPlugin`] during [`crate::Stage::Asset`].
+/// Each request compiles every combination of its mesh keys and material keys.
+#[derive(Default, Debug)]
+pub struct PipelinePrecompiler {
+    requests: Vec<(Vec<MeshKey>, Vec<MaterialKey>)>,
+}
+
+impl PipelinePrecompiler {
+
+    /// Requests that every combination of the mesh keys and material keys be compiled.
+    /// Should be called once after initial asset loading.
+    pub fn request(&mut self, mesh_keys: &[MeshKey], material_keys: &[MaterialKey]) {
+        self.requests.push((mesh_keys.to_vec(), material_keys.to_vec()));
+    }
+
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = (Vec<MeshKey>, Vec<MaterialKey>)> + '_ {
+        self.requests.drain(..)
+    }
+}
+
+/// Determines how [`G3D`] issues its draw calls.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub enum GpuDrivenMode {
+    /// One draw call is encoded per material / mesh batch.
+    #[default]
+    Disabled,
+    /// Draw arguments for every batch are written to a single indirect buffer,
+    /// and batches are drawn with indirect draw calls.
+    /// Reduces per-draw CPU overhead in large scenes.
+    Indirect,
+    /// Same as [`GpuDrivenMode::Indirect`], but opaque instances are frustum culled by a compute pass, which writes the instance count of each draw.
+    /// Instances are culled by a sphere around their volume, which is looser than the volume itself.
+    /// Requires compute shader support.
+    IndirectCulled,
 }
 
 /// Collection of "flattened" renderables to be rendered at a later time.
@@ -250,6 +1060,19 @@ pub struct RenderJobs<'a> {
 struct RenderJob<'a> {
     camera: FlatCamera<'a>,
     instance_batches: Vec<MatMeshInstances<'a>>,
+    transparent_batches: Vec<MatMeshInstances<'a>>,   // Sorted back to front
+    frustum: Frustum,                                   // Frustum of the camera, which opaque batches are culled by on the GPU
+    skybox: Option<SkyboxDraw>,                         // Skybox drawn by the camera, if any
+    gizmos: Option<GizmoDraw>,                          // Gizmos drawn by the camera, if it sees any
+    is_first_on_target: bool,                           // If true, the camera clears the depth of its target, and its color if it has a clear color
+}
+
+impl<'a> RenderJob<'a> {
+
+    /// Batches in the order they are drawn.
+    fn batches(&self) -> impl Iterator<Item = &MatMeshInstances<'a>> {
+        self.instance_batches.iter().chain(&self.transparent_batches)
+    }
 }
 
 /**
@@ -259,8 +1082,18 @@ pub struct Renderable {
     pub kind: RenderableKind,
     transform: Transform,
     previous_transform: Transform,
+    global_transform: Mat4,                 // Cached during flattening
     pub volume: Option<Volume>,
+    /// If true and there is no volume, mat meshes are culled by the bounding box of their mesh, as computed by [`Mesh::aabb`].
+    /// Skinned meshes are not culled, as their pose can move them out of it.
+    pub auto_volume: bool,
     pub interpolation_mode: InterpolationMode,
+    /// Layers the renderable is on. Only cameras whose layer mask shares a layer with it see it.
+    /// See [`LAYER_DEFAULT`] and others.
+    pub layer_mask: u32,
+    /// Less detailed meshes to render instead of the mat mesh's own as cameras get farther away.
+    pub lod: Option<MeshLod>,
+    pose: Vec<Mat4>,                        // Bone matrices, if the mesh is skinned
 }
 
 impl Default for Renderable {
@@ -269,8 +1102,13 @@ impl Default for Renderable {
             kind: RenderableKind::Empty,
             transform: Transform::IDENTITY,
             previous_transform: Transform::IDENTITY,
+            global_transform: Mat4::IDENTITY,
             volume: None,
+            auto_volume: false,
             interpolation_mode: InterpolationMode::Skip,
+            layer_mask: LAYER_ALL,
+            lod: None,
+            pose: Vec::new(),
         }
     }
 }
@@ -304,6 +1142,39 @@ impl Renderable {
         }
     }
 
+    /**
+     * Creates a [`PointLight`] renderable.
+     * The light's position is relative to the renderable's global transform.
+     */
+    pub fn point_light(light: PointLight) -> Self {
+        Self {
+            kind: RenderableKind::PointLight(light),
+            ..Default::default()
+        }
+    }
+
+    /**
+     * Creates a [`SpotLight`] renderable.
+     * The light's position and direction are relative to the renderable's global transform.
+     */
+    pub fn spot_light(light: SpotLight) -> Self {
+        Self {
+            kind: RenderableKind::SpotLight(light),
+            ..Default::default()
+        }
+    }
+
+    /**
+     * Creates a skybox renderable, drawn behind everything else.
+     * Its transform is ignored.
+     */
+    pub fn skybox(cube_map: Handle<CubeMap>) -> Self {
+        Self {
+            kind: RenderableKind::Skybox(cube_map),
+            ..Default::default()
+        }
+    }
+
     pub fn with_kind(mut self, kind: RenderableKind) -> Self {
         self.kind = kind;
         self
@@ -319,6 +1190,21 @@ impl Renderable {
         self
     }
 
+    pub fn with_point_light(mut self, light: PointLight) -> Self {
+        self.kind = RenderableKind::PointLight(light);
+        self
+    }
+
+    pub fn with_spot_light(mut self, light: SpotLight) -> Self {
+        self.kind = RenderableKind::SpotLight(light);
+        self
+    }
+
+    pub fn with_skybox(mut self, cube_map: Handle<CubeMap>) -> Self {
+        self.kind = RenderableKind::Skybox(cube_map);
+        self
+    }
+
     pub fn with_empty(mut self) -> Self {
         self.kind = RenderableKind::Empty;
         self
@@ -329,6 +1215,16 @@ impl Renderable {
         self
     }
 
+    pub fn with_layer_mask(mut self, layer_mask: u32) -> Self {
+        self.layer_mask = layer_mask;
+        self
+    }
+
+    pub fn with_lod(mut self, lod: MeshLod) -> Self {
+        self.lod = Some(lod);
+        self
+    }
+
     pub fn with_volume(mut self, volume: Volume) -> Self {
         self.volume = Some(volume);
         self
@@ -344,10 +1240,38 @@ impl Renderable {
         self
     }
 
+    pub fn with_obb_volume(mut self, center: Vec3, half_extents: Vec3, rotation: Quat) -> Self {
+        self.volume = Some(Volume::OBB(OBB::new(center, half_extents, rotation)));
+        self
+    }
+
+    /// Culls the mat mesh by the bounding box of its mesh, unless it is given a volume. See [`Renderable::auto_volume`].
+    pub fn with_auto_volume(mut self) -> Self {
+        self.auto_volume = true;
+        self
+    }
+
     pub fn transform(&self) -> Transform {
         self.transform
     }
 
+    /// Bone matrices that pose the renderable's mesh, if it is skinned.
+    /// See [`crate::g3d::SkinnedMeshPose`].
+    pub fn pose(&self) -> &[Mat4] {
+        &self.pose
+    }
+
+    pub fn set_pose(&mut self, matrices: &[Mat4]) {
+        self.pose.clear();
+        self.pose.extend_from_slice(matrices);
+    }
+
+    /// True if the transform differs from the previous transform.
+    /// If so, the renderable moves between ticks.
+    pub fn is_interpolating(&self) -> bool {
+        self.previous_transform != self.transform
+    }
+
     pub fn set_transform(&mut self, transform: Transform) {
         match self.interpolation_mode {
             InterpolationMode::Interpolate => {
@@ -378,6 +1302,14 @@ pub enum RenderableKind {
     /// No renderable content.
     /// 3D perspective or orthographic camera.
     Camera(Camera),
+    /// Light that affects meshes with normals, when lighting is enabled.
+    PointLight(PointLight),
+    /// Light that affects meshes with normals, when lighting is enabled.
+    SpotLight(SpotLight),
+    /// Cube map drawn behind everything else, from the perspective of each camera.
+    /// Also lights physically-based materials as their environment.
+    /// If there are several, only one is drawn.
+    Skybox(Handle<CubeMap>),
     /// No renderable content.
     /// Useful for grouping objects with no visible parent.
     Empty,
@@ -421,19 +1353,65 @@ pub struct FlatMatMesh<'a> {
     mat_mesh: &'a MatMesh,
     global_transform: Mat4,
     volume: Option<Volume>,
+    auto_volume: bool,
+    layer_mask: u32,
+    pose: &'a [Mat4],
+    lod: Option<&'a MeshLod>,
 }
 
 /// Camera with its transform propagated.
 pub struct FlatCamera<'a> {
-    _target: &'a CameraTarget,
+    target: &'a CameraTarget,
+    render_texture: Option<(AssetId, &'a RenderTexture)>,   // Texture of an off screen target, once resolved
     projection: Mat4,
     global_transform: Mat4,
     viewport: Option<Rect>,
+    clear: Option<Color>,
+    layer_mask: u32,
+    order: i32,
+    enabled: bool,
+}
+
+impl<'a> FlatCamera<'a> {
+
+    /// True if the camera sees renderables on any of the layers of layer_mask.
+    fn sees(&self, layer_mask: u32) -> bool {
+        self.layer_mask & layer_mask != 0
+    }
+
+    /// Sample count of the camera's target, where surface_sample_count is that of the surface.
+    /// Render textures are never multisampled.
+    fn sample_count(&self, surface_sample_count: u32) -> u32 {
+        match self.render_texture {
+            Some(_) => 1,
+            None => surface_sample_count,
+        }
+    }
+}
+
+/**
+ * Removes disabled cameras, and sorts the rest in the order they render.
+ * Cameras that render to textures go first, so that cameras on screen see what they rendered this frame.
+ * Otherwise, cameras render by ascending order, and cameras of the same order keep their order in the scene graph.
+ */
+fn sort_cameras(flat_cams: &mut Vec<FlatCamera>) {
+    flat_cams.retain(|flat_cam| flat_cam.enabled);
+    flat_cams.sort_by_key(|flat_cam| (flat_cam.render_texture.is_none(), flat_cam.order));
+}
+
+/// For each camera, true if no camera before it renders to the same target.
+fn firsts_on_target(flat_cams: &[FlatCamera]) -> Vec<bool> {
+    let mut seen_targets = HashSet::new();
+    flat_cams
+        .iter()
+        .map(|flat_cam| seen_targets.insert(flat_cam.render_texture.map(|(render_texture_id, _)| render_texture_id)))
+        .collect()
 }
 
 /// Used to select a pipeline from a cache.
+/// Includes the sample count, so that changing it compiles new pipelines instead of reusing incompatible ones.
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
-struct PipelineKey(MeshKey, MaterialKey);
+struct PipelineKey(MeshKey, MaterialKey, u32);
 impl identity_hash::IdentityHashable for PipelineKey {}
 
 /// Key used to collect material/meshes into instances
@@ -448,7 +1426,12 @@ struct MatMeshInstances<'a> {
     material: &'a PreparedMaterial,
     mesh: &'a Mesh,
     pipeline_key: PipelineKey,
+    lit: bool,
+    skin_offset: Option<u32>,       // Offset of the bone matrices of the instance, if its mesh is skinned
+    gpu_culled: bool,               // If true, instances are culled on the GPU, and drawn from the cull pass's instances
     instance_data: Vec<Mat4>,
+    bounds: Vec<Vec4>,              // Bounding sphere of each instance, if culled on the GPU
+    num_instances: u32,
 }
 
 impl<'a> MatMeshInstances<'a> {
@@ -456,59 +1439,182 @@ impl<'a> MatMeshInstances<'a> {
         material: &'a PreparedMaterial,
         mesh: &'a Mesh,
         pipeline_key: PipelineKey,
+        lit: bool,
+        skin_offset: Option<u32>,
+        gpu_culled: bool,
     ) -> Self {
         Self {
             material,
             mesh,
             pipeline_key,
+            lit,
+            skin_offset,
+            gpu_culled,
             instance_data: Vec::new(),
+            bounds: Vec::new(),
+            num_instances: 0,
         }
     }
+
+    fn instance_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.instance_data)
+    }
 }
 
-/// Creates a pipeline compatible with the material and mesh supplied.
+/// Creates the layout and bind group of the lights uniform, of the camera's slot in the views buffer, and of the environment map.
+/// Also binds the shadow map and its sampler if shadows are enabled.
+fn create_lights_bindings(
+    lights: &Buffer,
+    views: &Buffer,
+    shadow_pass: Option<&ShadowPass>,
+    environment: &CubeMap,
+    device: &Device,
+) -> (BindGroupLayout, BindGroup) {
+    let mut layout_entries = vec![BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }];
+    let mut entries = vec![BindGroupEntry {
+        binding: 0,
+        resource: lights.as_entire_binding(),
+    }];
+    layout_entries.push(BindGroupLayoutEntry {
+        binding: VIEW_BINDING,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: true,
+            min_binding_size: BufferSize::new(VIEW_SIZE),
+        },
+        count: None,
+    });
+    entries.push(BindGroupEntry {
+        binding: VIEW_BINDING,
+        resource: BindingResource::Buffer(BufferBinding {
+            buffer: views,
+            offset: 0,
+            size: BufferSize::new(VIEW_SIZE),
+        }),
+    });
+    layout_entries.push(BindGroupLayoutEntry {
+        binding: ENVIRONMENT_MAP_BINDING,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: true },
+            view_dimension: TextureViewDimension::Cube,
+            multisampled: false,
+        },
+        count: None,
+    });
+    entries.push(BindGroupEntry {
+        binding: ENVIRONMENT_MAP_BINDING,
+        resource: BindingResource::TextureView(&environment.view),
+    });
+    layout_entries.push(BindGroupLayoutEntry {
+        binding: ENVIRONMENT_SAMPLER_BINDING,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Sampler(SamplerBindingType::Filtering),
+        count: None,
+    });
+    entries.push(BindGroupEntry {
+        binding: ENVIRONMENT_SAMPLER_BINDING,
+        resource: BindingResource::Sampler(&environment.sampler),
+    });
+    if let Some(shadow_pass) = shadow_pass {
+        layout_entries.extend(ShadowPass::layout_entries());
+        entries.extend(shadow_pass.bind_group_entries());
+    }
+    let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("g3d_lights_layout"),
+        entries: &layout_entries,
+    });
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: Some("g3d_lights_bind_group"),
+        layout: &layout,
+        entries: &entries,
+    });
+    (layout, bind_group)
+}
+
+/// Creates a pipeline compatible with the material and mesh keys supplied.
+/// Skinned meshes bind their bone matrices after the lights, if lit.
+/// Fails if the shader could not be preprocessed, or if wgpu rejects the shader or pipeline.
 fn create_pipeline(
-    material: &PreparedMaterial,
-    mesh: &Mesh,
-    cull_mode: Option<Face>,
+    pipeline_key: PipelineKey,
     texture_format: TextureFormat,
     depth_format: TextureFormat,
+    lights_layout: Option<&BindGroupLayout>,
+    skin_layout: Option<&BindGroupLayout>,
+    shadows: bool,
     device: &Device
-) -> RenderPipeline {
+) -> Result<RenderPipeline, PipelineError> {
 
     // Extracts layout info and shader defs
+    let PipelineKey(mesh_key, material_key, sample_count) = pipeline_key;
     let mut shader_defs = ShaderPreprocessor::new();
-    material.write_shader_defs(&mut shader_defs);
-    let mesh_layout = mesh.key.layout(&mut shader_defs);
+    material_key.write_shader_defs(&mut shader_defs);
+    let material_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("g3d_material_layout"),
+        entries: material_key.layout().entries(),
+    });
+    let mesh_layout = mesh_key.layout(&mut shader_defs);
     let vertex_layout = mesh_layout.as_vertex_layout();
+    let instance_layout = match lights_layout {
+        Some(_) => {
+            shader_defs.add("LIGHTING");
+            if shadows {
+                shader_defs.add("SHADOW");
+            }
+            let normal_map_attribs = MeshKey::NORMAL | MeshKey::UV | MeshKey::TANGENT;
+            if material_key.flags.contains(MaterialFlags::NORMAL_MAP) && mesh_key.contains(normal_map_attribs) {
+                shader_defs.add("NORMAL_MAP");
+            }
+            LIT_INSTANCE_LAYOUT
+        },
+        None => INSTANCE_LAYOUT,
+    };
 
     // Generates shader module
     let shader_code = include_str!("shader.wgsl");
-    let shader_code = shader_defs.preprocess(shader_code).unwrap();
+    let shader_code = shader_defs.preprocess(shader_code)?;
+    device.push_error_scope(ErrorFilter::Validation);
     let module = device.create_shader_module(ShaderModuleDescriptor { label: Some("g3d_module"),
         source: ShaderSource::Wgsl(shader_code.into()),
     });
 
     // Creates pipeline
+    let mut bind_group_layouts = vec![&material_layout];
+    if let Some(lights_layout) = lights_layout {
+        bind_group_layouts.push(lights_layout);
+    }
+    if let Some(skin_layout) = skin_layout {
+        bind_group_layouts.push(skin_layout);
+    }
     let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
         label: Some("g3d_layout"),
-        bind_group_layouts: &[&material.bind_group_layout],
+        bind_group_layouts: &bind_group_layouts,
         push_constant_ranges: &[],
     });
-    device.create_render_pipeline(&RenderPipelineDescriptor {
+    let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
         label: Some("g3d_pipeline"),
         layout: Some(&layout),
         vertex: VertexState {
             module: &module,
             entry_point: "vertex_main",
-            buffers: &[INSTANCE_LAYOUT, vertex_layout],
+            buffers: &[instance_layout, vertex_layout],
         },
         fragment: Some(FragmentState {
             module: &module,
             entry_point: "fragment_main",
             targets: &[Some(ColorTargetState {
                 format: texture_format,
-                blend: Some(BlendState::REPLACE),
+                blend: Some(material_key.blend_mode.blend_state()),
                 write_mask: ColorWrites::ALL,
             })],
         }),
@@ -516,27 +1622,53 @@ fn create_pipeline(
             topology: PrimitiveTopology::TriangleList,
             strip_index_format: None,
             front_face: FrontFace::Ccw,
-            cull_mode,
+            cull_mode: material_key.cull_mode,
             unclipped_depth: false,
             polygon_mode: PolygonMode::Fill,
             conservative: false,
         },
         depth_stencil: Some(DepthStencilState {
             format: depth_format,
-            depth_write_enabled: true,
+            depth_write_enabled: !material_key.blend_mode.is_transparent(),
             depth_compare: CompareFunction::LessEqual,
             stencil: StencilState::default(),
             bias: DepthBiasState::default(),
         }),
-        multisample: Default::default(),
+        multisample: MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
         multiview: None,
-    })
+    });
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(err) => Err(PipelineError::Validation(err)),
+        None => Ok(pipeline),
+    }
+}
+
+/// Reason a G3D pipeline could not be compiled.
+#[derive(Error, Display, From, Debug)]
+pub enum PipelineError {
+    #[display(fmt="{_0}")]
+    Preprocessing(ShaderDefError),
+    #[display(fmt="{_0}")]
+    Validation(wgpu::Error),
+    #[display(fmt="Pipeline failed to compile previously")]
+    PreviouslyFailed,
 }
 
 /// A flattened [`SceneGraph`] where renderable is separated by type.
 pub(crate) struct FlatScene<'a> {
     flat_mat_meshes: Vec<FlatMatMesh<'a>>,
     flat_cams: Vec<FlatCamera<'a>>,
+    pub(crate) point_lights: Vec<FlatPointLight>,
+    pub(crate) spot_lights: Vec<FlatSpotLight>,
+    pub(crate) ambient_light: Option<AmbientLight>,
+    pub(crate) directional_light: Option<DirectionalLight>,
+    skybox_handle: Option<&'a Handle<CubeMap>>,
+    skybox: Option<(AssetId, &'a CubeMap)>,         // Cube map of the skybox, once loaded
+    pub(crate) gizmos: Option<&'a Gizmos>,
+    failed_render_targets: Vec<AssetId>,            // Render textures of cameras that were removed because they failed to load
 }
 
 impl<'a> FlatScene<'a> {
@@ -545,6 +1677,494 @@ impl<'a> FlatScene<'a> {
         Self {
             flat_mat_meshes: Vec::with_capacity(mat_meshes),
             flat_cams: Vec::with_capacity(cams),
+            point_lights: Vec::new(),
+            spot_lights: Vec::new(),
+            ambient_light: None,
+            directional_light: None,
+            skybox_handle: None,
+            skybox: None,
+            gizmos: None,
+            failed_render_targets: Vec::new(),
         }
     }
-}
\ No newline at end of file
+
+    /**
+     * Looks up the render textures of cameras that render off screen.
+     * Cameras whose render texture is not loaded are removed, as they have nothing to render to.
+     * Those whose render texture failed to load are reported when creating jobs.
+     */
+    pub fn resolve_render_targets(&mut self, render_textures: &'a AssetStorage<RenderTexture>) {
+        let failed_render_targets = &mut self.failed_render_targets;
+        self.flat_cams.retain_mut(|flat_cam| {
+            let CameraTarget::OffScreen(handle) = flat_cam.target else { return true };
+            match render_textures.get(handle) {
+                AssetState::Loaded(render_texture) => {
+                    flat_cam.render_texture = Some((handle.id(), render_texture));
+                    true
+                },
+                AssetState::Failed => {
+                    failed_render_targets.push(handle.id());
+                    false
+                },
+                AssetState::Loading => false,
+            }
+        });
+    }
+
+    /// Looks up the cube map of the scene's skybox.
+    /// The skybox is not drawn until this is called, and its cube map is loaded, faces included.
+    pub fn resolve_skybox(&mut self, cube_maps: &'a AssetStorage<CubeMap>) {
+        let Some(handle) = self.skybox_handle else { return };
+        match cube_maps.get(handle) {
+            AssetState::Loaded(cube_map) if !cube_map.is_pending() => self.skybox = Some((handle.id(), cube_map)),
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::time::Instant;
+    use glam::{Mat4, UVec2, Vec3, Vec4};
+    use wgpu::util::DrawIndexedIndirect;
+    use wgpu::{Device, LoadOp, Operations, Queue, RenderPassColorAttachment, RenderPassDescriptor, StoreOp};
+    use std::io::Cursor;
+    use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+    use crate::{AssetLoader, AssetManager, AssetPath, Color, Handle, RawProtocol, RenderTexture, Scene, Texture, TextureLoader, TextureLoaderConfig};
+    use crate::g3d::{BlendMode, Camera, CameraTarget, Cuboid, DirectionalLight, Material, Mesh, MeshData, MeshLod, ObjLoader};
+    use crate::Rect;
+    use crate::math::{Frustum, Transform, Volume, AABB};
+    use crate::plugins::graphics::headless;
+    use super::{evict_least_recently_used, flatten_scene, instance_ranges, is_culled, sort_cameras, CullBreakdown, GpuDrivenMode, Renderable, RenderableKind, G3D};
+
+    const TARGET_SIZE: UVec2 = UVec2::new(64, 64);
+
+    /// Assets with a white unit cube, and a material to draw it with.
+    fn cube_assets(device: &Device) -> (AssetManager, Handle<Material>, Handle<Mesh>) {
+        let mut assets = AssetManager::new();
+        assets.add_storage::<Mesh>();
+        assets.add_storage::<Material>();
+        assets.add_storage::<Texture>();
+        assets.add_storage::<RenderTexture>();
+        let mesh_data = MeshData::from(Cuboid { center: Vec3::ZERO, half_extents: Vec3::splat(0.5), color: Color::WHITE });
+        let mesh = assets.insert(Mesh::from_data(&mesh_data, device));
+        let material = assets.insert(Material::from(Color::WHITE));
+        (assets, material, mesh)
+    }
+
+    /// Camera at the origin looking down -z, with a 90 degree field of view.
+    fn camera() -> Renderable {
+        Renderable::empty().with_kind(RenderableKind::Camera(Camera::new(Mat4::perspective_rh(90f32.to_radians(), 1.0, 0.1, 100.0))))
+    }
+
+    fn at(renderable: Renderable, x: f32, y: f32, z: f32) -> Renderable {
+        let mut renderable = renderable;
+        renderable.set_transform(Transform::default().with_xyz(x, y, z));
+        renderable
+    }
+
+    /// Renders a frame of the scene to a target of TARGET_SIZE, and reads it back.
+    fn render(g3d: &mut G3D, scene: &mut Scene<Renderable>, assets: &AssetManager, clear_color: Color, device: &Device, queue: &Queue) -> Vec<[u8; 4]> {
+        render_lit(g3d, scene, None, assets, clear_color, device, queue)
+    }
+
+    /// Same as render, lighting the scene with a directional light.
+    fn render_lit(g3d: &mut G3D, scene: &mut Scene<Renderable>, light: Option<DirectionalLight>, assets: &AssetManager, clear_color: Color, device: &Device, queue: &Queue) -> Vec<[u8; 4]> {
+        let textures = assets.storage::<Texture>().unwrap();
+        let mut materials = assets.storage::<Material>().unwrap();
+        for material in materials.values_mut() {
+            let Some(material) = material.as_loaded_mut() else { continue };
+            material.prepare(&textures, device);
+        }
+        drop(materials);
+        let materials = assets.storage::<Material>().unwrap();
+        let meshes = assets.storage::<Mesh>().unwrap();
+        let (color, depth) = headless::targets(TARGET_SIZE, device);
+        let (color_view, depth_view) = (color.create_view(&Default::default()), depth.create_view(&Default::default()));
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let render_textures = assets.storage::<RenderTexture>().unwrap();
+        let mut flat_scene = flatten_scene(scene, 1.0);
+        flat_scene.directional_light = light;
+        flat_scene.resolve_render_targets(&render_textures);
+        let jobs = g3d.create_jobs(flat_scene, headless::COLOR_FORMAT, headless::DEPTH_FORMAT, 1, &materials, &meshes);
+        g3d.submit_jobs(jobs, &mut encoder, &color_view, None, &depth_view, clear_color);
+        queue.submit([encoder.finish()]);
+        headless::read_pixels(&color, device, queue)
+    }
+
+    fn pixel(pixels: &[[u8; 4]], x: u32, y: u32) -> [u8; 4] {
+        pixels[(y * TARGET_SIZE.x + x) as usize]
+    }
+
+    #[test]
+    fn cameras_render_by_order() {
+        let mut scene = Scene::<Renderable>::new();
+        let right = Camera { order: 1, viewport: Some(Rect::new(50.0, 0.0, 50.0, 100.0)), ..Default::default() };
+        let left = Camera { order: 0, viewport: Some(Rect::new(0.0, 0.0, 50.0, 100.0)), ..Default::default() };
+        let disabled = Camera { order: -1, enabled: false, ..Default::default() };
+        let _right = scene.insert(Renderable::empty().with_kind(RenderableKind::Camera(right)));
+        let _left = scene.insert(Renderable::empty().with_kind(RenderableKind::Camera(left)));
+        let _disabled = scene.insert(Renderable::empty().with_kind(RenderableKind::Camera(disabled)));
+        for _ in 0..3 {
+            let mut flat_scene = flatten_scene(&mut scene, 1.0);
+            sort_cameras(&mut flat_scene.flat_cams);
+            let viewports: Vec<_> = flat_scene.flat_cams.iter().map(|flat_cam| flat_cam.viewport.unwrap().origin.x).collect();
+            assert_eq!(vec![0.0, 50.0], viewports);
+        }
+    }
+
+    #[test]
+    fn culling_is_counted_by_volume() {
+        let frustum = Frustum::from(Mat4::perspective_rh(1.0, 1.0, 0.1, 100.0));
+        let behind = Mat4::from_translation(Vec3::new(0.0, 0.0, 10.0));
+        let ahead = Mat4::from_translation(Vec3::new(0.0, 0.0, -10.0));
+        let mut breakdown = CullBreakdown::default();
+        assert!(!is_culled(Volume::from(AABB::UNIT), ahead, &frustum, &mut breakdown));
+        assert!(is_culled(Volume::from(AABB::UNIT), behind, &frustum, &mut breakdown));
+        assert!(is_culled(Volume::sphere(Vec3::ZERO, 1.0), behind, &frustum, &mut breakdown));
+
+        // The box of a mesh without vertices is always culled.
+        assert!(is_culled(Volume::from(AABB::EMPTY), ahead, &frustum, &mut breakdown));
+        assert_eq!(CullBreakdown { aabb_culled: 2, sphere_culled: 1, passed: 1, ..Default::default() }, breakdown);
+    }
+
+    #[test]
+    fn cameras_only_see_their_layers() {
+        let mut scene = Scene::<Renderable>::new();
+        let camera = Camera { layer_mask: 0b10, ..Default::default() };
+        let _camera = scene.insert(Renderable::empty().with_kind(RenderableKind::Camera(camera)));
+        let flat_scene = flatten_scene(&mut scene, 1.0);
+        let flat_cam = &flat_scene.flat_cams[0];
+        assert!(!flat_cam.sees(Renderable::empty().with_layer_mask(0b01).layer_mask));
+        assert!(flat_cam.sees(Renderable::empty().with_layer_mask(0b11).layer_mask));
+        assert!(flat_cam.sees(Renderable::empty().layer_mask));
+    }
+
+    #[test]
+    fn evicts_least_recently_used_idle_entries() {
+        let mut cache: HashMap<u32, ((), u64)> = (0..6).map(|key| (key, ((), key as u64 * 10))).collect();
+
+        // Nothing is evicted while under capacity
+        evict_least_recently_used(&mut cache, 100, 6, 5);
+        assert_eq!(6, cache.len());
+
+        // Oldest entries are evicted first
+        evict_least_recently_used(&mut cache, 100, 4, 5);
+        let mut keys: Vec<u32> = cache.keys().copied().collect();
+        keys.sort();
+        assert_eq!(vec![2, 3, 4, 5], keys);
+
+        // Entries used recently are kept, even if over capacity
+        evict_least_recently_used(&mut cache, 52, 1, 5);
+        let mut keys: Vec<u32> = cache.keys().copied().collect();
+        keys.sort();
+        assert_eq!(vec![5], keys);
+        evict_least_recently_used(&mut cache, 50, 0, 1);
+        assert_eq!(1, cache.len());
+    }
+
+    #[test]
+    fn instance_ranges_do_not_overlap_across_cameras() {
+
+        // Two cameras that see the same two batches, plus one only the second camera sees.
+        let batch_sizes = vec![vec![128, 64], vec![128, 64, 256]];
+        let ranges = instance_ranges(&batch_sizes);
+        assert_eq!(vec![vec![0..128, 128..192], vec![192..320, 320..384, 384..640]], ranges);
+
+        let mut flat: Vec<_> = ranges.into_iter().flatten().collect();
+        flat.sort_by_key(|range| range.start);
+        for pair in flat.windows(2) {
+            assert!(pair[0].end <= pair[1].start);
+        }
+    }
+
+    #[test]
+    fn gpu_culling_draws_instances_in_frustum() {
+        let Some((device, queue)) = headless::device() else { return };
+        let mut g3d = G3D::new(device.clone(), queue.clone(), false).with_gpu_driven_mode(GpuDrivenMode::IndirectCulled);
+        let (assets, material, mesh) = cube_assets(&device);
+        let mut scene = Scene::<Renderable>::new();
+        let cube = || Renderable::mat_mesh(material.clone(), mesh.clone());
+        let _camera = scene.insert(camera());
+        let _ahead = scene.insert(at(cube().with_sphere_volume(Vec3::ZERO, 1.0), 0.0, 0.0, -3.0));
+        let _behind = scene.insert(at(cube().with_sphere_volume(Vec3::ZERO, 1.0), 0.0, 0.0, 3.0));
+        let _auto_behind = scene.insert(at(cube().with_auto_volume(), 0.0, 0.0, 5.0));
+        let _unbounded_behind = scene.insert(at(cube(), 0.0, 0.0, 7.0));
+        let pixels = render(&mut g3d, &mut scene, &assets, Color::BLACK, &device, &queue);
+        assert_eq!([255, 255, 255, 255], pixel(&pixels, 32, 32));
+        assert_eq!([0, 0, 0, 255], pixel(&pixels, 0, 0));
+
+        // The GPU kept the instance ahead, and the one without a volume.
+        let commands = headless::read_buffer(&g3d.indirect, &device, &queue);
+        let command: &[u32] = bytemuck::cast_slice(&commands[..std::mem::size_of::<DrawIndexedIndirect>()]);
+        assert_eq!(2, command[1], "instance count");
+        let breakdown = g3d.culling_stats().breakdown;
+        assert_eq!(CullBreakdown { gpu_tested: 3, no_volume: 1, ..Default::default() }, breakdown);
+    }
+
+    #[test]
+    fn gpu_culling_copies_unlit_instances_with_lighting() {
+        let Some((device, queue)) = headless::device() else { return };
+        let mut g3d = G3D::new(device.clone(), queue.clone(), true).with_gpu_driven_mode(GpuDrivenMode::IndirectCulled);
+        let (mut assets, material, lit_mesh) = cube_assets(&device);
+        let mut mesh_data = MeshData::from(Cuboid { center: Vec3::ZERO, half_extents: Vec3::splat(0.5), color: Color::WHITE });
+        mesh_data.normals = None;
+        let unlit_mesh = assets.insert(Mesh::from_data(&mesh_data, &device));
+        let mut scene = Scene::<Renderable>::new();
+        let unlit_cube = || Renderable::mat_mesh(material.clone(), unlit_mesh.clone()).with_sphere_volume(Vec3::ZERO, 1.0);
+        let _camera = scene.insert(camera());
+        let _lit = scene.insert(at(Renderable::mat_mesh(material.clone(), lit_mesh).with_sphere_volume(Vec3::ZERO, 1.0), 1.2, 0.0, -3.0));
+        let _behind = scene.insert(at(unlit_cube(), 0.0, 0.0, 3.0));        // First, so the one ahead is read past it
+        let _ahead = scene.insert(at(unlit_cube(), 0.0, 0.0, -3.0));
+        let pixels = render(&mut g3d, &mut scene, &assets, Color::BLACK, &device, &queue);
+        assert_eq!([255, 255, 255, 255], pixel(&pixels, 32, 32));
+    }
+
+    #[test]
+    fn target_is_cleared_without_cameras() {
+        let Some((device, queue)) = headless::device() else { return };
+        let mut g3d = G3D::new(device.clone(), queue.clone(), false);
+        let (assets, material, mesh) = cube_assets(&device);
+        let mut scene = Scene::<Renderable>::new();
+        let _cube = scene.insert(at(Renderable::mat_mesh(material, mesh), 0.0, 0.0, -3.0));
+        let pixels = render(&mut g3d, &mut scene, &assets, Color::RED, &device, &queue);
+        assert!(pixels.iter().all(|&pixel| pixel == [255, 0, 0, 255]));
+
+        // Disabled cameras do not count
+        let disabled = Camera { enabled: false, ..Default::default() };
+        let _disabled = scene.insert(Renderable::empty().with_kind(RenderableKind::Camera(disabled)));
+        let pixels = render(&mut g3d, &mut scene, &assets, Color::BLUE, &device, &queue);
+        assert!(pixels.iter().all(|&pixel| pixel == [0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn blinn_phong_highlights_face_the_viewer() {
+        let Some((device, queue)) = headless::device() else { return };
+        let mut g3d = G3D::new(device.clone(), queue.clone(), true);
+        let (mut assets, _, mesh) = cube_assets(&device);
+        let material = assets.insert(Material { shininess: 2000.0, ..Material::from(Color::new(0.25, 0.25, 0.25, 1.0)) });
+        let mut scene = Scene::<Renderable>::new();
+        let _camera = scene.insert(camera());
+        let _cube = scene.insert(at(Renderable::mat_mesh(material, mesh), 0.0, 0.0, -3.0));
+        let light = DirectionalLight { direction: Vec3::NEG_Z, color: Color::WHITE, intensity: 1.0, ..Default::default() };
+        let pixels = render_lit(&mut g3d, &mut scene, Some(light), &assets, Color::BLACK, &device, &queue);
+
+        // The highlight is where the half vector lines up with the normal, at the center of the face.
+        // Off center, only diffuse light remains.
+        let (center, off_center) = (pixel(&pixels, 32, 32)[0], pixel(&pixels, 37, 32)[0]);
+        assert!(center > 200, "center: {center}");
+        assert!((50..80).contains(&off_center), "off center: {off_center}");
+    }
+
+    #[test]
+    fn normal_maps_bend_lighting() {
+        let Some((device, queue)) = headless::device() else { return };
+        let mut g3d = G3D::new(device.clone(), queue.clone(), true);
+        let (mut assets, material, _) = cube_assets(&device);
+        let mut mesh_data = MeshData::from(Cuboid { center: Vec3::ZERO, half_extents: Vec3::splat(0.5), color: Color::WHITE });
+        mesh_data.compute_tangents();
+        let mesh = assets.insert(Mesh::from_data(&mesh_data, &device));
+
+        // Normal map whose normals point along the tangent, which is +x on the near face.
+        // Maps read the same whether they were loaded linearly or decoded from sRGB.
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([255, 128, 128, 255])))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        for srgb in [true, false] {
+            let loader = TextureLoader::new(device.clone(), queue.clone())
+                .with_config(TextureLoaderConfig { srgb, ..Default::default() });
+            let normal_texture = loader.load(&png, &AssetPath::parse("normal.png", Some("file")).unwrap()).unwrap();
+            assert_eq!(srgb, normal_texture.srgb);
+            let normal_texture = assets.insert(normal_texture);
+            let mapped = assets.insert(Material { normal_texture: Some(normal_texture), ..Material::from(Color::WHITE) });
+
+            // Light shining from +x only reaches the near face of the mapped cube
+            let mut scene = Scene::<Renderable>::new();
+            let _camera = scene.insert(camera());
+            let _mapped = scene.insert(at(Renderable::mat_mesh(mapped, mesh.clone()), -1.2, 0.0, -3.0));
+            let _plain = scene.insert(at(Renderable::mat_mesh(material.clone(), mesh.clone()), 1.2, 0.0, -3.0));
+            let light = DirectionalLight { direction: Vec3::NEG_X, color: Color::WHITE, intensity: 1.0, ..Default::default() };
+            let pixels = render_lit(&mut g3d, &mut scene, Some(light), &assets, Color::BLACK, &device, &queue);
+            let (mapped, plain) = (pixel(&pixels, 17, 32), pixel(&pixels, 47, 32));
+            assert!(mapped[0] > 240, "mapped with srgb {srgb}: {mapped:?}");
+            assert!(plain[0] < 10, "plain: {plain:?}");
+        }
+    }
+
+    /// Camera that renders to the left or right half of its target.
+    fn half_camera(order: i32, right: bool, clear: Option<Color>, target: CameraTarget) -> Renderable {
+        let x = if right { 32.0 } else { 0.0 };
+        let camera = Camera { order, viewport: Some(Rect::new(x, 0.0, 32.0, 64.0)), clear, target, ..Default::default() };
+        Renderable::empty().with_kind(RenderableKind::Camera(camera))
+    }
+
+    #[test]
+    fn only_first_camera_on_target_clears() {
+        let Some((device, queue)) = headless::device() else { return };
+        let mut g3d = G3D::new(device.clone(), queue.clone(), false);
+        let (mut assets, _, _) = cube_assets(&device);
+        let red = [255, 0, 0, 255];
+
+        // A later camera's clear color is ignored, even within its own viewport
+        let mut scene = Scene::<Renderable>::new();
+        let _first = scene.insert(half_camera(0, false, Some(Color::RED), CameraTarget::OnScreen));
+        let _second = scene.insert(half_camera(1, true, Some(Color::BLUE), CameraTarget::OnScreen));
+        let pixels = render(&mut g3d, &mut scene, &assets, Color::GREEN, &device, &queue);
+        assert!(pixels.iter().all(|&pixel| pixel == red));
+
+        // The surface is cleared with the clear color when its first camera has none
+        let mut scene = Scene::<Renderable>::new();
+        let _first = scene.insert(half_camera(0, false, None, CameraTarget::OnScreen));
+        let _second = scene.insert(half_camera(1, true, Some(Color::BLUE), CameraTarget::OnScreen));
+        let pixels = render(&mut g3d, &mut scene, &assets, Color::GREEN, &device, &queue);
+        assert!(pixels.iter().all(|&pixel| pixel == [0, 255, 0, 255]));
+
+        // Render textures keep their contents when their first camera has none
+        let render_texture = RenderTexture::with_formats(TARGET_SIZE.x, TARGET_SIZE.y, headless::COLOR_FORMAT, headless::DEPTH_FORMAT, &device);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &render_texture.view,
+                resolve_target: None,
+                ops: Operations { load: LoadOp::Clear(Color::RED.into()), store: StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        queue.submit([encoder.finish()]);
+        let handle = assets.insert(render_texture);
+        let mut scene = Scene::<Renderable>::new();
+        let _first = scene.insert(half_camera(0, false, None, CameraTarget::OffScreen(handle.clone())));
+        let _second = scene.insert(half_camera(1, true, Some(Color::BLUE), CameraTarget::OffScreen(handle.clone())));
+        render(&mut g3d, &mut scene, &assets, Color::GREEN, &device, &queue);
+        let render_textures = assets.storage::<RenderTexture>().unwrap();
+        let render_texture = render_textures.get(&handle).unwrap();
+        let pixels = headless::read_pixels(&render_texture.texture, &device, &queue);
+        assert!(pixels.iter().all(|&pixel| pixel == red));
+    }
+
+    #[test]
+    fn skinned_meshes_cast_posed_shadows() {
+        let Some((device, queue)) = headless::device() else { return };
+        let mut g3d = G3D::new(device.clone(), queue.clone(), true).with_shadows(256);
+        let (mut assets, material, _) = cube_assets(&device);
+        let wall_data = MeshData::from(Cuboid { center: Vec3::new(0.0, 0.0, -5.5), half_extents: Vec3::new(10.0, 10.0, 0.5), color: Color::WHITE });
+        let wall = assets.insert(Mesh::from_data(&wall_data, &device));
+        let mut cube_data = MeshData::from(Cuboid { center: Vec3::new(-2.0, 0.0, -3.0), half_extents: Vec3::splat(0.5), color: Color::WHITE });
+        cube_data.bone_indices = Some(vec![[0; 4]; cube_data.positions.len()]);
+        cube_data.bone_weights = Some(vec![Vec4::X; cube_data.positions.len()]);
+        let cube = assets.insert(Mesh::from_data(&cube_data, &device));
+        let light = DirectionalLight::new(Vec3::NEG_Z, Color::WHITE, 1.0).with_shadow_distance(10.0);
+
+        // In the bind pose, the cube's shadow lands on the wall beside it
+        let mut scene = Scene::<Renderable>::new();
+        let _camera = scene.insert(camera());
+        let _wall = scene.insert(Renderable::mat_mesh(material.clone(), wall.clone()));
+        let _cube = scene.insert(Renderable::mat_mesh(material.clone(), cube.clone()));
+        let pixels = render_lit(&mut g3d, &mut scene, Some(light), &assets, Color::BLACK, &device, &queue);
+        let shadow = pixel(&pixels, 19, 32)[0];
+        assert!(shadow < 20, "shadow: {shadow}");
+
+        // Posed out of the light, the cube casts no shadow where it used to
+        let mut scene = Scene::<Renderable>::new();
+        let _camera = scene.insert(camera());
+        let _wall = scene.insert(Renderable::mat_mesh(material.clone(), wall));
+        let mut posed_cube = Renderable::mat_mesh(material, cube);
+        posed_cube.set_pose(&[Mat4::from_translation(Vec3::new(50.0, 0.0, 0.0))]);
+        let _cube = scene.insert(posed_cube);
+        let pixels = render_lit(&mut g3d, &mut scene, Some(light), &assets, Color::BLACK, &device, &queue);
+        let lit = pixel(&pixels, 19, 32)[0];
+        assert!(lit > 240, "lit: {lit}");
+    }
+
+    #[test]
+    fn transparent_skinned_instances_keep_their_poses() {
+        let Some((device, queue)) = headless::device() else { return };
+        let mut g3d = G3D::new(device.clone(), queue.clone(), false);
+        let (mut assets, _, _) = cube_assets(&device);
+        let material = assets.insert(Material { blend_mode: BlendMode::AlphaBlend, ..Material::from(Color::WHITE) });
+        let mut cube_data = MeshData::from(Cuboid { center: Vec3::new(0.0, 0.0, -3.0), half_extents: Vec3::splat(0.5), color: Color::WHITE });
+        cube_data.bone_indices = Some(vec![[0; 4]; cube_data.positions.len()]);
+        cube_data.bone_weights = Some(vec![Vec4::X; cube_data.positions.len()]);
+        let cube = assets.insert(Mesh::from_data(&cube_data, &device));
+        let posed_cube = |x: f32| {
+            let mut renderable = Renderable::mat_mesh(material.clone(), cube.clone());
+            renderable.set_pose(&[Mat4::from_translation(Vec3::new(x, 0.0, 0.0))]);
+            renderable
+        };
+        let mut scene = Scene::<Renderable>::new();
+        let _camera = scene.insert(camera());
+        let _left = scene.insert(posed_cube(-1.2));
+        let _right = scene.insert(posed_cube(1.2));
+        let pixels = render(&mut g3d, &mut scene, &assets, Color::BLACK, &device, &queue);
+        assert_eq!([255, 255, 255, 255], pixel(&pixels, 17, 32));
+        assert_eq!([255, 255, 255, 255], pixel(&pixels, 47, 32));
+    }
+
+    #[test]
+    fn lods_render_own_mesh_until_loaded() {
+        let Some((device, queue)) = headless::device() else { return };
+        let mut g3d = G3D::new(device.clone(), queue.clone(), false);
+        let (mut assets, material, mesh) = cube_assets(&device);
+        assets.add_protocol(RawProtocol::from(""), true);
+        assets.add_loader(ObjLoader { device: device.clone() }).unwrap();
+        let lod_mesh = assets.load::<Mesh, _>("far.obj");
+        let lod = MeshLod::builder().add_lod(1.0, lod_mesh).build();
+        let mut scene = Scene::<Renderable>::new();
+        let _camera = scene.insert(camera());
+        let _cube = scene.insert(at(Renderable::mat_mesh(material, mesh).with_lod(lod), 0.0, 0.0, -3.0));
+        let pixels = render(&mut g3d, &mut scene, &assets, Color::BLACK, &device, &queue);
+        assert_eq!([255, 255, 255, 255], pixel(&pixels, 32, 32));
+    }
+
+    #[test]
+    fn cleared_pipelines_are_compiled_again() {
+        let Some((device, queue)) = headless::device() else { return };
+        let mut g3d = G3D::new(device.clone(), queue.clone(), false);
+        let (assets, material, mesh) = cube_assets(&device);
+        let mut scene = Scene::<Renderable>::new();
+        let _camera = scene.insert(camera());
+        let _cube = scene.insert(at(Renderable::mat_mesh(material, mesh), 0.0, 0.0, -3.0));
+        render(&mut g3d, &mut scene, &assets, Color::BLACK, &device, &queue);
+        assert_eq!(1, g3d.pipeline_count());
+        g3d.clear_pipelines();
+        assert_eq!(0, g3d.pipeline_count());
+        let pixels = render(&mut g3d, &mut scene, &assets, Color::BLACK, &device, &queue);
+        assert_eq!(1, g3d.pipeline_count());
+        assert_eq!([255, 255, 255, 255], pixel(&pixels, 32, 32));
+    }
+
+    /**
+     * Times frames of 100,000 cubes, half of which are behind the camera, in every GPU driven mode.
+     * Run with `cargo test --release gpu_culling_benchmark -- --ignored --nocapture`.
+     */
+    #[test]
+    #[ignore]
+    fn gpu_culling_benchmark() {
+        const INSTANCES: usize = 100_000;
+        const FRAMES: u32 = 20;
+        let Some((device, queue)) = headless::device() else { return };
+        let (assets, material, mesh) = cube_assets(&device);
+        let mut scene = Scene::<Renderable>::new();
+        let _camera = scene.insert(camera());
+        let _cubes: Vec<_> = (0..INSTANCES)
+            .map(|i| {
+                let (x, z) = ((i % 316) as f32 - 158.0, (i / 316) as f32 - 158.0);
+                scene.insert(at(Renderable::mat_mesh(material.clone(), mesh.clone()).with_sphere_volume(Vec3::ZERO, 1.0), x, -2.0, z))
+            })
+            .collect();
+        for mode in [GpuDrivenMode::Disabled, GpuDrivenMode::Indirect, GpuDrivenMode::IndirectCulled] {
+            let mut g3d = G3D::new(device.clone(), queue.clone(), false).with_gpu_driven_mode(mode);
+            render(&mut g3d, &mut scene, &assets, Color::BLACK, &device, &queue);
+            let start = Instant::now();
+            for _ in 0..FRAMES {
+                render(&mut g3d, &mut scene, &assets, Color::BLACK, &device, &queue);
+            }
+            println!("{mode:?}: {:?} per frame", start.elapsed() / FRAMES);
+        }
+    }
+}
@@ -0,0 +1,455 @@
+use std::f32::consts::TAU;
+use std::mem::size_of;
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, BufferBinding, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState, Device, FragmentState, FrontFace, MultisampleState, PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, Queue, RenderPass, RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState, TextureFormat, VertexAttribute, VertexBufferLayout, VertexFormat, VertexState, VertexStepMode};
+use crate::math::{Frustum, Ray, Sphere, AABB, OBB};
+use crate::{reserve_buffer, Color, HashMap};
+
+const VIEW_PROJ_SIZE: u64 = size_of::<Mat4>() as u64;
+
+const VERTEX_LAYOUT: VertexBufferLayout<'static> = VertexBufferLayout {
+    array_stride: size_of::<GizmoVertex>() as u64,
+    step_mode: VertexStepMode::Vertex,
+    attributes: &[
+        VertexAttribute {
+            format: VertexFormat::Float32x3,
+            offset: 0,
+            shader_location: 0,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: 3*4,
+            shader_location: 1,
+        },
+    ],
+};
+
+/**
+ * Domain of debug lines, drawn over the 3D scene by every camera that sees [`super::LAYER_DEBUG`].
+ * Lines are immediate-mode: they are drawn on the frame they are added, and then forgotten.
+ * Lines added while a tick runs are instead kept until the next tick starts, so that they do not flicker on frames without ticks.
+ * Without the `debug-gizmos` feature, every method is a no-op, and nothing is drawn.
+ */
+pub struct Gizmos {
+    /// If true, lines are hidden behind the meshes in front of them. Otherwise, they are drawn over everything.
+    pub depth_test: bool,
+    #[cfg(feature = "debug-gizmos")]
+    tick_vertices: Vec<GizmoVertex>,    // Lines added during the last tick
+    #[cfg(feature = "debug-gizmos")]
+    frame_vertices: Vec<GizmoVertex>,   // Lines added outside of ticks this frame
+    #[cfg(feature = "debug-gizmos")]
+    in_tick: bool,
+}
+
+impl Default for Gizmos {
+    fn default() -> Self {
+        Self {
+            depth_test: true,
+            #[cfg(feature = "debug-gizmos")]
+            tick_vertices: Vec::new(),
+            #[cfg(feature = "debug-gizmos")]
+            frame_vertices: Vec::new(),
+            #[cfg(feature = "debug-gizmos")]
+            in_tick: false,
+        }
+    }
+}
+
+impl Gizmos {
+
+    /// Draws a line between a and b.
+    #[cfg_attr(not(feature = "debug-gizmos"), allow(unused_variables))]
+    pub fn line(&mut self, a: Vec3, b: Vec3, color: Color) {
+        #[cfg(feature = "debug-gizmos")]
+        {
+            let vertices = match self.in_tick {
+                true => &mut self.tick_vertices,
+                false => &mut self.frame_vertices,
+            };
+            vertices.push(GizmoVertex { position: a, color });
+            vertices.push(GizmoVertex { position: b, color });
+        }
+    }
+
+    /// Draws a ray from its origin out to length.
+    pub fn ray(&mut self, ray: Ray, length: f32, color: Color) {
+        self.line(ray.origin, ray.origin + ray.direction * length, color);
+    }
+
+    /// Draws the twelve edges of a box.
+    pub fn aabb(&mut self, aabb: AABB, color: Color) {
+        self.cuboid(aabb.center, [Vec3::X * aabb.extents.x, Vec3::Y * aabb.extents.y, Vec3::Z * aabb.extents.z], color);
+    }
+
+    /// Draws the twelve edges of an oriented box.
+    pub fn obb(&mut self, obb: OBB, color: Color) {
+        let [x, y, z] = obb.axes();
+        self.cuboid(obb.center, [x * obb.half_extents.x, y * obb.half_extents.y, z * obb.half_extents.z], color);
+    }
+
+    /// Draws a sphere as three circles around its center, one around each axis.
+    /// Each circle is made of segments lines, and at least 3.
+    pub fn sphere(&mut self, sphere: Sphere, color: Color, segments: u32) {
+        let Sphere { center, radius } = sphere;
+        self.circle(center, Vec3::X * radius, Vec3::Y * radius, color, segments);
+        self.circle(center, Vec3::Y * radius, Vec3::Z * radius, color, segments);
+        self.circle(center, Vec3::Z * radius, Vec3::X * radius, color, segments);
+    }
+
+    /// Draws the twelve edges of a frustum, IE: that of a camera.
+    pub fn frustum(&mut self, frustum: &Frustum, color: Color) {
+        self.edges(frustum.corners(), color);
+    }
+
+    /// Draws a box from its center and the vectors from its center to the middle of three adjacent faces.
+    fn cuboid(&mut self, center: Vec3, [x, y, z]: [Vec3; 3], color: Color) {
+        let mut corners = [Vec3::ZERO; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let sign = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+            *corner = center + x * sign(1) + y * sign(2) + z * sign(4);
+        }
+        self.edges(corners, color);
+    }
+
+    /// Draws the edges between eight corners ordered by x, then y, then z, as [`Frustum::corners`] are.
+    fn edges(&mut self, corners: [Vec3; 8], color: Color) {
+        for i in 0..corners.len() {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    /// Draws a circle spanning the two axes, which should be perpendicular and as long as its radius.
+    fn circle(&mut self, center: Vec3, x: Vec3, y: Vec3, color: Color, segments: u32) {
+        let segments = segments.max(3);
+        let point = |i: u32| {
+            let angle = i as f32 / segments as f32 * TAU;
+            center + x * angle.cos() + y * angle.sin()
+        };
+        for i in 0..segments {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Vertices of every line to draw this frame, two per line.
+    #[cfg(feature = "debug-gizmos")]
+    pub(crate) fn vertices(&self) -> impl Iterator<Item = &GizmoVertex> {
+        self.tick_vertices.iter().chain(&self.frame_vertices)
+    }
+
+    #[cfg(not(feature = "debug-gizmos"))]
+    pub(crate) fn vertices(&self) -> impl Iterator<Item = &GizmoVertex> {
+        std::iter::empty()
+    }
+
+    /// Forgets the lines of the last tick, and keeps those added from now on until the next tick starts.
+    #[cfg(feature = "debug-gizmos")]
+    pub(crate) fn begin_tick(&mut self) {
+        self.tick_vertices.clear();
+        self.in_tick = true;
+    }
+
+    /// Lines added from now on are forgotten once the frame is drawn.
+    #[cfg(feature = "debug-gizmos")]
+    pub(crate) fn end_tick(&mut self) {
+        self.in_tick = false;
+    }
+
+    /// Forgets the lines that were added outside of ticks.
+    #[cfg(feature = "debug-gizmos")]
+    pub(crate) fn end_frame(&mut self) {
+        self.frame_vertices.clear();
+    }
+}
+
+/// Vertex of a line drawn by [`Gizmos`].
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Debug, Pod, Zeroable)]
+pub(crate) struct GizmoVertex {
+    pub position: Vec3,
+    pub color: Color,
+}
+
+/**
+ * Draws the lines of [`Gizmos`] after everything else in a camera's render pass.
+ * Depth is never written, and only tested if the gizmos request it.
+ */
+pub(crate) struct GizmoPass {
+    layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    module: ShaderModule,
+    pipelines: HashMap<GizmoKey, RenderPipeline>,   // Cache of pipelines by target formats, sample count and depth test
+    views: Buffer,                                  // View projection of each camera, one aligned slot per camera, bound with a dynamic offset
+    view_stride: u64,                               // Distance between slots in views
+    bind_group: BindGroup,
+    vertices: Buffer,
+    vertex_count: u32,                              // Number of vertices uploaded by the last call to prepare
+}
+
+impl GizmoPass {
+
+    pub fn new(device: &Device) -> Self {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("g3d_gizmo_layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: BufferSize::new(VIEW_PROJ_SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("g3d_gizmo_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("g3d_gizmo_module"),
+            source: ShaderSource::Wgsl(include_str!("gizmos.wgsl").into()),
+        });
+        let view_stride = (device.limits().min_uniform_buffer_offset_alignment as u64).max(VIEW_PROJ_SIZE);
+        let views = device.create_buffer(&BufferDescriptor {
+            label: Some("g3d_gizmo_views"),
+            size: view_stride,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = create_bind_group(&views, &layout, device);
+        Self {
+            layout,
+            pipeline_layout,
+            module,
+            pipelines: HashMap::default(),
+            views,
+            view_stride,
+            bind_group,
+            vertices: device.create_buffer(&BufferDescriptor {
+                label: Some("g3d_gizmo_vertices"),
+                size: 0,
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            vertex_count: 0,
+        }
+    }
+
+    /**
+     * Uploads the vertices of the lines, and the view projection of each camera that draws them.
+     * Rebinds the view projections if their buffer had to grow.
+     * Returns the offset of each camera's slot.
+     */
+    pub fn prepare(&mut self, vertices: &[GizmoVertex], view_projs: &[Mat4], device: &Device, queue: &Queue) -> Vec<u32> {
+        let mut view_bytes = vec![0; view_projs.len() * self.view_stride as usize];
+        for (view_proj, slot) in view_projs.iter().zip(view_bytes.chunks_exact_mut(self.view_stride as usize)) {
+            slot[..VIEW_PROJ_SIZE as usize].copy_from_slice(bytemuck::bytes_of(view_proj));
+        }
+        let views_size = self.views.size();
+        reserve_buffer(&mut self.views, view_bytes.len() as u64, device);
+        if self.views.size() != views_size {
+            self.bind_group = create_bind_group(&self.views, &self.layout, device);
+        }
+        queue.write_buffer(&self.views, 0, &view_bytes);
+
+        let vertex_bytes: &[u8] = bytemuck::cast_slice(vertices);
+        reserve_buffer(&mut self.vertices, vertex_bytes.len() as u64, device);
+        queue.write_buffer(&self.vertices, 0, vertex_bytes);
+        self.vertex_count = vertices.len() as u32;
+        (0..view_projs.len()).map(|i| (i as u64 * self.view_stride) as u32).collect()
+    }
+
+    /// Compiles the pipeline for the key, if it is not cached.
+    pub fn compile_pipeline(&mut self, key: GizmoKey, device: &Device) {
+        if self.pipelines.contains_key(&key) { return }
+        let pipeline = create_pipeline(key, &self.pipeline_layout, &self.module, device);
+        self.pipelines.insert(key, pipeline);
+    }
+
+    /// Draws the lines for a single camera.
+    /// The pipeline of the draw must have been compiled, and the lines prepared.
+    pub fn draw<'r>(&'r self, draw: GizmoDraw, pass: &mut RenderPass<'r>) {
+        let pipeline = self.pipelines.get(&draw.key).unwrap();
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[draw.offset]);
+        pass.set_vertex_buffer(0, self.vertices.slice(..));
+        pass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+/// Used to select a gizmo pipeline from a cache.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub(crate) struct GizmoKey {
+    pub texture_format: TextureFormat,
+    pub depth_format: TextureFormat,
+    pub sample_count: u32,
+    pub depth_test: bool,
+}
+
+/// Gizmos of a single camera, drawn in its render pass.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct GizmoDraw {
+    pub key: GizmoKey,
+    pub offset: u32,        // Offset of the camera's slot in the views buffer
+}
+
+fn create_bind_group(views: &Buffer, layout: &BindGroupLayout, device: &Device) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("g3d_gizmo_bind_group"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: views,
+                    offset: 0,
+                    size: BufferSize::new(VIEW_PROJ_SIZE),
+                }),
+            },
+        ],
+    })
+}
+
+fn create_pipeline(key: GizmoKey, layout: &PipelineLayout, module: &ShaderModule, device: &Device) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("g3d_gizmo_pipeline"),
+        layout: Some(layout),
+        vertex: VertexState {
+            module,
+            entry_point: "vertex_main",
+            buffers: &[VERTEX_LAYOUT],
+        },
+        fragment: Some(FragmentState {
+            module,
+            entry_point: "fragment_main",
+            targets: &[Some(ColorTargetState {
+                format: key.texture_format,
+                blend: Some(BlendState::ALPHA_BLENDING),
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::LineList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: Some(DepthStencilState {
+            format: key.depth_format,
+            depth_write_enabled: false,
+            depth_compare: match key.depth_test {
+                true => CompareFunction::LessEqual,
+                false => CompareFunction::Always,
+            },
+            stencil: StencilState::default(),
+            bias: DepthBiasState::default(),
+        }),
+        multisample: MultisampleState {
+            count: key.sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
+
+#[cfg(all(test, feature = "debug-gizmos"))]
+mod test {
+    use glam::{Quat, Vec3};
+    use crate::math::{Frustum, Sphere, AABB, OBB};
+    use crate::Color;
+    use super::Gizmos;
+
+    fn lines(gizmos: &Gizmos) -> Vec<(Vec3, Vec3)> {
+        let vertices: Vec<_> = gizmos.vertices().collect();
+        vertices.chunks_exact(2).map(|line| (line[0].position, line[1].position)).collect()
+    }
+
+    #[test]
+    fn boxes_have_twelve_edges() {
+        let mut gizmos = Gizmos::default();
+        gizmos.aabb(AABB::new(Vec3::new(1.0, 2.0, 3.0), Vec3::new(1.0, 2.0, 3.0)), Color::RED);
+        let edges = lines(&gizmos);
+        assert_eq!(12, edges.len());
+        for (a, b) in edges {
+            let edge = (b - a).abs();
+            assert!([Vec3::X * 2.0, Vec3::Y * 4.0, Vec3::Z * 6.0].contains(&edge), "{edge:?}");
+            assert!(a.cmpge(Vec3::ZERO).all() && a.cmple(Vec3::new(2.0, 4.0, 6.0)).all());
+        }
+
+        // Rotated boxes keep the length of their edges.
+        let mut gizmos = Gizmos::default();
+        gizmos.obb(OBB::new(Vec3::ZERO, Vec3::new(1.0, 2.0, 3.0), Quat::from_rotation_y(0.5)), Color::RED);
+        let mut lengths: Vec<f32> = lines(&gizmos).into_iter().map(|(a, b)| a.distance(b)).collect();
+        lengths.sort_by(f32::total_cmp);
+        for (i, expected) in [2.0, 4.0, 6.0].into_iter().enumerate() {
+            for length in &lengths[i*4..i*4+4] {
+                assert!((length - expected).abs() < 1e-5, "{length} != {expected}");
+            }
+        }
+    }
+
+    #[test]
+    fn spheres_are_three_circles() {
+        let mut gizmos = Gizmos::default();
+        let sphere = Sphere::new(Vec3::new(0.0, 5.0, 0.0), 2.0);
+        gizmos.sphere(sphere, Color::GREEN, 16);
+        let edges = lines(&gizmos);
+        assert_eq!(3 * 16, edges.len());
+        for (a, _) in edges {
+            assert!((a.distance(sphere.center) - sphere.radius).abs() < 1e-5);
+        }
+
+        // Circles are closed.
+        let edges = lines(&gizmos);
+        assert!(edges[15].1.distance(edges[0].0) < 1e-5);
+
+        // Too few segments are clamped.
+        let mut gizmos = Gizmos::default();
+        gizmos.sphere(sphere, Color::GREEN, 0);
+        assert_eq!(3 * 3, lines(&gizmos).len());
+    }
+
+    #[test]
+    fn frustums_connect_their_corners() {
+        let mut gizmos = Gizmos::default();
+        let frustum = Frustum::from(glam::Mat4::perspective_rh(1.0, 1.5, 0.1, 10.0));
+        gizmos.frustum(&frustum, Color::BLUE);
+        let corners = frustum.corners();
+        let edges = lines(&gizmos);
+        assert_eq!(12, edges.len());
+        for (a, b) in edges {
+            assert!(corners.contains(&a) && corners.contains(&b));
+        }
+    }
+
+    #[test]
+    fn tick_lines_outlive_frames() {
+        let mut gizmos = Gizmos::default();
+        gizmos.begin_tick();
+        gizmos.line(Vec3::ZERO, Vec3::X, Color::WHITE);
+        gizmos.end_tick();
+        gizmos.line(Vec3::ZERO, Vec3::Y, Color::WHITE);
+        assert_eq!(2, lines(&gizmos).len());
+
+        // Frames without ticks keep drawing the last tick's lines.
+        gizmos.end_frame();
+        assert_eq!(vec![(Vec3::ZERO, Vec3::X)], lines(&gizmos));
+        gizmos.end_frame();
+        assert_eq!(vec![(Vec3::ZERO, Vec3::X)], lines(&gizmos));
+
+        // The next tick replaces them.
+        gizmos.begin_tick();
+        assert!(lines(&gizmos).is_empty());
+    }
+}
@@ -0,0 +1,26 @@
+struct View {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> view: View;
+
+struct VertexIn {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vertex_main(in: VertexIn) -> VertexOut {
+    return VertexOut(view.view_proj * vec4<f32>(in.position, 1.0), in.color);
+}
+
+@fragment
+fn fragment_main(in: VertexOut) -> @location(0) vec4<f32> {
+    return in.color;
+}
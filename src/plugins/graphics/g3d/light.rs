@@ -0,0 +1,387 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3, Vec4};
+use crate::Color;
+
+/// Maximum number of point lights that can affect a scene at once.
+/// Lights beyond this count are ignored.
+pub const MAX_LIGHTS: usize = 16;
+
+/// Maximum number of spot lights that can affect a scene at once.
+/// Lights beyond this count are ignored.
+pub const MAX_SPOT_LIGHTS: usize = 8;
+
+/// A light that radiates in all directions from a single point.
+/// Spawned as a component in the [`hecs::World`].
+/// Only affects meshes with normals, and only when lighting is enabled in the [`crate::GraphicsPlugin`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PointLight {
+    /// Position of the light in world space.
+    pub position: Vec3,
+    pub color: Color,
+    /// Distance at which the light's contribution falls to zero.
+    pub radius: f32,
+    pub intensity: f32,
+}
+
+impl Default for PointLight {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            color: Color::WHITE,
+            radius: 10.0,
+            intensity: 1.0,
+        }
+    }
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, color: Color, radius: f32, intensity: f32) -> Self {
+        Self { position, color, radius, intensity }
+    }
+}
+
+/**
+ * A light that shines in a cone from a single point, like a flashlight.
+ * Spawned as a component in the [`hecs::World`].
+ * Full intensity inside the inner angle, fading to nothing at the outer angle.
+ * Only affects meshes with normals, and only when lighting is enabled in the [`crate::GraphicsPlugin`].
+ */
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SpotLight {
+    /// Position of the light in world space.
+    pub position: Vec3,
+    /// Direction the cone points in. Does not need to be normalized.
+    pub direction: Vec3,
+    pub color: Color,
+    /// Distance at which the light's contribution falls to zero.
+    pub radius: f32,
+    pub intensity: f32,
+    /// Angle in radians between the cone's axis and the edge of its fully lit center.
+    pub inner_angle: f32,
+    /// Angle in radians between the cone's axis and its edge.
+    pub outer_angle: f32,
+}
+
+impl Default for SpotLight {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            direction: Vec3::NEG_Y,
+            color: Color::WHITE,
+            radius: 10.0,
+            intensity: 1.0,
+            inner_angle: 0.4,
+            outer_angle: 0.5,
+        }
+    }
+}
+
+impl SpotLight {
+    pub fn new(position: Vec3, direction: Vec3, color: Color, radius: f32, intensity: f32) -> Self {
+        Self { position, direction, color, radius, intensity, ..Default::default() }
+    }
+
+    pub fn with_angles(mut self, inner_angle: f32, outer_angle: f32) -> Self {
+        self.inner_angle = inner_angle;
+        self.outer_angle = outer_angle;
+        self
+    }
+}
+
+/**
+ * A light infinitely far away that shines in a single direction, like the sun.
+ * Added to the [`crate::Game`] as a domain, and read every frame, so a system can animate it by mutating the domain.
+ * Only affects meshes with normals, and only when lighting is enabled in the [`crate::GraphicsPlugin`].
+ */
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DirectionalLight {
+    /// Direction the light travels in. Does not need to be normalized.
+    pub direction: Vec3,
+    pub color: Color,
+    pub intensity: f32,
+    /// If true, and shadows are enabled in the [`crate::GraphicsPlugin`], lit meshes cast shadows from this light.
+    pub cast_shadows: bool,
+    /// Half the width of the square area around the first camera that receives shadows.
+    /// Larger areas have blurrier shadows.
+    pub shadow_distance: f32,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: Vec3::NEG_Y,
+            color: Color::WHITE,
+            intensity: 1.0,
+            cast_shadows: true,
+            shadow_distance: 50.0,
+        }
+    }
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, color: Color, intensity: f32) -> Self {
+        Self { direction, color, intensity, ..Default::default() }
+    }
+
+    pub fn with_cast_shadows(mut self, cast_shadows: bool) -> Self {
+        self.cast_shadows = cast_shadows;
+        self
+    }
+
+    pub fn with_shadow_distance(mut self, shadow_distance: f32) -> Self {
+        self.shadow_distance = shadow_distance;
+        self
+    }
+}
+
+/**
+ * Light added evenly to every lit surface, regardless of its orientation.
+ * Added to the [`crate::Game`] as a domain.
+ * Only affects meshes with normals, and only when lighting is enabled in the [`crate::GraphicsPlugin`].
+ */
+#[derive(Copy, Clone, PartialEq, Default, Debug)]
+pub struct AmbientLight(pub Color);
+
+/// [`PointLight`] collected from the world for a single frame.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FlatPointLight {
+    pub position: Vec3,
+    pub color: Color,
+    pub radius: f32,
+    pub intensity: f32,
+}
+
+impl From<PointLight> for FlatPointLight {
+    fn from(light: PointLight) -> Self {
+        Self {
+            position: light.position,
+            color: light.color,
+            radius: light.radius,
+            intensity: light.intensity,
+        }
+    }
+}
+
+impl FlatPointLight {
+
+    /// Estimated brightness of the light at a point, ignoring surface orientation.
+    /// Zero beyond the light's radius.
+    pub fn brightness_at(&self, point: Vec3) -> f32 {
+        let distance = self.position.distance(point);
+        if distance >= self.radius {
+            return 0.0;
+        }
+        let falloff = 1.0 - distance / self.radius;
+        self.intensity * falloff * falloff
+    }
+}
+
+/// [`SpotLight`] collected from the world for a single frame.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FlatSpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub color: Color,
+    pub radius: f32,
+    pub intensity: f32,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+}
+
+impl From<SpotLight> for FlatSpotLight {
+    fn from(light: SpotLight) -> Self {
+        Self {
+            position: light.position,
+            direction: light.direction,
+            color: light.color,
+            radius: light.radius,
+            intensity: light.intensity,
+            inner_angle: light.inner_angle,
+            outer_angle: light.outer_angle,
+        }
+    }
+}
+
+/// Keeps the [`MAX_LIGHTS`] lights that are brightest at the viewer, brightest first.
+/// Does nothing if there are not too many lights.
+pub(crate) fn select_brightest_lights(lights: &mut Vec<FlatPointLight>, viewer: Vec3) {
+    if lights.len() <= MAX_LIGHTS {
+        return;
+    }
+    lights.sort_by(|a, b| b.brightness_at(viewer).total_cmp(&a.brightness_at(viewer)));
+    lights.truncate(MAX_LIGHTS);
+}
+
+/// GPU representation of a single [`FlatPointLight`].
+#[repr(C)]
+#[derive(Copy, Clone, Default, Pod, Zeroable)]
+pub(crate) struct PointLightUniform {
+    position_radius: Vec4,
+    color_intensity: Vec4,
+}
+
+impl From<&FlatPointLight> for PointLightUniform {
+    fn from(light: &FlatPointLight) -> Self {
+        let Color { r, g, b, .. } = light.color;
+        Self {
+            position_radius: light.position.extend(light.radius),
+            color_intensity: Vec4::new(r, g, b, light.intensity),
+        }
+    }
+}
+
+/// GPU representation of a single [`FlatSpotLight`].
+/// Angles are stored as cosines, so the shader can compare them with dot products.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Pod, Zeroable)]
+pub(crate) struct SpotLightUniform {
+    position_radius: Vec4,
+    direction_cos_outer: Vec4,
+    color_intensity: Vec4,
+    cos_inner: Vec4,
+}
+
+impl From<&FlatSpotLight> for SpotLightUniform {
+    fn from(light: &FlatSpotLight) -> Self {
+        let Color { r, g, b, .. } = light.color;
+        let outer_angle = light.outer_angle.max(0.0);
+        let inner_angle = light.inner_angle.clamp(0.0, outer_angle);
+        Self {
+            position_radius: light.position.extend(light.radius),
+            direction_cos_outer: light.direction.normalize_or_zero().extend(outer_angle.cos()),
+            color_intensity: Vec4::new(r, g, b, light.intensity),
+            cos_inner: Vec4::new(inner_angle.cos(), 0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// GPU representation of all lights in a scene.
+/// Layout matches the "Lights" struct in shader.wgsl.
+/// Lights that are absent are zeroed, so they contribute nothing.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub(crate) struct LightsUniform {
+    point_light_count: u32,
+    spot_light_count: u32,
+    _padding: [u32; 2],
+    ambient: Vec4,
+    directional_direction: Vec4,
+    directional_color_intensity: Vec4,
+    point_lights: [PointLightUniform; MAX_LIGHTS],
+    spot_lights: [SpotLightUniform; MAX_SPOT_LIGHTS],
+    shadow_view_proj: Mat4,
+    shadow_params: Vec4,        // x: 1 if shadows are enabled, y: size of a shadow map texel in UV space
+    environment: Vec4,          // x: 1 if the environment map is a skybox's, y: its highest mip level
+}
+
+impl LightsUniform {
+    pub fn new(point_lights: &[FlatPointLight]) -> Self {
+        let mut uniform = Self::zeroed();
+        for (dest, src) in uniform.point_lights.iter_mut().zip(point_lights) {
+            *dest = PointLightUniform::from(src);
+            uniform.point_light_count += 1;
+        }
+        uniform
+    }
+
+    pub fn with_spots(mut self, spot_lights: &[FlatSpotLight]) -> Self {
+        self.spot_light_count = 0;
+        for (dest, src) in self.spot_lights.iter_mut().zip(spot_lights) {
+            *dest = SpotLightUniform::from(src);
+            self.spot_light_count += 1;
+        }
+        self
+    }
+
+    /// Enables sampling of the directional light's shadow map, rendered with view_proj.
+    pub fn with_shadow(mut self, view_proj: Mat4, shadow_map_size: u32) -> Self {
+        self.shadow_view_proj = view_proj;
+        self.shadow_params = Vec4::new(1.0, 1.0 / shadow_map_size as f32, 0.0, 0.0);
+        self
+    }
+
+    /// Enables lighting from the environment map, which has as many mip levels as specified.
+    pub fn with_environment(mut self, mip_level_count: u32) -> Self {
+        self.environment = Vec4::new(1.0, mip_level_count.saturating_sub(1) as f32, 0.0, 0.0);
+        self
+    }
+
+    pub fn with_ambient(mut self, ambient: Option<AmbientLight>) -> Self {
+        if let Some(AmbientLight(Color { r, g, b, .. })) = ambient {
+            self.ambient = Vec4::new(r, g, b, 1.0);
+        }
+        self
+    }
+
+    pub fn with_directional(mut self, directional: Option<DirectionalLight>) -> Self {
+        if let Some(light) = directional {
+            let Color { r, g, b, .. } = light.color;
+            self.directional_direction = light.direction.normalize_or_zero().extend(0.0);
+            self.directional_color_intensity = Vec4::new(r, g, b, light.intensity);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lights_uniform_ignores_excess_lights() {
+        let lights = vec![FlatPointLight::from(PointLight::default()); MAX_LIGHTS + 4];
+        let uniform = LightsUniform::new(&lights);
+        assert_eq!(MAX_LIGHTS as u32, uniform.point_light_count);
+    }
+
+    #[test]
+    fn select_brightest_lights_keeps_nearest() {
+        let mut lights: Vec<_> = (0..MAX_LIGHTS + 4)
+            .map(|i| FlatPointLight::from(PointLight::new(Vec3::new(i as f32, 0.0, 0.0), Color::WHITE, 100.0, 1.0)))
+            .rev()
+            .collect();
+        select_brightest_lights(&mut lights, Vec3::ZERO);
+        assert_eq!(MAX_LIGHTS, lights.len());
+        assert_eq!(Vec3::ZERO, lights[0].position);
+        assert!(lights.iter().all(|light| light.position.x < MAX_LIGHTS as f32));
+    }
+
+    #[test]
+    fn lights_uniform_without_sun_or_ambient_is_dark() {
+        let uniform = LightsUniform::new(&[]).with_ambient(None).with_directional(None);
+        assert_eq!(Vec4::ZERO, uniform.ambient);
+        assert_eq!(Vec4::ZERO, uniform.directional_color_intensity);
+
+        let sun = DirectionalLight::new(Vec3::new(0.0, -2.0, 0.0), Color::RED, 3.0);
+        let uniform = uniform.with_ambient(Some(AmbientLight(Color::GRAY))).with_directional(Some(sun));
+        assert_eq!(Vec4::new(0.5, 0.5, 0.5, 1.0), uniform.ambient);
+        assert_eq!(Vec4::new(0.0, -1.0, 0.0, 0.0), uniform.directional_direction);
+        assert_eq!(Vec4::new(1.0, 0.0, 0.0, 3.0), uniform.directional_color_intensity);
+    }
+
+    #[test]
+    fn lights_uniform_spot_lights() {
+        let spot = SpotLight::new(Vec3::ZERO, Vec3::new(0.0, 0.0, -3.0), Color::WHITE, 5.0, 2.0).with_angles(0.8, 0.5);
+        let spots = vec![FlatSpotLight::from(spot); MAX_SPOT_LIGHTS + 2];
+        let uniform = LightsUniform::new(&[]).with_spots(&spots);
+        assert_eq!(MAX_SPOT_LIGHTS as u32, uniform.spot_light_count);
+
+        // Inner angle is clamped to the outer angle
+        let spot = uniform.spot_lights[0];
+        assert_eq!(Vec4::new(0.0, 0.0, -1.0, 0.5f32.cos()), spot.direction_cos_outer);
+        assert_eq!(0.5f32.cos(), spot.cos_inner.x);
+    }
+
+    #[test]
+    fn lights_uniform_layout() {
+        assert_eq!(16 + 48 + 32 * MAX_LIGHTS + 64 * MAX_SPOT_LIGHTS + 96, std::mem::size_of::<LightsUniform>());
+    }
+
+    #[test]
+    fn lights_uniform_environment() {
+        let uniform = LightsUniform::new(&[]);
+        assert_eq!(Vec4::ZERO, uniform.environment);
+        let uniform = uniform.with_environment(9);
+        assert_eq!(Vec4::new(1.0, 8.0, 0.0, 0.0), uniform.environment);
+    }
+}
@@ -0,0 +1,76 @@
+use crate::Handle;
+use crate::g3d::Mesh;
+
+/**
+ * Component that swaps the mesh of its entity's [`crate::g3d::MatMesh`] for less detailed ones as cameras get farther away.
+ * Each level pairs the distance it starts at with its mesh, and levels are sorted by distance.
+ * Closer than the first level, or while the level's mesh is loading, the mat mesh's own mesh is rendered.
+ * Levels are selected per camera, and shadows are always cast by the mat mesh's own mesh.
+ */
+#[derive(Clone, Default)]
+pub struct MeshLod {
+    pub lods: Vec<(f32, Handle<Mesh>)>,
+}
+
+impl MeshLod {
+
+    pub fn builder() -> MeshLodBuilder {
+        MeshLodBuilder::default()
+    }
+
+    /// Mesh of the level at the distance specified, or None if closer than the first level.
+    pub fn select(&self, distance: f32) -> Option<&Handle<Mesh>> {
+        select_level(&self.lods, distance)
+    }
+
+    /// True if both have the same levels, with the same meshes.
+    pub(crate) fn has_same_levels(&self, other: &MeshLod) -> bool {
+        self.lods.len() == other.lods.len() && self.lods
+            .iter()
+            .zip(&other.lods)
+            .all(|((a_start, a_mesh), (b_start, b_mesh))| a_start == b_start && a_mesh.id() == b_mesh.id())
+    }
+}
+
+/// Builds a [`MeshLod`] one level at a time, in any order.
+#[derive(Default)]
+pub struct MeshLodBuilder {
+    lods: Vec<(f32, Handle<Mesh>)>,
+}
+
+impl MeshLodBuilder {
+
+    /// Adds a level that renders mesh from distance onward.
+    pub fn add_lod(mut self, distance: f32, mesh: Handle<Mesh>) -> Self {
+        self.lods.push((distance, mesh));
+        self
+    }
+
+    pub fn build(mut self) -> MeshLod {
+        self.lods.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        MeshLod { lods: self.lods }
+    }
+}
+
+/// Value of the last level that starts at or before distance, where levels are sorted by the distance they start at.
+fn select_level<T>(levels: &[(f32, T)], distance: f32) -> Option<&T> {
+    let level = levels.partition_point(|(start, _)| *start <= distance);
+    let (_, value) = levels.get(level.checked_sub(1)?)?;
+    Some(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::select_level;
+
+    #[test]
+    fn selects_level_by_distance() {
+        let levels = [(10.0, "medium"), (50.0, "low")];
+        assert_eq!(None, select_level(&levels, 0.0));
+        assert_eq!(None, select_level(&levels, 9.9));
+        assert_eq!(Some(&"medium"), select_level(&levels, 10.0));
+        assert_eq!(Some(&"medium"), select_level(&levels, 49.0));
+        assert_eq!(Some(&"low"), select_level(&levels, 1000.0));
+        assert_eq!(None, select_level::<&str>(&[], 1000.0));
+    }
+}
@@ -1,15 +1,37 @@
 use crate::{Asset, AssetStorage, Color, Handle, ShaderPreprocessor, Texture};
 use bitflags::bitflags;
-use bytemuck::cast_slice;
+use bytemuck::{Pod, Zeroable};
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
-use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BufferBinding, BufferBindingType, BufferUsages, Device, Face, SamplerBindingType, ShaderStages, TextureSampleType, TextureViewDimension};
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent, BlendFactor, BlendOperation, BlendState, BufferBinding, BufferBindingType, BufferUsages, Device, Face, SamplerBindingType, ShaderStages, TextureSampleType, TextureViewDimension};
 
 
-#[derive(Default)]
 pub struct Material {
     pub base_color: Color,
     pub base_color_texture: Option<Handle<Texture>>,
+    /// Tangent space normals, with green pointing up the texture.
+    /// Best loaded linearly, see [`crate::TextureLoaderConfig::srgb`]. Textures decoded from sRGB are encoded back when sampled.
+    /// Only used by lit meshes with normals, UVs and tangents.
+    pub normal_texture: Option<Handle<Texture>>,
+    /// If true, lit meshes are shaded with a physically-based model that uses roughness and metalness.
+    /// Otherwise, lit meshes are shaded with Blinn-Phong, which uses shininess.
+    pub pbr: bool,
+    /// From 0 for a mirror to 1 for chalk. Only used if pbr is true.
+    pub roughness: f32,
+    /// From 0 for plastic to 1 for metal. Only used if pbr is true.
+    pub metalness: f32,
+    /// Roughness in green, and metalness in blue, like in glTF. Multiplies roughness and metalness.
+    /// Only used if pbr is true and the mesh has UVs.
+    pub roughness_metalness_texture: Option<Handle<Texture>>,
+    /// Ambient occlusion in red, like in glTF. Darkens ambient light in crevices.
+    /// Only used if pbr is true and the mesh has UVs.
+    pub ambient_occlusion_texture: Option<Handle<Texture>>,
+    /// Exponent of specular highlights, which get smaller and sharper as it grows. Only used if pbr is false.
+    pub shininess: f32,
     pub cull_mode: Option<Face>,
+    pub blend_mode: BlendMode,
+    /// If set, fragments with less alpha than the cutoff are discarded, and the rest are drawn as the blend mode dictates.
+    /// Useful for foliage and fences, which are typically opaque with cutouts.
+    pub alpha_cutoff: Option<f32>,
     pub prepared: Option<PreparedMaterial>,
 }
 
@@ -18,9 +40,22 @@ impl Material {
     const UNIFORM_BINDING: u32 = 0;
     const BASE_COLOR_TEX_BINDING: u32 = 1;
     const BASE_COLOR_SAM_BINDING: u32 = 2;
+    const NORMAL_TEX_BINDING: u32 = 3;
+    const NORMAL_SAM_BINDING: u32 = 4;
+    const ROUGHNESS_METALNESS_TEX_BINDING: u32 = 5;
+    const ROUGHNESS_METALNESS_SAM_BINDING: u32 = 6;
+    const AO_TEX_BINDING: u32 = 7;
+    const AO_SAM_BINDING: u32 = 8;
 
-    pub fn uniform_bytes(&self) -> &[u8] {
-        bytemuck::bytes_of(&self.base_color)
+    pub fn uniform_bytes(&self) -> Vec<u8> {
+        let uniform = MaterialUniform {
+            base_color: self.base_color,
+            alpha_cutoff: self.alpha_cutoff.unwrap_or(0.0),
+            roughness: self.roughness,
+            metalness: self.metalness,
+            shininess: self.shininess,
+        };
+        bytemuck::bytes_of(&uniform).to_vec()
     }
 
     /// Returns prepared material if all dependent textures are loaded.
@@ -32,13 +67,20 @@ impl Material {
         if !is_tex_loaded(&self.base_color_texture, textures) {
             return;
         }
+        if !is_tex_loaded(&self.normal_texture, textures) {
+            return;
+        }
+        if !is_tex_loaded(&self.roughness_metalness_texture, textures) {
+            return;
+        }
+        if !is_tex_loaded(&self.ambient_occlusion_texture, textures) {
+            return;
+        }
 
         // Color buffer
-        let color = &[self.base_color];
-        let uniform_bytes: &[u8] = cast_slice(color);
         let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: None,
-            contents: uniform_bytes,
+            contents: &self.uniform_bytes(),
             usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
         });
 
@@ -79,6 +121,56 @@ impl Material {
             flags |= MaterialFlags::BASE_COLOR_TEX;
         }
 
+        // Normal texture
+        if let Some(normal_texture) = &self.normal_texture {
+            let normal_texture = textures.get(normal_texture);
+            let normal_texture = normal_texture.unwrap();
+            let entries = normal_texture.create_entries(Self::NORMAL_TEX_BINDING, Self::NORMAL_SAM_BINDING);
+            layout_entries.push(entries.layout_texture_entry);
+            layout_entries.push(entries.layout_sampler_entry);
+            group_entries.push(entries.group_texture_entry);
+            group_entries.push(entries.group_sampler_entry);
+            flags |= MaterialFlags::NORMAL_MAP;
+            if normal_texture.srgb {
+                flags |= MaterialFlags::NORMAL_MAP_SRGB;
+            }
+        }
+
+        // Roughness / metalness texture
+        if let Some(roughness_metalness_texture) = &self.roughness_metalness_texture {
+            let roughness_metalness_texture = textures.get(roughness_metalness_texture);
+            let roughness_metalness_texture = roughness_metalness_texture.unwrap();
+            let entries = roughness_metalness_texture.create_entries(Self::ROUGHNESS_METALNESS_TEX_BINDING, Self::ROUGHNESS_METALNESS_SAM_BINDING);
+            layout_entries.push(entries.layout_texture_entry);
+            layout_entries.push(entries.layout_sampler_entry);
+            group_entries.push(entries.group_texture_entry);
+            group_entries.push(entries.group_sampler_entry);
+            flags |= MaterialFlags::ROUGHNESS_METALNESS_TEX;
+            if roughness_metalness_texture.srgb {
+                flags |= MaterialFlags::ROUGHNESS_METALNESS_TEX_SRGB;
+            }
+        }
+
+        // Ambient occlusion texture
+        if let Some(ambient_occlusion_texture) = &self.ambient_occlusion_texture {
+            let ambient_occlusion_texture = textures.get(ambient_occlusion_texture);
+            let ambient_occlusion_texture = ambient_occlusion_texture.unwrap();
+            let entries = ambient_occlusion_texture.create_entries(Self::AO_TEX_BINDING, Self::AO_SAM_BINDING);
+            layout_entries.push(entries.layout_texture_entry);
+            layout_entries.push(entries.layout_sampler_entry);
+            group_entries.push(entries.group_texture_entry);
+            group_entries.push(entries.group_sampler_entry);
+            flags |= MaterialFlags::AO_TEX;
+            if ambient_occlusion_texture.srgb {
+                flags |= MaterialFlags::AO_TEX_SRGB;
+            }
+        }
+
+        // Alpha cutoff
+        if self.alpha_cutoff.is_some() {
+            flags |= MaterialFlags::ALPHA_MASK;
+        }
+
         // Finishes preparing material
         let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: None,
@@ -90,7 +182,7 @@ impl Material {
             entries: &group_entries,
         });
         self.prepared = Some(PreparedMaterial {
-            key: MaterialKey { flags, cull_mode: self.cull_mode },
+            key: MaterialKey { flags, cull_mode: self.cull_mode, blend_mode: self.blend_mode, is_pbr: self.pbr },
             bind_group_layout,
             bind_group,
         });
@@ -98,6 +190,37 @@ impl Material {
 }
 impl Asset for Material {}
 
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_color: Color::default(),
+            base_color_texture: None,
+            cull_mode: None,
+            blend_mode: BlendMode::default(),
+            alpha_cutoff: None,
+            normal_texture: None,
+            pbr: false,
+            roughness: 1.0,
+            metalness: 0.0,
+            roughness_metalness_texture: None,
+            ambient_occlusion_texture: None,
+            shininess: 32.0,
+            prepared: None,
+        }
+    }
+}
+
+/// Uniform data of a [`Material`], as laid out in the shader.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct MaterialUniform {
+    base_color: Color,
+    alpha_cutoff: f32,
+    roughness: f32,
+    metalness: f32,
+    shininess: f32,
+}
+
 pub fn is_tex_loaded(texture: &Option<Handle<Texture>>, textures: &AssetStorage<Texture>) -> bool {
     if let Some(texture) = texture {
         if !textures.get(texture).is_loaded() {
@@ -117,23 +240,56 @@ pub struct PreparedMaterial {
 
 impl PreparedMaterial {
     pub fn write_shader_defs(&self, defs: &mut ShaderPreprocessor) {
-        let flags = self.key.flags;
-        if flags & MaterialFlags::BASE_COLOR_TEX != MaterialFlags::NONE {
-            defs.add("BASE_COLOR_TEX");
-        }
+        self.key.write_shader_defs(defs);
     }
 }
 
 pub struct MaterialLayout(Vec<BindGroupLayoutEntry>);
 
+impl MaterialLayout {
+    pub fn entries(&self) -> &[BindGroupLayoutEntry] {
+        &self.0
+    }
+}
+
 /// Info about materal used in pipeline selection.
 #[derive(Copy, Clone, Eq, PartialEq, Default, Debug, Hash)]
 pub struct MaterialKey {
     pub flags: MaterialFlags,
     pub cull_mode: Option<Face>,
+    pub blend_mode: BlendMode,
+    /// If true, lit meshes use the physically-based shading model.
+    pub is_pbr: bool,
 }
 
 impl MaterialKey {
+    pub fn write_shader_defs(&self, defs: &mut ShaderPreprocessor) {
+        if self.flags & MaterialFlags::BASE_COLOR_TEX != MaterialFlags::NONE {
+            defs.add("BASE_COLOR_TEX");
+        }
+        if self.flags & MaterialFlags::ALPHA_MASK != MaterialFlags::NONE {
+            defs.add("ALPHA_MASK");
+        }
+        if self.flags & MaterialFlags::NORMAL_MAP_SRGB != MaterialFlags::NONE {
+            defs.add("NORMAL_MAP_SRGB");
+        }
+        if self.is_pbr {
+            defs.add("PBR");
+            if self.flags & MaterialFlags::ROUGHNESS_METALNESS_TEX != MaterialFlags::NONE {
+                defs.add("ROUGHNESS_METALNESS_TEX");
+            }
+            if self.flags & MaterialFlags::ROUGHNESS_METALNESS_TEX_SRGB != MaterialFlags::NONE {
+                defs.add("ROUGHNESS_METALNESS_TEX_SRGB");
+            }
+            if self.flags & MaterialFlags::AO_TEX != MaterialFlags::NONE {
+                defs.add("AO_TEX");
+            }
+            if self.flags & MaterialFlags::AO_TEX_SRGB != MaterialFlags::NONE {
+                defs.add("AO_TEX_SRGB");
+            }
+        }
+    }
+
     pub fn layout(&self) -> MaterialLayout {
         
         // Base color
@@ -170,6 +326,32 @@ impl MaterialKey {
                 count: None,
             });
         }
+
+        // Normal, roughness / metalness and ambient occlusion textures and samplers
+        let textures = [
+            (MaterialFlags::NORMAL_MAP, Material::NORMAL_TEX_BINDING, Material::NORMAL_SAM_BINDING),
+            (MaterialFlags::ROUGHNESS_METALNESS_TEX, Material::ROUGHNESS_METALNESS_TEX_BINDING, Material::ROUGHNESS_METALNESS_SAM_BINDING),
+            (MaterialFlags::AO_TEX, Material::AO_TEX_BINDING, Material::AO_SAM_BINDING),
+        ];
+        for (flag, tex_binding, sam_binding) in textures {
+            if self.flags & flag == MaterialFlags::NONE { continue }
+            layout.push(BindGroupLayoutEntry {
+                binding: tex_binding,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::default(),
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+            layout.push(BindGroupLayoutEntry {
+                binding: sam_binding,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
         MaterialLayout(layout)
     }
 }
@@ -182,6 +364,69 @@ bitflags! {
     pub struct MaterialFlags: u8 {
         const NONE              = 0b00000000;
         const BASE_COLOR_TEX    = 0b00000001;
+        const NORMAL_MAP        = 0b00000010;
+        const ALPHA_MASK        = 0b00000100;
+        const ROUGHNESS_METALNESS_TEX = 0b00001000;
+        const AO_TEX            = 0b00010000;
+        const NORMAL_MAP_SRGB   = 0b00100000;   // Normal texture is decoded from sRGB when sampled
+        const ROUGHNESS_METALNESS_TEX_SRGB = 0b01000000;
+        const AO_TEX_SRGB       = 0b10000000;
         const ALL               = 0b11111111;
     }
-}
\ No newline at end of file
+}
+
+/// How the color of a material is combined with the color already rendered behind it.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug, Hash)]
+pub enum BlendMode {
+    /// Replaces the color behind it. Alpha is ignored.
+    #[default]
+    Opaque,
+    /// Mixes with the color behind it, weighted by alpha.
+    AlphaBlend,
+    /// Adds to the color behind it, weighted by alpha. Useful for glows and particles.
+    Additive,
+}
+
+impl BlendMode {
+
+    /// True if objects behind this material can be seen through it.
+    /// Transparent objects are drawn after opaque ones, sorted back to front, and do not write depth.
+    pub fn is_transparent(self) -> bool {
+        self != BlendMode::Opaque
+    }
+
+    pub fn blend_state(self) -> BlendState {
+        match self {
+            BlendMode::Opaque => BlendState::REPLACE,
+            BlendMode::AlphaBlend => BlendState::ALPHA_BLENDING,
+            BlendMode::Additive => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::SrcAlpha,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent::OVER,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Color;
+    use super::Material;
+
+    #[test]
+    fn uniform_bytes_include_cutoff() {
+        let material = Material {
+            base_color: Color::new(1.0, 0.5, 0.25, 0.75),
+            alpha_cutoff: Some(0.5),
+            ..Default::default()
+        };
+        let bytes = material.uniform_bytes();
+        assert_eq!(32, bytes.len());
+        let floats: &[f32] = bytemuck::cast_slice(&bytes);
+        assert_eq!(&[1.0, 0.5, 0.25, 0.75, 0.5, 1.0, 0.0], &floats[..7]);
+        assert_eq!(0.0, bytemuck::cast_slice::<u8, f32>(&Material::default().uniform_bytes())[4]);
+    }
+}
@@ -2,8 +2,9 @@ use std::mem::size_of;
 use bytemuck::bytes_of;
 use wgpu::util::{DeviceExt, BufferInitDescriptor};
 use wgpu::{VertexBufferLayout, VertexStepMode, VertexAttribute, VertexFormat, Buffer, Device, BufferUsages, IndexFormat};
-use glam::{Vec3, Vec2};
+use glam::{Vec2, Vec3, Vec4};
 use bitflags::bitflags;
+use crate::math::{Sphere, AABB};
 use crate::{Asset, Color, ShaderPreprocessor};
 
 /**
@@ -16,17 +17,29 @@ pub struct MeshData {
     pub colors:     Option<Vec<Color>>,
     pub normals:    Option<Vec<Vec3>>,
     pub uvs:        Option<Vec<Vec2>>,
+    /// Tangents in xyz, and the sign of the bitangent in w. See [`MeshData::compute_tangents`].
+    pub tangents:   Option<Vec<Vec4>>,
+    /// Indices of the four bones that influence each vertex, into the matrices of a [`crate::g3d::SkinnedMeshPose`].
+    pub bone_indices:   Option<Vec<[u8; 4]>>,
+    /// Weights of the four bones that influence each vertex, which should sum to one.
+    pub bone_weights:   Option<Vec<Vec4>>,
 }
 impl MeshData {
     const POSITION_LOCATION: u32    = 4;
     const COLOR_LOCATION: u32       = 5;
     const NORMAL_LOCATION: u32      = 6;
     const UV_LOCATION: u32          = 7;
+    const TANGENT_LOCATION: u32     = 12;
+    const BONE_INDICES_LOCATION: u32    = 13;
+    const BONE_WEIGHTS_LOCATION: u32    = 14;
 
     const POSITION_SIZE: usize      = size_of::<Vec3>();
     const COLOR_SIZE: usize         = size_of::<Color>();
     const NORMAL_SIZE: usize        = size_of::<Vec3>();
     const UV_SIZE: usize            = size_of::<Vec2>();
+    const TANGENT_SIZE: usize       = size_of::<Vec4>();
+    const BONE_INDICES_SIZE: usize  = size_of::<[u8; 4]>();
+    const BONE_WEIGHTS_SIZE: usize  = size_of::<Vec4>();
 
     pub fn new() -> Self {
         Self {
@@ -35,6 +48,9 @@ impl MeshData {
             colors: None,
             uvs: None,
             normals: None,
+            tangents: None,
+            bone_indices: None,
+            bone_weights: None,
         }
     }
 
@@ -52,6 +68,12 @@ impl MeshData {
         if self.uvs.is_some() {
             variant |= MeshKey::UV;
         }
+        if self.tangents.is_some() {
+            variant |= MeshKey::TANGENT;
+        }
+        if self.bone_indices.is_some() && self.bone_weights.is_some() {
+            variant |= MeshKey::SKINNED;
+        }
         variant
     }
 
@@ -68,6 +90,68 @@ impl MeshData {
         if let Some(uvs) = &mut self.uvs {
             uvs.clear();
         }
+        if let Some(tangents) = &mut self.tangents {
+            tangents.clear();
+        }
+        if let Some(bone_indices) = &mut self.bone_indices {
+            bone_indices.clear();
+        }
+        if let Some(bone_weights) = &mut self.bone_weights {
+            bone_weights.clear();
+        }
+    }
+
+    /**
+     * Computes tangents from positions, normals and UVs, using Lengyel's method.
+     * Tangents point in the direction of increasing U, and w holds the sign of the bitangent, which is negative where UVs are mirrored.
+     * Bitangents point up the texture, towards decreasing V, so that normal textures have green pointing up, like in glTF.
+     * Does nothing if the mesh has no normals or no UVs.
+     */
+    pub fn compute_tangents(&mut self) {
+        let (Some(normals), Some(uvs)) = (&self.normals, &self.uvs) else { return };
+        self.check_vertices();
+
+        // Accumulates the U and V directions of every triangle at its vertices
+        let mut u_dirs = vec![Vec3::ZERO; self.positions.len()];
+        let mut v_dirs = vec![Vec3::ZERO; self.positions.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [i0, i1, i2] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+            let edge1 = self.positions[i1] - self.positions[i0];
+            let edge2 = self.positions[i2] - self.positions[i0];
+            let duv1 = uvs[i1] - uvs[i0];
+            let duv2 = uvs[i2] - uvs[i0];
+            let det = duv1.x * duv2.y - duv2.x * duv1.y;
+            if det.abs() < f32::EPSILON { continue }
+            let r = 1.0 / det;
+            let u_dir = (edge1 * duv2.y - edge2 * duv1.y) * r;
+            let v_dir = (edge2 * duv1.x - edge1 * duv2.x) * r;
+            for i in [i0, i1, i2] {
+                u_dirs[i] += u_dir;
+                v_dirs[i] += v_dir;
+            }
+        }
+
+        // Orthogonalizes against the normal, and packs the handedness of the bitangent
+        let tangents = normals.iter().zip(u_dirs).zip(v_dirs)
+            .map(|((normal, u_dir), v_dir)| {
+                let tangent = (u_dir - *normal * normal.dot(u_dir))
+                    .try_normalize()
+                    .unwrap_or_else(|| normal.any_orthonormal_vector());
+                let sign = if normal.cross(tangent).dot(v_dir) > 0.0 { -1.0 } else { 1.0 };
+                tangent.extend(sign)
+            })
+            .collect();
+        self.tangents = Some(tangents);
+    }
+
+    /// Smallest axis-aligned box around the positions, or [`AABB::EMPTY`] if there are none.
+    pub fn compute_aabb(&self) -> AABB {
+        AABB::from_points(&self.positions)
+    }
+
+    /// Sphere around the positions, using Ritter's algorithm, or [`Sphere::EMPTY`] if there are none.
+    pub fn compute_bounding_sphere(&self) -> Sphere {
+        Sphere::from_points(&self.positions)
     }
 
     /**
@@ -100,6 +184,18 @@ impl MeshData {
                 let bytes = bytes_of(&uvs[i]);
                 vertex_data.extend_from_slice(bytes);
             }
+
+            // Tangents
+            if let Some(tangents) = &self.tangents {
+                let bytes = bytes_of(&tangents[i]);
+                vertex_data.extend_from_slice(bytes);
+            }
+
+            // Bone indices and weights
+            if let (Some(bone_indices), Some(bone_weights)) = (&self.bone_indices, &self.bone_weights) {
+                vertex_data.extend_from_slice(&bone_indices[i]);
+                vertex_data.extend_from_slice(bytes_of(&bone_weights[i]));
+            }
         }
         vertex_data
     }
@@ -125,6 +221,12 @@ impl MeshData {
         if self.uvs.is_some() {
             size += MeshData::UV_SIZE;
         }
+        if self.tangents.is_some() {
+            size += MeshData::TANGENT_SIZE;
+        }
+        if self.bone_indices.is_some() && self.bone_weights.is_some() {
+            size += MeshData::BONE_INDICES_SIZE + MeshData::BONE_WEIGHTS_SIZE;
+        }
         size
     }
 
@@ -146,6 +248,21 @@ impl MeshData {
                 panic!("UV buffer had an different length");
             }
         }
+        if let Some(tangents) = &self.tangents {
+            if tangents.len() != num_vertices {
+                panic!("Tangent buffer had an different length");
+            }
+        }
+        if let Some(bone_indices) = &self.bone_indices {
+            if bone_indices.len() != num_vertices {
+                panic!("Bone index buffer had an different length");
+            }
+        }
+        if let Some(bone_weights) = &self.bone_weights {
+            if bone_weights.len() != num_vertices {
+                panic!("Bone weight buffer had an different length");
+            }
+        }
     }
 }
 
@@ -159,6 +276,8 @@ bitflags! {
         const COLOR     = 0b00000001;
         const NORMAL    = 0b00000010;
         const UV        = 0b00000100;
+        const TANGENT   = 0b00001000;
+        const SKINNED   = 0b00010000;
         const ALL       = 0b11111111;
     }
 }
@@ -211,6 +330,34 @@ impl MeshKey {
             offset += MeshData::UV_SIZE as u64;
             defs.add("UV");
         }
+
+        // Tangent
+        if self & Self::TANGENT != Self::NONE {
+            layout.attributes.push(VertexAttribute {
+                format: VertexFormat::Float32x4,
+                offset,
+                shader_location: MeshData::TANGENT_LOCATION,
+            });
+            offset += MeshData::TANGENT_SIZE as u64;
+            defs.add("TANGENT");
+        }
+
+        // Bone indices and weights
+        if self & Self::SKINNED != Self::NONE {
+            layout.attributes.push(VertexAttribute {
+                format: VertexFormat::Uint8x4,
+                offset,
+                shader_location: MeshData::BONE_INDICES_LOCATION,
+            });
+            offset += MeshData::BONE_INDICES_SIZE as u64;
+            layout.attributes.push(VertexAttribute {
+                format: VertexFormat::Float32x4,
+                offset,
+                shader_location: MeshData::BONE_WEIGHTS_LOCATION,
+            });
+            offset += MeshData::BONE_WEIGHTS_SIZE as u64;
+            defs.add("SKINNED");
+        }
         layout.array_stride = offset;
         layout
     }
@@ -241,6 +388,7 @@ pub struct Mesh {
     pub(crate) index_format: IndexFormat,
     pub(crate) num_indices: u32,
     pub(crate) key: MeshKey,
+    pub(crate) aabb: AABB,
 }
 impl Asset for Mesh {}
 
@@ -260,6 +408,105 @@ impl Mesh {
             index_format: IndexFormat::Uint32,
             num_indices: mesh.indices.len() as u32,
             key: mesh.key(),
+            aabb: mesh.compute_aabb(),
         }
     }
+
+    /// Bounding box of the mesh's vertices, computed when it was created.
+    /// Used to cull renderables with [`crate::g3d::Renderable::with_auto_volume`].
+    pub fn aabb(&self) -> AABB {
+        self.aabb
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use glam::{Vec2, Vec3, Vec4};
+    use crate::g3d::Cuboid;
+    use crate::math::AABB;
+    use crate::{Color, ShaderPreprocessor};
+    use super::{MeshData, MeshKey};
+
+    fn quad(uvs: [Vec2; 4]) -> MeshData {
+        MeshData {
+            indices: vec![0, 1, 2, 2, 3, 0],
+            positions: vec![Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0), Vec3::new(-1.0, 1.0, 0.0)],
+            normals: Some(vec![Vec3::Z; 4]),
+            uvs: Some(uvs.to_vec()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn compute_tangents() {
+        let mut mesh = quad([Vec2::new(0.0, 1.0), Vec2::new(1.0, 1.0), Vec2::new(1.0, 0.0), Vec2::new(0.0, 0.0)]);
+        mesh.compute_tangents();
+        for tangent in mesh.tangents.as_ref().unwrap() {
+            assert!(tangent.abs_diff_eq(Vec4::new(1.0, 0.0, 0.0, 1.0), 1e-5));
+        }
+        assert!(mesh.key().contains(MeshKey::TANGENT));
+
+        // U runs right to left, so the tangent flips, while V is unchanged, so the bitangent sign flips
+        let mut mirrored = quad([Vec2::new(1.0, 1.0), Vec2::new(0.0, 1.0), Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0)]);
+        mirrored.compute_tangents();
+        for tangent in mirrored.tangents.as_ref().unwrap() {
+            assert!(tangent.abs_diff_eq(Vec4::new(-1.0, 0.0, 0.0, -1.0), 1e-5));
+        }
+
+        // Needs UVs
+        let mut without_uvs = MeshData { uvs: None, ..mesh.clone() };
+        without_uvs.tangents = None;
+        without_uvs.compute_tangents();
+        assert!(without_uvs.tangents.is_none());
+    }
+
+    #[test]
+    fn tangents_are_interleaved_last() {
+        let mut mesh = quad([Vec2::ZERO; 4]);
+        mesh.tangents = Some(vec![Vec4::new(1.0, 2.0, 3.0, -1.0); 4]);
+        let bytes = mesh.vertex_bytes();
+        assert_eq!(4 * (12 + 12 + 8 + 16), bytes.len());
+        let first_tangent: &[f32] = bytemuck::cast_slice(&bytes[32..48]);
+        assert_eq!(&[1.0, 2.0, 3.0, -1.0], first_tangent);
+
+        let mut defs = ShaderPreprocessor::new();
+        let layout = mesh.key().layout(&mut defs);
+        assert_eq!(48, layout.array_stride);
+        assert_eq!(32, layout.attributes.last().unwrap().offset);
+    }
+
+    #[test]
+    fn bones_are_interleaved_after_tangents() {
+        let mut mesh = quad([Vec2::ZERO; 4]);
+        mesh.bone_indices = Some(vec![[1, 2, 3, 4]; 4]);
+        assert!(!mesh.key().contains(MeshKey::SKINNED));
+        mesh.bone_weights = Some(vec![Vec4::new(0.4, 0.3, 0.2, 0.1); 4]);
+        assert!(mesh.key().contains(MeshKey::SKINNED));
+        let bytes = mesh.vertex_bytes();
+        assert_eq!(4 * (12 + 12 + 8 + 4 + 16), bytes.len());
+        assert_eq!(&[1, 2, 3, 4], &bytes[32..36]);
+        let first_weights: &[f32] = bytemuck::cast_slice(&bytes[36..52]);
+        assert_eq!(&[0.4, 0.3, 0.2, 0.1], first_weights);
+
+        let mut defs = ShaderPreprocessor::new();
+        let layout = mesh.key().layout(&mut defs);
+        assert_eq!(52, layout.array_stride);
+        assert_eq!(36, layout.attributes.last().unwrap().offset);
+    }
+
+    #[test]
+    fn bounding_volumes() {
+        let cuboid = Cuboid { center: Vec3::new(1.0, 2.0, 3.0), half_extents: Vec3::new(0.5, 1.0, 2.0), color: Color::WHITE };
+        let mesh = MeshData::from(cuboid);
+        assert_eq!(AABB::new(cuboid.center, cuboid.half_extents), mesh.compute_aabb());
+        let sphere = mesh.compute_bounding_sphere();
+        for position in &mesh.positions {
+            assert!(position.distance(sphere.center) <= sphere.radius + 1e-5);
+        }
+
+        // Meshes without vertices bound nothing.
+        assert!(MeshData::new().compute_aabb().is_empty());
+        assert!(MeshData::new().compute_bounding_sphere().is_empty());
+    }
 }
\ No newline at end of file
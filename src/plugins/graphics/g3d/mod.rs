@@ -3,9 +3,25 @@ mod material;
 mod mesh;
 mod shape;
 mod camera;
+mod light;
+mod obj;
+mod shadow;
+mod skybox;
+mod gizmos;
+mod skin;
+mod lod;
+mod cull;
+mod spawn;
 
 pub use g3d::*;
 pub use material::*;
 pub use mesh::*;
 pub use shape::*;
-pub use camera::*;
\ No newline at end of file
+pub use camera::*;
+pub use light::*;
+pub use obj::*;
+pub use shadow::*;
+pub use gizmos::*;
+pub use skin::*;
+pub use lod::*;
+pub use spawn::*;
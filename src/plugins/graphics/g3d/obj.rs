@@ -0,0 +1,468 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+use glam::{Vec2, Vec3};
+use tobj::LoadError;
+use wgpu::Device;
+use crate::{Asset, AssetLoader, AssetManager, AssetPath, AssetState, Color, Handle, Readiness};
+use crate::g3d::{BlendMode, Material, Mesh, MeshData};
+
+/// Loads a [`Mesh`] from a Wavefront OBJ file.
+/// Only geometry is read, and all objects are merged into a single mesh.
+/// Use an [`ObjSceneLoader`] to keep objects apart and apply their materials.
+pub struct ObjLoader {
+    pub device: Arc<Device>,
+}
+
+impl AssetLoader for ObjLoader {
+
+    type AssetType = Mesh;
+
+    fn load(&self, bytes: &[u8], _path: &AssetPath) -> anyhow::Result<Self::AssetType> {
+        let text = std::str::from_utf8(bytes)?;
+        let data = parse_obj(text)?;
+        Ok(Mesh::from_data(&data, &self.device))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["obj"]
+    }
+}
+
+/// Loads an [`ObjScene`] from a Wavefront OBJ file, with one mesh per object.
+pub struct ObjSceneLoader {
+    pub device: Arc<Device>,
+}
+
+impl AssetLoader for ObjSceneLoader {
+
+    type AssetType = ObjScene;
+
+    fn load(&self, bytes: &[u8], path: &AssetPath) -> anyhow::Result<Self::AssetType> {
+        let text = std::str::from_utf8(bytes)?;
+        let file = parse_obj_objects(text)?;
+
+        // Material libraries are resolved relative to the OBJ file
+        let material_lib = file.material_libs.first().map(|lib| path.resolve(lib));
+        let objects = file.objects
+            .into_iter()
+            .map(|object| (Mesh::from_data(&object.data, &self.device), object.material))
+            .collect();
+        Ok(ObjScene {
+            objects: Vec::new(),
+            pending: Some(PendingObjects { objects, material_lib, materials: None }),
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["obj"]
+    }
+}
+
+/**
+ * Objects of an OBJ file, each with its own mesh and material.
+ * Meshes are inserted into their storage on the first asset stage after loading.
+ * If the file references a material library, materials are inserted once the library finishes loading.
+ * Until then, the scene has no objects, and is not ready.
+ */
+pub struct ObjScene {
+    /// Mesh and material of each object, in the order they appear in the file.
+    /// Objects without a material, or whose material is missing from the library, share a white material.
+    pub objects: Vec<(Handle<Mesh>, Handle<Material>)>,
+    pending: Option<PendingObjects>,
+}
+
+impl Asset for ObjScene {
+    fn readiness(&self, _assets: &AssetManager) -> Readiness {
+        match self.pending {
+            Some(_) => Readiness::NotReady,
+            None => Readiness::Ready,
+        }
+    }
+}
+
+/// Assets created on a loader thread, awaiting insertion into their storages.
+struct PendingObjects {
+    objects: Vec<(Mesh, Option<String>)>,
+    material_lib: Option<String>,
+    materials: Option<Handle<ObjMaterials>>,
+}
+
+/// Inserts the meshes and materials of loaded [`ObjScene`]s into their storages.
+/// Starts loading material libraries first, if any.
+pub(crate) fn insert_obj_objects(assets: &mut AssetManager) {
+
+    // Starts loading libraries of newly loaded scenes
+    let mut lib_loads = Vec::new();
+    {
+        let mut scenes = assets.storage::<ObjScene>().unwrap();
+        for (index, scene) in scenes.iter() {
+            let Some(pending) = scene.as_loaded().and_then(|scene| scene.pending.as_ref()) else { continue };
+            if pending.materials.is_some() { continue }
+            let Some(material_lib) = &pending.material_lib else { continue };
+            lib_loads.push((index, material_lib.clone()));
+        }
+    }
+    let lib_handles: Vec<_> = lib_loads
+        .into_iter()
+        .map(|(index, material_lib)| {
+            let handle = assets.try_load::<ObjMaterials, _>(&material_lib);
+            if let Err(err) = &handle {
+                log::error!("Failed to load material library {material_lib}: {err}");
+            }
+            (index, handle.ok())
+        })
+        .collect();
+
+    // Inserts objects of scenes whose libraries finished loading, or that have none
+    let mut scenes = assets.storage::<ObjScene>().unwrap();
+    let mut meshes = assets.storage::<Mesh>().unwrap();
+    let mut materials = assets.storage::<Material>().unwrap();
+    let libs = assets.storage::<ObjMaterials>().unwrap();
+    for (index, handle) in lib_handles {
+        let Some(scene) = scenes.inner.get_mut(index).and_then(|scene| scene.as_loaded_mut()) else { continue };
+        let Some(pending) = &mut scene.pending else { continue };
+        match handle {
+            Some(handle) => pending.materials = Some(handle),
+            None => pending.material_lib = None,
+        }
+    }
+    for scene in scenes.values_mut() {
+        let Some(scene) = scene.as_loaded_mut() else { continue };
+        let Some(pending) = &scene.pending else { continue };
+        let lib = match &pending.materials {
+            Some(handle) => match libs.get(handle) {
+                AssetState::Loading => continue,
+                AssetState::Loaded(lib) => Some(lib),
+                AssetState::Failed => None,
+            },
+            None => None,
+        };
+        let pending = scene.pending.take().unwrap();
+        let mut material_handles: HashMap<Option<String>, Handle<Material>> = HashMap::new();
+        scene.objects = pending.objects
+            .into_iter()
+            .map(|(mesh, material_name)| {
+                let color = lib.and_then(|lib| lib.colors.get(material_name.as_ref()?).copied());
+                let key = color.and(material_name);
+                let material = material_handles
+                    .entry(key)
+                    .or_insert_with(|| materials.insert(obj_material(color.unwrap_or(Color::WHITE))))
+                    .clone();
+                (meshes.insert(mesh), material)
+            })
+            .collect();
+    }
+}
+
+fn obj_material(color: Color) -> Material {
+    Material {
+        base_color: color,
+        blend_mode: if color.a < 1.0 { BlendMode::AlphaBlend } else { BlendMode::Opaque },
+        ..Default::default()
+    }
+}
+
+/// Loads [`ObjMaterials`] from a Wavefront MTL file.
+pub struct MtlLoader;
+
+impl AssetLoader for MtlLoader {
+
+    type AssetType = ObjMaterials;
+
+    fn load(&self, bytes: &[u8], _path: &AssetPath) -> anyhow::Result<Self::AssetType> {
+        let text = std::str::from_utf8(bytes)?;
+        Ok(parse_mtl(text)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["mtl"]
+    }
+}
+
+/// Materials of an MTL file.
+/// Only diffuse colors are read, with dissolve as alpha.
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct ObjMaterials {
+    pub colors: HashMap<String, Color>,
+}
+
+impl Asset for ObjMaterials {}
+
+/// Parses the text of an MTL file into [`ObjMaterials`].
+pub fn parse_mtl(text: &str) -> Result<ObjMaterials, LoadError> {
+    let (materials, _) = tobj::load_mtl_buf(&mut text.as_bytes())?;
+    let colors = materials
+        .into_iter()
+        .map(|material| {
+            let [r, g, b] = material.diffuse.unwrap_or([1.0; 3]);
+            let alpha = material.dissolve.unwrap_or(1.0);
+            (material.name, Color::new(r, g, b, alpha))
+        })
+        .collect();
+    Ok(ObjMaterials { colors })
+}
+
+/**
+ * Parses the text of an OBJ file into [`MeshData`].
+ * Faces with more than 3 vertices are triangulated as fans.
+ * Vertices that share the same position / uv / normal triple are deduplicated within each object.
+ * Normals and UVs are only included if every face vertex references them.
+ */
+pub fn parse_obj(text: &str) -> Result<MeshData, LoadError> {
+    let file = parse_obj_objects(text)?;
+    let mut data = MeshData::new();
+    let has_uvs = file.objects.iter().all(|object| object.data.uvs.is_some());
+    let has_normals = file.objects.iter().all(|object| object.data.normals.is_some());
+    if !file.objects.is_empty() {
+        if has_uvs { data.uvs = Some(Vec::new()) }
+        if has_normals { data.normals = Some(Vec::new()) }
+    }
+    for object in file.objects {
+        let offset = data.positions.len() as u32;
+        data.indices.extend(object.data.indices.iter().map(|index| index + offset));
+        data.positions.extend(object.data.positions);
+        if let (Some(uvs), Some(object_uvs)) = (&mut data.uvs, object.data.uvs) {
+            uvs.extend(object_uvs);
+        }
+        if let (Some(normals), Some(object_normals)) = (&mut data.normals, object.data.normals) {
+            normals.extend(object_normals);
+        }
+    }
+    Ok(data)
+}
+
+/**
+ * Same as [`parse_obj`], but splits the file into objects.
+ * A new object starts at each "o" or "g" statement, and whenever "usemtl" changes the material of an object that has faces.
+ * Objects without faces are dropped.
+ */
+pub fn parse_obj_objects(text: &str) -> Result<ObjFile, LoadError> {
+
+    // Material libraries are loaded as assets of their own, so tobj is handed placeholder materials.
+    // They are named after every "usemtl" in the file, so that objects keep the names of their materials.
+    let placeholders: Vec<tobj::Material> = text
+        .lines()
+        .filter(|line| line.split_whitespace().next() == Some("usemtl"))
+        .filter_map(|line| line.split_once(' '))
+        .map(|(_, name)| tobj::Material { name: name.trim().to_owned(), ..Default::default() })
+        .collect();
+    let material_libs = RefCell::new(Vec::new());
+    let options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ignore_points: true,
+        ignore_lines: true,
+    };
+    let (models, materials) = tobj::load_obj_buf(&mut text.as_bytes(), &options, |path| {
+        material_libs.borrow_mut().push(path.to_string_lossy().into_owned());
+        let names = placeholders.iter().enumerate().map(|(id, material)| (material.name.clone(), id)).collect();
+        Ok((placeholders.clone(), names))
+    })?;
+    let materials = materials.unwrap_or_default();
+
+    let objects = models
+        .into_iter()
+        .filter(|model| !model.mesh.indices.is_empty())
+        .map(|model| {
+            let mesh = model.mesh;
+            let num_vertices = mesh.positions.len() / 3;
+            let mut data = MeshData::new();
+            data.positions = mesh.positions
+                .chunks_exact(3)
+                .map(Vec3::from_slice)
+                .collect();
+            if mesh.texcoords.len() == num_vertices * 2 {
+                data.uvs = Some(mesh.texcoords
+                    .chunks_exact(2)
+                    .map(|uv| Vec2::new(uv[0], 1.0 - uv[1]))
+                    .collect());
+            }
+            if mesh.normals.len() == num_vertices * 3 {
+                data.normals = Some(mesh.normals
+                    .chunks_exact(3)
+                    .map(Vec3::from_slice)
+                    .collect());
+            }
+            data.indices = mesh.indices;
+            ObjObject {
+                name: Some(model.name).filter(|name| !name.is_empty()),
+                material: mesh.material_id.and_then(|id| materials.get(id)).map(|material| material.name.clone()),
+                data,
+            }
+        })
+        .collect();
+    Ok(ObjFile { objects, material_libs: material_libs.into_inner() })
+}
+
+/// Objects and material libraries of an OBJ file.
+#[derive(Clone)]
+pub struct ObjFile {
+    pub objects: Vec<ObjObject>,
+    /// Material libraries referenced with "mtllib", relative to the OBJ file.
+    pub material_libs: Vec<String>,
+}
+
+/// Geometry of a single object in an OBJ file.
+#[derive(Clone)]
+pub struct ObjObject {
+    pub name: Option<String>,
+    /// Name of the material in the file's material libraries.
+    pub material: Option<String>,
+    pub data: MeshData,
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use glam::{Vec2, Vec3};
+    use crate::{AssetManager, AssetPath, Color, Protocol, Readiness};
+    use tobj::LoadError;
+    use crate::g3d::{insert_obj_objects, parse_mtl, parse_obj, parse_obj_objects, Material, Mesh, MtlLoader, ObjMaterials, ObjScene, ObjSceneLoader};
+    use crate::plugins::graphics::headless;
+
+    /// Serves files from memory, at paths that include the path prefix.
+    struct MemoryProtocol(HashMap<&'static str, &'static str>);
+    impl Protocol for MemoryProtocol {
+        fn name(&self) -> &str { "file" }
+        fn read(&self, path: &AssetPath) -> anyhow::Result<Vec<u8>> {
+            match self.0.get(path.without_protocol().as_str()) {
+                Some(text) => Ok(text.as_bytes().to_vec()),
+                None => anyhow::bail!("No such file {path}"),
+            }
+        }
+    }
+
+    #[test]
+    fn quad_is_triangulated_and_deduplicated() {
+        let obj =
+"# A unit quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+f 1/1/1 2/2/1 3/3/1";
+        let data = parse_obj(obj).unwrap();
+        assert_eq!(vec![0, 1, 2, 0, 2, 3, 0, 1, 2], data.indices);
+        assert_eq!(4, data.positions.len());
+        assert_eq!(Vec2::new(1.0, 0.0), data.uvs.unwrap()[2]);
+        assert_eq!(vec![Vec3::Z; 4], data.normals.unwrap());
+    }
+
+    #[test]
+    fn missing_attributes() {
+        let obj =
+"v 0 0 0
+v 1 0 0
+v 1 1 0
+vn 0 0 1
+f 1//1 2//1 -1";
+        let data = parse_obj(obj).unwrap();
+        assert_eq!(vec![0, 1, 2], data.indices);
+        assert!(data.uvs.is_none());
+        assert!(data.normals.is_none());
+    }
+
+    #[test]
+    fn invalid_face() {
+        let obj =
+"v 0 0 0
+v 1 0 0
+f 1 2 3";
+        assert_eq!(LoadError::FaceVertexOutOfBounds, parse_obj(obj).err().unwrap());
+    }
+
+    #[test]
+    fn objects_are_split() {
+        let obj =
+"mtllib scene.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+o first
+f 1 2 3
+o empty
+o second
+usemtl red
+f 1 2 3 4
+usemtl blue
+f -1 -2 -3";
+        let file = parse_obj_objects(obj).unwrap();
+        assert_eq!(vec![String::from("scene.mtl")], file.material_libs);
+        let objects: Vec<_> = file.objects
+            .iter()
+            .map(|object| (object.name.as_deref(), object.material.as_deref(), object.data.indices.clone()))
+            .collect();
+        assert_eq!(vec![
+            (Some("first"), None, vec![0, 1, 2]),
+            (Some("second"), Some("red"), vec![0, 1, 2, 0, 2, 3]),
+            (Some("second"), Some("blue"), vec![0, 1, 2]),
+        ], objects);
+        assert_eq!(3, file.objects[2].data.positions.len());
+
+        // Without splitting, objects are merged
+        let data = parse_obj(obj).unwrap();
+        assert_eq!(10, data.positions.len());
+        assert_eq!(12, data.indices.len());
+        assert_eq!(&[3, 4, 5, 3, 5, 6, 7, 8, 9], &data.indices[3..]);
+    }
+
+    #[test]
+    fn materials() {
+        let mtl =
+"newmtl red
+Kd 1 0 0
+newmtl glass
+Kd 0 0 1
+d 0.5
+newmtl plain";
+        let materials = parse_mtl(mtl).unwrap();
+        assert_eq!(3, materials.colors.len());
+        assert_eq!(Color::new(1.0, 0.0, 0.0, 1.0), materials.colors["red"]);
+        assert_eq!(Color::new(0.0, 0.0, 1.0, 0.5), materials.colors["glass"]);
+        assert_eq!(Color::WHITE, materials.colors["plain"]);
+
+        let err = parse_mtl("newmtl red\nKd 1 red 0").err().unwrap();
+        assert_eq!(LoadError::MaterialParseError, err);
+    }
+
+    #[test]
+    fn material_lib_is_relative_to_obj() {
+        let Some((device, _queue)) = headless::device() else { return };
+        let mut assets = AssetManager::new();
+        assets.set_path_prefix(Some("game/assets"));
+        assets.add_protocol(MemoryProtocol(HashMap::from([
+            ("game/assets/models/ship.obj", "mtllib ship.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nusemtl hull\nf 1 2 3"),
+            ("game/assets/models/ship.mtl", "newmtl hull\nKd 1 0 0"),
+        ])), true);
+        assets.add_storage::<ObjScene>();
+        assets.add_storage::<ObjMaterials>();
+        assets.add_storage::<Mesh>();
+        assets.add_storage::<Material>();
+        assets.add_loader(ObjSceneLoader { device }).unwrap();
+        assets.add_loader(MtlLoader).unwrap();
+        let scene = assets.load::<ObjScene, _>("models/ship.obj");
+        for _ in 0..100 {
+            assets.try_handle_messages();
+            insert_obj_objects(&mut assets);
+            if assets.dyn_readiness(scene.id()) == Readiness::Ready {
+                let scenes = assets.storage::<ObjScene>().unwrap();
+                let materials = assets.storage::<Material>().unwrap();
+                let (_, material) = &scenes.get(&scene).unwrap().objects[0];
+                assert_eq!(Color::new(1.0, 0.0, 0.0, 1.0), materials.get(material).unwrap().base_color);
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Scene did not finish loading");
+    }
+}
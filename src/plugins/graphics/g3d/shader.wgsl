@@ -3,6 +3,12 @@ struct InstanceIn {
     @location(1) model_1: vec4<f32>,
     @location(2) model_2: vec4<f32>,
     @location(3) model_3: vec4<f32>,
+    #ifdef LIGHTING
+    @location(8) world_0: vec4<f32>,
+    @location(9) world_1: vec4<f32>,
+    @location(10) world_2: vec4<f32>,
+    @location(11) world_3: vec4<f32>,
+    #endif
 }
 
 struct VertexIn {
@@ -16,6 +22,13 @@ struct VertexIn {
     #ifdef UV
     @location(7) uv: vec2<f32>,
     #endif
+    #ifdef TANGENT
+    @location(12) tangent: vec4<f32>,
+    #endif
+    #ifdef SKINNED
+    @location(13) bone_indices: vec4<u32>,
+    @location(14) bone_weights: vec4<f32>,
+    #endif
 }
 
 struct VertexOut {
@@ -29,6 +42,12 @@ struct VertexOut {
     #ifdef UV
     @location(2) uv: vec2<f32>,
     #endif
+    #ifdef LIGHTING
+    @location(3) world_position: vec3<f32>,
+    #endif
+    #ifdef TANGENT
+    @location(4) tangent: vec4<f32>,
+    #endif
 }
 
 struct FragmentIn {
@@ -42,20 +61,293 @@ struct FragmentIn {
     #ifdef UV
     @location(2) uv: vec2<f32>,
     #endif
+    #ifdef LIGHTING
+    @location(3) world_position: vec3<f32>,
+    #endif
+    #ifdef TANGENT
+    @location(4) tangent: vec4<f32>,
+    #endif
 }
 
 struct Uniform {
     base_color: vec4<f32>,
+    alpha_cutoff: f32,
+    roughness: f32,
+    metalness: f32,
+    shininess: f32,
 }
 
 @group(0) @binding(0)
 var<uniform> uni: Uniform;
+
+// Normal, roughness / metalness and occlusion textures store linear values.
+// Those loaded as sRGB are decoded when sampled, so they are encoded back.
+fn encode_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
 #ifdef BASE_COLOR_TEX
 @group(0) @binding(1)
 var base_color_tex: texture_2d<f32>;
 @group(0) @binding(2)
 var base_color_sam: sampler;
 #endif
+#ifdef NORMAL_MAP
+@group(0) @binding(3)
+var normal_tex: texture_2d<f32>;
+@group(0) @binding(4)
+var normal_sam: sampler;
+
+// Transforms the normal sampled from the normal texture from tangent space to world space.
+fn normal_mapped(normal: vec3<f32>, tangent: vec4<f32>, uv: vec2<f32>) -> vec3<f32> {
+    let t = normalize(tangent.xyz - normal * dot(normal, tangent.xyz));
+    let b = cross(normal, t) * tangent.w;
+    var sampled = textureSample(normal_tex, normal_sam, uv).rgb;
+    #ifdef NORMAL_MAP_SRGB
+    sampled = encode_srgb(sampled);
+    #endif
+    sampled = sampled * 2.0 - 1.0;
+    return normalize(mat3x3<f32>(t, b, normal) * sampled);
+}
+#endif
+#ifdef ROUGHNESS_METALNESS_TEX
+@group(0) @binding(5)
+var roughness_metalness_tex: texture_2d<f32>;
+@group(0) @binding(6)
+var roughness_metalness_sam: sampler;
+#endif
+#ifdef AO_TEX
+@group(0) @binding(7)
+var ao_tex: texture_2d<f32>;
+@group(0) @binding(8)
+var ao_sam: sampler;
+#endif
+
+#ifdef SKINNED
+const MAX_BONES: u32 = 256u;
+
+struct Bones {
+    matrices: array<mat4x4<f32>, MAX_BONES>,
+}
+
+#ifdef LIGHTING
+@group(2) @binding(0)
+#else
+@group(1) @binding(0)
+#endif
+var<uniform> bones: Bones;
+
+// Blends the matrices of the bones that influence a vertex by their weights.
+fn skin_transform(indices: vec4<u32>, weights: vec4<f32>) -> mat4x4<f32> {
+    return bones.matrices[indices.x] * weights.x
+        + bones.matrices[indices.y] * weights.y
+        + bones.matrices[indices.z] * weights.z
+        + bones.matrices[indices.w] * weights.w;
+}
+#endif
+
+#ifdef LIGHTING
+const MAX_LIGHTS: u32 = 16u;
+const MAX_SPOT_LIGHTS: u32 = 8u;
+
+struct PointLight {
+    position_radius: vec4<f32>,
+    color_intensity: vec4<f32>,
+}
+
+struct SpotLight {
+    position_radius: vec4<f32>,
+    direction_cos_outer: vec4<f32>,
+    color_intensity: vec4<f32>,
+    cos_inner: vec4<f32>,
+}
+
+struct Lights {
+    point_light_count: u32,
+    spot_light_count: u32,
+    ambient: vec4<f32>,
+    directional_direction: vec4<f32>,
+    directional_color_intensity: vec4<f32>,
+    point_lights: array<PointLight, MAX_LIGHTS>,
+    spot_lights: array<SpotLight, MAX_SPOT_LIGHTS>,
+    shadow_view_proj: mat4x4<f32>,
+    shadow_params: vec4<f32>,
+    environment: vec4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> lights: Lights;
+#ifdef SHADOW
+@group(1) @binding(1)
+var shadow_map: texture_depth_2d;
+@group(1) @binding(2)
+var shadow_sam: sampler_comparison;
+
+// Fraction of the directional light that reaches a point, filtered over 3x3 shadow map texels.
+// One if shadows are disabled this frame, or if the point is outside the shadow map.
+fn directional_shadow(world_position: vec3<f32>) -> f32 {
+    if lights.shadow_params.x == 0.0 {
+        return 1.0;
+    }
+    let clip = lights.shadow_view_proj * vec4<f32>(world_position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+    let texel_size = lights.shadow_params.y;
+    var lit = 0.0;
+    for(var x = -1; x <= 1; x++) {
+        for(var y = -1; y <= 1; y++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel_size;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sam, uv + offset, ndc.z);
+        }
+    }
+    return lit / 9.0;
+}
+#endif
+
+// Properties of the fragment being lit.
+struct Surface {
+    normal: vec3<f32>,
+    to_view: vec3<f32>,
+    albedo: vec3<f32>,
+    roughness: f32,
+    metalness: f32,
+    shininess: f32,
+}
+
+struct View {
+    position: vec4<f32>,
+}
+
+@group(1) @binding(3)
+var<uniform> viewer: View;
+
+#ifdef PBR
+const PI: f32 = 3.14159265359;
+
+@group(1) @binding(4)
+var environment_map: texture_cube<f32>;
+@group(1) @binding(5)
+var environment_sam: sampler;
+
+// Trowbridge-Reitz GGX normal distribution.
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / max(PI * denom * denom, 0.0001);
+}
+
+// Smith's method with Schlick-GGX, for both the light and view directions.
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+// Schlick's approximation of the Fresnel factor.
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+#endif
+
+// Fraction of the light coming from to_light that the surface reflects toward the viewer, times n·l.
+// Cook-Torrance if PBR is defined, otherwise Blinn-Phong.
+// Scaled by PI so that light intensities match between both models.
+fn reflectance(surface: Surface, to_light: vec3<f32>) -> vec3<f32> {
+    let n_dot_l = max(dot(surface.normal, to_light), 0.0);
+    #ifdef PBR
+    let n_dot_v = max(dot(surface.normal, surface.to_view), 0.0001);
+    let half_dir = normalize(to_light + surface.to_view);
+    let n_dot_h = max(dot(surface.normal, half_dir), 0.0);
+    let h_dot_v = max(dot(half_dir, surface.to_view), 0.0);
+    let roughness = clamp(surface.roughness, 0.04, 1.0);
+    let f0 = mix(vec3<f32>(0.04), surface.albedo, surface.metalness);
+    let f = fresnel_schlick(h_dot_v, f0);
+    let d = distribution_ggx(n_dot_h, roughness);
+    let g = geometry_smith(n_dot_v, n_dot_l, roughness);
+    let specular = d * g * f / max(4.0 * n_dot_v * n_dot_l, 0.0001);
+    let kd = (1.0 - f) * (1.0 - surface.metalness);
+    return (kd * surface.albedo + specular * PI) * n_dot_l;
+    #else
+    let half_dir = normalize(to_light + surface.to_view);
+    let specular = select(0.0, pow(max(dot(surface.normal, half_dir), 0.0), surface.shininess), n_dot_l > 0.0);
+    return surface.albedo * n_dot_l + vec3<f32>(specular);
+    #endif
+}
+
+#ifdef PBR
+// Light reflected from the skybox's environment map. Zero if there is no skybox.
+// The map is not convolved, so blurrier mips stand in for the irradiance and for rough reflections.
+fn environment_lighting(surface: Surface) -> vec3<f32> {
+    if lights.environment.x == 0.0 {
+        return vec3<f32>(0.0);
+    }
+    let max_mip = lights.environment.y;
+    let n_dot_v = max(dot(surface.normal, surface.to_view), 0.0);
+    let f0 = mix(vec3<f32>(0.04), surface.albedo, surface.metalness);
+    let f = f0 + (max(vec3<f32>(1.0 - surface.roughness), f0) - f0) * pow(1.0 - n_dot_v, 5.0);
+    let kd = (1.0 - f) * (1.0 - surface.metalness);
+    let irradiance = textureSampleLevel(environment_map, environment_sam, surface.normal, max_mip).rgb;
+    let reflected_dir = reflect(-surface.to_view, surface.normal);
+    let reflected = textureSampleLevel(environment_map, environment_sam, reflected_dir, surface.roughness * max_mip).rgb;
+    return kd * surface.albedo * irradiance + f * reflected;
+}
+#endif
+
+// Inverse-square falloff, windowed so that it reaches zero at the light's radius.
+fn point_light_attenuation(distance: f32, radius: f32) -> f32 {
+    let ratio = distance / radius;
+    let window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window / max(distance * distance, 0.0001);
+}
+
+fn point_lighting(world_position: vec3<f32>, surface: Surface) -> vec3<f32> {
+    var result = vec3<f32>(0.0);
+    let count = min(lights.point_light_count, MAX_LIGHTS);
+    for(var i = 0u; i < count; i++) {
+        let light = lights.point_lights[i];
+        let to_light = light.position_radius.xyz - world_position;
+        let distance = length(to_light);
+        let attenuation = point_light_attenuation(distance, light.position_radius.w);
+        let reflected = reflectance(surface, to_light / max(distance, 0.0001));
+        result += light.color_intensity.rgb * light.color_intensity.w * attenuation * reflected;
+    }
+    return result;
+}
+
+// Same as point lighting, but faded from the cone's inner angle to its outer angle.
+fn spot_lighting(world_position: vec3<f32>, surface: Surface) -> vec3<f32> {
+    var result = vec3<f32>(0.0);
+    let count = min(lights.spot_light_count, MAX_SPOT_LIGHTS);
+    for(var i = 0u; i < count; i++) {
+        let light = lights.spot_lights[i];
+        let to_light = light.position_radius.xyz - world_position;
+        let distance = length(to_light);
+        let direction = to_light / max(distance, 0.0001);
+        let attenuation = point_light_attenuation(distance, light.position_radius.w);
+        let cos_outer = light.direction_cos_outer.w;
+        let cos_angle = dot(-direction, light.direction_cos_outer.xyz);
+        let cone = smoothstep(cos_outer, max(light.cos_inner.x, cos_outer + 0.0001), cos_angle);
+        let reflected = reflectance(surface, direction);
+        result += light.color_intensity.rgb * light.color_intensity.w * attenuation * cone * reflected;
+    }
+    return result;
+}
+
+// Light reflected from the directional light. Zero if there is none.
+fn directional_lighting(surface: Surface) -> vec3<f32> {
+    let to_light = -lights.directional_direction.xyz;
+    let reflected = reflectance(surface, to_light);
+    let color_intensity = lights.directional_color_intensity;
+    return color_intensity.rgb * color_intensity.w * reflected;
+}
+#endif
 
 @vertex
 fn vertex_main(instance: InstanceIn, vert: VertexIn) -> VertexOut {
@@ -65,17 +357,59 @@ fn vertex_main(instance: InstanceIn, vert: VertexIn) -> VertexOut {
         instance.model_2,
         instance.model_3,
     );
+    #ifdef LIGHTING
+    let world = mat4x4<f32>(
+        instance.world_0,
+        instance.world_1,
+        instance.world_2,
+        instance.world_3,
+    );
+    #endif
+
+    // Poses the vertex before it is transformed by the instance
+    var position = vec4<f32>(vert.position, 1.0);
+    #ifdef NORMAL
+    var normal = vec4<f32>(vert.normal, 0.0);
+    #endif
+    #ifdef TANGENT
+    var tangent = vec4<f32>(vert.tangent.xyz, 0.0);
+    #endif
+    #ifdef SKINNED
+    let skin = skin_transform(vert.bone_indices, vert.bone_weights);
+    position = skin * position;
+    #ifdef NORMAL
+    normal = skin * normal;
+    #endif
+    #ifdef TANGENT
+    tangent = skin * tangent;
+    #endif
+    #endif
+
     return VertexOut(
-        mvp * vec4<f32>(vert.position, 1.0),
+        mvp * position,
         #ifdef COLOR
         vert.color,
         #endif
         #ifdef NORMAL
-        vert.normal,
+        #ifdef LIGHTING
+        (world * normal).xyz,
+        #else
+        normal.xyz,
+        #endif
         #endif
         #ifdef UV
         vert.uv,
         #endif
+        #ifdef LIGHTING
+        (world * position).xyz,
+        #endif
+        #ifdef TANGENT
+        #ifdef LIGHTING
+        vec4<f32>((world * tangent).xyz, vert.tangent.w),
+        #else
+        vec4<f32>(tangent.xyz, vert.tangent.w),
+        #endif
+        #endif
     );
 }
 
@@ -95,5 +429,56 @@ fn fragment_main(in: FragmentIn) -> @location(0) vec4<f32> {
     color *= in.color;
     #endif
 
+    // Cutout
+    #ifdef ALPHA_MASK
+    if color.a < uni.alpha_cutoff {
+        discard;
+    }
+    #endif
+
+    // Ambient, directional, point and spot lights
+    #ifdef LIGHTING
+    var normal = normalize(in.normal);
+    #ifdef NORMAL_MAP
+    normal = normal_mapped(normal, in.tangent, in.uv);
+    #endif
+    let to_view = normalize(viewer.position.xyz - in.world_position);
+    var surface = Surface(normal, to_view, color.rgb, uni.roughness, uni.metalness, uni.shininess);
+    var occlusion = 1.0;
+    #ifdef PBR
+    #ifdef UV
+    #ifdef ROUGHNESS_METALNESS_TEX
+    var roughness_metalness = textureSample(roughness_metalness_tex, roughness_metalness_sam, in.uv).rgb;
+    #ifdef ROUGHNESS_METALNESS_TEX_SRGB
+    roughness_metalness = encode_srgb(roughness_metalness);
+    #endif
+    surface.roughness *= roughness_metalness.g;
+    surface.metalness *= roughness_metalness.b;
+    #endif
+    #ifdef AO_TEX
+    var ao = textureSample(ao_tex, ao_sam, in.uv).rgb;
+    #ifdef AO_TEX_SRGB
+    ao = encode_srgb(ao);
+    #endif
+    occlusion = ao.r;
+    #endif
+    #endif
+    #endif
+    var directional = directional_lighting(surface);
+    #ifdef SHADOW
+    directional *= directional_shadow(in.world_position);
+    #endif
+    var environment = vec3<f32>(0.0);
+    #ifdef PBR
+    environment = environment_lighting(surface) * occlusion;
+    #endif
+    let lighting = lights.ambient.rgb * surface.albedo * occlusion
+        + environment
+        + directional
+        + point_lighting(in.world_position, surface)
+        + spot_lighting(in.world_position, surface);
+    color = vec4<f32>(lighting, color.a);
+    #endif
+
     return color;
 }
\ No newline at end of file
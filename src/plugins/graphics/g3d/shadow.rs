@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use glam::{Mat4, Vec3};
+use tracing::instrument;
+use wgpu::{AddressMode, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferDescriptor, BufferUsages, CommandEncoder, CompareFunction, DepthBiasState, DepthStencilState, Device, Extent3d, FilterMode, FrontFace, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, Queue, RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState, StoreOp, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDimension, VertexState};
+use crate::{reserve_buffer, AssetId, ShaderPreprocessor};
+use crate::g3d::{DirectionalLight, Mesh, MeshKey};
+use super::INSTANCE_LAYOUT;
+use super::skin::SkinBindings;
+
+/// Width and height of the shadow map, unless configured otherwise.
+pub const DEFAULT_SHADOW_MAP_SIZE: u32 = 2048;
+
+const SHADOW_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+const SHADOW_MAP_BINDING: u32 = 1;
+const SHADOW_SAMPLER_BINDING: u32 = 2;
+
+/**
+ * Depth-only render pass that draws opaque meshes from the perspective of the [`DirectionalLight`].
+ * The resulting shadow map is sampled by lit pipelines, which darken fragments the light cannot see.
+ */
+pub(crate) struct ShadowPass {
+    size: u32,
+    view: TextureView,
+    sampler: Sampler,
+    pipelines: HashMap<MeshKey, RenderPipeline>,    // Cache of depth-only pipelines by vertex layout, which skinned ones bind bone matrices for
+    instances: Buffer,
+}
+
+impl ShadowPass {
+
+    pub fn new(size: u32, device: &Device) -> Self {
+        let size = size.max(1);
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("g3d_shadow_map"),
+            size: Extent3d { width: size, height: size, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("g3d_shadow_sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            compare: Some(CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        Self {
+            size,
+            view: texture.create_view(&Default::default()),
+            sampler,
+            pipelines: HashMap::default(),
+            instances: device.create_buffer(&BufferDescriptor {
+                label: Some("g3d_shadow_instances"),
+                size: 0,
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+        }
+    }
+
+    /// Width and height of the shadow map in texels.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Entries of the shadow map and its sampler, appended to the lights layout.
+    pub fn layout_entries() -> [BindGroupLayoutEntry; 2] {
+        [
+            BindGroupLayoutEntry {
+                binding: SHADOW_MAP_BINDING,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Depth,
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: SHADOW_SAMPLER_BINDING,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Comparison),
+                count: None,
+            },
+        ]
+    }
+
+    pub fn bind_group_entries(&self) -> [BindGroupEntry<'_>; 2] {
+        [
+            BindGroupEntry {
+                binding: SHADOW_MAP_BINDING,
+                resource: BindingResource::TextureView(&self.view),
+            },
+            BindGroupEntry {
+                binding: SHADOW_SAMPLER_BINDING,
+                resource: BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+
+    /**
+     * Compiles the depth-only pipeline for meshes with the key, if it is not cached.
+     * Pipelines of skinned meshes bind bone matrices with the skin layout.
+     */
+    pub fn compile_pipeline(&mut self, mesh_key: MeshKey, skin_layout: &BindGroupLayout, device: &Device) {
+        self.pipelines
+            .entry(mesh_key)
+            .or_insert_with(|| create_pipeline(mesh_key, skin_layout, device));
+    }
+
+    /**
+     * Uploads instances of the job, then draws them into the shadow map.
+     * Pipelines of every mesh in the job must have been compiled.
+     * Skinned batches are posed by the bone matrices of skin, which must have been prepared.
+     */
+    #[instrument(skip_all)]
+    pub fn submit(&mut self, job: ShadowJob, skin: &SkinBindings, encoder: &mut CommandEncoder, device: &Device, queue: &Queue) {
+        let instance_data: Vec<Mat4> = job.batches
+            .iter()
+            .flat_map(|batch| batch.transforms.iter().map(|transform| job.view_proj * *transform))
+            .collect();
+        let instance_bytes: &[u8] = bytemuck::cast_slice(&instance_data);
+        reserve_buffer(&mut self.instances, instance_bytes.len() as u64, device);
+        queue.write_buffer(&self.instances, 0, instance_bytes);
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("g3d_shadow_pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: &self.view,
+                depth_ops: Some(Operations { load: LoadOp::Clear(1.0), store: StoreOp::Store }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        let mut first_instance = 0;
+        for batch in &job.batches {
+            let num_instances = batch.transforms.len() as u32;
+            let pipeline = self.pipelines.get(&batch.mesh.key).unwrap();
+            pass.set_pipeline(pipeline);
+            if let Some(skin_offset) = batch.skin_offset {
+                pass.set_bind_group(0, skin.bind_group(), &[skin_offset]);
+            }
+            pass.set_vertex_buffer(0, self.instances.slice(..));
+            pass.set_vertex_buffer(1, batch.mesh.vertices.slice(..));
+            pass.set_index_buffer(batch.mesh.indices.slice(..), batch.mesh.index_format);
+            pass.draw_indexed(0..batch.mesh.num_indices, 0, first_instance..first_instance + num_instances);
+            first_instance += num_instances;
+        }
+    }
+}
+
+/// Meshes to draw into the shadow map for a single frame.
+pub(crate) struct ShadowJob<'a> {
+    pub view_proj: Mat4,
+    pub batches: Vec<ShadowBatch<'a>>,
+}
+
+/// Global transforms of every instance of a single mesh.
+pub(crate) struct ShadowBatch<'a> {
+    pub mesh: &'a Mesh,
+    pub transforms: Vec<Mat4>,
+    pub skin_offset: Option<u32>,   // Offset of the bone matrices of the batch's only instance, if its mesh is skinned
+}
+
+/**
+ * Groups instances by mesh, so that each mesh is drawn once.
+ * Skinned instances, which come with the offset of their bone matrices, get a batch each.
+ */
+pub(crate) fn shadow_batches<'a>(instances: impl Iterator<Item = (AssetId, &'a Mesh, Mat4, Option<u32>)>) -> Vec<ShadowBatch<'a>> {
+    let mut batch_indices: HashMap<AssetId, usize> = HashMap::new();
+    let mut batches: Vec<ShadowBatch> = Vec::new();
+    for (mesh_id, mesh, transform, skin_offset) in instances {
+        if skin_offset.is_some() {
+            batches.push(ShadowBatch { mesh, transforms: vec![transform], skin_offset });
+            continue;
+        }
+        let index = *batch_indices.entry(mesh_id).or_insert_with(|| {
+            batches.push(ShadowBatch { mesh, transforms: Vec::new(), skin_offset: None });
+            batches.len() - 1
+        });
+        batches[index].transforms.push(transform);
+    }
+    batches
+}
+
+/**
+ * Orthographic projection of the light, covering a cube centered on center.
+ * half_extent is half the width of the cube.
+ * Depth is in the 0..1 range, like the projections of cameras.
+ */
+pub(crate) fn light_view_proj(light: &DirectionalLight, center: Vec3) -> Mat4 {
+    let direction = light.direction.try_normalize().unwrap_or(Vec3::NEG_Y);
+    let half_extent = light.shadow_distance.max(0.001);
+    let up = match direction.abs().abs_diff_eq(Vec3::Y, 1e-4) {
+        true => Vec3::Z,
+        false => Vec3::Y,
+    };
+    let eye = center - direction * half_extent;
+    let view = Mat4::look_to_rh(eye, direction, up);
+    let proj = Mat4::orthographic_rh(-half_extent, half_extent, -half_extent, half_extent, 0.0, 2.0 * half_extent);
+    proj * view
+}
+
+fn create_pipeline(mesh_key: MeshKey, skin_layout: &BindGroupLayout, device: &Device) -> RenderPipeline {
+    let mut shader_defs = ShaderPreprocessor::new();
+    let mesh_layout = mesh_key.layout(&mut shader_defs);
+    let shader_code = shader_defs.preprocess(include_str!("shadow.wgsl")).expect("Shadow shader failed to preprocess");
+    let module = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("g3d_shadow_module"),
+        source: ShaderSource::Wgsl(shader_code.into()),
+    });
+    let bind_group_layouts = match mesh_key.contains(MeshKey::SKINNED) {
+        true => vec![skin_layout],
+        false => vec![],
+    };
+    let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("g3d_shadow_layout"),
+        bind_group_layouts: &bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("g3d_shadow_pipeline"),
+        layout: Some(&layout),
+        vertex: VertexState {
+            module: &module,
+            entry_point: "vertex_main",
+            buffers: &[INSTANCE_LAYOUT, mesh_layout.as_vertex_layout()],
+        },
+        fragment: None,
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: Some(DepthStencilState {
+            format: SHADOW_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: CompareFunction::LessEqual,
+            stencil: StencilState::default(),
+            bias: DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            },
+        }),
+        multisample: MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use glam::Vec3;
+    use crate::Color;
+    use crate::g3d::DirectionalLight;
+    use super::light_view_proj;
+
+    #[test]
+    fn light_view_proj_covers_area() {
+        let light = DirectionalLight::new(Vec3::NEG_Y, Color::WHITE, 1.0).with_shadow_distance(10.0);
+        let center = Vec3::new(5.0, 0.0, 5.0);
+        let view_proj = light_view_proj(&light, center);
+
+        // Center lands in the middle of the map, halfway through its depth
+        let ndc = view_proj.project_point3(center);
+        assert!(ndc.abs_diff_eq(Vec3::new(0.0, 0.0, 0.5), 1e-5));
+
+        // Points nearer the light have less depth
+        let above = view_proj.project_point3(center + Vec3::Y);
+        assert!(above.z < ndc.z);
+
+        // Corners of the area land on the edges of the map
+        let corner = view_proj.project_point3(center + Vec3::new(10.0, 0.0, 10.0));
+        assert!((corner.x.abs() - 1.0).abs() < 1e-5);
+        assert!((corner.y.abs() - 1.0).abs() < 1e-5);
+    }
+}
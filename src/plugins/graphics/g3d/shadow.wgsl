@@ -0,0 +1,48 @@
+struct InstanceIn {
+    @location(0) model_0: vec4<f32>,
+    @location(1) model_1: vec4<f32>,
+    @location(2) model_2: vec4<f32>,
+    @location(3) model_3: vec4<f32>,
+}
+
+struct VertexIn {
+    @location(4) position: vec3<f32>,
+    #ifdef SKINNED
+    @location(13) bone_indices: vec4<u32>,
+    @location(14) bone_weights: vec4<f32>,
+    #endif
+}
+
+#ifdef SKINNED
+const MAX_BONES: u32 = 256u;
+
+struct Bones {
+    matrices: array<mat4x4<f32>, MAX_BONES>,
+}
+
+@group(0) @binding(0)
+var<uniform> bones: Bones;
+
+// Blends the matrices of the bones that influence a vertex by their weights.
+fn skin_transform(indices: vec4<u32>, weights: vec4<f32>) -> mat4x4<f32> {
+    return bones.matrices[indices.x] * weights.x
+        + bones.matrices[indices.y] * weights.y
+        + bones.matrices[indices.z] * weights.z
+        + bones.matrices[indices.w] * weights.w;
+}
+#endif
+
+@vertex
+fn vertex_main(instance: InstanceIn, vert: VertexIn) -> @builtin(position) vec4<f32> {
+    let light_mvp = mat4x4<f32>(
+        instance.model_0,
+        instance.model_1,
+        instance.model_2,
+        instance.model_3,
+    );
+    var position = vec4<f32>(vert.position, 1.0);
+    #ifdef SKINNED
+    position = skin_transform(vert.bone_indices, vert.bone_weights) * position;
+    #endif
+    return light_mvp * position;
+}
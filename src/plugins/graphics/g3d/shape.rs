@@ -84,6 +84,9 @@ impl From<Cuboid> for MeshData {
                 20,21,22,22,23,20,
             ],
             uvs: Some(uvs),
+            tangents: None,
+            bone_indices: None,
+            bone_weights: None,
         }
     }
 }
\ No newline at end of file
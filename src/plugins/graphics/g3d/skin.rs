@@ -0,0 +1,105 @@
+use std::mem::size_of;
+use glam::Mat4;
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, Device, Queue, ShaderStages};
+use crate::reserve_buffer;
+
+/// Most bones a [`SkinnedMeshPose`] can have. Bone indices of a [`crate::g3d::MeshData`] must be less than this.
+pub const MAX_BONES: usize = 256;
+
+const POSE_SIZE: u64 = (MAX_BONES * size_of::<Mat4>()) as u64;
+
+/**
+ * Component that poses the skinned mesh of its entity.
+ * Each matrix transforms the vertices a bone influences from the mesh's bind pose, in mesh space.
+ * Bones without a matrix stay in the bind pose. Matrices past [`MAX_BONES`] are ignored.
+ */
+#[derive(Clone, Default, Debug)]
+pub struct SkinnedMeshPose {
+    pub matrices: Vec<Mat4>,
+}
+
+/**
+ * Bone matrices of every skinned instance rendered in a frame.
+ * Each instance has its own slot of [`MAX_BONES`] matrices, bound with a dynamic offset.
+ */
+pub(crate) struct SkinBindings {
+    layout: BindGroupLayout,
+    bone_matrices: Buffer,
+    bind_group: BindGroup,
+}
+
+impl SkinBindings {
+
+    pub fn new(device: &Device) -> Self {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("g3d_skin_layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: BufferSize::new(POSE_SIZE),
+                },
+                count: None,
+            }],
+        });
+        let bone_matrices = device.create_buffer(&BufferDescriptor {
+            label: Some("g3d_bone_matrices"),
+            size: POSE_SIZE,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = create_bind_group(&layout, &bone_matrices, device);
+        Self { layout, bone_matrices, bind_group }
+    }
+
+    pub fn layout(&self) -> &BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    /// Offset of the nth slot in the bone matrices buffer.
+    pub fn offset(slot: usize) -> u32 {
+        (slot as u64 * POSE_SIZE) as u32
+    }
+
+    /**
+     * Uploads the bone matrices of each pose to its own slot, padded with identity matrices.
+     * The nth pose is at [`SkinBindings::offset`] of n.
+     * Rebinds if the buffer had to grow.
+     */
+    pub fn prepare(&mut self, poses: &[&[Mat4]], device: &Device, queue: &Queue) {
+        if poses.is_empty() { return }
+        let mut bone_matrices = vec![Mat4::IDENTITY; poses.len() * MAX_BONES];
+        for (pose, slot) in poses.iter().zip(bone_matrices.chunks_exact_mut(MAX_BONES)) {
+            let bone_count = pose.len().min(MAX_BONES);
+            slot[..bone_count].copy_from_slice(&pose[..bone_count]);
+        }
+        let bone_bytes: &[u8] = bytemuck::cast_slice(&bone_matrices);
+        let buffer_size = self.bone_matrices.size();
+        reserve_buffer(&mut self.bone_matrices, bone_bytes.len() as u64, device);
+        if self.bone_matrices.size() != buffer_size {
+            self.bind_group = create_bind_group(&self.layout, &self.bone_matrices, device);
+        }
+        queue.write_buffer(&self.bone_matrices, 0, bone_bytes);
+    }
+}
+
+fn create_bind_group(layout: &BindGroupLayout, bone_matrices: &Buffer, device: &Device) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("g3d_skin_bind_group"),
+        layout,
+        entries: &[BindGroupEntry {
+            binding: 0,
+            resource: BindingResource::Buffer(BufferBinding {
+                buffer: bone_matrices,
+                offset: 0,
+                size: BufferSize::new(POSE_SIZE),
+            }),
+        }],
+    })
+}
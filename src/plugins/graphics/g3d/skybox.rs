@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+use glam::Mat4;
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState, Device, FragmentState, FrontFace, MultisampleState, PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, Queue, RenderPass, RenderPipeline, RenderPipelineDescriptor, SamplerBindingType, ShaderModule, ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState, TextureFormat, TextureSampleType, TextureViewDimension, VertexState};
+use crate::{reserve_buffer, AssetId, CubeMap};
+
+const SKY_SIZE: u64 = size_of::<Mat4>() as u64;
+
+/**
+ * Draws the [`CubeMap`] of a skybox behind everything else, with a fullscreen triangle at the far plane.
+ * Depth is tested, but not written, so that only pixels no mesh was drawn to are covered.
+ */
+pub(crate) struct SkyboxPass {
+    layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    module: ShaderModule,
+    pipelines: HashMap<SkyboxKey, RenderPipeline>,  // Cache of pipelines by target formats and sample count
+    skies: Buffer,                                  // Inverse view projection of each camera, one aligned slot per camera, bound with a dynamic offset
+    sky_stride: u64,                                // Distance between slots in skies
+    bind_group: Option<(AssetId, BindGroup)>,       // Bind group and the cube map bound to it
+}
+
+impl SkyboxPass {
+
+    pub fn new(device: &Device) -> Self {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("g3d_skybox_layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: BufferSize::new(SKY_SIZE),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("g3d_skybox_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("g3d_skybox_module"),
+            source: ShaderSource::Wgsl(include_str!("skybox.wgsl").into()),
+        });
+        let sky_stride = (device.limits().min_uniform_buffer_offset_alignment as u64).max(SKY_SIZE);
+        Self {
+            layout,
+            pipeline_layout,
+            module,
+            pipelines: HashMap::default(),
+            skies: device.create_buffer(&BufferDescriptor {
+                label: Some("g3d_skies"),
+                size: sky_stride,
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            sky_stride,
+            bind_group: None,
+        }
+    }
+
+    /**
+     * Uploads the inverse of each view projection, which should not include the camera's translation.
+     * Binds the cube map if it differs from the last one, or if the buffer had to grow.
+     * Returns the offset of each camera's slot.
+     */
+    pub fn prepare(&mut self, cube_map_id: AssetId, cube_map: &CubeMap, view_projs: &[Mat4], device: &Device, queue: &Queue) -> Vec<u32> {
+        let mut sky_bytes = vec![0; view_projs.len() * self.sky_stride as usize];
+        for (view_proj, slot) in view_projs.iter().zip(sky_bytes.chunks_exact_mut(self.sky_stride as usize)) {
+            let inverse_view_proj = view_proj.inverse();
+            slot[..SKY_SIZE as usize].copy_from_slice(bytemuck::bytes_of(&inverse_view_proj));
+        }
+        let skies_size = self.skies.size();
+        reserve_buffer(&mut self.skies, sky_bytes.len() as u64, device);
+        let is_bound = matches!(&self.bind_group, Some((bound_id, _)) if *bound_id == cube_map_id);
+        if !is_bound || self.skies.size() != skies_size {
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                label: Some("g3d_skybox_bind_group"),
+                layout: &self.layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer: &self.skies,
+                            offset: 0,
+                            size: BufferSize::new(SKY_SIZE),
+                        }),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&cube_map.view),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::Sampler(&cube_map.sampler),
+                    },
+                ],
+            });
+            self.bind_group = Some((cube_map_id, bind_group));
+        }
+        queue.write_buffer(&self.skies, 0, &sky_bytes);
+        (0..view_projs.len()).map(|i| (i as u64 * self.sky_stride) as u32).collect()
+    }
+
+    /// Compiles the pipeline for the key, if it is not cached.
+    pub fn compile_pipeline(&mut self, key: SkyboxKey, device: &Device) {
+        if self.pipelines.contains_key(&key) { return }
+        let pipeline = create_pipeline(key, &self.pipeline_layout, &self.module, device);
+        self.pipelines.insert(key, pipeline);
+    }
+
+    /// Draws the skybox of a single camera.
+    /// The pipeline of the draw must have been compiled, and the skybox prepared.
+    pub fn draw<'r>(&'r self, draw: SkyboxDraw, pass: &mut RenderPass<'r>) {
+        let Some((_, bind_group)) = &self.bind_group else { return };
+        let pipeline = self.pipelines.get(&draw.key).unwrap();
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[draw.offset]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+/// Used to select a skybox pipeline from a cache.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub(crate) struct SkyboxKey {
+    pub texture_format: TextureFormat,
+    pub depth_format: TextureFormat,
+    pub sample_count: u32,
+}
+
+/// Skybox of a single camera, drawn in its render pass.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SkyboxDraw {
+    pub key: SkyboxKey,
+    pub offset: u32,        // Offset of the camera's slot in the skies buffer
+}
+
+fn create_pipeline(key: SkyboxKey, layout: &PipelineLayout, module: &ShaderModule, device: &Device) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("g3d_skybox_pipeline"),
+        layout: Some(layout),
+        vertex: VertexState {
+            module,
+            entry_point: "vertex_main",
+            buffers: &[],
+        },
+        fragment: Some(FragmentState {
+            module,
+            entry_point: "fragment_main",
+            targets: &[Some(ColorTargetState {
+                format: key.texture_format,
+                blend: None,
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: Some(DepthStencilState {
+            format: key.depth_format,
+            depth_write_enabled: false,
+            depth_compare: CompareFunction::LessEqual,
+            stencil: StencilState::default(),
+            bias: DepthBiasState::default(),
+        }),
+        multisample: MultisampleState {
+            count: key.sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
@@ -0,0 +1,30 @@
+struct Sky {
+    inverse_view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> sky: Sky;
+@group(0) @binding(1)
+var sky_tex: texture_cube<f32>;
+@group(0) @binding(2)
+var sky_sam: sampler;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+// Fullscreen triangle at the far plane, so that it is hidden behind anything drawn before it.
+@vertex
+fn vertex_main(@builtin(vertex_index) index: u32) -> VertexOut {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = uv * 2.0 - 1.0;
+    return VertexOut(vec4<f32>(ndc, 1.0, 1.0), ndc);
+}
+
+@fragment
+fn fragment_main(in: VertexOut) -> @location(0) vec4<f32> {
+    let point = sky.inverse_view_proj * vec4<f32>(in.ndc, 0.5, 1.0);
+    let direction = point.xyz / point.w;
+    return vec4<f32>(textureSample(sky_tex, sky_sam, direction).rgb, 1.0);
+}
@@ -0,0 +1,50 @@
+use hecs::{Entity, World};
+use crate::g3d::Renderable;
+use crate::math::Transform;
+use crate::{GltfScene, NodeId, Scene};
+
+/**
+ * Spawns an entity for each node of a loaded [`GltfScene`], with the node's [`Transform`] and a tracked [`Renderable`].
+ * Renderables of child nodes are inserted under those of their parents, so that their transforms stay relative to them.
+ * Nodes with several primitives get a child entity per primitive, and nodes without any get an empty renderable.
+ * Returns the entities spawned, parents before their children.
+ * Spawns nothing if the scene's assets have not been inserted yet.
+ */
+pub fn spawn_gltf(scene: &GltfScene, scene_graph: &mut Scene<Renderable>, world: &mut World) -> Vec<Entity> {
+    let mut entities = Vec::new();
+    let mut to_spawn: Vec<(usize, Option<NodeId>)> = scene.roots.iter().rev().map(|root| (*root, None)).collect();
+    while let Some((node_idx, parent_id)) = to_spawn.pop() {
+        let Some(node) = scene.nodes.get(node_idx) else { continue };
+        let renderable = match node.primitives.as_slice() {
+            [(mesh, material)] => Renderable::mat_mesh(material.clone(), mesh.clone()),
+            _ => Renderable::empty(),
+        };
+        let (entity, node_id) = spawn_node(renderable, node.transform, parent_id, scene_graph, world);
+        entities.push(entity);
+        if node.primitives.len() > 1 {
+            for (mesh, material) in &node.primitives {
+                let renderable = Renderable::mat_mesh(material.clone(), mesh.clone());
+                let (entity, _) = spawn_node(renderable, Transform::default(), Some(node_id), scene_graph, world);
+                entities.push(entity);
+            }
+        }
+        to_spawn.extend(node.children.iter().rev().map(|child_idx| (*child_idx, Some(node_id))));
+    }
+    entities
+}
+
+fn spawn_node(
+    mut renderable: Renderable,
+    transform: Transform,
+    parent_id: Option<NodeId>,
+    scene_graph: &mut Scene<Renderable>,
+    world: &mut World,
+) -> (Entity, NodeId) {
+    renderable.set_transform(transform);
+    let tracker = match parent_id {
+        Some(parent_id) => scene_graph.insert_child(renderable, parent_id).unwrap(),
+        None => scene_graph.insert(renderable),
+    };
+    let node_id = tracker.id();
+    (world.spawn((transform, tracker)), node_id)
+}
@@ -1,71 +1,280 @@
-use hecs::World;
+use glam::Vec2;
+use hecs::{Entity, World};
+use rayon::prelude::*;
 use tracing::instrument;
-use wgpu::{Color as WgpuColor, CommandEncoderDescriptor, Device, LoadOp, Operations, RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor, StoreOp, SurfaceTexture};
+use wgpu::{CommandEncoderDescriptor, SurfaceTexture};
 use crate::g3d::{Material, Mesh};
 use crate::math::Transform;
-use crate::{g3d, AppBuilder, AssetManager, AssetStorage, Camera, Game, GraphicsState, Plugin, RunContext, Scene, SceneGraph, Stage, Texture, TextureLoader, Tracker};
+use crate::{g2d, g3d, AppBuilder, Children, AssetManager, AssetStorage, Camera, Color, CubeMap, CubeMapFace, CubeMapFaceLoader, CubeMapLoader, Game, GlobalTransform, GraphicsState, Plugin, RenderTargetCamera, RenderTexture, RunContext, Scene, SceneGraph, Stage, Texture, TextureLoader, TextureLoaderConfig, Tracker};
 
 
 /// Adds primitive [`GraphicsState`].
 /// Adds a 2D and 3D graphics engine.
-pub struct GraphicsPlugin;
+#[derive(Default)]
+pub struct GraphicsPlugin {
+    /// If true, meshes with normals are lit by [`g3d::PointLight`]s and [`g3d::SpotLight`]s,
+    /// and by the [`g3d::DirectionalLight`] and [`g3d::AmbientLight`] domains if present.
+    pub lighting: bool,
+    /// How the 3D engine issues its draw calls.
+    pub gpu_driven_mode: g3d::GpuDrivenMode,
+    /// If set, the [`g3d::DirectionalLight`] casts shadows rendered to a shadow map of this size.
+    /// The shadow map is owned by the 3D engine. Only has an effect when lighting is enabled.
+    pub shadow_map_size: Option<u32>,
+    /// If set, the maximum number of pipelines the 3D engine caches, and the number of frames one must go unused before it can be evicted.
+    /// Otherwise, [`g3d::DEFAULT_MAX_PIPELINES`] and [`g3d::DEFAULT_PIPELINE_IDLE_FRAMES`].
+    pub pipeline_eviction: Option<(usize, u64)>,
+    /// If set, overrides the number of samples per pixel [`GraphicsState`] was created with. 1 disables MSAA.
+    pub msaa_samples: Option<u32>,
+    /// How textures are loaded.
+    pub texture_loader_config: TextureLoaderConfig,
+}
+
+impl GraphicsPlugin {
+    pub fn with_lighting(mut self, lighting: bool) -> Self {
+        self.lighting = lighting;
+        self
+    }
+
+    pub fn with_gpu_driven_mode(mut self, gpu_driven_mode: g3d::GpuDrivenMode) -> Self {
+        self.gpu_driven_mode = gpu_driven_mode;
+        self
+    }
+
+    pub fn with_shadows(mut self, shadow_map_size: u32) -> Self {
+        self.shadow_map_size = Some(shadow_map_size);
+        self
+    }
+
+    pub fn with_pipeline_eviction(mut self, max_pipelines: usize, idle_frames: u64) -> Self {
+        self.pipeline_eviction = Some((max_pipelines, idle_frames));
+        self
+    }
+
+    pub fn with_msaa(mut self, samples: u32) -> Self {
+        self.msaa_samples = Some(samples);
+        self
+    }
+
+    pub fn with_texture_loader_config(mut self, texture_loader_config: TextureLoaderConfig) -> Self {
+        self.texture_loader_config = texture_loader_config;
+        self
+    }
+}
+
 impl Plugin for GraphicsPlugin {
     fn install(&mut self, builder: &mut AppBuilder) {
+        builder.system(Stage::Asset, precompile_pipelines);
+        builder.system(Stage::Asset, insert_obj_objects);
+        builder.system(Stage::Asset, insert_cube_map_faces);
+        builder.system(Stage::PreRender, sync_graphics);
+        builder.system(Stage::PreRender, prepare_materials);
         builder.system(Stage::Render, render_3d);
+        builder.event_handler(clear_pipelines);
+        #[cfg(feature = "debug-gizmos")]
+        builder
+            .system_with_priority(Stage::PreUpdate, begin_gizmo_tick, i32::MIN)
+            .system_with_priority(Stage::Cleanup, end_gizmo_tick, i32::MAX)
+            .system_with_priority(Stage::Render, end_gizmo_frame, i32::MAX);
         let game = builder.game();
+        assert!(game.contains::<GraphicsState>(), "WindowPlugin must be installed before GraphicsPlugin");
+        assert!(game.contains::<AssetManager>(), "AssetPlugin must be installed before GraphicsPlugin");
         game.add(Scene::<g3d::Renderable>::new());
+        game.add(Scene::<g2d::Sprite>::new());
+        game.add(Scene::<g2d::Text>::new());
+        game.add(g3d::CullingStats::default());
+        game.add(g3d::PipelinePrecompiler::default());
+        game.add(g3d::Gizmos::default());
+        game.init(|_| ClearColor::default());
         let (device, queue) = {
-            let state = game.get::<&GraphicsState>();
+            let mut state = game.get::<&mut GraphicsState>();
+            log::info!("Rendering with {}", state.rendering_api_info());
+            if let Some(msaa_samples) = self.msaa_samples {
+                state.set_sample_count(msaa_samples);
+            }
             (state.device.clone(), state.queue.clone())
         };
-        game.add(g3d::G3D::new(device.clone(), queue.clone()));
+        let mut g3d = g3d::G3D::new(device.clone(), queue.clone(), self.lighting)
+            .with_gpu_driven_mode(self.gpu_driven_mode);
+        if let Some(shadow_map_size) = self.shadow_map_size {
+            g3d = g3d.with_shadows(shadow_map_size);
+        }
+        if let Some((max_pipelines, idle_frames)) = self.pipeline_eviction {
+            g3d = g3d.with_pipeline_eviction(max_pipelines, idle_frames);
+        }
+        game.add(g3d);
+        game.add(g2d::G2D::new(device.clone(), queue.clone()));
         let mut assets = game.get::<&mut AssetManager>();
-        assets.add_loader(TextureLoader { device, queue, }).unwrap();
+        assets.add_loader(TextureLoader::new(device.clone(), queue.clone()).with_config(self.texture_loader_config)).unwrap();
+        assets.add_loader(CubeMapLoader { device: device.clone(), queue, }).unwrap();
+        assets.add_loader(CubeMapFaceLoader).unwrap();
+        assets.add_loader(g3d::ObjLoader { device: device.clone() }).unwrap();
+        assets.add_loader(g3d::ObjSceneLoader { device }).unwrap();
+        assets.add_loader(g3d::MtlLoader).unwrap();
+        assets.add_loader(g2d::FontLoader).unwrap();
+        assets.add_storage::<g3d::ObjScene>();
+        assets.add_storage::<g3d::ObjMaterials>();
+        assets.add_storage::<CubeMap>();
+        assets.add_storage::<CubeMapFace>();
+        assets.add_storage::<RenderTexture>();
+        assets.add_storage::<g2d::Font>();
     }
 }
 
+fn sync_graphics(game: &mut Game, ctx: RunContext) {
+    if !ctx.is_tick() { return }
+    let mut world = game.get::<&mut World>();
+    let mut g3d_scene = game.get::<&mut Scene<g3d::Renderable>>();
+    sync_renderables(&mut world, &mut g3d_scene.graph);
+    let mut g2d_scene = game.get::<&mut Scene<g2d::Sprite>>();
+    sync_sprites(&mut world, &mut g2d_scene.graph);
+    let mut g2d_texts = game.get::<&mut Scene<g2d::Text>>();
+    sync_texts(&mut world, &mut g2d_texts.graph);
+}
+
 #[instrument(skip_all)]
-fn sync_graphics(world: &mut World, g3d_scene: &mut SceneGraph<g3d::Renderable>) {
+fn sync_renderables(world: &mut World, g3d_scene: &mut SceneGraph<g3d::Renderable>) {
     
-    // Syncs transforms
-    let renderable_query = world.query_mut::<(&Transform, &Tracker<g3d::Renderable>)>();
-    rayon::scope(|s| {
-        for batch in renderable_query.into_iter_batched(10000) {
-            s.spawn(|_| {
-                for (_, (transform, tracker)) in batch {
-                    let renderable = unsafe {
-                        g3d_scene.get_mut_unsafe(tracker.id())
-                    };
-                    let Some(renderable) = renderable else { continue };
-                    renderable.set_transform(*transform);
+    // Skips interpolation of teleported renderables, and of those attached under them.
+    // Renderables that never interpolate keep their mode.
+    let mut teleported: Vec<Entity> = world.query_mut::<()>().with::<&TeleportFlag>().into_iter().map(|(entity, _)| entity).collect();
+    for entity in &teleported {
+        let _ = world.remove_one::<TeleportFlag>(*entity);
+    }
+    while let Some(entity) = teleported.pop() {
+        if let Ok(tracker) = world.get::<&Tracker<g3d::Renderable>>(entity) {
+            if let Some(renderable) = g3d_scene.get_mut(tracker.id()) {
+                if renderable.interpolation_mode == InterpolationMode::Interpolate {
+                    renderable.interpolation_mode = InterpolationMode::Skip;
                 }
-            });
+            }
+        }
+        if let Ok(children) = world.get::<&Children>(entity) {
+            teleported.extend(&children.0);
+        }
+    }
+
+    // Syncs transforms
+    let renderable_batches: Vec<_> = world
+        .query_mut::<(&Transform, Option<&GlobalTransform>, &Tracker<g3d::Renderable>)>()
+        .into_iter_batched(10000)
+        .collect();
+    let transforms = renderable_batches
+        .into_par_iter()
+        .flat_map_iter(|batch| batch.map(|(_, (transform, global, tracker))| (tracker.id(), world_transform(transform, global))));
+    g3d_scene.par_update(transforms, |renderable, transform| {
+
+        // Skips renderables that are at rest, so they stay clean in the scene graph.
+        if renderable.transform() == *transform && !renderable.is_interpolating() {
+            return false;
         }
+        renderable.set_transform(*transform);
+        true
     });
 
-    // Syncs cameras
-    let camera_query = world.query_mut::<(&Camera, &Tracker<g3d::Renderable>)>();
-    for (_, (camera, tracker)) in camera_query {
+    // Syncs poses of skinned meshes
+    for (_, (pose, tracker)) in world.query_mut::<(&g3d::SkinnedMeshPose, &Tracker<g3d::Renderable>)>() {
+        let Some(renderable) = g3d_scene.get(tracker.id()) else { continue };
+        if renderable.pose() == pose.matrices.as_slice() { continue }
+        let Some(renderable) = g3d_scene.get_mut(tracker.id()) else { continue };
+        renderable.set_pose(&pose.matrices);
+    }
+
+    // Syncs levels of detail
+    for (_, (lod, tracker)) in world.query_mut::<(&g3d::MeshLod, &Tracker<g3d::Renderable>)>() {
+        let Some(renderable) = g3d_scene.get(tracker.id()) else { continue };
+        if renderable.lod.as_ref().is_some_and(|synced| synced.has_same_levels(lod)) { continue }
+        let Some(renderable) = g3d_scene.get_mut(tracker.id()) else { continue };
+        renderable.lod = Some(lod.clone());
+    }
+
+    // Syncs cameras, and routes those with a render target to it
+    let camera_query = world.query_mut::<(&Camera, &Tracker<g3d::Renderable>, Option<&RenderTargetCamera>)>();
+    for (_, (camera, tracker, render_target)) in camera_query {
         let Some(renderable) = g3d_scene.get_mut(tracker.id()) else { continue };
         let Some(render_cam) = renderable.kind.as_camera_mut() else { continue };
         render_cam.viewport = camera.viewport;
+        render_cam.clear = camera.clear;
+        render_cam.layer_mask = camera.layer_mask;
+        render_cam.order = camera.order;
+        render_cam.enabled = camera.enabled;
         render_cam.set_projection(camera.projection);
+        match (&render_cam.target, render_target) {
+            (g3d::CameraTarget::OffScreen(handle), Some(RenderTargetCamera(target))) if handle.id() == target.id() => {},
+            (_, Some(RenderTargetCamera(target))) => render_cam.target = g3d::CameraTarget::OffScreen(target.clone()),
+            (_, None) => render_cam.target = g3d::CameraTarget::OnScreen,
+        }
+    }
+}
+
+#[instrument(skip_all)]
+fn sync_sprites(world: &mut World, g2d_scene: &mut SceneGraph<g2d::Sprite>) {
+    let sprite_batches: Vec<_> = world
+        .query_mut::<(&Transform, Option<&GlobalTransform>, &Tracker<g2d::Sprite>)>()
+        .into_iter_batched(10000)
+        .collect();
+    let transforms = sprite_batches
+        .into_par_iter()
+        .flat_map_iter(|batch| batch.map(|(_, (transform, global, tracker))| (tracker.id(), world_transform(transform, global))));
+    g2d_scene.par_update(transforms, |sprite, transform| {
+        if sprite.transform() == *transform { return false }
+        sprite.set_transform(*transform);
+        true
+    });
+}
+
+#[instrument(skip_all)]
+fn sync_texts(world: &mut World, g2d_texts: &mut SceneGraph<g2d::Text>) {
+    for (_, (transform, global, tracker)) in world.query_mut::<(&Transform, Option<&GlobalTransform>, &Tracker<g2d::Text>)>() {
+        let Some(text) = g2d_texts.get(tracker.id()) else { continue };
+        let transform = world_transform(transform, global);
+        if text.transform() == *transform { continue }
+        let Some(text) = g2d_texts.get_mut(tracker.id()) else { continue };
+        text.set_transform(*transform);
+    }
+}
+
+/// World transform of an entity, which is its transform unless it is part of a hierarchy.
+fn world_transform<'a>(transform: &'a Transform, global: Option<&'a GlobalTransform>) -> &'a Transform {
+    global.map_or(transform, |global| &global.0)
+}
+
+fn insert_obj_objects(game: &mut Game, _ctx: RunContext) {
+    let mut assets = game.get::<&mut AssetManager>();
+    g3d::insert_obj_objects(&mut assets);
+}
+
+fn insert_cube_map_faces(game: &mut Game, _ctx: RunContext) {
+    let graphics_state = game.get::<&GraphicsState>();
+    let mut assets = game.get::<&mut AssetManager>();
+    super::cube_map::insert_cube_map_faces(&mut assets, &graphics_state.device, &graphics_state.queue);
+}
+
+fn precompile_pipelines(game: &mut Game, _ctx: RunContext) {
+    let Some(graphics_state) = game.try_get::<&GraphicsState>() else { return };
+    let mut precompiler = game.get::<&mut g3d::PipelinePrecompiler>();
+    let mut g3d = game.get::<&mut g3d::G3D>();
+    let texture_format = graphics_state.format();
+    let depth_format = graphics_state.depth_format();
+    let sample_count = graphics_state.sample_count();
+    for (mesh_keys, material_keys) in precompiler.drain() {
+        let compiled = g3d.precompile_pipelines(&mesh_keys, &material_keys, texture_format, depth_format, sample_count);
+        log::debug!("Precompiled {compiled} pipeline(s), {} cached in total", g3d.pipeline_count());
     }
 }
 
 fn render_3d(game: &mut Game, ctx: RunContext) {
 
+    let Some(graphics_state) = game.try_get::<&GraphicsState>() else { return };
     let mut world           = game.get::<&mut World>();
-    let graphics_state      = game.get::<&GraphicsState>();
     let mut g3d             = game.get::<&mut g3d::G3D>();
     let mut g3d_scene       = game.get::<&mut Scene<g3d::Renderable>>();
+    let mut g2d             = game.get::<&mut g2d::G2D>();
+    let mut g2d_scene       = game.get::<&mut Scene<g2d::Sprite>>();
+    let mut g2d_texts       = game.get::<&mut Scene<g2d::Text>>();
     let assets              = game.get::<&AssetManager>();
+    let gizmos              = game.get::<&g3d::Gizmos>();
+    let clear_color         = game.get::<&ClearColor>().0;
 
-    if ctx.is_tick() {
-        let g3d_scene = &mut g3d_scene.graph;
-        sync_graphics(&mut world, g3d_scene);
-    }
-    
     let surface_tex = match graphics_state.surface().get_current_texture() {
         Ok(surface_tex) => surface_tex,
         Err(err) => {
@@ -74,78 +283,133 @@ fn render_3d(game: &mut Game, ctx: RunContext) {
         }
     };
 
-    let textures = assets.storage::<Texture>().unwrap();
     let meshes = assets.storage::<Mesh>().unwrap();
-    let mut materials = assets.storage::<Material>().unwrap();
+    let materials = assets.storage::<Material>().unwrap();
+    let textures = assets.storage::<Texture>().unwrap();
+    let cube_maps = assets.storage::<CubeMap>().unwrap();
+    let render_textures = assets.storage::<RenderTexture>().unwrap();
+    let fonts = assets.storage::<g2d::Font>().unwrap();
 
-    prepare_materials(&mut materials, &textures, &graphics_state.device);
-    enqueue_render(&graphics_state, &mut g3d_scene, &mut g3d, &surface_tex, ctx.partial_ticks(), &materials, &meshes);
+    // Removes nodes that are no longer tracked
+    g3d_scene.prune_nodes();
+    g2d_scene.prune_nodes();
+    g2d_texts.prune_nodes();
+
+    // Flattens scene, and collects lights from both the scene and the world
+    let mut flat_scene = g3d::flatten_scene(&mut g3d_scene, ctx.partial_ticks());
+    flat_scene.point_lights.extend(collect_point_lights(&mut world));
+    flat_scene.spot_lights.extend(collect_spot_lights(&mut world));
+    flat_scene.ambient_light = game.try_get::<&g3d::AmbientLight>().map(|light| *light);
+    flat_scene.directional_light = game.try_get::<&g3d::DirectionalLight>().map(|light| *light);
+    flat_scene.resolve_skybox(&cube_maps);
+    flat_scene.resolve_render_targets(&render_textures);
+    flat_scene.gizmos = Some(&gizmos);
+
+    let render_ctx = RenderContext {
+        g2d_scene: g2d::G2DScene {
+            sprites: &mut g2d_scene,
+            texts: &mut g2d_texts,
+            textures: &textures,
+            fonts: &fonts,
+        },
+        materials: &materials,
+        meshes: &meshes,
+    };
+    enqueue_render(&graphics_state, flat_scene, &mut g3d, &mut g2d, &surface_tex, clear_color, render_ctx);
+    *game.get::<&mut g3d::CullingStats>() = g3d.culling_stats();
     surface_tex.present();
 }
 
-fn prepare_materials(
-    materials: &mut AssetStorage<Material>,
-    textures: &AssetStorage<Texture>,
-    device: &Device,
-) {
+fn collect_point_lights(world: &mut World) -> Vec<g3d::FlatPointLight> {
+    world
+        .query_mut::<&g3d::PointLight>()
+        .into_iter()
+        .map(|(_, light)| g3d::FlatPointLight::from(*light))
+        .collect()
+}
+
+fn collect_spot_lights(world: &mut World) -> Vec<g3d::FlatSpotLight> {
+    world
+        .query_mut::<&g3d::SpotLight>()
+        .into_iter()
+        .map(|(_, light)| g3d::FlatSpotLight::from(*light))
+        .collect()
+}
+
+/// Forgets the gizmos of the last tick before any system of this one runs.
+#[cfg(feature = "debug-gizmos")]
+fn begin_gizmo_tick(game: &mut Game, _ctx: RunContext) {
+    game.get::<&mut g3d::Gizmos>().begin_tick();
+}
+
+/// Gizmos added after every system of a tick ran are only drawn for the current frame.
+#[cfg(feature = "debug-gizmos")]
+fn end_gizmo_tick(game: &mut Game, _ctx: RunContext) {
+    game.get::<&mut g3d::Gizmos>().end_tick();
+}
+
+/// Forgets the gizmos added outside of ticks once the frame is rendered.
+#[cfg(feature = "debug-gizmos")]
+fn end_gizmo_frame(game: &mut Game, _ctx: RunContext) {
+    game.get::<&mut g3d::Gizmos>().end_frame();
+}
+
+fn prepare_materials(game: &mut Game, _ctx: RunContext) {
+    let Some(graphics_state) = game.try_get::<&GraphicsState>() else { return };
+    let assets = game.get::<&AssetManager>();
+    let textures = assets.storage::<Texture>().unwrap();
+    let mut materials = assets.storage::<Material>().unwrap();
     for material in materials.values_mut() {
         let Some(material) = material.as_loaded_mut() else { continue };
-        material.prepare(textures, device);
+        material.prepare(&textures, &graphics_state.device);
     }
 }
 
+/// Scenes and asset storages that a frame is rendered from, besides the flattened 3D scene.
+struct RenderContext<'a> {
+    g2d_scene: g2d::G2DScene<'a>,
+    materials: &'a AssetStorage<'a, Material>,
+    meshes: &'a AssetStorage<'a, Mesh>,
+}
+
 #[instrument(skip_all)]
 fn enqueue_render(
     graphics_state: &GraphicsState,
-    g3d_scene: &mut Scene<g3d::Renderable>,
+    flat_scene: g3d::FlatScene,
     g3d: &mut g3d::G3D,
+    g2d: &mut g2d::G2D,
     surface_tex: &SurfaceTexture,
-    partial_ticks: f32,
-    materials: &AssetStorage<Material>,
-    meshes: &AssetStorage<Mesh>,
+    clear_color: Color,
+    render_ctx: RenderContext,
 ) {
     let texture_format = graphics_state.format();
     let depth_format = graphics_state.depth_format();
     let depth_view = graphics_state.depth_view();
+    let sample_count = graphics_state.sample_count();
 
-    // Removes nodes that are no longer tracked
-    g3d_scene.prune_nodes();
+    // Renders to the multisampled texture and resolves to the surface when MSAA is enabled
+    let view = surface_tex.texture.create_view(&Default::default());
+    let (color_view, resolve_view) = match graphics_state.msaa_view() {
+        Some(msaa_view) => (msaa_view, Some(&view)),
+        None => (&view, None),
+    };
 
     // Traverses scene and encodes commands
-    let view = surface_tex.texture.create_view(&Default::default());
     let mut encoder = graphics_state.device.create_command_encoder(&CommandEncoderDescriptor::default());
     {
-        // Flattens scene, and creates render jobs
-        let flat_scene = g3d::flatten_scene(&g3d_scene, partial_ticks);
-        let g3d_jobs = g3d.create_jobs(flat_scene, texture_format, depth_format, &materials, &meshes);
-
-        // Creates render pass
-        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
-            label: None,
-            color_attachments: &[
-                Some(RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: Operations {
-                        load: LoadOp::Clear(WgpuColor::GREEN),
-                        store: StoreOp::Store,
-                    },
-                })
-            ],
-            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                view: depth_view,
-                depth_ops: Some(Operations {
-                    load: LoadOp::Clear(1.0),
-                    store: StoreOp::Store,
-                }),
-                stencil_ops: None,
-            }),
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
-
-        // Submits render jobs
-        g3d.submit_jobs(g3d_jobs, &mut pass);
+        // Creates render jobs, and submits them with one render pass per camera
+        let g3d_jobs = g3d.create_jobs(flat_scene, texture_format, depth_format, sample_count, render_ctx.materials, render_ctx.meshes);
+        g3d.submit_jobs(g3d_jobs, &mut encoder, color_view, resolve_view, depth_view, clear_color);
+
+        // Draws sprites and text over the 3D scene, in pixel space
+        let g2d_target = g2d::G2DTarget {
+            color_view,
+            resolve_view,
+            format: texture_format,
+            sample_count,
+            size: Vec2::new(surface_tex.texture.width() as f32, surface_tex.texture.height() as f32),
+        };
+        g2d.render(render_ctx.g2d_scene, g2d_target, &mut encoder);
     }
 
     // Submits render commands
@@ -153,6 +417,42 @@ fn enqueue_render(
     graphics_state.queue.submit(commands);
 }
 
+/// Handles [`ClearPipelines`] by dropping the pipelines cached by [`g3d::G3D`].
+fn clear_pipelines(game: &mut Game, _event: &ClearPipelines, _ctx: &mut RunContext) {
+    game.get::<&mut g3d::G3D>().clear_pipelines();
+    log::debug!("Cleared 3D pipelines");
+}
+
+/**
+ * Event that drops the render pipelines cached by [`g3d::G3D`], so that they compile again the next time they are rendered.
+ * Useful after shader sources change, or to retry pipelines that failed to compile.
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub struct ClearPipelines;
+
+/**
+ * Domain storing the color that the screen is cleared with, unless the first camera that renders to it has its own clear color.
+ * The screen is cleared with it as well when no enabled camera renders to it.
+ * Read every frame, so changes take effect on the next frame.
+ */
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ClearColor(pub Color);
+
+impl Default for ClearColor {
+    fn default() -> Self {
+        Self(Color::BLACK)
+    }
+}
+
+/**
+ * Component that marks an entity as teleported this tick, so that its renderable jumps to its new transform instead of moving there.
+ * Sets the [`InterpolationMode`] of the renderable tracked by the entity, and by those attached under it, to [`InterpolationMode::Skip`].
+ * Renderables in [`InterpolationMode::None`] are left as they are.
+ * Removed once graphics are synced.
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub struct TeleportFlag;
+
 /// Determines how
 #[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
 pub enum InterpolationMode {
@@ -167,4 +467,52 @@ pub enum InterpolationMode {
     /// Graphics will be shown at current location only.
     /// Good for consistency, but looks choppy if frame rate is higher than tick rate.
     None,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use hecs::World;
+    use crate::math::Transform;
+    use crate::{g3d, HierarchyExt, InterpolationMode, Scene, TeleportFlag};
+    use super::sync_renderables;
+
+    #[test]
+    fn teleport_skips_interpolation() {
+        let mut world = World::new();
+        let mut scene = Scene::<g3d::Renderable>::new();
+        let tank_id = scene.insert(g3d::Renderable::empty().with_interpolation_mode(InterpolationMode::Interpolate));
+        let turret_id = scene.insert(g3d::Renderable::empty().with_interpolation_mode(InterpolationMode::Interpolate));
+        let (tank_node, turret_node) = (tank_id.id(), turret_id.id());
+        let tank = world.spawn((tank_id, Transform::default()));
+        let turret = world.spawn((turret_id, Transform::default()));
+        world.attach(turret, tank).unwrap();
+        sync_renderables(&mut world, &mut scene.graph);
+
+        // Renderables interpolate towards new transforms.
+        world.get::<&mut Transform>(tank).unwrap().translation.x = 1.0;
+        world.get::<&mut Transform>(turret).unwrap().translation.x = 1.0;
+        sync_renderables(&mut world, &mut scene.graph);
+        assert!(scene.get(tank_node).unwrap().is_interpolating());
+
+        // Teleported renderables, and those attached under them, jump to them.
+        world.get::<&mut Transform>(tank).unwrap().translation.x = 100.0;
+        world.get::<&mut Transform>(turret).unwrap().translation.x = 100.0;
+        world.insert_one(tank, TeleportFlag).unwrap();
+        sync_renderables(&mut world, &mut scene.graph);
+        assert!(!scene.get(tank_node).unwrap().is_interpolating());
+        assert!(!scene.get(turret_node).unwrap().is_interpolating());
+        assert!(world.get::<&TeleportFlag>(tank).is_err());
+    }
+
+    #[test]
+    fn teleport_keeps_renderables_that_never_interpolate() {
+        let mut world = World::new();
+        let mut scene = Scene::<g3d::Renderable>::new();
+        let tracker = scene.insert(g3d::Renderable::empty().with_interpolation_mode(InterpolationMode::None));
+        let node = tracker.id();
+        let entity = world.spawn((tracker, Transform::default(), TeleportFlag));
+        sync_renderables(&mut world, &mut scene.graph);
+        assert_eq!(InterpolationMode::None, scene.get(node).unwrap().interpolation_mode);
+        assert!(world.get::<&TeleportFlag>(entity).is_err());
+    }
+}
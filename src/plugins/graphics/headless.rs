@@ -0,0 +1,85 @@
+//! Helpers for tests that render without a window.
+//! Tests return early when no adapter is available, so that they pass on machines without a GPU or software renderer.
+
+use std::sync::Arc;
+use glam::UVec2;
+use wgpu::{Backends, Buffer, BufferDescriptor, BufferUsages, Device, DeviceDescriptor, Extent3d, ImageCopyBuffer, ImageDataLayout, Instance, InstanceDescriptor, Maintain, MapMode, Queue, RequestAdapterOptions, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages};
+
+pub const COLOR_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// Device and queue of the first adapter found, whose validation errors panic.
+/// None if there is no adapter.
+pub fn device() -> Option<(Arc<Device>, Arc<Queue>)> {
+    let instance = Instance::new(InstanceDescriptor { backends: Backends::all(), ..Default::default() });
+    let Some(adapter) = pollster::block_on(instance.request_adapter(&RequestAdapterOptions::default())) else {
+        eprintln!("No adapter found, skipping");
+        return None;
+    };
+    let (device, queue) = pollster::block_on(adapter.request_device(&DeviceDescriptor::default(), None)).ok()?;
+    device.on_uncaptured_error(Box::new(|err| panic!("{err}")));
+    Some((Arc::new(device), Arc::new(queue)))
+}
+
+/// Color and depth textures to render to, in [`COLOR_FORMAT`] and [`DEPTH_FORMAT`].
+/// The color texture can be read with [`read_pixels`].
+pub fn targets(size: UVec2, device: &Device) -> (Texture, Texture) {
+    let extent = Extent3d { width: size.x, height: size.y, depth_or_array_layers: 1 };
+    let texture = |format, usage| device.create_texture(&TextureDescriptor {
+        label: None,
+        size: extent,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+    });
+    let color = texture(COLOR_FORMAT, TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC);
+    let depth = texture(DEPTH_FORMAT, TextureUsages::RENDER_ATTACHMENT);
+    (color, depth)
+}
+
+/// Rows of RGBA pixels of the first mip level of a [`COLOR_FORMAT`] texture, whose width is a multiple of 64.
+pub fn read_pixels(texture: &Texture, device: &Device, queue: &Queue) -> Vec<[u8; 4]> {
+    let (width, height) = (texture.width(), texture.height());
+    let staging = device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: (width * height * 4) as u64,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&Default::default());
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        ImageCopyBuffer {
+            buffer: &staging,
+            layout: ImageDataLayout { offset: 0, bytes_per_row: Some(width * 4), rows_per_image: None },
+        },
+        Extent3d { width, height, depth_or_array_layers: 1 },
+    );
+    queue.submit([encoder.finish()]);
+    map(&staging, device).chunks_exact(4).map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]]).collect()
+}
+
+/// Contents of a buffer that can be copied from.
+pub fn read_buffer(buffer: &Buffer, device: &Device, queue: &Queue) -> Vec<u8> {
+    let staging = device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: buffer.size(),
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&Default::default());
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+    queue.submit([encoder.finish()]);
+    map(&staging, device)
+}
+
+fn map(staging: &Buffer, device: &Device) -> Vec<u8> {
+    staging.slice(..).map_async(MapMode::Read, |result| result.unwrap());
+    device.poll(Maintain::Wait);
+    let bytes = staging.slice(..).get_mapped_range().to_vec();
+    staging.unmap();
+    bytes
+}
@@ -1,18 +1,26 @@
 //! Module that defines both graphics primitives, and multiple graphics engines that make use of those primitives.
 //! The graphics primitives are stored in the domain [`GraphicsState`].
 //! The 3D graphics engine is [`G3D`]
+//! The 2D graphics engine draws [`g2d::Sprite`]s and [`g2d::Text`] over the 3D scene.
 
 mod graphics;
 mod texture;
+mod cube_map;
+mod render_texture;
 mod state;
 mod color;
 mod shader;
 mod scene;
 mod buffer;
+pub mod g2d;
 pub mod g3d;
+#[cfg(test)]
+pub(crate) mod headless;
 
 pub use graphics::*;
 pub use texture::*;
+pub use cube_map::*;
+pub use render_texture::*;
 pub use state::*;
 pub use color::*;
 pub use shader::*;
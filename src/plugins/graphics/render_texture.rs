@@ -0,0 +1,110 @@
+use glam::UVec2;
+use wgpu::{AddressMode, Device, Extent3d, FilterMode, SamplerDescriptor, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor};
+use crate::{Asset, GraphicsState, Handle, Texture};
+
+/**
+ * A texture that cameras can render to instead of the surface. IE: portals, mirrors and security camera feeds.
+ * Has the same color and depth formats as the [`GraphicsState`] it was created with, so that it shares its pipelines.
+ * Is never multisampled.
+ */
+pub struct RenderTexture {
+    pub texture: wgpu::Texture,
+    /// View of the texture that is rendered to.
+    pub view: TextureView,
+    pub sampler: wgpu::Sampler,
+    /// Width and height in texels.
+    pub size: UVec2,
+    depth_view: TextureView,
+}
+
+impl RenderTexture {
+
+    /// Render texture of the given size, formatted like the surface of the graphics state.
+    /// Its depth buffer is sized to match.
+    pub fn new(width: u32, height: u32, graphics_state: &GraphicsState) -> Self {
+        Self::with_formats(width, height, graphics_state.format(), graphics_state.depth_format(), &graphics_state.device)
+    }
+
+    /// Render texture of the given size and formats, which must match those of the pipelines that render to it.
+    pub(crate) fn with_formats(width: u32, height: u32, format: TextureFormat, depth_format: TextureFormat, device: &Device) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        let texture = create_texture(
+            device,
+            width,
+            height,
+            format,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC,
+        );
+        let depth_texture = create_texture(
+            device,
+            width,
+            height,
+            depth_format,
+            TextureUsages::RENDER_ATTACHMENT,
+        );
+        Self {
+            view: texture.create_view(&TextureViewDescriptor::default()),
+            sampler: create_sampler(device),
+            size: UVec2::new(width, height),
+            depth_view: depth_texture.create_view(&TextureViewDescriptor::default()),
+            texture,
+        }
+    }
+
+    /// Format of the texture.
+    pub fn format(&self) -> TextureFormat {
+        self.texture.format()
+    }
+
+    /// View of the depth buffer.
+    pub fn depth_view(&self) -> &TextureView {
+        &self.depth_view
+    }
+
+    /**
+     * Texture that samples the same image, for use in a [`crate::g3d::Material`].
+     * A camera must not render materials that sample its own target, as a texture cannot be sampled and rendered to in the same pass.
+     */
+    pub fn create_texture(&self, device: &Device) -> Texture {
+        Texture {
+            view: self.texture.create_view(&TextureViewDescriptor::default()),
+            sampler: create_sampler(device),
+            size: self.size,
+            srgb: self.format().is_srgb(),
+        }
+    }
+}
+
+impl Asset for RenderTexture {}
+
+/**
+ * Component that routes the [`crate::Camera`] of its entity to a [`RenderTexture`], instead of the surface.
+ * Cameras whose render texture is not loaded are skipped.
+ */
+pub struct RenderTargetCamera(pub Handle<RenderTexture>);
+
+fn create_texture(device: &Device, width: u32, height: u32, format: TextureFormat, usage: TextureUsages) -> wgpu::Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some("render_texture"),
+        size: Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+    })
+}
+
+fn create_sampler(device: &Device) -> wgpu::Sampler {
+    device.create_sampler(&SamplerDescriptor {
+        label: Some("render_texture_sampler"),
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        address_mode_w: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        mipmap_filter: FilterMode::Nearest,
+        ..Default::default()
+    })
+}
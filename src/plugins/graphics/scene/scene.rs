@@ -51,6 +51,16 @@ impl<R: HasId> Scene<R> {
         Ok(Tracker::new(node_id, self.sender.clone()))
     }
 
+    /**
+     * Tracks an existing node that has no tracker, such as one whose tracker was detached.
+     * The node is removed once the new tracker drops, like any other.
+     * Adopting a node that is already tracked schedules its removal twice, which is harmless,
+     * but removes it as soon as either tracker drops.
+     */
+    pub fn adopt(&mut self, node_id: R::Id) -> Tracker<R> {
+        Tracker::new(node_id, self.sender.clone())
+    }
+
     pub fn get(&self, node_id: R::Id) -> Option<&R> {
         self.graph.get(node_id)
     }
@@ -79,6 +89,20 @@ impl<R: HasId> Scene<R> {
         self.graph.remove_reparent(node_id);
     }
 
+    /**
+     * Detaches a node from its parent, making it a root node.
+     */
+    pub fn move_to_root(&mut self, node_id: R::Id) -> Result<(), SceneGraphError> {
+        self.graph.move_to_root(node_id)
+    }
+
+    /**
+     * Moves a node under a new parent, or to the root set if None, keeping its children under it.
+     */
+    pub fn move_node(&mut self, node_id: R::Id, new_parent_id: Option<R::Id>) -> Result<(), SceneGraphError> {
+        self.graph.move_node(node_id, new_parent_id)
+    }
+
     /**
      * Removes all [`Node`]s.
      */
@@ -93,6 +117,24 @@ impl<R: HasId> Scene<R> {
         self.graph.len()
     }
 
+    /**
+     * Marks every node as dirty.
+     */
+    pub fn mark_all_dirty(&self) {
+        self.graph.mark_all_dirty();
+    }
+
+    /**
+     * Number of nodes currently marked as dirty.
+     */
+    pub fn dirty_count(&self) -> usize {
+        self.graph.dirty_count()
+    }
+
+    /**
+     * Removes the nodes whose trackers were dropped or retargeted since the last prune, along with their children.
+     * Nodes of detached trackers are kept. Nodes that were already removed are skipped.
+     */
     #[instrument(skip_all)]
     pub fn prune_nodes(&mut self) {
         for node_id in self.receiver.iter() {
@@ -100,3 +142,60 @@ impl<R: HasId> Scene<R> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{HasId, NodeId, Scene};
+
+    struct Value;
+    impl HasId for Value {
+        type Id = NodeId;
+    }
+
+    #[test]
+    fn detach_and_adopt() {
+        let mut scene = Scene::new();
+        let tracker = scene.insert(Value);
+        let child = scene.insert_child(Value, tracker.id()).unwrap().detach();
+        let id = tracker.detach();
+        scene.prune_nodes();
+        assert!(scene.contains(id));
+        assert!(scene.contains(child));
+
+        // Adopted nodes are pruned with their children once the new tracker drops.
+        let tracker = scene.adopt(id);
+        scene.prune_nodes();
+        assert_eq!(2, scene.len());
+        drop(tracker);
+        scene.prune_nodes();
+        assert_eq!(0, scene.len());
+    }
+
+    #[test]
+    fn retarget() {
+        let mut scene = Scene::new();
+        let mut tracker = scene.insert(Value);
+        let old_id = tracker.id();
+
+        // Retargeting to the tracked node keeps it alive.
+        tracker.retarget(old_id);
+        scene.prune_nodes();
+        assert!(scene.contains(old_id));
+        assert_eq!(1, scene.len());
+
+        let new_id = scene.insert(Value).detach();
+        scene.insert_child(Value, new_id).unwrap().detach();
+        tracker.retarget(new_id);
+        scene.prune_nodes();
+        assert!(!scene.contains(old_id));
+        assert!(scene.contains(new_id));
+        assert_eq!(2, scene.len());
+
+        // Old id is not removed a second time, and the new node is removed once.
+        drop(tracker);
+        scene.prune_nodes();
+        assert_eq!(0, scene.len());
+        scene.prune_nodes();
+        assert_eq!(0, scene.len());
+    }
+}
@@ -1,10 +1,14 @@
 use std::cell::UnsafeCell;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use rayon::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use slotmap::{new_key_type, SlotMap};
 use smallvec::SmallVec;
 use derive_more::*;
 
-use crate::HasId;
+use crate::{HasId, HashMap};
 
 /// A hierarchical collection of [`Node`]s with parent/child relationships.
 /// Useful for representing the graphics of a game, where each [`Node`] contains a renderable object.
@@ -44,6 +48,7 @@ impl<R: HasId> SceneGraph<R> {
      * Iterator over all objects in the scene in no particular order.
      */
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut R> {
+        self.mark_all_dirty();
         self.nodes
             .values_mut()
             .map(|node| &mut node.get_mut().value)
@@ -57,6 +62,8 @@ impl<R: HasId> SceneGraph<R> {
             value,
             parent_id: None,
             children_ids: SmallVec::new(),
+            dirty: AtomicBool::new(true),
+            locked: AtomicBool::new(false),
         });
         let node_id = self.nodes.insert(node);
         self.root_ids.push(node_id);
@@ -71,6 +78,8 @@ impl<R: HasId> SceneGraph<R> {
             value,
             parent_id: Some(parent_id),
             children_ids: SmallVec::new(),
+            dirty: AtomicBool::new(true),
+            locked: AtomicBool::new(false),
         });
         let node_id = self.nodes.insert(node);
         match self.nodes.get_mut(parent_id) {
@@ -80,6 +89,7 @@ impl<R: HasId> SceneGraph<R> {
                 return Err(SceneGraphError::NoSuchNode);
             },
         };
+        mark_dirty(node_id, &self.nodes);
         Ok(node_id)
     }
 
@@ -94,22 +104,52 @@ impl<R: HasId> SceneGraph<R> {
 
     /**
      * Gets an object by id.
+     * Marks it and its ancestors as dirty.
      */
     pub fn get_mut(&mut self, node_id: R::Id) -> Option<&mut R> {
+        mark_dirty(node_id, &self.nodes);
         self.nodes
             .get_mut(node_id)
             .map(|node| &mut node.get_mut().value)
     }
 
     /**
-     * Gets an object by id.
+     * Updates many objects in parallel, passing each the data paired with its id.
+     * The function returns true if it changed the object, which marks it and its ancestors as dirty.
+     * Ids that are not stored are skipped.
+     * If an id repeats, the function runs once for each of its data, one at a time, in no particular order.
      */
-    pub unsafe fn get_mut_unsafe(&self, node_id: R::Id) -> Option<&mut R> {
-        self.nodes
-            .get(node_id)
-            .map(|node| unsafe {
-                &mut node.get_mut_unsafe().value
-            })
+    pub fn par_update<T, I, F>(&mut self, items: I, function: F)
+    where
+        T: Send,
+        I: IntoParallelIterator<Item = (R::Id, T)>,
+        F: Fn(&mut R, T) -> bool + Sync,
+    {
+        let nodes = &self.nodes;
+        let contended = Mutex::new(Vec::new());
+        items.into_par_iter().for_each(|(node_id, data)| {
+            let Some(node) = nodes.get(node_id) else { return };
+
+            // Defers the update if another thread is updating the same node
+            if node.locked().swap(true, Ordering::Acquire) {
+                contended.lock().unwrap().push((node_id, data));
+                return;
+            }
+            // Safety: The node is locked, and the graph is borrowed mutably, so no other reference to its value exists.
+            let changed = function(unsafe { node.value_mut_unsafe() }, data);
+            node.locked().store(false, Ordering::Release);
+            if changed {
+                mark_dirty(node_id, nodes);
+            }
+        });
+
+        // Runs deferred updates one at a time
+        for (node_id, data) in contended.into_inner().unwrap() {
+            let Some(node) = self.nodes.get_mut(node_id) else { continue };
+            if function(&mut node.get_mut().value, data) {
+                mark_dirty(node_id, &self.nodes);
+            }
+        }
     }
 
     /**
@@ -141,19 +181,23 @@ impl<R: HasId> SceneGraph<R> {
         // Children are reparented
         if let Some(parent_id) = node.get().parent_id {
             let parent = self.nodes.get_mut(parent_id).unwrap();
+            parent.get_mut().children_ids.retain(|child_id| *child_id != node_id);
             parent.get_mut().children_ids.extend_from_slice(&node.get().children_ids);
             for child_id in &node.get().children_ids {
                 let child = self.nodes.get_mut(*child_id).unwrap();
                 child.get_mut().parent_id = Some(parent_id);
+                mark_dirty(*child_id, &self.nodes);
             }
         }
 
         // Children become roots
         else {
+            self.root_ids.retain(|root_id| *root_id != node_id);
             for child_id in &node.get().children_ids {
                 let child = self.nodes.get_mut(*child_id).unwrap();
                 child.get_mut().parent_id = None;
-                self.root_ids.push(node_id);
+                *child.get_mut().dirty.get_mut() = true;
+                self.root_ids.push(*child_id);
             }
         }
 
@@ -161,6 +205,90 @@ impl<R: HasId> SceneGraph<R> {
         node.get_mut().children_ids.clear();
     }
 
+    /**
+     * Detaches a node from its parent, making it a root node.
+     * The node's children remain attached to it.
+     */
+    pub fn move_to_root(&mut self, node_id: R::Id) -> Result<(), SceneGraphError> {
+        let node = self.nodes.get_mut(node_id).ok_or(SceneGraphError::NoSuchNode)?;
+        let Some(parent_id) = node.get_mut().parent_id.take() else {
+            return Err(SceneGraphError::AlreadyRoot);
+        };
+        *node.get_mut().dirty.get_mut() = true;
+        let parent = self.nodes.get_mut(parent_id).unwrap();
+        parent.get_mut().children_ids.retain(|child_id| *child_id != node_id);
+        self.root_ids.push(node_id);
+        Ok(())
+    }
+
+    /**
+     * Moves a node under a new parent, or to the root set if None, keeping its children under it.
+     * The node becomes the last child of its new parent.
+     * Fails if the new parent is the node itself or one of its descendants.
+     */
+    pub fn move_node(&mut self, node_id: R::Id, new_parent_id: Option<R::Id>) -> Result<(), SceneGraphError> {
+        let node = self.nodes.get(node_id).ok_or(SceneGraphError::NoSuchNode)?;
+        let old_parent_id = node.get().parent_id;
+        if old_parent_id == new_parent_id {
+            return Ok(());
+        }
+
+        // Ensures the new parent exists, and is not in the node's subtree
+        if let Some(new_parent_id) = new_parent_id {
+            let mut next_id = Some(new_parent_id);
+            while let Some(ancestor_id) = next_id {
+                if ancestor_id == node_id {
+                    return Err(SceneGraphError::CyclicMove);
+                }
+                let ancestor = self.nodes.get(ancestor_id).ok_or(SceneGraphError::NoSuchNode)?;
+                next_id = ancestor.get().parent_id;
+            }
+        }
+
+        // Detaches from old parent
+        match old_parent_id {
+            Some(old_parent_id) => {
+                let old_parent = self.nodes.get_mut(old_parent_id).unwrap();
+                old_parent.get_mut().children_ids.retain(|child_id| *child_id != node_id);
+            },
+            None => self.root_ids.retain(|root_id| *root_id != node_id),
+        }
+
+        // Attaches to new parent
+        match new_parent_id {
+            Some(new_parent_id) => {
+                let new_parent = self.nodes.get_mut(new_parent_id).unwrap();
+                new_parent.get_mut().children_ids.push(node_id);
+            },
+            None => self.root_ids.push(node_id),
+        }
+        let node = self.nodes.get_mut(node_id).unwrap().get_mut();
+        node.parent_id = new_parent_id;
+        *node.dirty.get_mut() = true;
+        Ok(())
+    }
+
+    /**
+     * Marks every [`Node`] as dirty.
+     * Useful for bulk invalidation during the next call to [`SceneGraph::propagate_dirty`].
+     */
+    pub fn mark_all_dirty(&self) {
+        for node in self.nodes.values() {
+            node.get().dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /**
+     * Number of [`Node`]s currently marked as dirty.
+     * Useful for profiling.
+     */
+    pub fn dirty_count(&self) -> usize {
+        self.nodes
+            .values()
+            .filter(|node| node.get().dirty.load(Ordering::Relaxed))
+            .count()
+    }
+
     /**
      * Removes all [`Node`]s.
      */
@@ -187,6 +315,66 @@ impl<R: HasId> SceneGraph<R> {
             propagate_at(&self.nodes, *root_id, accum.clone(), &mut function);
         };
     }
+
+    /// Same as [`SceneGraph::propagate`], but tracks which nodes changed since the last invocation.
+    /// The function receives true if the node is dirty, or if its parent's value changed.
+    /// It returns the accumulated value, and whether that value changed.
+    /// A clean node can return an accumulation it cached earlier, skipping recomputation.
+    /// Clears all dirty flags.
+    pub fn propagate_dirty<'a, A, F>(&'a mut self, accum: A, mut function: F)
+    where
+        A: Clone,
+        F: FnMut(A, &'a mut R, bool) -> (A, bool)
+    {
+        for root_id in &self.root_ids {
+            propagate_dirty_at(&self.nodes, *root_id, accum.clone(), false, &mut function);
+        };
+    }
+
+    /**
+     * Rebuilds a graph from records produced by serializing one.
+     * Nodes are inserted first, then attached to their parents. Children keep the order of their records, as do roots.
+     * Fails if an id repeats, if a parent id has no record, or if the records form a cycle.
+     */
+    pub fn from_serialized(records: impl IntoIterator<Item = SerializedNode<R>>) -> Result<Self, SceneGraphError> {
+        let mut graph = Self::new();
+
+        // Inserts nodes
+        let mut ids: HashMap<u64, R::Id> = HashMap::default();
+        let mut parents = Vec::new();
+        for record in records {
+            let node_id = graph.nodes.insert(NodeWrapper::new(Node {
+                value: record.value,
+                parent_id: None,
+                children_ids: SmallVec::new(),
+                dirty: AtomicBool::new(true),
+                locked: AtomicBool::new(false),
+            }));
+            if ids.insert(record.id, node_id).is_some() {
+                return Err(SceneGraphError::DuplicateSerializedId);
+            }
+            parents.push((node_id, record.parent_id));
+        }
+
+        // Attaches them to their parents
+        for (node_id, parent_id) in parents {
+            let Some(parent_id) = parent_id else {
+                graph.root_ids.push(node_id);
+                continue;
+            };
+            let parent_id = *ids.get(&parent_id).ok_or(SceneGraphError::NoSuchNode)?;
+            graph.nodes[parent_id].get_mut().children_ids.push(node_id);
+            graph.nodes[node_id].get_mut().parent_id = Some(parent_id);
+        }
+
+        // Nodes in a cycle are unreachable from the roots
+        let mut reachable = 0;
+        graph.propagate((), |_, _| reachable += 1);
+        if reachable != graph.len() {
+            return Err(SceneGraphError::SerializedCycle);
+        }
+        Ok(graph)
+    }
 }
 
 fn propagate_at<'a, R: HasId, A, F>(
@@ -206,6 +394,39 @@ where
     }
 }
 
+fn propagate_dirty_at<'a, R: HasId, A, F>(
+    nodes: &'a SlotMap<R::Id, NodeWrapper<R>>,
+    node_id: R::Id,
+    accum: A,
+    parent_changed: bool,
+    function: &mut F
+)
+where
+    A: Clone,
+    F: FnMut(A, &'a mut R, bool) -> (A, bool)
+{
+    // Safety: Nodes are visited once each, so no two mutable references alias.
+    let node = unsafe { nodes.get_unchecked(node_id).get_mut_unsafe() };
+    let dirty = std::mem::replace(node.dirty.get_mut(), false) || parent_changed;
+    let (current, changed) = function(accum, &mut node.value, dirty);
+    for child_id in &node.children_ids {
+        propagate_dirty_at(nodes, *child_id, current.clone(), changed, function);
+    }
+}
+
+/// Marks a node and its ancestors as dirty.
+/// Does not reference their values, so it is safe while another thread updates them.
+fn mark_dirty<R: HasId>(node_id: R::Id, nodes: &SlotMap<R::Id, NodeWrapper<R>>) {
+    let mut next_id = Some(node_id);
+    while let Some(node_id) = next_id {
+        let Some(node) = nodes.get(node_id) else { return };
+        if node.dirty().swap(true, Ordering::Relaxed) {
+            return;
+        }
+        next_id = node.parent_id();
+    }
+}
+
 fn remove<R: HasId>(node_id: R::Id, nodes: &mut SlotMap<R::Id, NodeWrapper<R>>) {
     let Some(node) = nodes.remove(node_id) else { return };
     for child_id in &node.get().children_ids {
@@ -215,6 +436,10 @@ fn remove<R: HasId>(node_id: R::Id, nodes: &mut SlotMap<R::Id, NodeWrapper<R>>)
 
 
 struct NodeWrapper<R: HasId>(UnsafeCell<Node<R>>);
+
+// Safety: Values are only referenced mutably through a mutably borrowed graph, or while locked by par_update().
+unsafe impl<R: HasId> Sync for NodeWrapper<R> {}
+
 impl<R: HasId> NodeWrapper<R> {
 
     fn new(node: Node<R>) -> Self {
@@ -234,6 +459,29 @@ impl<R: HasId> NodeWrapper<R> {
         let ptr = self.0.get();
         &mut *ptr
     }
+
+    // Field accessors that do not reference the whole node, so that they
+    // do not alias a reference to its value held by another thread.
+
+    fn dirty(&self) -> &AtomicBool {
+        let ptr = self.0.get();
+        unsafe { &*std::ptr::addr_of!((*ptr).dirty) }
+    }
+
+    fn locked(&self) -> &AtomicBool {
+        let ptr = self.0.get();
+        unsafe { &*std::ptr::addr_of!((*ptr).locked) }
+    }
+
+    fn parent_id(&self) -> Option<R::Id> {
+        let ptr = self.0.get();
+        unsafe { *std::ptr::addr_of!((*ptr).parent_id) }
+    }
+
+    unsafe fn value_mut_unsafe(&self) -> &mut R {
+        let ptr = self.0.get();
+        &mut *std::ptr::addr_of_mut!((*ptr).value)
+    }
 }
 
 /// Container of a scene graph value, and a reference to its parent and children.
@@ -241,6 +489,8 @@ struct Node<R: HasId> {
     value: R,
     parent_id: Option<R::Id>,
     children_ids: SmallVec<[R::Id; 8]>,
+    dirty: AtomicBool,
+    locked: AtomicBool,     // Held while par_update() references the value
 }
 
 new_key_type! {
@@ -250,8 +500,221 @@ new_key_type! {
     pub struct NodeId;
 }
 
+/**
+ * Record of a single node of a serialized [`SceneGraph`].
+ * Ids are local to the serialized graph, as node ids are not stable across runs.
+ */
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct SerializedNode<V> {
+    pub id: u64,
+    pub parent_id: Option<u64>,
+    pub value: V,
+}
+
+/// Serializes as a flat list of [`SerializedNode`]s, depth-first, so that parents come before their children.
+impl<R: HasId + Serialize> Serialize for SceneGraph<R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+
+        // Numbers nodes in the order they are serialized
+        let mut ids: HashMap<R::Id, u64> = HashMap::default();
+        let mut order = Vec::with_capacity(self.len());
+        let mut stack: Vec<R::Id> = self.root_ids.iter().rev().copied().collect();
+        while let Some(node_id) = stack.pop() {
+            let Some(node) = self.nodes.get(node_id) else { continue };
+            ids.insert(node_id, order.len() as u64);
+            order.push(node.get());
+            stack.extend(node.get().children_ids.iter().rev());
+        }
+
+        serializer.collect_seq(order.into_iter().enumerate().map(|(id, node)| SerializedNode {
+            id: id as u64,
+            parent_id: node.parent_id.map(|parent_id| ids[&parent_id]),
+            value: &node.value,
+        }))
+    }
+}
+
+/// Deserializes from a list of [`SerializedNode`]s. See [`SceneGraph::from_serialized`].
+impl<'de, R: HasId + Deserialize<'de>> Deserialize<'de> for SceneGraph<R> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let records = Vec::<SerializedNode<R>>::deserialize(deserializer)?;
+        Self::from_serialized(records).map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Error, Display, Debug, From)]
 pub enum SceneGraphError {
     #[display(fmt="No such node")]
     NoSuchNode,
-}
\ No newline at end of file
+    #[display(fmt="Node is already a root")]
+    AlreadyRoot,
+    #[display(fmt="Node cannot be moved under itself or its descendants")]
+    CyclicMove,
+    #[display(fmt="Serialized node id is used more than once")]
+    DuplicateSerializedId,
+    #[display(fmt="Serialized nodes form a cycle")]
+    SerializedCycle,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{HasId, NodeId, SceneGraph, SceneGraphError};
+
+    fn propagate_dirty_values(graph: &mut SceneGraph<Value>) -> Vec<(u32, bool)> {
+        let mut visited = Vec::new();
+        graph.propagate_dirty(0, |accum, value, dirty| {
+            visited.push((value.0, dirty));
+            (accum, dirty)
+        });
+        visited
+    }
+
+    struct Value(u32);
+    impl HasId for Value {
+        type Id = NodeId;
+    }
+
+    #[test]
+    fn move_to_root() {
+        let mut graph = SceneGraph::new();
+        let root = graph.insert(Value(0));
+        let child = graph.insert_child(Value(1), root).unwrap();
+        let grandchild = graph.insert_child(Value(2), child).unwrap();
+        graph.move_to_root(child).unwrap();
+        assert_eq!(&[root, child], graph.root_ids());
+
+        // Subtree stays attached to the moved node.
+        let mut visited = Vec::new();
+        graph.propagate(0, |depth, value| {
+            visited.push((value.0, depth));
+            depth + 1
+        });
+        assert_eq!(vec![(0, 0), (1, 0), (2, 1)], visited);
+        assert!(graph.get(grandchild).is_some());
+    }
+
+    #[test]
+    fn move_to_root_errors() {
+        let mut graph = SceneGraph::new();
+        let root = graph.insert(Value(0));
+        assert!(matches!(graph.move_to_root(root), Err(SceneGraphError::AlreadyRoot)));
+        graph.remove(root);
+        assert!(matches!(graph.move_to_root(root), Err(SceneGraphError::NoSuchNode)));
+    }
+
+    #[test]
+    fn remove_reparent() {
+        let mut graph = SceneGraph::new();
+        let root = graph.insert(Value(0));
+        let child_a = graph.insert_child(Value(1), root).unwrap();
+        let child_b = graph.insert_child(Value(2), root).unwrap();
+        let grandchild = graph.insert_child(Value(3), child_a).unwrap();
+
+        // Children of a removed child move to its parent.
+        graph.remove_reparent(child_a);
+        assert_eq!(3, graph.len());
+        let mut visited = Vec::new();
+        graph.propagate(0, |depth, value| {
+            visited.push((value.0, depth));
+            depth + 1
+        });
+        assert_eq!(vec![(0, 0), (2, 1), (3, 1)], visited);
+
+        // Children of a removed root become roots.
+        graph.remove_reparent(root);
+        assert_eq!(&[child_b, grandchild], graph.root_ids());
+        let mut visited = Vec::new();
+        graph.propagate(0, |depth, value| {
+            visited.push((value.0, depth));
+            depth + 1
+        });
+        assert_eq!(vec![(2, 0), (3, 0)], visited);
+    }
+
+    #[test]
+    fn move_node() {
+        let mut graph = SceneGraph::new();
+        let root = graph.insert(Value(0));
+        let child = graph.insert_child(Value(1), root).unwrap();
+        let _grandchild = graph.insert_child(Value(2), child).unwrap();
+        let other_root = graph.insert(Value(3));
+        propagate_dirty_values(&mut graph);
+
+        // Subtree moves with the node, and only the moved node is dirty.
+        graph.move_node(child, Some(other_root)).unwrap();
+        assert_eq!(&[root, other_root], graph.root_ids());
+        assert_eq!(vec![(0, false), (3, false), (1, true), (2, true)], propagate_dirty_values(&mut graph));
+
+        graph.move_node(child, None).unwrap();
+        assert_eq!(&[root, other_root, child], graph.root_ids());
+        graph.move_node(child, None).unwrap();
+        assert_eq!(&[root, other_root, child], graph.root_ids());
+
+        graph.move_node(root, Some(child)).unwrap();
+        assert_eq!(&[other_root, child], graph.root_ids());
+        let mut visited = Vec::new();
+        graph.propagate(0, |depth, value| {
+            visited.push((value.0, depth));
+            depth + 1
+        });
+        assert_eq!(vec![(3, 0), (1, 0), (2, 1), (0, 1)], visited);
+    }
+
+    #[test]
+    fn move_node_errors() {
+        let mut graph = SceneGraph::new();
+        let root = graph.insert(Value(0));
+        let child = graph.insert_child(Value(1), root).unwrap();
+        let grandchild = graph.insert_child(Value(2), child).unwrap();
+        assert!(matches!(graph.move_node(root, Some(root)), Err(SceneGraphError::CyclicMove)));
+        assert!(matches!(graph.move_node(root, Some(grandchild)), Err(SceneGraphError::CyclicMove)));
+        graph.remove(grandchild);
+        assert!(matches!(graph.move_node(grandchild, None), Err(SceneGraphError::NoSuchNode)));
+        assert!(matches!(graph.move_node(child, Some(grandchild)), Err(SceneGraphError::NoSuchNode)));
+        assert_eq!(&[root], graph.root_ids());
+    }
+
+    #[test]
+    fn par_update() {
+        let mut graph = SceneGraph::new();
+        let root = graph.insert(Value(0));
+        let child = graph.insert_child(Value(0), root).unwrap();
+        let other_root = graph.insert(Value(0));
+        let removed = graph.insert(Value(0));
+        graph.remove(removed);
+        propagate_dirty_values(&mut graph);
+
+        // Repeated ids are each applied, and only changed nodes are marked dirty.
+        let mut items = vec![(child, 1), (other_root, 0), (removed, 1)];
+        items.extend(std::iter::repeat((child, 1)).take(999));
+        graph.par_update(items, |value, amount| {
+            value.0 += amount;
+            amount != 0
+        });
+        assert_eq!(1000, graph.get(child).unwrap().0);
+        assert_eq!(vec![(0, true), (1000, true), (0, false)], propagate_dirty_values(&mut graph));
+    }
+
+    #[test]
+    fn dirty_tracking() {
+        let mut graph = SceneGraph::new();
+        let root = graph.insert(Value(0));
+        let child = graph.insert_child(Value(1), root).unwrap();
+        let _grandchild = graph.insert_child(Value(2), child).unwrap();
+        let other_root = graph.insert(Value(3));
+        assert_eq!(4, graph.dirty_count());
+        assert_eq!(vec![(0, true), (1, true), (2, true), (3, true)], propagate_dirty_values(&mut graph));
+        assert_eq!(0, graph.dirty_count());
+
+        // Touching a node marks it and its ancestors dirty. Descendants are passed as dirty.
+        graph.get_mut(child).unwrap();
+        assert_eq!(2, graph.dirty_count());
+        assert_eq!(vec![(0, true), (1, true), (2, true), (3, false)], propagate_dirty_values(&mut graph));
+
+        graph.get_mut(other_root).unwrap();
+        assert_eq!(vec![(0, false), (1, false), (2, false), (3, true)], propagate_dirty_values(&mut graph));
+
+        graph.mark_all_dirty();
+        assert_eq!(4, graph.dirty_count());
+    }
+}
@@ -1,8 +1,12 @@
 use vecmap::VecSet;
 use derive_more::*;
 
+/// Maximum number of nested #include directives.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
 /// Stores flags that are used during shader preprocessing.
-/// These flags determine if #ifdef blocks get included or stripped out in the final shader.
+/// These flags determine which branches of #ifdef / #ifndef blocks get included or stripped out in the final shader.
+/// Blocks may have #elifdef, #elifndef and #else branches, of which only the first whose condition is met is included.
 pub struct ShaderPreprocessor(VecSet<String>);
 
 impl ShaderPreprocessor {
@@ -22,114 +26,204 @@ impl ShaderPreprocessor {
 
     /**
      * Preprocesses shader code.
+     * Fails on #include directives, as there is nothing to resolve them with.
      */
     pub fn preprocess(&mut self, shader_template: &str) -> Result<String, ShaderDefError> {
-        let mut result = String::new();
-        let mut state = State::new(shader_template);
-        self.inner_preprocess(&mut result, &mut state)?;
-        Ok(result)
+        self.preprocess_with_includes(shader_template, &|_| None)
     }
 
-    fn inner_preprocess(&mut self, result: &mut String, state: &mut State) -> Result<(), ShaderDefError> {
+    /**
+     * Preprocesses shader code, splicing in the source of #include "path" directives before handling #ifdef blocks.
+     * resolve returns the source of an included path, or None if there is none.
+     * Included sources may include others, up to a depth of 16.
+     * Errors in included sources are reported on the line of the outermost #include.
+     */
+    pub fn preprocess_with_includes(
+        &mut self,
+        shader_template: &str,
+        resolve: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<String, ShaderDefError> {
+        let mut lines = Vec::new();
+        let mut line_nums = Vec::new();
+        let mut include_stack = Vec::new();
+        for (i, line) in shader_template.split('\n').enumerate() {
+            let line_num = i as u32 + 1;
+            expand_line(line, line_num, resolve, &mut include_stack, &mut lines, &mut line_nums)?;
+        }
+        let expanded = lines.join("\n");
+        let mut result = String::new();
+        let mut state = State::new(&expanded, &line_nums);
+        match self.preprocess_block(&mut result, &mut state, true)? {
+            None => Ok(result),
+            Some(BlockEnd::Endif { line_num }) => Err(ShaderDefError::new(line_num, ShaderDefErrorKind::UnexpectedEndif)),
+            Some(BlockEnd::Else { line_num } | BlockEnd::Elif { line_num, .. }) => {
+                Err(ShaderDefError::new(line_num, ShaderDefErrorKind::UnexpectedElse))
+            },
+        }
+    }
 
+    /**
+     * Handles lines until a #else, #elifdef, #elifndef or #endif that closes the current block, which is returned.
+     * Returns None at the end of the template.
+     * Lines are only written to the result if active. Inactive blocks are still parsed to track nesting.
+     */
+    fn preprocess_block(&self, result: &mut String, state: &mut State, active: bool) -> Result<Option<BlockEnd>, ShaderDefError> {
         while let Some(line) = state.line {
             let trim_line = line.trim();
 
             // Handles command
             if trim_line.starts_with('#') {
-                let (command, param) = trim_line.split_once(' ').unwrap_or((trim_line, ""));
+                let (command, param) = trim_line.split_once(char::is_whitespace).unwrap_or((trim_line, ""));
                 let param = param.trim();
+                let line_num = state.line_num;
                 match command {
-                    "#ifdef" => {
+                    "#ifdef" | "#ifndef" => {
                         state.next_line();
-                        if self.0.contains(param) {
-                            state.ifdef_count += 1;
-                            self.inner_preprocess(result, state)?;
-                        }
-                        else {
-                            Self::skip_past_endif(state)?;
-                        }
+                        let is_met = self.0.contains(param) == (command == "#ifdef");
+                        self.preprocess_conditional(result, state, active, is_met, line_num)?;
                     },
-                    "#ifndef" => {
+                    "#elifdef" | "#elifndef" => {
                         state.next_line();
-                        if !self.0.contains(param) {
-                            state.ifdef_count += 1;
-                            self.inner_preprocess(result, state)?;
-                        }
-                        else {
-                            Self::skip_past_endif(state)?;
-                        }
+                        let is_met = self.0.contains(param) == (command == "#elifdef");
+                        return Ok(Some(BlockEnd::Elif { is_met, line_num }));
                     },
-                    "#endif" => {
+                    "#else" | "#endif" => {
                         if !param.is_empty() {
-                            return Err(ShaderDefError::new(state.line_num, ShaderDefErrorKind::UnexpectedParam))
-                        }
-                        if state.ifdef_count == 0 {
-                            return Err(ShaderDefError::new(state.line_num, ShaderDefErrorKind::UnexpectedEndif))
-                        }
-                        else {
-                            state.next_line();
-                            state.ifdef_count -= 1;
-                            return Ok(());
+                            return Err(ShaderDefError::new(line_num, ShaderDefErrorKind::UnexpectedParam))
                         }
+                        state.next_line();
+                        return Ok(Some(match command {
+                            "#else" => BlockEnd::Else { line_num },
+                            _ => BlockEnd::Endif { line_num },
+                        }));
                     },
-                    _ => return Err(ShaderDefError::new(state.line_num, ShaderDefErrorKind::InvalidCommand)),
+                    _ if !active => state.next_line(),
+                    _ => return Err(ShaderDefError::new(line_num, ShaderDefErrorKind::InvalidCommand)),
                 }
             }
 
             // Handles normal line
             else {
-                result.push_str(line);
                 state.next_line();
-                if state.line.is_some() {
-                    result.push('\n');
+                if active {
+                    result.push_str(line);
+                    if state.line.is_some() {
+                        result.push('\n');
+                    }
                 }
             }
         }
-
-        // At EOF, fail if we're in an #ifdef block.
-        if state.ifdef_count != 0 {
-            return Err(ShaderDefError::new(state.line_num, ShaderDefErrorKind::MissingEndif))
-        }
-        Ok(())
+        Ok(None)
     }
 
-    fn skip_past_endif(state: &mut State) -> Result<(), ShaderDefError> {
-        let mut ifdef_counter = 1;
-        while let Some(line) = state.line {
-            let line = line.trim_start();
-            if line.starts_with("#ifdef") {
-                ifdef_counter += 1;
-            }
-            else if line.starts_with("#endif") {
-                ifdef_counter -= 1;
-                if ifdef_counter == 0 {
-                    state.next_line();
-                    return Ok(())
-                }
+    /**
+     * Handles the branches of an #ifdef or #ifndef block, whose opening line was already consumed.
+     * Only the first branch whose condition is met is active, and only if the enclosing block is.
+     * opening_line_num is where the block began, reported if it is never closed.
+     */
+    fn preprocess_conditional(
+        &self,
+        result: &mut String,
+        state: &mut State,
+        active: bool,
+        mut is_met: bool,
+        opening_line_num: u32,
+    ) -> Result<(), ShaderDefError> {
+        let mut is_taken = false;
+        let mut has_else = false;
+        loop {
+            let branch_active = active && is_met && !is_taken;
+            is_taken |= is_met;
+            match self.preprocess_block(result, state, branch_active)? {
+                Some(BlockEnd::Endif { .. }) => return Ok(()),
+                Some(BlockEnd::Else { line_num }) => {
+                    if has_else {
+                        return Err(ShaderDefError::new(line_num, ShaderDefErrorKind::ElseAfterElse));
+                    }
+                    has_else = true;
+                    is_met = true;
+                },
+                Some(BlockEnd::Elif { is_met: is_elif_met, line_num }) => {
+                    if has_else {
+                        return Err(ShaderDefError::new(line_num, ShaderDefErrorKind::ElseAfterElse));
+                    }
+                    is_met = is_elif_met;
+                },
+                None => {
+                    let line_num = state.line_num.max(opening_line_num);
+                    return Err(ShaderDefError::new(line_num, ShaderDefErrorKind::MissingEndif));
+                },
             }
-            state.next_line();
         }
-        return Err(ShaderDefError::new(state.line_num, ShaderDefErrorKind::MissingEndif))
     }
 }
 
+/// Command that ended a block of lines.
+enum BlockEnd {
+    Else { line_num: u32 },
+    Elif { is_met: bool, line_num: u32 },
+    Endif { line_num: u32 },
+}
+
+/**
+ * Appends a line of a template to lines.
+ * If it is an #include directive, appends the lines of the included source instead, recursively.
+ * line_num is the line in the original template that every appended line is attributed to.
+ */
+fn expand_line(
+    line: &str,
+    line_num: u32,
+    resolve: &dyn Fn(&str) -> Option<String>,
+    include_stack: &mut Vec<String>,
+    lines: &mut Vec<String>,
+    line_nums: &mut Vec<u32>,
+) -> Result<(), ShaderDefError> {
+    let trim_line = line.trim();
+    let Some(param) = trim_line.strip_prefix("#include") else {
+        lines.push(line.to_owned());
+        line_nums.push(line_num);
+        return Ok(());
+    };
+    let path = param.trim();
+    let path = match path.strip_prefix('"').and_then(|path| path.strip_suffix('"')) {
+        Some(path) if !path.is_empty() => path,
+        _ => return Err(ShaderDefError::new(line_num, ShaderDefErrorKind::UnexpectedParam)),
+    };
+    if include_stack.iter().any(|included| included == path) {
+        return Err(ShaderDefError::new(line_num, ShaderDefErrorKind::IncludeCycle { path: path.to_owned() }));
+    }
+    if include_stack.len() >= MAX_INCLUDE_DEPTH {
+        return Err(ShaderDefError::new(line_num, ShaderDefErrorKind::IncludeTooDeep));
+    }
+    let Some(source) = resolve(path) else {
+        return Err(ShaderDefError::new(line_num, ShaderDefErrorKind::IncludeNotFound { path: path.to_owned() }));
+    };
+    include_stack.push(path.to_owned());
+    for included_line in source.split('\n') {
+        expand_line(included_line, line_num, resolve, include_stack, lines, line_nums)?;
+    }
+    include_stack.pop();
+    Ok(())
+}
+
 /// Current state of preprocessing.
 struct State<'a> {
-    line_num: u32,                  // Current line number
+    line_num: u32,                  // Line number of the current line in the original template
     line: Option<&'a str>,          // Contents of current line
     template: Option<&'a str>,      // Remainder of the template to parse
-    ifdef_count: u32,               // Counter for ifdef/endif validation
+    line_nums: &'a [u32],           // Line numbers in the original template of every line
+    line_index: usize,              // Index of the next line
 }
 
 impl<'a> State<'a> {
 
-    fn new(template: &'a str) -> Self {
+    fn new(template: &'a str, line_nums: &'a [u32]) -> Self {
         let mut result = Self {
             line_num: 0,
             line: None,
             template: Some(template),
-            ifdef_count: 0,
+            line_nums,
+            line_index: 0,
         };
         result.next_line();
         result
@@ -144,6 +238,8 @@ impl<'a> State<'a> {
                 };
                 self.line = line;
                 self.template = template;
+                self.line_num = self.line_nums.get(self.line_index).copied().unwrap_or(self.line_num + 1);
+                self.line_index += 1;
             },
             None => {
                 self.line = None;
@@ -154,7 +250,7 @@ impl<'a> State<'a> {
 }
 
 
-#[derive(Error, Copy, Clone, Eq, PartialEq, Display, Debug)]
+#[derive(Error, Clone, Eq, PartialEq, Display, Debug)]
 #[display(fmt="Preprocessing error on line {line_num}: {kind}")]
 pub struct ShaderDefError {
     pub line_num: u32,
@@ -167,7 +263,7 @@ impl ShaderDefError {
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Display, Debug)]
+#[derive(Clone, Eq, PartialEq, Display, Debug)]
 pub enum ShaderDefErrorKind {
     #[display(fmt="Invalid command")]
     InvalidCommand,
@@ -179,12 +275,22 @@ pub enum ShaderDefErrorKind {
     MissingEndif,
     #[display(fmt="Unexpected #endif")]
     UnexpectedEndif,
+    #[display(fmt="#else or #elifdef outside of an #ifdef block")]
+    UnexpectedElse,
+    #[display(fmt="#else or #elifdef after #else")]
+    ElseAfterElse,
+    #[display(fmt="Included file '{path}' not found")]
+    IncludeNotFound { path: String },
+    #[display(fmt="File '{path}' includes itself")]
+    IncludeCycle { path: String },
+    #[display(fmt="Includes nested too deeply")]
+    IncludeTooDeep,
 }
 
 
 #[cfg(test)]
 mod test {
-    use crate::ShaderPreprocessor;
+    use crate::{ShaderDefError, ShaderDefErrorKind, ShaderPreprocessor};
 
     #[test]
     fn ifdef() {
@@ -270,4 +376,206 @@ This line will be included.
 This is another normal line";
         assert_eq!(Ok(expected.to_owned()), result);
     }
+
+    #[test]
+    fn ifndef_nested_in_skipped_block() {
+        let template =
+"This is a normal line.
+#ifdef HERP
+#ifndef DERP
+This line will be sripped out.
+#endif
+#endif
+This is another normal line";
+        let mut defs = ShaderPreprocessor::new();
+        let result = defs.preprocess(template);
+        let expected =
+"This is a normal line.
+This is another normal line";
+        assert_eq!(Ok(expected.to_owned()), result);
+    }
+
+    #[test]
+    fn else_branches() {
+        let template =
+"This is a normal line.
+#ifdef HERP
+This line will be included.
+#else
+This line will be stripped out.
+#endif
+#ifdef DERP
+This line will be stripped out.
+#else
+This line will be included too.
+#endif";
+        let mut defs = ShaderPreprocessor::new();
+        defs.add("HERP");
+        let result = defs.preprocess(template);
+        let expected =
+"This is a normal line.
+This line will be included.
+This line will be included too.
+";
+        assert_eq!(Ok(expected.to_owned()), result);
+    }
+
+    #[test]
+    fn elifdef_branches() {
+        let template =
+"#ifdef HERP
+Herp
+#elifdef DERP
+Derp
+#elifndef FLERP
+Not flerp
+#else
+Neither
+#endif";
+        let cases: [(&[&str], &str); 4] = [
+            (&["HERP", "DERP"], "Herp\n"),
+            (&["DERP"], "Derp\n"),
+            (&[], "Not flerp\n"),
+            (&["FLERP"], "Neither\n"),
+        ];
+        for (flags, expected) in cases {
+            let mut defs = ShaderPreprocessor::new();
+            for flag in flags {
+                defs.add(*flag);
+            }
+            assert_eq!(Ok(expected.to_owned()), defs.preprocess(template));
+        }
+    }
+
+    #[test]
+    fn else_nested() {
+        let template =
+"#ifdef HERP
+#ifdef DERP
+Herp and derp
+#else
+Herp only
+#endif
+#else
+#ifdef DERP
+Derp only
+#else
+Neither
+#endif
+#endif
+End";
+        let cases: [(&[&str], &str); 4] = [
+            (&["HERP", "DERP"], "Herp and derp\nEnd"),
+            (&["HERP"], "Herp only\nEnd"),
+            (&["DERP"], "Derp only\nEnd"),
+            (&[], "Neither\nEnd"),
+        ];
+        for (flags, expected) in cases {
+            let mut defs = ShaderPreprocessor::new();
+            for flag in flags {
+                defs.add(*flag);
+            }
+            assert_eq!(Ok(expected.to_owned()), defs.preprocess(template));
+        }
+    }
+
+    #[test]
+    fn unexpected_else() {
+        let template =
+"This is a normal line.
+#else
+This is another normal line";
+        let mut defs = ShaderPreprocessor::new();
+        let result = defs.preprocess(template);
+        assert_eq!(Err(ShaderDefError::new(2, ShaderDefErrorKind::UnexpectedElse)), result);
+
+        let template =
+"This is a normal line.
+#elifdef HERP";
+        let result = defs.preprocess(template);
+        assert_eq!(Err(ShaderDefError::new(2, ShaderDefErrorKind::UnexpectedElse)), result);
+    }
+
+    #[test]
+    fn else_after_else() {
+        let template =
+"#ifdef HERP
+#else
+#elifdef DERP
+#endif";
+        let mut defs = ShaderPreprocessor::new();
+        let result = defs.preprocess(template);
+        assert_eq!(Err(ShaderDefError::new(3, ShaderDefErrorKind::ElseAfterElse)), result);
+
+        let template =
+"#ifdef HERP
+#ifdef DERP
+#endif
+#else
+#else
+#endif";
+        let result = defs.preprocess(template);
+        assert_eq!(Err(ShaderDefError::new(5, ShaderDefErrorKind::ElseAfterElse)), result);
+    }
+
+    fn resolve(path: &str) -> Option<String> {
+        match path {
+            "lighting.wgsl" => Some("Lighting line.\n#include \"color.wgsl\"".to_owned()),
+            "color.wgsl" => Some("#ifdef HERP\nColor line.\n#endif".to_owned()),
+            "cycle_a.wgsl" => Some("#include \"cycle_b.wgsl\"".to_owned()),
+            "cycle_b.wgsl" => Some("#include \"cycle_a.wgsl\"".to_owned()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn include_nested() {
+        let template =
+"This is a normal line.
+#include \"lighting.wgsl\"
+This is another normal line";
+        let mut defs = ShaderPreprocessor::new();
+        defs.add("HERP");
+        let result = defs.preprocess_with_includes(template, &resolve);
+        let expected =
+"This is a normal line.
+Lighting line.
+Color line.
+This is another normal line";
+        assert_eq!(Ok(expected.to_owned()), result);
+    }
+
+    #[test]
+    fn include_cycle() {
+        let template =
+"This is a normal line.
+#include \"cycle_a.wgsl\"";
+        let mut defs = ShaderPreprocessor::new();
+        let result = defs.preprocess_with_includes(template, &resolve);
+        let kind = ShaderDefErrorKind::IncludeCycle { path: "cycle_a.wgsl".to_owned() };
+        assert_eq!(Err(ShaderDefError::new(2, kind)), result);
+    }
+
+    #[test]
+    fn include_not_found() {
+        let template =
+"This is a normal line.
+#include \"missing.wgsl\"";
+        let mut defs = ShaderPreprocessor::new();
+        let result = defs.preprocess_with_includes(template, &resolve);
+        let kind = ShaderDefErrorKind::IncludeNotFound { path: "missing.wgsl".to_owned() };
+        assert_eq!(Err(ShaderDefError::new(2, kind.clone())), result);
+        assert_eq!(Err(ShaderDefError::new(2, kind)), defs.preprocess(template));
+    }
+
+    #[test]
+    fn error_line_after_include() {
+        let template =
+"#include \"lighting.wgsl\"
+This is a normal line.
+#endif";
+        let mut defs = ShaderPreprocessor::new();
+        let result = defs.preprocess_with_includes(template, &resolve);
+        assert_eq!(Err(ShaderDefError::new(3, ShaderDefErrorKind::UnexpectedEndif)), result);
+    }
 }
\ No newline at end of file
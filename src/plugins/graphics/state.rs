@@ -1,3 +1,4 @@
+use std::fmt;
 use std::sync::Arc;
 use winit::window::Window;
 use wgpu::*;
@@ -12,11 +13,20 @@ pub struct GraphicsState {
     surface_config: SurfaceConfiguration,
     depth_format: TextureFormat,
     depth_view: TextureView,
+    sample_count: u32,
+    msaa_view: Option<TextureView>,
+    color_features: TextureFormatFeatureFlags,  // Features of the surface's format, used to validate sample counts
+    depth_features: TextureFormatFeatureFlags,  // Features of the depth format, used to validate sample counts
+    adapter_info: AdapterInfo,
 }
 
 impl GraphicsState {
 
-    pub fn new(window: &Window, depth_format: TextureFormat) -> Self {
+    /// Creates state that renders with sample_count samples per pixel.
+    /// A sample_count of 1 disables MSAA.
+    ///
+    /// Panics if sample_count is not 1, 2, 4 or 8, or if the surface or depth format cannot be multisampled that many times.
+    pub fn new(window: &Window, depth_format: TextureFormat, sample_count: u32) -> Self {
         let instance = wgpu::Instance::new(InstanceDescriptor::default());
         let surface = unsafe {
             instance.create_surface(window).expect("Failed to create surface")
@@ -27,7 +37,13 @@ impl GraphicsState {
             force_fallback_adapter: false,
         });
         let adapter = pollster::block_on(adapter).expect("Compatible adapter not found");
-        let device_queue = adapter.request_device(&DeviceDescriptor::default(), None);
+        let adapter_info = adapter.get_info();
+        // Sample counts other than 1 and 4 are only available with adapter specific format features
+        let device_descriptor = DeviceDescriptor {
+            features: adapter.features() & Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+            ..Default::default()
+        };
+        let device_queue = adapter.request_device(&device_descriptor, None);
         let (device, queue) = pollster::block_on(device_queue).expect("Failed to request device");
         let window_size = window.inner_size();
         let surface_config = SurfaceConfiguration {
@@ -35,12 +51,17 @@ impl GraphicsState {
             format: TextureFormat::Bgra8UnormSrgb,
             width: window_size.width,
             height: window_size.height,
-            present_mode: PresentMode::Fifo,
+            present_mode: PresentMode::AutoVsync,
             alpha_mode: CompositeAlphaMode::Auto,
             view_formats: vec![],
         };
         surface.configure(&device, &surface_config);
-        let depth_view = create_depth_view(&device, window_size.width, window_size.height, depth_format);
+        let color_features = format_features(&adapter, &device, surface_config.format);
+        let depth_features = format_features(&adapter, &device, depth_format);
+        let sample_count = sample_count.max(1);
+        assert_sample_count(sample_count, color_features, depth_features);
+        let depth_view = create_depth_view(&device, window_size.width, window_size.height, depth_format, sample_count);
+        let msaa_view = create_msaa_view(&device, &surface_config, sample_count);
         Self {
             device: Arc::new(device),
             queue: Arc::new(queue),
@@ -48,9 +69,19 @@ impl GraphicsState {
             surface_config,
             depth_format,
             depth_view,
+            sample_count,
+            msaa_view,
+            color_features,
+            depth_features,
+            adapter_info,
         }
     }
 
+    /// Info about the GPU and backend being rendered with.
+    pub fn rendering_api_info(&self) -> RenderingApiInfo {
+        RenderingApiInfo::from(&self.adapter_info)
+    }
+
     /// Current texture view to render on.
     pub fn surface(&self) -> &Surface {
         &self.surface
@@ -78,6 +109,59 @@ impl GraphicsState {
         &self.depth_view
     }
 
+    /// Number of samples per pixel that color and depth targets have.
+    /// 1 if MSAA is disabled.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Multisampled color texture to render to before resolving to the surface.
+    /// None if MSAA is disabled.
+    pub fn msaa_view(&self) -> Option<&TextureView> {
+        self.msaa_view.as_ref()
+    }
+
+    /// True if both the surface and depth formats can be rendered to with sample_count samples per pixel.
+    pub fn supports_sample_count(&self, sample_count: u32) -> bool {
+        self.color_features.sample_count_supported(sample_count) &&
+        self.depth_features.sample_count_supported(sample_count)
+    }
+
+    /// Changes the number of samples per pixel, recreating the color and depth targets.
+    /// Pipelines for the new sample count are compiled the next time they are needed.
+    ///
+    /// Panics if sample_count is not 1, 2, 4 or 8, or if it is not supported by the surface or depth format.
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        let sample_count = sample_count.max(1);
+        if sample_count == self.sample_count {
+            return;
+        }
+        assert_sample_count(sample_count, self.color_features, self.depth_features);
+        self.sample_count = sample_count;
+        self.recreate_targets();
+    }
+
+    /// Present mode of the surface.
+    pub fn present_mode(&self) -> PresentMode {
+        self.surface_config.present_mode
+    }
+
+    /// Reconfigures the surface with a new present mode.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        self.surface_config.present_mode = present_mode;
+        self.surface.configure(&self.device, &self.surface_config);
+    }
+
+    /// Enables or disables vsync.
+    /// Uses [`PresentMode::AutoVsync`] or [`PresentMode::AutoNoVsync`] respectively.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        let present_mode = match vsync {
+            true => PresentMode::AutoVsync,
+            false => PresentMode::AutoNoVsync,
+        };
+        self.set_present_mode(present_mode);
+    }
+
     /// Resizes pixel size of surface.
     /// Commonly invoked when window size changes.
     pub(crate) fn resize(&mut self, width: u32, height: u32) {
@@ -85,11 +169,66 @@ impl GraphicsState {
         self.surface_config.width = width;
         self.surface_config.height = height;
         self.surface.configure(&self.device, &self.surface_config);
-        self.depth_view = create_depth_view(&self.device, width, height, self.depth_format);
+        self.recreate_targets();
+    }
+
+    /// Recreates the depth and MSAA textures to match the surface.
+    fn recreate_targets(&mut self) {
+        let (width, height) = (self.surface_config.width, self.surface_config.height);
+        self.depth_view = create_depth_view(&self.device, width, height, self.depth_format, self.sample_count);
+        self.msaa_view = create_msaa_view(&self.device, &self.surface_config, self.sample_count);
+    }
+}
+
+/// Describes the GPU and graphics backend in use.
+/// Useful for bug reports, and for selecting rendering code paths based on the backend.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct RenderingApiInfo {
+    /// Graphics backend, IE: "Vulkan", "Metal", "Dx12" or "Gl".
+    pub backend: String,
+    pub device_name: String,
+    /// Name and version of the driver, if reported.
+    pub driver_info: String,
+}
+
+impl From<&AdapterInfo> for RenderingApiInfo {
+    fn from(info: &AdapterInfo) -> Self {
+        let driver_info = match (info.driver.is_empty(), info.driver_info.is_empty()) {
+            (false, false) => format!("{} {}", info.driver, info.driver_info),
+            (false, true) => info.driver.clone(),
+            _ => info.driver_info.clone(),
+        };
+        Self {
+            backend: format!("{:?}", info.backend),
+            device_name: info.name.clone(),
+            driver_info,
+        }
     }
 }
 
-fn create_depth_view(device: &Device, width: u32, height: u32, format: TextureFormat) -> TextureView {
+impl fmt::Display for RenderingApiInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on {} ({})", self.backend, self.device_name, self.driver_info)
+    }
+}
+
+/// Features of a format on the device.
+/// Only the guaranteed features if the device was created without adapter specific format features.
+fn format_features(adapter: &Adapter, device: &Device, format: TextureFormat) -> TextureFormatFeatureFlags {
+    match device.features().contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+        true => adapter.get_texture_format_features(format).flags,
+        false => format.guaranteed_format_features(device.features()).flags,
+    }
+}
+
+/// Multisampling a target with an unsupported sample count is a validation error, so it is caught early.
+fn assert_sample_count(sample_count: u32, color_features: TextureFormatFeatureFlags, depth_features: TextureFormatFeatureFlags) {
+    assert!(matches!(sample_count, 1 | 2 | 4 | 8), "Sample count must be 1, 2, 4 or 8, got {sample_count}");
+    assert!(color_features.sample_count_supported(sample_count), "Surface format does not support a sample count of {sample_count}");
+    assert!(depth_features.sample_count_supported(sample_count), "Depth format does not support a sample count of {sample_count}");
+}
+
+fn create_depth_view(device: &Device, width: u32, height: u32, format: TextureFormat, sample_count: u32) -> TextureView {
     let texture = device.create_texture(&TextureDescriptor {
         label: Some("depth_texture"),
         size: Extent3d {
@@ -98,11 +237,32 @@ fn create_depth_view(device: &Device, width: u32, height: u32, format: TextureFo
             depth_or_array_layers: 1,
         },
         mip_level_count: 1,
-        sample_count: 1,
+        sample_count,
         dimension: TextureDimension::D2,
         format,
         usage: TextureUsages::RENDER_ATTACHMENT,
         view_formats: &[],
     });
     texture.create_view(&TextureViewDescriptor::default())
+}
+
+fn create_msaa_view(device: &Device, surface_config: &SurfaceConfiguration, sample_count: u32) -> Option<TextureView> {
+    if sample_count == 1 {
+        return None;
+    }
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("msaa_texture"),
+        size: Extent3d {
+            width: surface_config.width,
+            height: surface_config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: TextureDimension::D2,
+        format: surface_config.format,
+        usage: TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    Some(texture.create_view(&TextureViewDescriptor::default()))
 }
\ No newline at end of file
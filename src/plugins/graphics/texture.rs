@@ -1,6 +1,8 @@
 use std::io::Cursor;
 use std::sync::Arc;
+use glam::UVec2;
 use image::{DynamicImage, ImageFormat};
+use image::imageops::FilterType;
 use wgpu::{AddressMode, BindGroupEntry, BindGroupLayoutEntry, BindingResource, BindingType, Device, Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, SamplerBindingType, SamplerDescriptor, ShaderStages, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureViewDescriptor, TextureViewDimension};
 use image::io::Reader as ImageReader;
 use derive_more::*;
@@ -10,6 +12,63 @@ use crate::{Asset, AssetLoader, AssetPath};
 pub struct TextureLoader {
     pub device: Arc<Device>,
     pub queue: Arc<Queue>,
+    pub config: TextureLoaderConfig,
+}
+
+impl TextureLoader {
+
+    /// Loader with the default [`TextureLoaderConfig`].
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>) -> Self {
+        Self { device, queue, config: TextureLoaderConfig::default() }
+    }
+
+    pub fn with_config(mut self, config: TextureLoaderConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Uploads the texels of a single mip level.
+    fn write_level(&self, texture: &wgpu::Texture, mip_level: u32, tex_data: &TextureData) {
+        let copy_texture = ImageCopyTexture {
+            texture,
+            mip_level,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        };
+        let layout = ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(tex_data.width * tex_data.format.pixel_size() as u32),
+            rows_per_image: None,
+        };
+        let size = Extent3d {
+            width: tex_data.width,
+            height: tex_data.height,
+            depth_or_array_layers: 1,
+        };
+        self.queue.write_texture(copy_texture, &tex_data.data, layout, size);
+    }
+}
+
+/// How a [`TextureLoader`] creates textures.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct TextureLoaderConfig {
+    /// If true, textures get a full mip chain, downsampled on the CPU. Reduces aliasing of distant textures.
+    pub generate_mipmaps: bool,
+    /// If true, 8-bit images are decoded from sRGB. Should be false for data textures, IE: normal maps.
+    /// Materials still read data textures decoded from sRGB correctly, at the cost of precision in dark values.
+    pub srgb: bool,
+    /// How textures are sampled outside of the 0-1 range.
+    pub address_mode: AddressMode,
+}
+
+impl Default for TextureLoaderConfig {
+    fn default() -> Self {
+        Self {
+            generate_mipmaps: false,
+            srgb: true,
+            address_mode: AddressMode::Repeat,
+        }
+    }
 }
 
 impl AssetLoader for TextureLoader {
@@ -24,7 +83,21 @@ impl AssetLoader for TextureLoader {
         let mut reader = ImageReader::new(Cursor::new(bytes));
         reader.set_format(format);
         let dyn_img = reader.decode()?;
-        let tex_data = get_texture_data(dyn_img, true);
+
+        // Downsamples every mip level below the base from the full image
+        let (width, height) = (dyn_img.width(), dyn_img.height());
+        let mip_level_count = match self.config.generate_mipmaps {
+            true => mip_level_count(width, height),
+            false => 1,
+        };
+        let mip_images: Vec<DynamicImage> = (1..mip_level_count)
+            .map(|mip_level| {
+                let (mip_width, mip_height) = mip_size(width, height, mip_level);
+                dyn_img.resize_exact(mip_width, mip_height, FilterType::Triangle)
+            })
+            .collect();
+
+        let tex_data = get_texture_data(dyn_img, self.config.srgb);
         let size = Extent3d {
             width: tex_data.width,
             height: tex_data.height,
@@ -33,7 +106,7 @@ impl AssetLoader for TextureLoader {
         let texture = self.device.create_texture(&TextureDescriptor {
             label: None,
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: TextureDimension::D2,
             format: tex_data.format,
@@ -41,29 +114,26 @@ impl AssetLoader for TextureLoader {
             view_formats: &[],
         });
         let view = texture.create_view(&TextureViewDescriptor::default());
-        let copy_texture = ImageCopyTexture {
-            texture: &texture,
-            mip_level: 0,
-            origin: Origin3d::ZERO,
-            aspect: TextureAspect::All,
-        };
-        let layout = ImageDataLayout {
-            offset: 0,
-            bytes_per_row: Some(tex_data.width * tex_data.format.pixel_size() as u32),
-            rows_per_image: None,
+        self.write_level(&texture, 0, &tex_data);
+        for (mip_image, mip_level) in mip_images.into_iter().zip(1..) {
+            self.write_level(&texture, mip_level, &get_texture_data(mip_image, self.config.srgb));
+        }
+        let mipmap_filter = match mip_level_count {
+            1 => FilterMode::Nearest,
+            _ => FilterMode::Linear,
         };
-        self.queue.write_texture(copy_texture, &tex_data.data, layout, size);
+        let address_mode = self.config.address_mode;
         let sampler = self.device.create_sampler(&SamplerDescriptor {
             label: None,
-            address_mode_u: AddressMode::Repeat,
-            address_mode_v: AddressMode::Repeat,
-            address_mode_w: AddressMode::Repeat,
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
             mag_filter: FilterMode::Nearest,
             min_filter: FilterMode::Nearest,
-            mipmap_filter: FilterMode::Nearest,
+            mipmap_filter,
             ..Default::default()
         });
-        Ok(Texture { view, sampler })
+        Ok(Texture { view, sampler, size: UVec2::new(tex_data.width, tex_data.height), srgb: tex_data.format.is_srgb() })
     }
 
     fn extensions(&self) -> &[&str] {
@@ -71,6 +141,16 @@ impl AssetLoader for TextureLoader {
     }
 }
 
+/// Number of mip levels in a full mip chain, down to 1x1.
+fn mip_level_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+/// Width and height of a mip level. Neither is ever less than 1.
+fn mip_size(width: u32, height: u32, mip_level: u32) -> (u32, u32) {
+    ((width >> mip_level).max(1), (height >> mip_level).max(1))
+}
+
 struct TextureData {
     data: Vec<u8>,
     width: u32,
@@ -227,6 +307,10 @@ pub struct TextureEntries<'a> {
 pub struct Texture {
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
+    /// Width and height in texels.
+    pub size: UVec2,
+    /// If true, texels are decoded from sRGB when sampled.
+    pub srgb: bool,
 }
 
 impl Texture {
@@ -288,4 +372,19 @@ impl TextureFormatPixelInfo for TextureFormat {
             _ => panic!("Using pixel_size for compressed textures is invalid"),
         }
     }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{mip_level_count, mip_size};
+
+    #[test]
+    fn mip_chains() {
+        assert_eq!(1, mip_level_count(1, 1));
+        assert_eq!(9, mip_level_count(256, 256));
+        assert_eq!(9, mip_level_count(300, 20));
+        assert_eq!((37, 2), mip_size(300, 20, 3));
+        assert_eq!((1, 1), mip_size(300, 20, 8));
+    }
 }
\ No newline at end of file
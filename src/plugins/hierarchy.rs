@@ -0,0 +1,309 @@
+use hecs::{Entity, World};
+use smallvec::SmallVec;
+use derive_more::*;
+use crate::math::Transform;
+use crate::{EntityTarget, Game, HashSet, RunContext};
+
+/**
+ * Component that attaches an entity to another, making its [`Transform`] relative to the other's [`GlobalTransform`].
+ * Kept consistent with the parent's [`Children`] by [`HierarchyExt::attach`] and [`HierarchyExt::detach`].
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Parent(pub Entity);
+
+/**
+ * Component listing the entities attached to an entity, in the order they were attached.
+ */
+#[derive(Clone, Default, Eq, PartialEq, Debug)]
+pub struct Children(pub SmallVec<[Entity; 8]>);
+
+/**
+ * Component with the world transform of an entity, computed from its [`Transform`] and those of its ancestors.
+ * Added to every entity in a hierarchy, and updated at [`crate::Stage::PostUpdate`].
+ * When present, it is what renderables and sprites are synced to, instead of the [`Transform`].
+ */
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct GlobalTransform(pub Transform);
+
+/// Ways that attaching or detaching an entity can fail.
+#[derive(Error, Display, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum HierarchyError {
+    #[display(fmt="No such entity")]
+    NoSuchEntity,
+    #[display(fmt="Entity cannot be attached to itself or its descendants")]
+    Cycle,
+}
+
+/// Methods for attaching entities of a [`World`] to each other.
+pub trait HierarchyExt {
+
+    /**
+     * Attaches child to parent, detaching it from its current parent if any.
+     * The child's [`Transform`] becomes relative to the parent, and is left as is.
+     */
+    fn attach(&mut self, child: Entity, parent: Entity) -> Result<(), HierarchyError>;
+
+    /**
+     * Detaches child from its parent, if it has one.
+     * Its world transform is baked into its [`Transform`], so that it stays where it was.
+     */
+    fn detach(&mut self, child: Entity) -> Result<(), HierarchyError>;
+}
+
+impl HierarchyExt for World {
+
+    fn attach(&mut self, child: Entity, parent: Entity) -> Result<(), HierarchyError> {
+        if !self.contains(child) || !self.contains(parent) {
+            return Err(HierarchyError::NoSuchEntity);
+        }
+
+        // Ensures the parent is not the child itself, or one of its descendants
+        let mut next = Some(parent);
+        while let Some(ancestor) = next {
+            if ancestor == child {
+                return Err(HierarchyError::Cycle);
+            }
+            next = self.get::<&Parent>(ancestor).ok().map(|parent| parent.0);
+        }
+
+        remove_child(self, child);
+        self.insert_one(child, Parent(parent)).unwrap();
+        match self.query_one_mut::<&mut Children>(parent) {
+            Ok(children) => children.0.push(child),
+            Err(_) => self.insert_one(parent, Children(SmallVec::from_slice(&[child]))).unwrap(),
+        }
+        Ok(())
+    }
+
+    fn detach(&mut self, child: Entity) -> Result<(), HierarchyError> {
+        if !self.contains(child) {
+            return Err(HierarchyError::NoSuchEntity);
+        }
+        if self.get::<&Parent>(child).is_err() {
+            return Ok(());
+        }
+        remove_child(self, child);
+        bake_global_transform(self, child);
+        self.remove_one::<Parent>(child).unwrap();
+        Ok(())
+    }
+}
+
+/// Attaches and detaches entities at the end of the current [`Stage`](crate::Stage).
+impl<'a> RunContext<'a> {
+
+    /// Attaches child to parent at the end of the stage. See [`HierarchyExt::attach`].
+    pub fn attach(&mut self, child: impl EntityTarget, parent: impl EntityTarget) {
+        self.run_command(move |game: &mut Game| {
+            let (Some(child), Some(parent)) = (child.resolve(), parent.resolve()) else {
+                log::warn!("Failed to attach entity: placeholder was never spawned");
+                return;
+            };
+            let Some(mut world) = game.try_get::<&mut World>() else {
+                log::warn!("Failed to attach entity {child:?}: World not found");
+                return;
+            };
+            if let Err(err) = world.attach(child, parent) {
+                log::warn!("Failed to attach entity {child:?} to {parent:?}: {err}");
+            }
+        });
+    }
+
+    /// Detaches child from its parent at the end of the stage. See [`HierarchyExt::detach`].
+    pub fn detach(&mut self, child: impl EntityTarget) {
+        self.run_command(move |game: &mut Game| {
+            let Some(child) = child.resolve() else {
+                log::warn!("Failed to detach entity: placeholder was never spawned");
+                return;
+            };
+            let Some(mut world) = game.try_get::<&mut World>() else {
+                log::warn!("Failed to detach entity {child:?}: World not found");
+                return;
+            };
+            if let Err(err) = world.detach(child) {
+                log::warn!("Failed to detach entity {child:?}: {err}");
+            }
+        });
+    }
+}
+
+pub(crate) fn propagate_transforms(game: &mut Game, _ctx: RunContext) {
+    let mut world = game.get::<&mut World>();
+    propagate(&mut world);
+}
+
+/**
+ * Computes the [`GlobalTransform`] of every entity in a hierarchy, and of every root that has one.
+ * Entities whose parent was despawned are detached first, keeping their last world transform.
+ */
+fn propagate(world: &mut World) {
+
+    // Detaches orphans
+    let orphans: Vec<Entity> = world.query::<&Parent>()
+        .iter()
+        .filter(|(_, parent)| !world.contains(parent.0))
+        .map(|(entity, _)| entity)
+        .collect();
+    for orphan in orphans {
+        bake_global_transform(world, orphan);
+        world.remove_one::<Parent>(orphan).unwrap();
+    }
+
+    // Roots are at their own transforms
+    for (_, (transform, global)) in world.query_mut::<(&Transform, &mut GlobalTransform)>().without::<&Parent>() {
+        global.0 = *transform;
+    }
+
+    // Walks down from roots, skipping children that were despawned or attached elsewhere
+    let mut globals = Vec::new();
+    let mut stale = Vec::new();
+    let mut stack = Vec::new();
+    for (root, (transform, children)) in world.query::<(Option<&Transform>, &Children)>().without::<&Parent>().iter() {
+        let transform = transform.copied().unwrap_or(Transform::IDENTITY);
+        globals.push((root, transform));
+        stack.push((root, transform, children.0.clone()));
+    }
+    while let Some((parent, parent_global, children)) = stack.pop() {
+        for child in children {
+            let is_attached = world.get::<&Parent>(child).is_ok_and(|child_parent| child_parent.0 == parent);
+            if !is_attached {
+                stale.push((parent, child));
+                continue;
+            }
+            let transform = world.get::<&Transform>(child).map(|transform| *transform).unwrap_or(Transform::IDENTITY);
+            let global = parent_global * transform;
+            globals.push((child, global));
+            if let Ok(grandchildren) = world.get::<&Children>(child) {
+                stack.push((child, global, grandchildren.0.clone()));
+            }
+        }
+    }
+
+    // Writes world transforms, and forgets children that are no longer attached
+    if cfg!(debug_assertions) {
+        check_cycles(world, &globals);
+    }
+    for (entity, global) in globals {
+        match world.query_one_mut::<&mut GlobalTransform>(entity) {
+            Ok(current) => current.0 = global,
+            Err(_) => world.insert_one(entity, GlobalTransform(global)).unwrap(),
+        }
+    }
+    for (parent, child) in stale {
+        if let Ok(mut children) = world.get::<&mut Children>(parent) {
+            children.0.retain(|other| *other != child);
+        }
+    }
+}
+
+/// Logs an error for every entity whose ancestors loop back to it, and which was therefore not reached from a root.
+fn check_cycles(world: &World, reached: &[(Entity, Transform)]) {
+    let reached: HashSet<Entity> = reached.iter().map(|(entity, _)| *entity).collect();
+    for (entity, parent) in world.query::<&Parent>().iter() {
+        if reached.contains(&entity) { continue }
+        let mut visited = HashSet::default();
+        let mut next = Some(parent.0);
+        while let Some(ancestor) = next {
+            if ancestor == entity {
+                log::error!("Entity {entity:?} is its own ancestor, so its GlobalTransform is not updated");
+                break;
+            }
+            if !visited.insert(ancestor) { break }
+            next = world.get::<&Parent>(ancestor).ok().map(|parent| parent.0);
+        }
+    }
+}
+
+/// Removes an entity from the children of its parent, if it has one.
+fn remove_child(world: &mut World, child: Entity) {
+    let Ok(parent) = world.get::<&Parent>(child).map(|parent| parent.0) else { return };
+    if let Ok(mut children) = world.get::<&mut Children>(parent) {
+        children.0.retain(|other| *other != child);
+    }
+}
+
+/// Replaces the transform of an entity with its world transform.
+fn bake_global_transform(world: &mut World, entity: Entity) {
+    let Ok(global) = world.get::<&GlobalTransform>(entity).map(|global| global.0) else { return };
+    match world.query_one_mut::<&mut Transform>(entity) {
+        Ok(transform) => *transform = global,
+        Err(_) => world.insert_one(entity, global).unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::Vec3;
+    use hecs::World;
+    use crate::math::Transform;
+    use crate::{Children, GlobalTransform, HierarchyError, HierarchyExt, Parent};
+    use super::propagate;
+
+    fn global(world: &World, entity: hecs::Entity) -> Vec3 {
+        world.get::<&GlobalTransform>(entity).unwrap().0.translation
+    }
+
+    #[test]
+    fn propagates_down_hierarchy() {
+        let mut world = World::new();
+        let tank = world.spawn((Transform::default().with_xyz(10.0, 0.0, 0.0),));
+        let turret = world.spawn((Transform::default().with_xyz(0.0, 2.0, 0.0),));
+        let barrel = world.spawn((Transform::default().with_xyz(0.0, 0.0, 3.0).with_scale_xyz(2.0, 2.0, 2.0),));
+        let flag = world.spawn((Transform::default().with_xyz(1.0, 0.0, 0.0),));
+        world.attach(turret, tank).unwrap();
+        world.attach(barrel, turret).unwrap();
+        world.attach(flag, barrel).unwrap();
+        propagate(&mut world);
+        assert_eq!(Vec3::new(10.0, 0.0, 0.0), global(&world, tank));
+        assert_eq!(Vec3::new(10.0, 2.0, 0.0), global(&world, turret));
+        assert_eq!(Vec3::new(10.0, 2.0, 3.0), global(&world, barrel));
+        assert_eq!(Vec3::new(12.0, 2.0, 3.0), global(&world, flag));
+
+        // Moving the root moves every descendant.
+        world.get::<&mut Transform>(tank).unwrap().translation.x = 0.0;
+        propagate(&mut world);
+        assert_eq!(Vec3::new(2.0, 2.0, 3.0), global(&world, flag));
+    }
+
+    #[test]
+    fn attach_and_detach() {
+        let mut world = World::new();
+        let a = world.spawn((Transform::default().with_xyz(1.0, 0.0, 0.0),));
+        let b = world.spawn((Transform::default().with_xyz(2.0, 0.0, 0.0),));
+        let child = world.spawn((Transform::default().with_xyz(0.0, 1.0, 0.0),));
+        world.attach(child, a).unwrap();
+        assert_eq!(Err(HierarchyError::Cycle), world.attach(a, child));
+        assert_eq!(Err(HierarchyError::Cycle), world.attach(a, a));
+
+        // Reattaching moves the child between both lists of children.
+        world.attach(child, b).unwrap();
+        assert!(world.get::<&Children>(a).unwrap().0.is_empty());
+        assert_eq!([child].as_slice(), world.get::<&Children>(b).unwrap().0.as_slice());
+        propagate(&mut world);
+        assert_eq!(Vec3::new(2.0, 1.0, 0.0), global(&world, child));
+
+        // Detaching keeps the world transform.
+        world.detach(child).unwrap();
+        assert!(world.get::<&Parent>(child).is_err());
+        assert!(world.get::<&Children>(b).unwrap().0.is_empty());
+        assert_eq!(Vec3::new(2.0, 1.0, 0.0), world.get::<&Transform>(child).unwrap().translation);
+        propagate(&mut world);
+        assert_eq!(Vec3::new(2.0, 1.0, 0.0), global(&world, child));
+    }
+
+    #[test]
+    fn orphans_keep_world_transform() {
+        let mut world = World::new();
+        let parent = world.spawn((Transform::default().with_xyz(5.0, 0.0, 0.0),));
+        let child = world.spawn((Transform::default().with_xyz(0.0, 1.0, 0.0),));
+        let grandchild = world.spawn((Transform::default().with_xyz(0.0, 1.0, 0.0),));
+        world.attach(child, parent).unwrap();
+        world.attach(grandchild, child).unwrap();
+        propagate(&mut world);
+        world.despawn(parent).unwrap();
+        propagate(&mut world);
+        assert!(world.get::<&Parent>(child).is_err());
+        assert_eq!(Vec3::new(5.0, 1.0, 0.0), world.get::<&Transform>(child).unwrap().translation);
+        assert_eq!(Vec3::new(5.0, 2.0, 0.0), global(&world, grandchild));
+    }
+}
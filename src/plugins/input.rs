@@ -1,9 +1,9 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::hash::Hash;
 use glam::Vec2;
 use winit::keyboard::KeyCode;
 use winit::window::Fullscreen;
-use crate::{AppBuilder, Game, HashSet, Plugin, RunContext, Stage};
+use crate::{AppBuilder, Game, HashMap, HashSet, Plugin, RunContext, Stage};
 
 pub struct InputPlugin;
 impl Plugin for InputPlugin {
@@ -11,7 +11,8 @@ impl Plugin for InputPlugin {
         builder.game()
             .add(WindowRequests::default())
             .add(Keyboard::default())
-            .add(Cursor::default());
+            .add(Cursor::default())
+            .add(Gamepads::default());
         builder.system(Stage::Cleanup, sync_inputs);
     }
 }
@@ -27,6 +28,8 @@ pub struct Cursor {
     pub(crate) scroll: Vec2,
     pub(crate) is_grabbed: bool,
     pub(crate) is_visible: bool,
+    pub(crate) buttons: ButtonState<MouseButton>,
+    pub(crate) press_positions: HashMap<MouseButton, Vec2>,
 }
 
 impl Cursor {
@@ -52,9 +55,54 @@ impl Cursor {
         self.scroll
     }
 
+    /**
+     * True if a mouse button is pressed.
+    */
+    pub fn is_pressed(&self, button: MouseButton) -> bool {
+        self.buttons.is_pressed(button)
+    }
+
+    /**
+     * True if a mouse button is pressed, but wasn't in the previous tick.
+    */
+    pub fn is_just_pressed(&self, button: MouseButton) -> bool {
+        self.buttons.is_just_pressed(button)
+    }
+
+    /**
+     * True if a mouse button is not pressed, but was in the previous tick.
+    */
+    pub fn is_just_released(&self, button: MouseButton) -> bool {
+        self.buttons.is_just_released(button)
+    }
+
+    /**
+     * Position of the cursor when the mouse button was last pressed.
+     * None if it was never pressed.
+    */
+    pub fn press_position(&self, button: MouseButton) -> Option<Vec2> {
+        self.press_positions.get(&button).copied()
+    }
+
+    /**
+     * Simulates a mouse button press at the current cursor position.
+    */
+    pub fn press(&mut self, button: MouseButton) {
+        self.buttons.press(button);
+        self.press_positions.insert(button, self.position);
+    }
+
+    /**
+     * Simulates a mouse button release.
+    */
+    pub fn release(&mut self, button: MouseButton) {
+        self.buttons.release(button);
+    }
+
     pub(crate) fn sync(&mut self) {
         self.movement = Vec2::ZERO;
         self.scroll = Vec2::ZERO;
+        self.buttons.sync_previous_state();
     }
 }
 
@@ -66,6 +114,32 @@ impl Default for Cursor {
             scroll: Vec2::ZERO,
             is_grabbed: false,
             is_visible: true,
+            buttons: ButtonState::default(),
+            press_positions: HashMap::default(),
+        }
+    }
+}
+
+/// Button on a mouse.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+    Other(u16),
+}
+
+impl From<winit::event::MouseButton> for MouseButton {
+    fn from(button: winit::event::MouseButton) -> Self {
+        match button {
+            winit::event::MouseButton::Left => Self::Left,
+            winit::event::MouseButton::Right => Self::Right,
+            winit::event::MouseButton::Middle => Self::Middle,
+            winit::event::MouseButton::Back => Self::Back,
+            winit::event::MouseButton::Forward => Self::Forward,
+            winit::event::MouseButton::Other(code) => Self::Other(code),
         }
     }
 }
@@ -191,11 +265,236 @@ where
 }
 
 
+/// Identifies a single connected gamepad.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct GamepadId(pub usize);
+
+/// Fired when a gamepad is connected, or reconnected.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct GamepadConnected(pub GamepadId);
+
+/// Fired when a gamepad is disconnected.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct GamepadDisconnected(pub GamepadId);
+
+/// Button on a gamepad, named by position rather than by label.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    Mode,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+/// Analog axis on a gamepad.
+/// Sticks range from -1.0 to 1.0, and triggers range from 0.0 to 1.0.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+/**
+ * Change to a gamepad, as reported by the gamepad backend.
+ * Mirrors the events of gilrs, with triggers reported as axes rather than buttons.
+ */
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GamepadEvent {
+    Connected,
+    Disconnected,
+    ButtonPressed(GamepadButton),
+    ButtonReleased(GamepadButton),
+    AxisChanged(GamepadAxis, f32),
+}
+
+/// State of a single gamepad.
+#[derive(Default)]
+pub struct Gamepad {
+    buttons: ButtonState<GamepadButton>,
+    axes: HashMap<GamepadAxis, f32>,
+    dead_zone: f32,
+}
+
+impl Gamepad {
+
+    /**
+     * True if a button is pressed.
+    */
+    pub fn is_pressed(&self, button: GamepadButton) -> bool {
+        self.buttons.is_pressed(button)
+    }
+
+    /**
+     * True if a button is pressed, but wasn't in the previous tick.
+    */
+    pub fn is_just_pressed(&self, button: GamepadButton) -> bool {
+        self.buttons.is_just_pressed(button)
+    }
+
+    /**
+     * True if a button is not pressed, but was in the previous tick.
+    */
+    pub fn is_just_released(&self, button: GamepadButton) -> bool {
+        self.buttons.is_just_released(button)
+    }
+
+    /**
+     * Value of an axis, after applying the dead zone.
+     * Values within the dead zone are 0.0, and the remaining range is rescaled so that it still reaches 1.0.
+    */
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        let value = self.raw_axis(axis);
+        if value.abs() <= self.dead_zone {
+            return 0.0;
+        }
+        value.signum() * (value.abs() - self.dead_zone) / (1.0 - self.dead_zone)
+    }
+
+    /**
+     * Value of an axis, ignoring the dead zone.
+    */
+    pub fn raw_axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes.get(&axis).copied().unwrap_or(0.0)
+    }
+
+    /**
+     * Simulates a button press.
+    */
+    pub fn press(&mut self, button: GamepadButton) {
+        self.buttons.press(button);
+    }
+
+    /**
+     * Simulates a button release.
+    */
+    pub fn release(&mut self, button: GamepadButton) {
+        self.buttons.release(button);
+    }
+
+    /**
+     * Simulates moving an axis.
+    */
+    pub fn set_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.axes.insert(axis, value);
+    }
+
+    /**
+     * Sync previous button state with current button state.
+    */
+    pub fn sync(&mut self) {
+        self.buttons.sync_previous_state();
+    }
+}
+
+/**
+ * All connected gamepads.
+ * Polled by the window runner once per frame, before game logic runs.
+ */
+pub struct Gamepads {
+    gamepads: BTreeMap<GamepadId, Gamepad>,
+    dead_zone: f32,
+}
+
+impl Default for Gamepads {
+    fn default() -> Self {
+        Self {
+            gamepads: BTreeMap::new(),
+            dead_zone: 0.1,
+        }
+    }
+}
+
+impl Gamepads {
+
+    pub fn get(&self, id: GamepadId) -> Option<&Gamepad> {
+        self.gamepads.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: GamepadId) -> Option<&mut Gamepad> {
+        self.gamepads.get_mut(&id)
+    }
+
+    /// Connected gamepads, ordered by id.
+    pub fn iter(&self) -> impl Iterator<Item = (GamepadId, &Gamepad)> {
+        self.gamepads.iter().map(|(id, gamepad)| (*id, gamepad))
+    }
+
+    pub fn is_connected(&self, id: GamepadId) -> bool {
+        self.gamepads.contains_key(&id)
+    }
+
+    pub fn dead_zone(&self) -> f32 {
+        self.dead_zone
+    }
+
+    /// Sets the dead zone of all gamepads, including those connected later.
+    pub fn set_dead_zone(&mut self, dead_zone: f32) {
+        self.dead_zone = dead_zone.clamp(0.0, 0.99);
+        for gamepad in self.gamepads.values_mut() {
+            gamepad.dead_zone = self.dead_zone;
+        }
+    }
+
+    /// Simulates connecting a gamepad.
+    /// Returns the gamepad, which starts with no buttons pressed.
+    pub fn connect(&mut self, id: GamepadId) -> &mut Gamepad {
+        let gamepad = self.gamepads.entry(id).or_default();
+        gamepad.dead_zone = self.dead_zone;
+        gamepad
+    }
+
+    /// Simulates disconnecting a gamepad.
+    pub fn disconnect(&mut self, id: GamepadId) {
+        self.gamepads.remove(&id);
+    }
+
+    /// Simulates an event of a gamepad.
+    /// Events other than disconnection connect the gamepad if it is not already.
+    pub fn apply(&mut self, id: GamepadId, event: GamepadEvent) {
+        match event {
+            GamepadEvent::Connected => { self.connect(id); },
+            GamepadEvent::Disconnected => self.disconnect(id),
+            GamepadEvent::ButtonPressed(button) => self.connect(id).press(button),
+            GamepadEvent::ButtonReleased(button) => self.connect(id).release(button),
+            GamepadEvent::AxisChanged(axis, value) => self.connect(id).set_axis(axis, value),
+        }
+    }
+
+    /**
+     * Sync previous button state with current button state, for every gamepad.
+    */
+    pub fn sync(&mut self) {
+        for gamepad in self.gamepads.values_mut() {
+            gamepad.sync();
+        }
+    }
+}
+
+
 fn sync_inputs(game: &mut Game, _ctx: RunContext) {
     let mut keyboard = game.get::<&mut Keyboard>();
     let mut cursor = game.get::<&mut Cursor>();
+    let mut gamepads = game.get::<&mut Gamepads>();
     keyboard.sync();
     cursor.sync();
+    gamepads.sync();
 }
 
 /// Queue of requests to dispatch to the application's window.
@@ -219,6 +518,18 @@ impl WindowRequests {
         self.push(WindowRequest::SetFullscreen(fullscreen));
     }
 
+    /// Enables or disables vsync.
+    /// Disabling vsync lowers latency at the cost of possible tearing.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.push(WindowRequest::SetVsync(vsync));
+    }
+
+    /// Changes the window's title.
+    /// Does nothing if the title is unchanged, so it can be set every frame.
+    pub fn set_title(&mut self, title: impl Into<String>) {
+        self.push(WindowRequest::SetTitle(title.into()));
+    }
+
     pub fn push(&mut self, request: WindowRequest) {
         self.0.push_back(request);
     }
@@ -236,4 +547,137 @@ pub enum WindowRequest {
     SetCursorVisible(bool),
     SetCursorGrab(bool),
     SetFullscreen(Option<Fullscreen>),
-}
\ No newline at end of file
+    SetVsync(bool),
+    SetTitle(String),
+}
+
+
+#[cfg(test)]
+mod test {
+    use glam::Vec2;
+    use crate::{App, Cursor, InputPlugin, MouseButton};
+    use super::{GamepadAxis, GamepadButton, GamepadEvent, GamepadId, Gamepads};
+
+    const PAD: GamepadId = GamepadId(0);
+
+    #[test]
+    fn dead_zone_filters_axes() {
+        let mut gamepads = Gamepads::default();
+        gamepads.set_dead_zone(0.2);
+        gamepads.apply(PAD, GamepadEvent::AxisChanged(GamepadAxis::LeftStickX, 0.15));
+        gamepads.apply(PAD, GamepadEvent::AxisChanged(GamepadAxis::LeftStickY, -0.6));
+        gamepads.apply(PAD, GamepadEvent::AxisChanged(GamepadAxis::RightTrigger, 1.0));
+        let gamepad = gamepads.get(PAD).unwrap();
+        assert_eq!(0.0, gamepad.axis(GamepadAxis::LeftStickX));
+        assert_eq!(0.15, gamepad.raw_axis(GamepadAxis::LeftStickX));
+        assert!((gamepad.axis(GamepadAxis::LeftStickY) + 0.5).abs() < 1e-6);
+        assert_eq!(1.0, gamepad.axis(GamepadAxis::RightTrigger));
+        assert_eq!(0.0, gamepad.axis(GamepadAxis::RightStickX));
+
+        // Gamepads connected later share the dead zone
+        gamepads.apply(GamepadId(1), GamepadEvent::AxisChanged(GamepadAxis::LeftStickX, 0.15));
+        assert_eq!(0.0, gamepads.get(GamepadId(1)).unwrap().axis(GamepadAxis::LeftStickX));
+    }
+
+    #[test]
+    fn buttons_match_keyboard_semantics() {
+        let mut gamepads = Gamepads::default();
+        gamepads.apply(PAD, GamepadEvent::Connected);
+        gamepads.apply(PAD, GamepadEvent::ButtonPressed(GamepadButton::South));
+        let gamepad = gamepads.get(PAD).unwrap();
+        assert!(gamepad.is_pressed(GamepadButton::South));
+        assert!(gamepad.is_just_pressed(GamepadButton::South));
+        assert!(!gamepad.is_just_released(GamepadButton::South));
+
+        gamepads.sync();
+        let gamepad = gamepads.get(PAD).unwrap();
+        assert!(gamepad.is_pressed(GamepadButton::South));
+        assert!(!gamepad.is_just_pressed(GamepadButton::South));
+
+        gamepads.apply(PAD, GamepadEvent::ButtonReleased(GamepadButton::South));
+        let gamepad = gamepads.get(PAD).unwrap();
+        assert!(!gamepad.is_pressed(GamepadButton::South));
+        assert!(gamepad.is_just_released(GamepadButton::South));
+
+        gamepads.sync();
+        assert!(!gamepads.get(PAD).unwrap().is_just_released(GamepadButton::South));
+    }
+
+    /// App whose inputs are synced on cleanup, once per tick.
+    fn input_app() -> App {
+        let mut builder = App::builder();
+        builder.plugin(InputPlugin);
+        builder.build()
+    }
+
+    fn tick(app: &mut App) {
+        let tick_duration = app.tick_duration();
+        app.run_frame(tick_duration);
+    }
+
+    #[test]
+    fn mouse_buttons_match_keyboard_semantics() {
+        let mut app = input_app();
+        app.game.get::<&mut Cursor>().press(MouseButton::Left);
+        {
+            let cursor = app.game.get::<&Cursor>();
+            assert!(cursor.is_pressed(MouseButton::Left));
+            assert!(cursor.is_just_pressed(MouseButton::Left));
+            assert!(!cursor.is_just_released(MouseButton::Left));
+            assert!(!cursor.is_pressed(MouseButton::Right));
+        }
+
+        tick(&mut app);
+        {
+            let cursor = app.game.get::<&Cursor>();
+            assert!(cursor.is_pressed(MouseButton::Left));
+            assert!(!cursor.is_just_pressed(MouseButton::Left));
+        }
+
+        app.game.get::<&mut Cursor>().release(MouseButton::Left);
+        {
+            let cursor = app.game.get::<&Cursor>();
+            assert!(!cursor.is_pressed(MouseButton::Left));
+            assert!(cursor.is_just_released(MouseButton::Left));
+        }
+
+        tick(&mut app);
+        assert!(!app.game.get::<&Cursor>().is_just_released(MouseButton::Left));
+    }
+
+    #[test]
+    fn press_position() {
+        let mut app = input_app();
+        assert_eq!(None, app.game.get::<&Cursor>().press_position(MouseButton::Left));
+        {
+            let mut cursor = app.game.get::<&mut Cursor>();
+            cursor.position = Vec2::new(10.0, 20.0);
+            cursor.press(MouseButton::Left);
+            cursor.position = Vec2::new(30.0, 40.0);
+        }
+        tick(&mut app);
+        {
+            let cursor = app.game.get::<&Cursor>();
+            assert_eq!(Some(Vec2::new(10.0, 20.0)), cursor.press_position(MouseButton::Left));
+            assert_eq!(None, cursor.press_position(MouseButton::Right));
+        }
+
+        // Kept after release, and replaced by the next press
+        app.game.get::<&mut Cursor>().release(MouseButton::Left);
+        tick(&mut app);
+        assert_eq!(Some(Vec2::new(10.0, 20.0)), app.game.get::<&Cursor>().press_position(MouseButton::Left));
+        app.game.get::<&mut Cursor>().press(MouseButton::Left);
+        assert_eq!(Some(Vec2::new(30.0, 40.0)), app.game.get::<&Cursor>().press_position(MouseButton::Left));
+    }
+
+    #[test]
+    fn reconnecting_resets_state() {
+        let mut gamepads = Gamepads::default();
+        gamepads.apply(PAD, GamepadEvent::ButtonPressed(GamepadButton::Start));
+        assert!(gamepads.is_connected(PAD));
+        gamepads.apply(PAD, GamepadEvent::Disconnected);
+        assert!(!gamepads.is_connected(PAD));
+        gamepads.apply(PAD, GamepadEvent::Connected);
+        assert!(!gamepads.get(PAD).unwrap().is_pressed(GamepadButton::Start));
+    }
+}
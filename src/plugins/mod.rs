@@ -1,15 +1,23 @@
 mod ecs;
+mod hierarchy;
 mod asset;
 mod engine;
 mod window;
 mod graphics;
 mod input;
 mod camera;
+mod state;
+mod fps;
+mod physics;
 
 pub use ecs::*;
+pub use hierarchy::*;
 pub use asset::*;
 pub use engine::*;
 pub use window::*;
 pub use graphics::*;
 pub use input::*;
 pub use camera::*;
+pub use state::*;
+pub use fps::*;
+pub use physics::*;
@@ -0,0 +1,400 @@
+use derive_more::From;
+use glam::{I64Vec3, IVec3, Mat4, Vec3};
+use hecs::{Entity, World};
+use crate::math::{Sphere, Transform, AABB};
+use crate::{AppBuilder, Game, HashMap, Plugin, RunContext, Stage};
+
+/// Most cells of the broadphase grid that a body is sorted into.
+/// Bodies that span more, like large static floors, are tested against every other body instead.
+const MAX_BODY_CELLS: i64 = 64;
+
+/**
+ * Moves kinematic bodies every tick at [`Stage::UpdatePhysics`], and pushes them out of the static bodies they overlap.
+ * Bodies are entities with a [`Transform`], a [`Collider`] and a [`RigidBodyKind`].
+ * Their transforms are treated as world space, so they should not be attached to other entities.
+ * Fires a [`CollisionEvent`] for every pair of overlapping bodies that are not both static.
+ * [`crate::EcsPlugin`] must be installed first.
+ */
+#[derive(Default)]
+pub struct PhysicsPlugin {
+    pub settings: PhysicsSettings,
+}
+
+impl PhysicsPlugin {
+    pub fn with_settings(mut self, settings: PhysicsSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+}
+
+impl Plugin for PhysicsPlugin {
+    fn install(&mut self, builder: &mut AppBuilder) {
+        builder.system(Stage::UpdatePhysics, update_physics);
+        let game = builder.game();
+        assert!(game.contains::<World>(), "EcsPlugin must be installed before PhysicsPlugin");
+        assert!(self.settings.cell_size > 0.0, "PhysicsSettings::cell_size must be positive");
+        game.add(self.settings);
+    }
+}
+
+/// Domain configuring the [`PhysicsPlugin`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PhysicsSettings {
+    /// Acceleration of kinematic bodies with a [`Velocity`], in units per second squared.
+    pub gravity: Vec3,
+    /// Width of the cells that bodies are sorted into before testing them for overlaps.
+    /// Only bodies that share a cell are tested, so cells should be a little larger than most bodies.
+    /// Must be positive.
+    pub cell_size: f32,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        Self {
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            cell_size: 4.0,
+        }
+    }
+}
+
+/// Component with the velocity of a body, in units per second.
+/// Only moves kinematic bodies.
+#[derive(Copy, Clone, PartialEq, Default, Debug)]
+pub struct Velocity(pub Vec3);
+
+/// Component with the shape of a body, relative to its [`Transform`].
+#[derive(Copy, Clone, PartialEq, From, Debug)]
+pub enum Collider {
+    /// Box that stays axis-aligned when rotated, growing to fit the rotated box instead.
+    AABB(AABB),
+    Sphere(Sphere),
+}
+
+impl Collider {
+
+    pub fn transform(self, mat: Mat4) -> Self {
+        match self {
+            Self::AABB(aabb) => Self::AABB(aabb.transform(mat)),
+            Self::Sphere(sphere) => Self::Sphere(sphere.transform(mat)),
+        }
+    }
+
+    /// Smallest box around the shape.
+    pub fn bounds(&self) -> AABB {
+        match *self {
+            Self::AABB(aabb) => aabb,
+            Self::Sphere(sphere) => AABB::new(sphere.center, Vec3::splat(sphere.radius)),
+        }
+    }
+
+    fn translate(&mut self, offset: Vec3) {
+        match self {
+            Self::AABB(aabb) => aabb.center += offset,
+            Self::Sphere(sphere) => sphere.center += offset,
+        }
+    }
+}
+
+/// Component that determines how a body reacts to the others.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum RigidBodyKind {
+    /// Never moves, and stops kinematic bodies.
+    Static,
+    /// Moved by its [`Velocity`] and gravity, and pushed out of static bodies.
+    /// Kinematic bodies that overlap each other are reported, but not pushed apart.
+    Kinematic,
+}
+
+/// Event fired when two bodies overlap at the end of a physics step.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct CollisionEvent {
+    pub a: Entity,
+    pub b: Entity,
+    /// Direction that a would have to move in to stop overlapping b, IE: pointing out of b.
+    pub normal: Vec3,
+    /// Distance that a would have to move along the normal to stop overlapping b, before either was pushed apart.
+    pub depth: f32,
+}
+
+fn update_physics(game: &mut Game, mut ctx: RunContext) {
+    let mut world = game.get::<&mut World>();
+    let settings = game.get::<&PhysicsSettings>();
+    for event in step(&mut world, &settings, ctx.delta_secs()) {
+        ctx.fire(event);
+    }
+}
+
+/// Body gathered for a single physics step, with its collider in world space.
+struct Body {
+    entity: Entity,
+    kind: RigidBodyKind,
+    collider: Collider,
+}
+
+/**
+ * Advances the bodies of the world by delta seconds, returning the collisions found.
+ * Bodies are processed in the order of their entities, so that the same world always steps the same way.
+ */
+fn step(world: &mut World, settings: &PhysicsSettings, delta: f32) -> Vec<CollisionEvent> {
+
+    // Integrates the velocity of kinematic bodies, then their position
+    for (_, (transform, velocity, kind)) in world.query_mut::<(&mut Transform, &mut Velocity, &RigidBodyKind)>() {
+        if *kind != RigidBodyKind::Kinematic { continue }
+        velocity.0 += settings.gravity * delta;
+        transform.translation += velocity.0 * delta;
+    }
+
+    // Collects bodies, and pairs up those that share a cell
+    let mut bodies: Vec<Body> = world
+        .query_mut::<(&Transform, &Collider, &RigidBodyKind)>()
+        .into_iter()
+        .map(|(entity, (transform, collider, kind))| Body {
+            entity,
+            kind: *kind,
+            collider: collider.transform(Mat4::from(*transform)),
+        })
+        .collect();
+    bodies.sort_by_key(|body| body.entity);
+    let bounds: Vec<AABB> = bodies.iter().map(|body| body.collider.bounds()).collect();
+    let pairs = broadphase(&bounds, settings.cell_size);
+
+    // Reports overlapping pairs, and pushes kinematic bodies out of static ones by the shortest way out
+    let mut events = Vec::new();
+    for (i, j) in pairs {
+        let (a, b) = (&bodies[i], &bodies[j]);
+        if a.kind == RigidBodyKind::Static && b.kind == RigidBodyKind::Static { continue }
+        let Some((normal, depth)) = contact(a.collider, b.collider) else { continue };
+        events.push(CollisionEvent { a: a.entity, b: b.entity, normal, depth });
+        let (pushed, normal) = match (a.kind, b.kind) {
+            (RigidBodyKind::Kinematic, RigidBodyKind::Static) => (i, normal),
+            (RigidBodyKind::Static, RigidBodyKind::Kinematic) => (j, -normal),
+            _ => continue,
+        };
+        let body = &mut bodies[pushed];
+        body.collider.translate(normal * depth);
+        if let Ok(mut transform) = world.get::<&mut Transform>(body.entity) {
+            transform.translation += normal * depth;
+        }
+
+        // Stops the body from moving further into what it hit
+        if let Ok(mut velocity) = world.get::<&mut Velocity>(body.entity) {
+            let approach = velocity.0.dot(normal);
+            if approach < 0.0 {
+                velocity.0 -= normal * approach;
+            }
+        }
+    }
+    events
+}
+
+/**
+ * Pairs of indices of the boxes that share at least one cell of a uniform grid, each pair once and in ascending order.
+ * The first index of each pair is the lower one.
+ * Boxes that span more than [`MAX_BODY_CELLS`] cells are paired with every other box.
+ */
+fn broadphase(bounds: &[AABB], cell_size: f32) -> Vec<(usize, usize)> {
+    let mut cells: HashMap<IVec3, Vec<usize>> = HashMap::default();
+    let mut large = Vec::new();
+    let mut pairs = Vec::new();
+    for (i, aabb) in bounds.iter().enumerate() {
+        let min = ((aabb.center - aabb.extents) / cell_size).floor().as_ivec3();
+        let max = ((aabb.center + aabb.extents) / cell_size).floor().as_ivec3();
+        let span = max.as_i64vec3() - min.as_i64vec3() + I64Vec3::ONE;
+        if span.x.saturating_mul(span.y).saturating_mul(span.z) > MAX_BODY_CELLS {
+            large.push(i);
+            continue;
+        }
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let cell = cells.entry(IVec3::new(x, y, z)).or_default();
+                    pairs.extend(cell.iter().map(|&j| (j, i)));
+                    cell.push(i);
+                }
+            }
+        }
+    }
+    for &i in &large {
+        pairs.extend((0..bounds.len()).filter(|&j| j != i).map(|j| (i.min(j), i.max(j))));
+    }
+    pairs.sort_unstable();
+    pairs.dedup();
+    pairs
+}
+
+/// Normal pointing out of b, and depth, of the overlap between a and b, if any.
+fn contact(a: Collider, b: Collider) -> Option<(Vec3, f32)> {
+    match (a, b) {
+        (Collider::AABB(a), Collider::AABB(b)) => {
+            let offset = a.center - b.center;
+            let overlap = a.extents + b.extents - offset.abs();
+            if overlap.cmple(Vec3::ZERO).any() { return None }
+            let axis = min_axis(overlap);
+            Some((axis_normal(axis, offset[axis]), overlap[axis]))
+        },
+        (Collider::Sphere(a), Collider::Sphere(b)) => {
+            let offset = a.center - b.center;
+            let depth = a.radius + b.radius - offset.length();
+            if depth <= 0.0 { return None }
+            Some((offset.try_normalize().unwrap_or(Vec3::Y), depth))
+        },
+        (Collider::Sphere(a), Collider::AABB(b)) => sphere_aabb_contact(a, b),
+        (Collider::AABB(a), Collider::Sphere(b)) => sphere_aabb_contact(b, a).map(|(normal, depth)| (-normal, depth)),
+    }
+}
+
+/// Normal pointing out of the box, and depth, of the overlap between a sphere and a box, if any.
+fn sphere_aabb_contact(sphere: Sphere, aabb: AABB) -> Option<(Vec3, f32)> {
+    let local = sphere.center - aabb.center;
+    let closest = local.clamp(-aabb.extents, aabb.extents);
+    let offset = local - closest;
+    let distance_squared = offset.length_squared();
+    if distance_squared > 0.0 {
+        let distance = distance_squared.sqrt();
+        let depth = sphere.radius - distance;
+        return (depth > 0.0).then(|| (offset / distance, depth));
+    }
+
+    // The center is inside the box, so the sphere leaves through the nearest face
+    let face_distances = aabb.extents - local.abs();
+    let axis = min_axis(face_distances);
+    Some((axis_normal(axis, local[axis]), face_distances[axis] + sphere.radius))
+}
+
+/// Index of the smallest component, preferring x, then y.
+fn min_axis(v: Vec3) -> usize {
+    if v.x <= v.y && v.x <= v.z { 0 }
+    else if v.y <= v.z { 1 }
+    else { 2 }
+}
+
+/// Unit vector along the axis, facing the same way as sign, or positive if sign is zero.
+fn axis_normal(axis: usize, sign: f32) -> Vec3 {
+    let mut normal = Vec3::ZERO;
+    normal[axis] = if sign < 0.0 { -1.0 } else { 1.0 };
+    normal
+}
+
+#[cfg(test)]
+mod test {
+    use glam::Vec3;
+    use hecs::{Entity, World};
+    use crate::math::{Sphere, Transform, AABB};
+    use crate::{App, EcsPlugin, Events};
+    use super::{broadphase, contact, Collider, CollisionEvent, PhysicsPlugin, PhysicsSettings, RigidBodyKind, Velocity};
+
+    /// App with a floor whose top is at 0, and a box dropped from above it.
+    fn falling_box_app() -> (App, Entity) {
+        let mut builder = App::builder();
+        builder
+            .plugin(EcsPlugin)
+            .plugin(PhysicsPlugin::default())
+            .tick_rate(60.0);
+        let app = builder.build();
+        let mut world = app.game.get::<&mut World>();
+        world.spawn((
+            Transform::default().with_xyz(0.0, -0.5, 0.0),
+            Collider::from(AABB::new(Vec3::ZERO, Vec3::new(10.0, 0.5, 10.0))),
+            RigidBodyKind::Static,
+        ));
+        let falling = world.spawn((
+            Transform::default().with_xyz(0.3, 3.0, -0.2),
+            Collider::from(AABB::UNIT),
+            RigidBodyKind::Kinematic,
+            Velocity::default(),
+        ));
+        drop(world);
+        (app, falling)
+    }
+
+    #[test]
+    fn box_falls_onto_floor_and_rests() {
+        let (mut app, falling) = falling_box_app();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(120, tick_duration);
+        {
+            let world = app.game.get::<&World>();
+            let transform = world.get::<&Transform>(falling).unwrap();
+            let velocity = world.get::<&Velocity>(falling).unwrap();
+            assert!((transform.translation.y - 0.5).abs() < 1e-4, "{:?}", transform.translation);
+            assert_eq!(0.3, transform.translation.x);
+            assert_eq!(-0.2, transform.translation.z);
+            assert_eq!(0.0, velocity.0.y);
+        }
+
+        // Resting on the floor touches it every tick.
+        let events = app.game.get::<&Events<CollisionEvent>>();
+        let event = events.iter().last().unwrap();
+        assert_eq!(falling, event.b);
+        assert_eq!(Vec3::NEG_Y, event.normal);
+        assert!(event.depth > 0.0 && event.depth < 0.01);
+    }
+
+    #[test]
+    fn steps_are_deterministic() {
+        let run = || {
+            let (mut app, falling) = falling_box_app();
+            let tick_duration = app.tick_duration();
+            let mut translations = Vec::new();
+            for _ in 0..60 {
+                app.run_frame(tick_duration);
+                translations.push(app.game.get::<&World>().get::<&Transform>(falling).unwrap().translation);
+            }
+            translations
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn broadphase_pairs_neighbors_once() {
+        let bounds = [
+            AABB::new(Vec3::ZERO, Vec3::splat(3.0)),            // Spans several cells
+            AABB::new(Vec3::splat(1.0), Vec3::splat(0.5)),
+            AABB::new(Vec3::splat(100.0), Vec3::splat(0.5)),    // Far from the others
+        ];
+        assert_eq!(vec![(0, 1)], broadphase(&bounds, 2.0));
+    }
+
+    #[test]
+    fn broadphase_pairs_large_boxes_with_all() {
+        let bounds = [
+            AABB::new(Vec3::splat(100.0), Vec3::splat(0.5)),
+            AABB::new(Vec3::ZERO, Vec3::splat(1e9)),            // Would span every cell of the grid
+            AABB::new(Vec3::splat(-100.0), Vec3::splat(0.5)),
+        ];
+        assert_eq!(vec![(0, 1), (1, 2)], broadphase(&bounds, 1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "cell_size must be positive")]
+    fn cell_size_must_be_positive() {
+        let settings = PhysicsSettings { cell_size: f32::NAN, ..Default::default() };
+        let mut builder = App::builder();
+        builder
+            .plugin(EcsPlugin)
+            .plugin(PhysicsPlugin::default().with_settings(settings));
+    }
+
+    #[test]
+    fn contacts_point_out_of_b() {
+        let floor = Collider::from(AABB::new(Vec3::ZERO, Vec3::new(5.0, 1.0, 5.0)));
+        let aabb = Collider::from(AABB::new(Vec3::new(0.0, 1.25, 0.0), Vec3::splat(0.5)));
+        assert_eq!(Some((Vec3::Y, 0.25)), contact(aabb, floor));
+        assert_eq!(Some((Vec3::NEG_Y, 0.25)), contact(floor, aabb));
+
+        let sphere = Collider::from(Sphere::new(Vec3::new(0.0, 1.5, 0.0), 1.0));
+        assert_eq!(Some((Vec3::Y, 0.5)), contact(sphere, floor));
+        assert_eq!(Some((Vec3::NEG_Y, 0.5)), contact(floor, sphere));
+
+        // Spheres whose center is inside a box leave through its nearest face.
+        let sunk = Collider::from(Sphere::new(Vec3::new(4.5, 0.0, 0.0), 1.0));
+        assert_eq!(Some((Vec3::X, 1.5)), contact(sunk, floor));
+
+        let other = Collider::from(Sphere::new(Vec3::new(1.5, 1.5, 0.0), 1.0));
+        let (normal, depth) = contact(sphere, other).unwrap();
+        assert!(normal.abs_diff_eq(Vec3::NEG_X, 1e-6) && (depth - 0.5).abs() < 1e-6);
+
+        let apart = Collider::from(AABB::new(Vec3::new(0.0, 2.0, 0.0), Vec3::splat(0.5)));
+        assert_eq!(None, contact(apart, floor));
+    }
+}
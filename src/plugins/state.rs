@@ -0,0 +1,219 @@
+use std::hash::Hash;
+use vecmap::VecSet;
+use crate::{AppBuilder, Game, HashMap, Plugin, RunContext, Stage, System};
+
+/// Value that can be used as a mutually exclusive app state.
+/// IE: MainMenu, Playing, Paused.
+pub trait State: Clone + Eq + Hash + Send + Sync + 'static {}
+impl<S: Clone + Eq + Hash + Send + Sync + 'static> State for S {}
+
+/**
+ * Adds a [`CurrentState`] domain, and switches between sets of systems as the state changes.
+ * Systems are attached to states with [`AppBuilder::on_enter`], [`AppBuilder::on_exit`] and [`AppBuilder::on_update`].
+ * Transitions requested with [`RunContext::transition_to`] are applied at the start of the next [`Stage::PreUpdate`].
+ */
+pub struct StatePlugin<S: State> {
+    initial_state: S,
+}
+
+impl<S: State> StatePlugin<S> {
+    pub fn new(initial_state: S) -> Self {
+        Self { initial_state }
+    }
+}
+
+impl<S: State> Plugin for StatePlugin<S> {
+    fn install(&mut self, builder: &mut AppBuilder) {
+        builder.game()
+            .add(CurrentState::new(self.initial_state.clone()))
+            .init(|_| StateSystems::<S>::default());
+        builder.system_with_priority(Stage::PreUpdate, apply_transitions::<S>, i32::MIN);
+    }
+}
+
+/// Domain that stores the current state of type S.
+pub struct CurrentState<S: State> {
+    state: S,
+    next: Option<S>,
+    entered: bool,
+}
+
+impl<S: State> CurrentState<S> {
+
+    fn new(state: S) -> Self {
+        Self { state, next: None, entered: false }
+    }
+
+    pub fn get(&self) -> &S {
+        &self.state
+    }
+
+    /// Requests a transition to a new state.
+    /// Applied at the start of the next [`Stage::PreUpdate`].
+    /// Does nothing if the state is the current state.
+    pub fn transition_to(&mut self, state: S) {
+        self.next = Some(state);
+    }
+}
+
+/// Systems attached to each state of type S.
+struct StateSystems<S: State> {
+    on_enter: HashMap<S, VecSet<System>>,
+    on_exit: HashMap<S, VecSet<System>>,
+    on_update: HashMap<S, VecSet<System>>,
+}
+
+impl<S: State> Default for StateSystems<S> {
+    fn default() -> Self {
+        Self {
+            on_enter: HashMap::default(),
+            on_exit: HashMap::default(),
+            on_update: HashMap::default(),
+        }
+    }
+}
+
+impl AppBuilder {
+
+    /// Adds a system that runs once whenever the state is entered.
+    pub fn on_enter<S: State>(&mut self, state: S, system: System) -> &mut Self {
+        state_systems::<S>(self).on_enter.entry(state).or_default().insert(system);
+        self
+    }
+
+    /// Adds a system that runs once whenever the state is exited.
+    pub fn on_exit<S: State>(&mut self, state: S, system: System) -> &mut Self {
+        state_systems::<S>(self).on_exit.entry(state).or_default().insert(system);
+        self
+    }
+
+    /// Adds a system to [`Stage::Update`] that is only enabled while in the state.
+    /// Like any system, it can only be added once, so it cannot be shared across states.
+    pub fn on_update<S: State>(&mut self, state: S, system: System) -> &mut Self {
+        state_systems::<S>(self).on_update.entry(state).or_default().insert(system);
+        self.system_enabled(Stage::Update, system, false)
+    }
+}
+
+impl<'a> RunContext<'a> {
+
+    /**
+     * Requests a transition to a new state.
+     * See [`CurrentState::transition_to`].
+     */
+    pub fn transition_to<S: State>(&mut self, state: S) {
+        self.run_command(move |game: &mut Game| {
+            game.get::<&mut CurrentState<S>>().transition_to(state.clone());
+        });
+    }
+}
+
+fn state_systems<S: State>(builder: &mut AppBuilder) -> std::cell::RefMut<'_, StateSystems<S>> {
+    let game = builder.game();
+    game.init(|_| StateSystems::<S>::default());
+    game.get::<&mut StateSystems<S>>()
+}
+
+/// Enters the initial state, or applies the pending transition if any.
+fn apply_transitions<S: State>(game: &mut Game, mut ctx: RunContext) {
+
+    // Determines which states were exited and entered.
+    let (exited, entered) = {
+        let mut current = game.get::<&mut CurrentState<S>>();
+        if !current.entered {
+            current.entered = true;
+            (None, current.state.clone())
+        }
+        else {
+            let Some(next) = current.next.take() else { return };
+            if next == current.state {
+                return;
+            }
+            let exited = std::mem::replace(&mut current.state, next.clone());
+            (Some(exited), next)
+        }
+    };
+
+    // Runs exit hooks, then enter hooks, and swaps which update systems are enabled.
+    let (on_exit, on_enter, exit_updates, enter_updates) = {
+        let systems = game.get::<&StateSystems<S>>();
+        let collect = |systems: &HashMap<S, VecSet<System>>, state: Option<&S>| -> Vec<System> {
+            let Some(state) = state else { return Vec::new() };
+            systems.get(state).into_iter().flatten().copied().collect()
+        };
+        (
+            collect(&systems.on_exit, exited.as_ref()),
+            collect(&systems.on_enter, Some(&entered)),
+            collect(&systems.on_update, exited.as_ref()),
+            collect(&systems.on_update, Some(&entered)),
+        )
+    };
+    for system in on_exit {
+        system(game, ctx.reborrow());
+    }
+    for system in on_enter {
+        system(game, ctx.reborrow());
+    }
+    for system in exit_updates {
+        ctx.disable_system(system);
+    }
+    for system in enter_updates {
+        ctx.enable_system(system);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::{App, CurrentState, Game, RunContext, StatePlugin};
+
+    #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+    enum GameState { Menu, Playing }
+
+    #[derive(Default)]
+    struct Counts {
+        menu_updates: u32,
+        menu_exits: u32,
+        playing_enters: u32,
+        playing_updates: u32,
+    }
+
+    fn update_menu(game: &mut Game, mut ctx: RunContext) {
+        game.get::<&mut Counts>().menu_updates += 1;
+        ctx.transition_to(GameState::Playing);
+    }
+
+    fn exit_menu(game: &mut Game, _ctx: RunContext) {
+        game.get::<&mut Counts>().menu_exits += 1;
+    }
+
+    fn enter_playing(game: &mut Game, _ctx: RunContext) {
+        game.get::<&mut Counts>().playing_enters += 1;
+    }
+
+    fn update_playing(game: &mut Game, _ctx: RunContext) {
+        game.get::<&mut Counts>().playing_updates += 1;
+    }
+
+    #[test]
+    fn transitions() {
+        let mut builder = App::builder();
+        builder
+            .plugin(StatePlugin::new(GameState::Menu))
+            .on_update(GameState::Menu, update_menu)
+            .on_exit(GameState::Menu, exit_menu)
+            .on_enter(GameState::Playing, enter_playing)
+            .on_update(GameState::Playing, update_playing);
+        builder.game().add(Counts::default());
+        let mut app = builder.build();
+        let tick_duration = app.tick_duration();
+        app.run_n_frames_simulated(4, tick_duration);
+
+        assert_eq!(GameState::Playing, *app.game.get::<&CurrentState<GameState>>().get());
+        let counts = app.game.get::<&Counts>();
+        assert_eq!(1, counts.menu_updates);
+        assert_eq!(1, counts.menu_exits);
+        assert_eq!(1, counts.playing_enters);
+        assert_eq!(3, counts.playing_updates);
+    }
+}
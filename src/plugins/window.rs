@@ -1,3 +1,4 @@
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, Duration};
 use glam::Vec2;
 use wgpu::TextureFormat;
@@ -6,15 +7,25 @@ use winit::event::{DeviceEvent, ElementState, Event, MouseScrollDelta, WindowEve
 use winit::event_loop::{EventLoop, EventLoopBuilder, EventLoopWindowTarget};
 use winit::keyboard::PhysicalKey;
 use winit::monitor::{MonitorHandle, VideoMode};
-use winit::window::{CursorGrabMode, Fullscreen, Window as WinitWindow, WindowBuilder};
+use winit::window::{CursorGrabMode, Fullscreen, Icon, Window as WinitWindow, WindowBuilder};
 use crate::{App, AppBuilder, AppRunner, Cursor, GraphicsState, Keyboard, Plugin, WindowRequest, WindowRequests};
 
 /// Opens a window and injects a [`GraphicsState`] for use in a graphics engine.
 /// Adds a runner that is synced with the framerate.
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[derive(Clone, Eq, PartialEq, Debug)]
 pub struct WindowPlugin {
     pub window_width: u32,
     pub window_height: u32,
+    pub title: String,
+    pub resizable: bool,
+    /// Smallest size the window can be resized to, in pixels.
+    pub min_size: Option<(u32, u32)>,
+    /// Largest size the window can be resized to, in pixels.
+    pub max_size: Option<(u32, u32)>,
+    /// Path to an image file to use as the window's icon.
+    pub icon: Option<PathBuf>,
+    /// Samples per pixel when rendering. 1 disables MSAA.
+    pub msaa_samples: u32,
     pub features: WindowFeatures,
 }
 
@@ -23,6 +34,12 @@ impl Default for WindowPlugin {
         Self {
             window_width: 512,
             window_height: 512,
+            title: String::from("Game"),
+            resizable: true,
+            min_size: None,
+            max_size: None,
+            icon: None,
+            msaa_samples: 1,
             features: WindowFeatures::default(),
         }
     }
@@ -31,18 +48,32 @@ impl Default for WindowPlugin {
 impl Plugin for WindowPlugin {
     fn install(&mut self, builder: &mut AppBuilder) {
         let event_loop = EventLoopBuilder::<()>::with_user_event().build().unwrap();
-        let window = WindowBuilder::new()
+        let mut window_builder = WindowBuilder::new()
             .with_inner_size(PhysicalSize::new(self.window_width, self.window_height))
-            .build(&event_loop).unwrap();
+            .with_title(&self.title)
+            .with_resizable(self.resizable);
+        if let Some((width, height)) = self.min_size {
+            window_builder = window_builder.with_min_inner_size(PhysicalSize::new(width, height));
+        }
+        if let Some((width, height)) = self.max_size {
+            window_builder = window_builder.with_max_inner_size(PhysicalSize::new(width, height));
+        }
+        if let Some(icon_path) = &self.icon {
+            match load_icon(icon_path) {
+                Ok(icon) => window_builder = window_builder.with_window_icon(Some(icon)),
+                Err(err) => log::error!("Failed to load window icon {}: {err}", icon_path.display()),
+            }
+        }
+        let window = window_builder.build(&event_loop).unwrap();
         let current_monitor = window.current_monitor();
-        let mut inner_window = Window::new(current_monitor);
+        let mut inner_window = Window::new(current_monitor, self.title.clone());
         for monitor in window.available_monitors() {
             for video_mode in monitor.video_modes() {
                 inner_window.video_modes.push((monitor.clone(), video_mode));
             }
         }
         builder.game()
-            .add(GraphicsState::new(&window, TextureFormat::Depth24Plus))
+            .add(GraphicsState::new(&window, TextureFormat::Depth24Plus, self.msaa_samples))
             .add(inner_window);
         builder.runner(WindowRunner {
             event_loop: Some(event_loop),
@@ -52,6 +83,14 @@ impl Plugin for WindowPlugin {
     }
 }
 
+/// Decodes an image file into a window icon.
+fn load_icon(path: &Path) -> anyhow::Result<Icon> {
+    let image = image::open(path)?.into_rgba8();
+    let (width, height) = image.dimensions();
+    let icon = Icon::from_rgba(image.into_raw(), width, height)?;
+    Ok(icon)
+}
+
 /// Optional features
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct WindowFeatures {
@@ -85,18 +124,34 @@ impl AppRunner for WindowRunner {
 
         let event_loop = self.event_loop.take().unwrap();
 
+        // Connects to gamepads
+        #[cfg(feature = "gamepad")]
+        let mut gilrs = match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(err) => {
+                log::warn!("Gamepads unavailable: {err}");
+                None
+            }
+        };
+
         // Starts game loop
         let mut last_update: Option<SystemTime> = None;
         event_loop.run(move |event, target| {
             match event {
-                Event::WindowEvent { event, .. } => handle_window_event(
-                    event,
-                    target,
-                    &mut app,
-                    &self.window,
-                    &self.features,
-                    &mut last_update
-                ),
+                Event::WindowEvent { event, .. } => {
+                    #[cfg(feature = "gamepad")]
+                    if let (WindowEvent::RedrawRequested, Some(gilrs)) = (&event, &mut gilrs) {
+                        poll_gamepads(gilrs, &mut app);
+                    }
+                    handle_window_event(
+                        event,
+                        target,
+                        &mut app,
+                        &self.window,
+                        &self.features,
+                        &mut last_update
+                    )
+                },
                 Event::DeviceEvent { event, .. } => handle_device_event(event, &mut app),
                 _ => {}
             }
@@ -114,16 +169,19 @@ pub struct Window {
     pub(crate) current_monitor: Option<MonitorHandle>,
     /// Size of the window's inner content
     pub(crate) size: Vec2,
+    /// Title shown in the window's title bar
+    pub(crate) title: String,
 }
 
 impl Window {
 
-    pub(crate) fn new(current_monitor: Option<MonitorHandle>) -> Self {
+    pub(crate) fn new(current_monitor: Option<MonitorHandle>, title: String) -> Self {
         Self {
             fullscreen: None,
             video_modes: Vec::new(),
             current_monitor,
             size: Vec2::ZERO,
+            title,
         }
     }
 
@@ -155,6 +213,10 @@ impl Window {
     pub fn size(&self) -> Vec2 {
         self.size
     }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
 }
 
 fn handle_window_event(
@@ -184,6 +246,13 @@ fn handle_window_event(
                 ElementState::Released => keyboard.release(key_code),
             }
         },
+        WindowEvent::MouseInput { state, button, .. } => {
+            let mut cursor = app.game.get::<&mut Cursor>();
+            match state {
+                ElementState::Pressed => cursor.press(button.into()),
+                ElementState::Released => cursor.release(button.into()),
+            }
+        },
         WindowEvent::CursorMoved { position, .. } => {
             let mut cursor = app.game.get::<&mut Cursor>();
             cursor.position = Vec2::new(position.x as f32, position.y as f32);
@@ -205,7 +274,10 @@ fn handle_window_event(
             run_game_logic(app, last_update, window, &features, target);
             window.request_redraw();
         },
-        WindowEvent::CloseRequested => target.exit(),
+        WindowEvent::CloseRequested => {
+            app.shutdown();
+            target.exit();
+        },
         _ => {}
     }
 }
@@ -295,6 +367,88 @@ fn run_game_logic<'a>(
                 window.set_fullscreen(fullscreen.clone());
                 inner_window.fullscreen = fullscreen;
             }, 
+            WindowRequest::SetVsync(vsync) => {
+                log::debug!("Setting vsync: {vsync}");
+                app.game.get::<&mut GraphicsState>().set_vsync(vsync);
+            },
+            WindowRequest::SetTitle(title) => {
+                let mut inner_window = app.game.get::<&mut Window>();
+                if inner_window.title != title {
+                    window.set_title(&title);
+                    inner_window.title = title;
+                }
+            },
+        }
+    }
+}
+
+/// Applies gamepad events received since the last frame to the [`Gamepads`](crate::Gamepads) domain.
+/// Fires connection events through the app's event bus.
+#[cfg(feature = "gamepad")]
+fn poll_gamepads(gilrs: &mut gilrs::Gilrs, app: &mut App) {
+    use crate::{GamepadConnected, GamepadDisconnected, GamepadEvent, GamepadId, Gamepads};
+    while let Some(gilrs::Event { id, event, .. }) = gilrs.next_event() {
+        let id = GamepadId(usize::from(id));
+        let Some(event) = gamepad_event(event) else { continue };
+        app.game.get::<&mut Gamepads>().apply(id, event);
+        match event {
+            GamepadEvent::Connected => app.fire(GamepadConnected(id)),
+            GamepadEvent::Disconnected => app.fire(GamepadDisconnected(id)),
+            _ => {},
         }
     }
+}
+
+/// Event of the [`Gamepads`](crate::Gamepads) domain that a gilrs event maps to, if any.
+#[cfg(feature = "gamepad")]
+fn gamepad_event(event: gilrs::EventType) -> Option<crate::GamepadEvent> {
+    use gilrs::EventType;
+    use crate::{GamepadAxis, GamepadEvent};
+    let event = match event {
+        EventType::Connected => GamepadEvent::Connected,
+        EventType::Disconnected => GamepadEvent::Disconnected,
+        EventType::ButtonPressed(button, _) => GamepadEvent::ButtonPressed(gamepad_button(button)?),
+        EventType::ButtonReleased(button, _) => GamepadEvent::ButtonReleased(gamepad_button(button)?),
+        EventType::ButtonChanged(gilrs::Button::LeftTrigger2, value, _) => GamepadEvent::AxisChanged(GamepadAxis::LeftTrigger, value),
+        EventType::ButtonChanged(gilrs::Button::RightTrigger2, value, _) => GamepadEvent::AxisChanged(GamepadAxis::RightTrigger, value),
+        EventType::AxisChanged(axis, value, _) => {
+            let axis = match axis {
+                gilrs::Axis::LeftStickX => GamepadAxis::LeftStickX,
+                gilrs::Axis::LeftStickY => GamepadAxis::LeftStickY,
+                gilrs::Axis::RightStickX => GamepadAxis::RightStickX,
+                gilrs::Axis::RightStickY => GamepadAxis::RightStickY,
+                _ => return None,
+            };
+            GamepadEvent::AxisChanged(axis, value)
+        },
+        _ => return None,
+    };
+    Some(event)
+}
+
+#[cfg(feature = "gamepad")]
+fn gamepad_button(button: gilrs::Button) -> Option<crate::GamepadButton> {
+    use gilrs::Button;
+    use crate::GamepadButton;
+    let button = match button {
+        Button::South => GamepadButton::South,
+        Button::East => GamepadButton::East,
+        Button::North => GamepadButton::North,
+        Button::West => GamepadButton::West,
+        Button::LeftTrigger => GamepadButton::LeftBumper,
+        Button::RightTrigger => GamepadButton::RightBumper,
+        Button::LeftTrigger2 => GamepadButton::LeftTrigger,
+        Button::RightTrigger2 => GamepadButton::RightTrigger,
+        Button::Select => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        Button::Mode => GamepadButton::Mode,
+        Button::LeftThumb => GamepadButton::LeftStick,
+        Button::RightThumb => GamepadButton::RightStick,
+        Button::DPadUp => GamepadButton::DPadUp,
+        Button::DPadDown => GamepadButton::DPadDown,
+        Button::DPadLeft => GamepadButton::DPadLeft,
+        Button::DPadRight => GamepadButton::DPadRight,
+        _ => return None,
+    };
+    Some(button)
 }
\ No newline at end of file
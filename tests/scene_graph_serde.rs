@@ -0,0 +1,58 @@
+use hecs_game::{HasId, NodeId, SceneGraph, SceneGraphError, SerializedNode};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct Named(String);
+impl HasId for Named {
+    type Id = NodeId;
+}
+
+fn named(name: &str) -> Named {
+    Named(name.to_owned())
+}
+
+/// Names of every node with their depths, depth-first.
+fn hierarchy(graph: &SceneGraph<Named>) -> Vec<(String, u32)> {
+    let mut visited = Vec::new();
+    graph.propagate(0, |depth, value| {
+        visited.push((value.0.clone(), depth));
+        depth + 1
+    });
+    visited
+}
+
+#[test]
+fn round_trip() {
+    let mut graph = SceneGraph::new();
+    let body = graph.insert(named("body"));
+    let arm = graph.insert_child(named("arm"), body).unwrap();
+    graph.insert_child(named("hand"), arm).unwrap();
+    graph.insert_child(named("head"), body).unwrap();
+    graph.insert(named("ground"));
+
+    let yaml = serde_yaml::to_string(&graph).unwrap();
+    let restored: SceneGraph<Named> = serde_yaml::from_str(&yaml).unwrap();
+    assert_eq!(5, restored.len());
+    assert_eq!(2, restored.root_ids().len());
+    assert_eq!(hierarchy(&graph), hierarchy(&restored));
+    assert_eq!(
+        vec![("body".to_owned(), 0), ("arm".to_owned(), 1), ("hand".to_owned(), 2), ("head".to_owned(), 1), ("ground".to_owned(), 0)],
+        hierarchy(&restored),
+    );
+}
+
+#[test]
+fn from_serialized_errors() {
+    let record = |id, parent_id| SerializedNode { id, parent_id, value: named("node") };
+    let result = SceneGraph::from_serialized([record(0, None), record(0, None)]);
+    assert!(matches!(result, Err(SceneGraphError::DuplicateSerializedId)));
+    let result = SceneGraph::from_serialized([record(0, None), record(1, Some(2))]);
+    assert!(matches!(result, Err(SceneGraphError::NoSuchNode)));
+    let result = SceneGraph::from_serialized([record(0, None), record(1, Some(2)), record(2, Some(1))]);
+    assert!(matches!(result, Err(SceneGraphError::SerializedCycle)));
+
+    // Children may come before their parents.
+    let graph = SceneGraph::from_serialized([record(1, Some(0)), record(0, None)]).unwrap();
+    assert_eq!(1, graph.root_ids().len());
+    assert_eq!(2, graph.len());
+}